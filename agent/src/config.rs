@@ -7,6 +7,8 @@ use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tracing::{info, warn};
 
+use crate::core::path_policy::PathRule;
+
 /// 启动配置（Bootstrap Configuration）
 /// 仅包含连接服务器所需的最小信息
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +29,18 @@ pub struct AgentConfig {
     pub file_operations: FileOperationsSection,
     pub commands: CommandsSection,
     pub reconnect: ReconnectSection,
+    #[serde(default)]
+    pub terminal: TerminalSection,
+    #[serde(default)]
+    pub tunnel: TunnelSection,
+    #[serde(default)]
+    pub file_transfer: FileTransferSection,
+    #[serde(default)]
+    pub file_backup: FileBackupSection,
+    #[serde(default)]
+    pub integrity: IntegritySection,
+    #[serde(default)]
+    pub log_shipping: LogShippingSection,
     pub service: Option<ServiceSection>,
 }
 
@@ -89,6 +103,9 @@ pub struct FileOperationsSection {
     pub allowed_paths: Vec<String>,
     #[serde(default)]
     pub blocked_paths: Vec<String>,
+    /// 路径访问规则（glob + 只读/读写/拒绝），追加在 allowed_paths 与 blocked_paths 之后
+    #[serde(default)]
+    pub rules: Vec<PathRule>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -108,6 +125,207 @@ pub struct ReconnectSection {
     pub jitter: bool,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerminalSection {
+    pub max_sessions: usize,
+    /// 空闲超时（秒），0 表示不限制
+    pub idle_timeout: u64,
+    /// 最长存活时间（秒），0 表示不限制
+    pub max_duration: u64,
+    /// 每个会话的输出缓冲区容量（字节）
+    pub buffer_size: usize,
+    /// 是否捕获终端中输入的命令行写入审计日志
+    pub audit_commands: bool,
+    /// 每个会话输出上报的速率上限（字节/秒），0 表示不限速
+    pub output_rate_limit: u64,
+    /// 输出上报允许的突发字节数，积压超过该值时合并为屏幕快照
+    pub output_burst: u64,
+}
+
+impl Default for TerminalSection {
+    fn default() -> Self {
+        Self {
+            max_sessions: 10,
+            idle_timeout: 1800,
+            max_duration: 8 * 3600,
+            buffer_size: 10 * 1024 * 1024,
+            audit_commands: false,
+            output_rate_limit: 256 * 1024,
+            output_burst: 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TunnelSection {
+    /// 是否允许 TCP 端口转发隧道
+    pub enabled: bool,
+    pub max_tunnels: usize,
+    /// 每条隧道未确认回传数据的窗口大小（字节）
+    pub window_size: usize,
+    /// 连接目标的超时（秒）
+    pub connect_timeout: u64,
+    /// 空闲超时（秒），0 表示不限制
+    pub idle_timeout: u64,
+    /// 允许的目标，格式为 `host:ports`，如 `127.0.0.1:8080`、`10.0.0.0/8:*`、`*.corp.example.com:443`
+    pub allow: Vec<String>,
+    /// 拒绝的目标，格式同 allow，优先于 allow
+    pub deny: Vec<String>,
+    /// 是否作为 SOCKS5 出口接受 CONNECT 转发（目标同样受 allow/deny 限制）
+    ///
+    /// 仅 Agent 端：操作者监听端与服务端中继尚未提供，见 docs/tunnel_protocol.md
+    pub socks_enabled: bool,
+}
+
+impl Default for TunnelSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_tunnels: 16,
+            window_size: 256 * 1024,
+            connect_timeout: 10,
+            idle_timeout: 600,
+            allow: Vec::new(),
+            deny: Vec::new(),
+            socks_enabled: false,
+        }
+    }
+}
+
+/// 分块文件传输
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileTransferSection {
+    /// 分块传输允许的最大文件大小，如 "10GB"
+    pub max_transfer_size: String,
+    /// 单个分块的最大字节数
+    pub max_chunk_size: usize,
+    /// 未完成的上传暂存文件保留时间（秒），超时后删除，0 表示不清理
+    pub stale_timeout: u64,
+    /// 目录归档内的最大文件数
+    pub max_archive_entries: usize,
+}
+
+impl Default for FileTransferSection {
+    fn default() -> Self {
+        Self {
+            max_transfer_size: "10GB".to_string(),
+            max_chunk_size: 1024 * 1024,
+            stale_timeout: 24 * 3600,
+            max_archive_entries: 10_000,
+        }
+    }
+}
+
+/// 覆盖写入前的版本备份
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileBackupSection {
+    pub enabled: bool,
+    /// 每个文件保留的历史版本数
+    pub max_versions: usize,
+    /// 全部备份的总大小上限，如 "512MB"
+    pub max_total_size: String,
+    /// 超过该大小的文件不备份
+    pub max_file_size: String,
+}
+
+impl Default for FileBackupSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_versions: 10,
+            max_total_size: "512MB".to_string(),
+            max_file_size: "16MB".to_string(),
+        }
+    }
+}
+
+/// 文件完整性监控
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IntegritySection {
+    pub enabled: bool,
+    /// 监控的文件或目录
+    pub paths: Vec<String>,
+    /// 排除的 glob，如 `/etc/mtab`、`**/*.cache`
+    pub exclude: Vec<String>,
+    /// 全量重新扫描的间隔（秒）
+    pub scan_interval: u64,
+    /// Linux 下使用 inotify 实时检测变化
+    pub use_inotify: bool,
+    /// 超过该大小的文件只记录元数据，不计算哈希
+    pub max_hash_size: u64,
+}
+
+impl Default for IntegritySection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            paths: vec![
+                "/etc".to_string(),
+                "/usr/bin".to_string(),
+                "/usr/sbin".to_string(),
+            ],
+            exclude: vec![],
+            scan_interval: 3600,
+            use_inotify: true,
+            max_hash_size: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// 日志采集与上送
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogShippingSection {
+    pub enabled: bool,
+    /// 采集的文件 glob，如 `/var/log/*.log`
+    pub paths: Vec<String>,
+    /// 排除的 glob
+    pub exclude: Vec<String>,
+    /// 服务端接收地址（相对于 server.base_url）
+    pub endpoint: String,
+    /// 首次发现的文件从末尾开始采集，不上送已有内容
+    pub start_at_end: bool,
+    /// 重新展开 glob 的间隔（秒）
+    pub scan_interval: u64,
+    /// 读取新增内容的间隔（毫秒）
+    pub poll_interval_ms: u64,
+    /// 每批上送的行数
+    pub batch_size: usize,
+    /// 不足一批时的上送间隔（秒）
+    pub batch_interval_secs: u64,
+    /// 未上送的行数上限，达到后暂停读取
+    pub max_buffered_lines: usize,
+    /// 超长的行被截断
+    pub max_line_length: usize,
+    pub max_retries: u32,
+    pub retry_interval_secs: u64,
+}
+
+impl Default for LogShippingSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            paths: vec!["/var/log/*.log".to_string()],
+            exclude: vec![],
+            endpoint: "/agent/logs".to_string(),
+            start_at_end: true,
+            scan_interval: 30,
+            poll_interval_ms: 1000,
+            batch_size: 500,
+            batch_interval_secs: 10,
+            max_buffered_lines: 10000,
+            max_line_length: 16 * 1024,
+            max_retries: 3,
+            retry_interval_secs: 5,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceSection {
     pub auto_start: bool,
@@ -385,6 +603,7 @@ impl ConfigManager {
                     "/root/.ssh".to_string(),
                     "C:\\Windows\\System32".to_string(),
                 ],
+                rules: vec![],
             },
             commands: CommandsSection {
                 default_timeout: 300,
@@ -403,6 +622,12 @@ impl ConfigManager {
                 max_attempts: 0,
                 jitter: true,
             },
+            terminal: TerminalSection::default(),
+            tunnel: TunnelSection::default(),
+            file_transfer: FileTransferSection::default(),
+            file_backup: FileBackupSection::default(),
+            integrity: IntegritySection::default(),
+            log_shipping: LogShippingSection::default(),
             service: None,
         }
     }
@@ -500,6 +725,11 @@ impl AgentConfig {
     pub fn max_file_size_bytes(&self) -> Result<u64> {
         Self::parse_file_size(&self.file_operations.max_file_size)
     }
+
+    /// 获取分块传输的最大文件大小（字节）
+    pub fn max_transfer_size_bytes(&self) -> Result<u64> {
+        Self::parse_file_size(&self.file_transfer.max_transfer_size)
+    }
 }
 
 impl Default for AgentConfig {
@@ -558,6 +788,7 @@ impl Default for AgentConfig {
                     "/root/.ssh".to_string(),
                     "C:\\Windows\\System32".to_string(),
                 ],
+                rules: vec![],
             },
             commands: CommandsSection {
                 default_timeout: 300,
@@ -576,6 +807,12 @@ impl Default for AgentConfig {
                 max_attempts: 0,
                 jitter: true,
             },
+            terminal: TerminalSection::default(),
+            tunnel: TunnelSection::default(),
+            file_transfer: FileTransferSection::default(),
+            file_backup: FileBackupSection::default(),
+            integrity: IntegritySection::default(),
+            log_shipping: LogShippingSection::default(),
             service: None, 
         }
     }
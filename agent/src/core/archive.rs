@@ -0,0 +1,438 @@
+// agent/src/core/archive.rs
+// 目录打包（tar.gz）与上传归档解包
+//
+// 打包时不跟随符号链接，逐个文件经过路径策略检查；解包时拒绝绝对路径与 `..`，
+// 只还原普通文件与目录，每个目标路径同样经过路径策略检查。解包先把全部文件
+// 写入目标旁的暂存文件，全部成功后再逐个替换，中途出错不会留下半个目录。
+
+use anyhow::{anyhow, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+use crate::core::path_policy::{glob_match, Access};
+
+/// 路径检查回调：返回允许访问的真实路径
+pub type PathCheck<'a> = &'a dyn Fn(&Path, Access) -> Result<PathBuf>;
+
+/// 覆盖已有文件前的回调（用于保存版本备份）
+pub type BeforeReplace<'a> = &'a dyn Fn(&Path);
+
+/// 按相对路径过滤归档内容
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ArchiveFilter {
+    /// 包含的 glob（相对于归档根目录），为空表示全部
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// 排除的 glob，优先于 include；匹配目录时整个目录不再遍历
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl ArchiveFilter {
+    pub fn matches(&self, relative: &str) -> bool {
+        if self.excludes(relative) {
+            return false;
+        }
+        self.include.is_empty() || self.include.iter().any(|pattern| glob_match(pattern, relative))
+    }
+
+    pub fn excludes(&self, relative: &str) -> bool {
+        self.exclude.iter().any(|pattern| glob_match(pattern, relative))
+    }
+}
+
+/// 归档大小限制
+#[derive(Debug, Clone, Copy)]
+pub struct ArchiveLimits {
+    /// 文件内容总字节数上限
+    pub max_total_size: u64,
+    pub max_entries: usize,
+}
+
+/// 打包或解包结果
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ArchiveSummary {
+    pub files: usize,
+    /// 文件内容总字节数（未压缩）
+    pub bytes: u64,
+    /// 因路径策略、符号链接或不支持的条目类型而跳过的数量
+    pub skipped: usize,
+    /// 打包时达到大小上限而提前结束
+    pub truncated: bool,
+}
+
+/// 将 `root` 目录打包为 tar.gz 写入 `dest`
+pub fn create_tar_gz(
+    root: &Path,
+    dest: &Path,
+    filter: &ArchiveFilter,
+    limits: ArchiveLimits,
+    check: PathCheck,
+) -> Result<ArchiveSummary> {
+    let root = check(root, Access::Read)?;
+    if !root.is_dir() {
+        return Err(anyhow!("Path is not a directory: {}", root.display()));
+    }
+
+    let encoder = GzEncoder::new(File::create(dest)?, Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    builder.follow_symlinks(false);
+
+    let mut summary = ArchiveSummary::default();
+    let mut pending = vec![root.clone()];
+    'walk: while let Some(dir) = pending.pop() {
+        let mut entries: Vec<fs::DirEntry> = fs::read_dir(&dir)?.filter_map(|entry| entry.ok()).collect();
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            let relative = relative_name(&root, &path);
+            if file_type.is_dir() {
+                if !filter.excludes(&relative) {
+                    pending.push(path);
+                }
+                continue;
+            }
+            if !filter.matches(&relative) {
+                continue;
+            }
+            if !file_type.is_file() || check(&path, Access::Read).is_err() {
+                summary.skipped += 1;
+                continue;
+            }
+
+            let mut file = File::open(&path)?;
+            let metadata = file.metadata()?;
+            let size = metadata.len();
+            if summary.files >= limits.max_entries || summary.bytes + size > limits.max_total_size {
+                summary.truncated = true;
+                break 'walk;
+            }
+            // 头部大小固定为打开时的大小：打包期间文件增长只截取前 size 字节，
+            // 缩短则补零，保证归档结构完整且与 summary.bytes 一致
+            let mut header = tar::Header::new_gnu();
+            header.set_metadata(&metadata);
+            header.set_size(size);
+            let data = (&mut file).take(size).chain(io::repeat(0)).take(size);
+            builder.append_data(&mut header, &relative, data)?;
+            summary.files += 1;
+            summary.bytes += size;
+        }
+    }
+
+    builder.into_inner()?.finish()?;
+    Ok(summary)
+}
+
+/// 将 tar.gz 解包到 `dest` 目录
+///
+/// 所有文件先写入目标旁的暂存文件，任一条目出错时删除暂存文件与新建的目录，
+/// 已有文件保持不变；全部写入后再调用 `before_replace` 并逐个重命名替换。
+pub fn extract_tar_gz(
+    archive: &Path,
+    dest: &Path,
+    limits: ArchiveLimits,
+    check: PathCheck,
+    before_replace: BeforeReplace,
+) -> Result<ArchiveSummary> {
+    let dest = check(dest, Access::Write)?;
+    let mut staged = StagedExtract::new();
+    let result = staged
+        .create_dirs(&dest)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| stage_entries(archive, &dest, limits, check, &mut staged))
+        .and_then(|summary| {
+            staged.commit(before_replace)?;
+            Ok(summary)
+        });
+    if result.is_err() {
+        staged.rollback();
+    }
+    result
+}
+
+fn stage_entries(
+    archive: &Path,
+    dest: &Path,
+    limits: ArchiveLimits,
+    check: PathCheck,
+    staged: &mut StagedExtract,
+) -> Result<ArchiveSummary> {
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(archive)?));
+    let mut summary = ArchiveSummary::default();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.into_owned();
+        let relative = safe_relative_path(&name)?;
+
+        let entry_type = entry.header().entry_type();
+        if !entry_type.is_file() && !entry_type.is_dir() {
+            // 符号链接、硬链接与设备文件一律不还原
+            summary.skipped += 1;
+            continue;
+        }
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+
+        let target = check(&dest.join(&relative), Access::Write)?;
+        if entry_type.is_dir() {
+            staged.create_dirs(&target)?;
+            continue;
+        }
+
+        summary.files += 1;
+        if summary.files > limits.max_entries {
+            return Err(anyhow!("Archive exceeds maximum of {} entries", limits.max_entries));
+        }
+        if target.is_dir() {
+            return Err(anyhow!("Archive entry would replace a directory: {}", name.display()));
+        }
+        if let Some(parent) = target.parent() {
+            staged.create_dirs(parent)?;
+        }
+        // 按头部声明的大小之外再限制实际读取量，防止解压炸弹
+        let remaining = limits.max_total_size - summary.bytes;
+        let temp = staged.stage(target)?;
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&temp)?;
+        let written = io::copy(&mut (&mut entry).take(remaining + 1), &mut file)?;
+        if written > remaining {
+            return Err(anyhow!(
+                "Archive exceeds maximum extracted size of {} bytes",
+                limits.max_total_size
+            ));
+        }
+        summary.bytes += written;
+    }
+    Ok(summary)
+}
+
+/// 解包过程中新建的目录与暂存文件
+struct StagedExtract {
+    /// 暂存文件名中的随机部分，避免与并发的解包冲突
+    nonce: String,
+    staged: usize,
+    /// 按创建顺序记录，回滚时逆序删除
+    created_dirs: Vec<PathBuf>,
+    /// (目标, 暂存文件)，同名条目只保留最后一个
+    files: Vec<(PathBuf, PathBuf)>,
+    index: HashMap<PathBuf, usize>,
+}
+
+impl StagedExtract {
+    fn new() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        Self {
+            nonce: format!("{}.{}", std::process::id(), nanos),
+            staged: 0,
+            created_dirs: Vec::new(),
+            files: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    fn create_dirs(&mut self, dir: &Path) -> io::Result<()> {
+        let missing: Vec<&Path> = dir.ancestors().take_while(|path| !path.exists()).collect();
+        for path in missing.into_iter().rev() {
+            fs::create_dir(path)?;
+            self.created_dirs.push(path.to_path_buf());
+        }
+        Ok(())
+    }
+
+    /// 为目标分配同目录下的暂存文件名（保证重命名是原子的）
+    fn stage(&mut self, target: PathBuf) -> Result<PathBuf> {
+        let name = target
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid path: {}", target.display()))?
+            .to_string_lossy()
+            .to_string();
+        let temp = target.with_file_name(format!(".{}.{}.{}.extract", name, self.nonce, self.staged));
+        self.staged += 1;
+        match self.index.get(&target) {
+            Some(&i) => {
+                let _ = fs::remove_file(&self.files[i].1);
+                self.files[i].1 = temp.clone();
+            }
+            None => {
+                self.index.insert(target.clone(), self.files.len());
+                self.files.push((target, temp.clone()));
+            }
+        }
+        Ok(temp)
+    }
+
+    /// 逐个替换目标；出错时保留尚未替换的暂存文件，交由回滚删除
+    fn commit(&mut self, before_replace: BeforeReplace) -> io::Result<()> {
+        let files = std::mem::take(&mut self.files);
+        for (i, (target, temp)) in files.iter().enumerate() {
+            if target.is_file() {
+                before_replace(target);
+            }
+            if let Err(e) = fs::rename(temp, target) {
+                self.files = files[i..].to_vec();
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// 删除尚未替换的暂存文件与新建的空目录
+    fn rollback(&mut self) {
+        for (_, temp) in self.files.drain(..) {
+            let _ = fs::remove_file(temp);
+        }
+        for dir in self.created_dirs.drain(..).rev() {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+}
+
+/// 归档内的相对名称，统一使用 `/` 分隔
+pub fn relative_name(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// 拒绝绝对路径与 `..`，防止解包到目标目录之外
+pub fn safe_relative_path(name: &Path) -> Result<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            _ => return Err(anyhow!("Unsafe path in archive: {}", name.display())),
+        }
+    }
+    Ok(relative)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::path_policy::AccessMode;
+    use crate::core::test_support::policy_for;
+    use tempfile::tempdir;
+
+    const LIMITS: ArchiveLimits = ArchiveLimits {
+        max_total_size: 1024,
+        max_entries: 100,
+    };
+
+    #[test]
+    fn test_archive_round_trip_with_filters() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("logs");
+        fs::create_dir_all(src.join("nginx")).unwrap();
+        fs::write(src.join("app.log"), b"app").unwrap();
+        fs::write(src.join("nginx/access.log"), b"access").unwrap();
+        fs::write(src.join("nginx/debug.txt"), b"debug").unwrap();
+        fs::write(src.join("server.key"), b"secret").unwrap();
+
+        let policy = policy_for(dir.path(), AccessMode::ReadWrite, &["**/*.key"]);
+        let check = |path: &Path, access: Access| policy.check(path, access);
+        let filter = ArchiveFilter {
+            include: vec!["**/*.log".to_string(), "**/*.key".to_string()],
+            exclude: vec!["nginx/debug*".to_string()],
+        };
+        let archive = dir.path().join("logs.tar.gz");
+        let summary = create_tar_gz(&src, &archive, &filter, LIMITS, &check).unwrap();
+        assert_eq!(summary.files, 2);
+        // 被路径策略拒绝的 server.key 计为跳过
+        assert_eq!(summary.skipped, 1);
+        assert!(!summary.truncated);
+
+        let out = dir.path().join("out");
+        let extracted = extract_tar_gz(&archive, &out, LIMITS, &check, &|_| {}).unwrap();
+        assert_eq!(extracted.files, 2);
+        assert_eq!(fs::read(out.join("nginx/access.log")).unwrap(), b"access");
+        assert!(!out.join("nginx/debug.txt").exists());
+
+        // 被排除的目录整体跳过
+        let skip_dir = ArchiveFilter {
+            include: Vec::new(),
+            exclude: vec!["nginx".to_string()],
+        };
+        let summary = create_tar_gz(&src, &archive, &skip_dir, LIMITS, &check).unwrap();
+        assert_eq!((summary.files, summary.skipped), (1, 1));
+
+        let small = ArchiveLimits {
+            max_total_size: 4,
+            max_entries: 100,
+        };
+        let summary = create_tar_gz(&src, &archive, &ArchiveFilter::default(), small, &check).unwrap();
+        assert!(summary.truncated);
+    }
+
+    #[test]
+    fn test_extract_rejects_traversal() {
+        let dir = tempdir().unwrap();
+        let archive = dir.path().join("evil.tar.gz");
+        {
+            let mut builder = tar::Builder::new(GzEncoder::new(File::create(&archive).unwrap(), Compression::default()));
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            header.set_mode(0o644);
+            // set_path 会拒绝 `..`，直接写入原始名称
+            header.as_gnu_mut().unwrap().name[..9].copy_from_slice(b"../escape");
+            header.set_cksum();
+            builder.append(&header, &b"evil"[..]).unwrap();
+            builder.into_inner().unwrap().finish().unwrap();
+        }
+
+        let policy = policy_for(dir.path(), AccessMode::ReadWrite, &["**/*.key"]);
+        let check = |path: &Path, access: Access| policy.check(path, access);
+        let out = dir.path().join("out");
+        let err = extract_tar_gz(&archive, &out, LIMITS, &check, &|_| {}).unwrap_err();
+        assert!(err.to_string().contains("Unsafe path"));
+        assert!(!dir.path().join("escape").exists());
+    }
+
+    #[test]
+    fn test_extract_is_all_or_nothing() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(src.join("conf")).unwrap();
+        fs::write(src.join("app.ini"), b"new").unwrap();
+        fs::write(src.join("conf/big.bin"), vec![b'x'; 64]).unwrap();
+        let policy = policy_for(dir.path(), AccessMode::ReadWrite, &[]);
+        let check = |path: &Path, access: Access| policy.check(path, access);
+        let archive = dir.path().join("src.tar.gz");
+        create_tar_gz(&src, &archive, &ArchiveFilter::default(), LIMITS, &check).unwrap();
+
+        let out = dir.path().join("out");
+        fs::create_dir_all(&out).unwrap();
+        fs::write(out.join("app.ini"), b"old").unwrap();
+
+        // 第二个文件超出大小上限：已有文件不变，暂存文件与新建目录被清理
+        let small = ArchiveLimits {
+            max_total_size: 16,
+            max_entries: 100,
+        };
+        assert!(extract_tar_gz(&archive, &out, small, &check, &|_| {}).is_err());
+        assert_eq!(fs::read(out.join("app.ini")).unwrap(), b"old");
+        let names: Vec<_> = fs::read_dir(&out).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names, vec!["app.ini"]);
+
+        // 成功时覆盖前回调收到已有文件
+        let replaced = std::cell::RefCell::new(Vec::new());
+        let before = |path: &Path| replaced.borrow_mut().push(fs::read(path).unwrap());
+        extract_tar_gz(&archive, &out, LIMITS, &check, &before).unwrap();
+        assert_eq!(fs::read(out.join("app.ini")).unwrap(), b"new");
+        assert_eq!(replaced.into_inner(), vec![b"old".to_vec()]);
+    }
+}
@@ -1,21 +1,35 @@
 use anyhow::Result;
-use base64::Engine;
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::sync::{mpsc, Mutex};
 
+use crate::core::audit_chain::{AuditChain, ChainLink, PersistedAuditBatch};
+use crate::core::crypto::{CryptoManager, SharedSigner};
+use crate::core::integrity::FileRecord;
+use crate::core::search::{SearchProgress, SearchQuery};
+
 /// 审计事件类型
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum AuditEventType {
     CommandExecute,
     FileList,
+    FileSearch,
     FileDownload,
     FileUpload,
     FileDelete,
+    FileModify,
     SessionConnect,
     SessionDisconnect,
+    TerminalResize,
+    TerminalInput,
+    TerminalCommand,
+    TerminalViewerAttach,
+    TerminalViewerDetach,
+    TunnelOpen,
+    TunnelClose,
+    FileIntegrity,
     DeviceRegister,
     SecurityViolation,
     AuthenticationFailure,
@@ -31,6 +45,45 @@ pub struct AuditEvent {
     pub data: AuditEventData,
     pub result: AuditResult,
     pub error_message: Option<String>,
+    /// 触发事件的操作者（由服务端下发）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operator: Option<String>,
+    /// 哈希链位置，由事件处理器在入队时分配
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain: Option<ChainLink>,
+}
+
+/// 文件修改事件的内容
+#[derive(Debug, Clone)]
+pub struct FileModifyEvent<'a> {
+    pub operation: &'a str,
+    pub path: &'a str,
+    /// 移动、复制与符号链接的另一端
+    pub target: Option<String>,
+    pub detail: Option<String>,
+    pub operation_id: &'a str,
+}
+
+impl<'a> FileModifyEvent<'a> {
+    pub fn new(operation: &'a str, path: &'a str, operation_id: &'a str) -> Self {
+        Self {
+            operation,
+            path,
+            target: None,
+            detail: None,
+            operation_id,
+        }
+    }
+
+    pub fn with_target(mut self, target: String) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    pub fn with_detail(mut self, detail: String) -> Self {
+        self.detail = Some(detail);
+        self
+    }
 }
 
 /// 审计事件具体数据
@@ -51,6 +104,17 @@ pub enum AuditEventData {
         file_count: usize,
         operation_id: String,
     },
+    FileSearch {
+        root: String,
+        /// 名称 glob
+        name: Vec<String>,
+        /// 内容正则
+        content: Option<String>,
+        match_count: usize,
+        scanned: u64,
+        truncated: bool,
+        operation_id: String,
+    },
     FileDownload {
         path: String,
         file_size: u64,
@@ -67,6 +131,16 @@ pub enum AuditEventData {
         path: String,
         operation_id: String,
     },
+    FileModify {
+        /// chmod / chown / mkdir / rename / copy / symlink
+        operation: String,
+        path: String,
+        /// 重命名、复制的目标路径或符号链接指向的路径
+        target: Option<String>,
+        /// 操作参数，如新的权限位或属主
+        detail: Option<String>,
+        operation_id: String,
+    },
     SessionConnect {
         session_id: String,
         connection_time: u64,
@@ -76,6 +150,66 @@ pub enum AuditEventData {
         disconnect_reason: String,
         duration_ms: u64,
     },
+    TerminalResize {
+        session_id: String,
+        cols: u16,
+        rows: u16,
+    },
+    TerminalInput {
+        session_id: String,
+        viewer_id: Option<String>,
+        client_seq: u64,
+        byte_count: usize,
+    },
+    TerminalCommand {
+        session_id: String,
+        /// 捕获的命令行；密码输入被替换为占位符
+        command: String,
+        redacted: bool,
+    },
+    TerminalViewerAttach {
+        session_id: String,
+        viewer_id: String,
+        role: String,
+        operator: Option<String>,
+    },
+    TerminalViewerDetach {
+        session_id: String,
+        viewer_id: String,
+        role: String,
+        operator: Option<String>,
+        detach_reason: String,
+        duration_ms: u64,
+    },
+    TunnelOpen {
+        tunnel_id: String,
+        /// tcp 或 socks5
+        kind: String,
+        /// 请求的目标（host:port）
+        destination: String,
+        /// 实际连接的地址
+        peer_addr: Option<String>,
+        proxy_session_id: Option<String>,
+    },
+    TunnelClose {
+        tunnel_id: String,
+        kind: String,
+        destination: String,
+        proxy_session_id: Option<String>,
+        /// 目标 → 服务端方向的字节数
+        bytes_in: u64,
+        /// 服务端 → 目标方向的字节数
+        bytes_out: u64,
+        duration_ms: u64,
+        close_reason: String,
+    },
+    FileIntegrity {
+        path: String,
+        /// added / removed / modified
+        change: String,
+        before: Option<FileRecord>,
+        after: Option<FileRecord>,
+    },
     SecurityViolation {
         violation_type: String,
         details: String,
@@ -99,6 +233,8 @@ pub enum AuditResult {
     Success,
     Error,
     Timeout,
+    /// 被策略拒绝
+    Denied,
 }
 
 /// 威胁级别
@@ -114,6 +250,7 @@ pub enum ThreatLevel {
 #[derive(Clone)]
 pub struct AuditLogger {
     device_id: String,
+    operator: Option<String>,
     sender: mpsc::UnboundedSender<AuditEvent>,
 }
 
@@ -121,7 +258,23 @@ impl AuditLogger {
     pub fn new(device_id: String) -> (Self, mpsc::UnboundedReceiver<AuditEvent>) {
         let (sender, receiver) = mpsc::unbounded_channel();
 
-        (Self { device_id, sender }, receiver)
+        (
+            Self {
+                device_id,
+                operator: None,
+                sender,
+            },
+            receiver,
+        )
+    }
+
+    /// 返回以指定操作者身份记录事件的记录器（共享同一事件通道）
+    pub fn for_operator(&self, operator: Option<String>) -> Self {
+        Self {
+            device_id: self.device_id.clone(),
+            operator,
+            sender: self.sender.clone(),
+        }
     }
 
     /// 记录命令执行事件
@@ -164,6 +317,8 @@ impl AuditLogger {
             },
             result,
             error_message,
+            operator: self.operator.clone(),
+            chain: None,
         };
 
         self.send_event(event)
@@ -191,6 +346,40 @@ impl AuditLogger {
             },
             result,
             error_message,
+            operator: self.operator.clone(),
+            chain: None,
+        };
+
+        self.send_event(event)
+    }
+
+    /// 记录文件搜索事件（搜索结束时记录匹配总数）
+    pub fn log_file_search(
+        &self,
+        query: &SearchQuery,
+        progress: &SearchProgress,
+        operation_id: &str,
+        result: AuditResult,
+        error_message: Option<String>,
+    ) -> Result<()> {
+        let event = AuditEvent {
+            event_type: AuditEventType::FileSearch,
+            timestamp: self.current_timestamp(),
+            device_id: self.device_id.clone(),
+            session_id: None,
+            data: AuditEventData::FileSearch {
+                root: query.root.clone(),
+                name: query.name.clone(),
+                content: query.content.clone(),
+                match_count: progress.found,
+                scanned: progress.scanned,
+                truncated: progress.truncated,
+                operation_id: operation_id.to_string(),
+            },
+            result,
+            error_message,
+            operator: self.operator.clone(),
+            chain: None,
         };
 
         self.send_event(event)
@@ -221,6 +410,8 @@ impl AuditLogger {
             },
             result,
             error_message,
+            operator: self.operator.clone(),
+            chain: None,
         };
 
         self.send_event(event)
@@ -251,6 +442,63 @@ impl AuditLogger {
             },
             result,
             error_message,
+            operator: self.operator.clone(),
+            chain: None,
+        };
+
+        self.send_event(event)
+    }
+
+    /// 记录文件删除事件
+    pub fn log_file_delete(
+        &self,
+        session_id: Option<String>,
+        path: &str,
+        operation_id: &str,
+        result: AuditResult,
+        error_message: Option<String>,
+    ) -> Result<()> {
+        let event = AuditEvent {
+            event_type: AuditEventType::FileDelete,
+            timestamp: self.current_timestamp(),
+            device_id: self.device_id.clone(),
+            session_id,
+            data: AuditEventData::FileDelete {
+                path: path.to_string(),
+                operation_id: operation_id.to_string(),
+            },
+            result,
+            error_message,
+            operator: self.operator.clone(),
+            chain: None,
+        };
+
+        self.send_event(event)
+    }
+
+    /// 记录文件修改操作事件（权限、属主、目录、移动、复制、符号链接）
+    pub fn log_file_modify(
+        &self,
+        modify: FileModifyEvent<'_>,
+        result: AuditResult,
+        error_message: Option<String>,
+    ) -> Result<()> {
+        let event = AuditEvent {
+            event_type: AuditEventType::FileModify,
+            timestamp: self.current_timestamp(),
+            device_id: self.device_id.clone(),
+            session_id: None,
+            data: AuditEventData::FileModify {
+                operation: modify.operation.to_string(),
+                path: modify.path.to_string(),
+                target: modify.target,
+                detail: modify.detail,
+                operation_id: modify.operation_id.to_string(),
+            },
+            result,
+            error_message,
+            operator: self.operator.clone(),
+            chain: None,
         };
 
         self.send_event(event)
@@ -275,6 +523,8 @@ impl AuditLogger {
             },
             result,
             error_message,
+            operator: self.operator.clone(),
+            chain: None,
         };
 
         self.send_event(event)
@@ -301,6 +551,240 @@ impl AuditLogger {
             },
             result,
             error_message,
+            operator: self.operator.clone(),
+            chain: None,
+        };
+
+        self.send_event(event)
+    }
+
+    /// 记录终端窗口调整事件
+    pub fn log_terminal_resize(&self, session_id: &str, cols: u16, rows: u16) -> Result<()> {
+        let event = AuditEvent {
+            event_type: AuditEventType::TerminalResize,
+            timestamp: self.current_timestamp(),
+            device_id: self.device_id.clone(),
+            session_id: Some(session_id.to_string()),
+            data: AuditEventData::TerminalResize {
+                session_id: session_id.to_string(),
+                cols,
+                rows,
+            },
+            result: AuditResult::Success,
+            error_message: None,
+            operator: self.operator.clone(),
+            chain: None,
+        };
+
+        self.send_event(event)
+    }
+
+    /// 记录终端输入事件（只记录字节数，不记录内容）
+    pub fn log_terminal_input(
+        &self,
+        session_id: &str,
+        viewer_id: Option<String>,
+        client_seq: u64,
+        byte_count: usize,
+        result: AuditResult,
+        error_message: Option<String>,
+    ) -> Result<()> {
+        let event = AuditEvent {
+            event_type: AuditEventType::TerminalInput,
+            timestamp: self.current_timestamp(),
+            device_id: self.device_id.clone(),
+            session_id: Some(session_id.to_string()),
+            data: AuditEventData::TerminalInput {
+                session_id: session_id.to_string(),
+                viewer_id,
+                client_seq,
+                byte_count,
+            },
+            result,
+            error_message,
+            operator: self.operator.clone(),
+            chain: None,
+        };
+
+        self.send_event(event)
+    }
+
+    /// 记录终端中输入的命令行
+    pub fn log_terminal_command(&self, session_id: &str, command: &str, redacted: bool) -> Result<()> {
+        let event = AuditEvent {
+            event_type: AuditEventType::TerminalCommand,
+            timestamp: self.current_timestamp(),
+            device_id: self.device_id.clone(),
+            session_id: Some(session_id.to_string()),
+            data: AuditEventData::TerminalCommand {
+                session_id: session_id.to_string(),
+                command: command.to_string(),
+                redacted,
+            },
+            result: AuditResult::Success,
+            error_message: None,
+            operator: self.operator.clone(),
+            chain: None,
+        };
+
+        self.send_event(event)
+    }
+
+    /// 记录隧道打开事件（含策略拒绝）
+    #[allow(clippy::too_many_arguments)]
+    pub fn log_tunnel_open(
+        &self,
+        tunnel_id: &str,
+        kind: &str,
+        destination: &str,
+        peer_addr: Option<String>,
+        proxy_session_id: Option<String>,
+        result: AuditResult,
+        error_message: Option<String>,
+    ) -> Result<()> {
+        let event = AuditEvent {
+            event_type: AuditEventType::TunnelOpen,
+            timestamp: self.current_timestamp(),
+            device_id: self.device_id.clone(),
+            session_id: Some(proxy_session_id.clone().unwrap_or_else(|| tunnel_id.to_string())),
+            data: AuditEventData::TunnelOpen {
+                tunnel_id: tunnel_id.to_string(),
+                kind: kind.to_string(),
+                destination: destination.to_string(),
+                peer_addr,
+                proxy_session_id,
+            },
+            result,
+            error_message,
+            operator: self.operator.clone(),
+            chain: None,
+        };
+
+        self.send_event(event)
+    }
+
+    /// 记录隧道关闭事件及双向字节数
+    #[allow(clippy::too_many_arguments)]
+    pub fn log_tunnel_close(
+        &self,
+        tunnel_id: &str,
+        kind: &str,
+        destination: &str,
+        proxy_session_id: Option<String>,
+        bytes_in: u64,
+        bytes_out: u64,
+        duration: Duration,
+        close_reason: &str,
+    ) -> Result<()> {
+        let event = AuditEvent {
+            event_type: AuditEventType::TunnelClose,
+            timestamp: self.current_timestamp(),
+            device_id: self.device_id.clone(),
+            session_id: Some(proxy_session_id.clone().unwrap_or_else(|| tunnel_id.to_string())),
+            data: AuditEventData::TunnelClose {
+                tunnel_id: tunnel_id.to_string(),
+                kind: kind.to_string(),
+                destination: destination.to_string(),
+                proxy_session_id,
+                bytes_in,
+                bytes_out,
+                duration_ms: duration.as_millis() as u64,
+                close_reason: close_reason.to_string(),
+            },
+            result: AuditResult::Success,
+            error_message: None,
+            operator: self.operator.clone(),
+            chain: None,
+        };
+
+        self.send_event(event)
+    }
+
+    /// 记录文件完整性变化事件
+    pub fn log_file_integrity(
+        &self,
+        path: &str,
+        change: &str,
+        before: Option<FileRecord>,
+        after: Option<FileRecord>,
+    ) -> Result<()> {
+        let event = AuditEvent {
+            event_type: AuditEventType::FileIntegrity,
+            timestamp: self.current_timestamp(),
+            device_id: self.device_id.clone(),
+            session_id: None,
+            data: AuditEventData::FileIntegrity {
+                path: path.to_string(),
+                change: change.to_string(),
+                before,
+                after,
+            },
+            result: AuditResult::Success,
+            error_message: None,
+            operator: self.operator.clone(),
+            chain: None,
+        };
+
+        self.send_event(event)
+    }
+
+    /// 记录终端查看者接入事件
+    pub fn log_viewer_attach(
+        &self,
+        session_id: &str,
+        viewer_id: &str,
+        role: &str,
+        operator: Option<String>,
+        result: AuditResult,
+        error_message: Option<String>,
+    ) -> Result<()> {
+        let event = AuditEvent {
+            event_type: AuditEventType::TerminalViewerAttach,
+            timestamp: self.current_timestamp(),
+            device_id: self.device_id.clone(),
+            session_id: Some(session_id.to_string()),
+            data: AuditEventData::TerminalViewerAttach {
+                session_id: session_id.to_string(),
+                viewer_id: viewer_id.to_string(),
+                role: role.to_string(),
+                operator,
+            },
+            result,
+            error_message,
+            operator: self.operator.clone(),
+            chain: None,
+        };
+
+        self.send_event(event)
+    }
+
+    /// 记录终端查看者断开事件
+    pub fn log_viewer_detach(
+        &self,
+        session_id: &str,
+        viewer_id: &str,
+        role: &str,
+        operator: Option<String>,
+        detach_reason: &str,
+        duration: Duration,
+    ) -> Result<()> {
+        let event = AuditEvent {
+            event_type: AuditEventType::TerminalViewerDetach,
+            timestamp: self.current_timestamp(),
+            device_id: self.device_id.clone(),
+            session_id: Some(session_id.to_string()),
+            data: AuditEventData::TerminalViewerDetach {
+                session_id: session_id.to_string(),
+                viewer_id: viewer_id.to_string(),
+                role: role.to_string(),
+                operator,
+                detach_reason: detach_reason.to_string(),
+                duration_ms: duration.as_millis() as u64,
+            },
+            result: AuditResult::Success,
+            error_message: None,
+            operator: self.operator.clone(),
+            chain: None,
         };
 
         self.send_event(event)
@@ -326,6 +810,8 @@ impl AuditLogger {
             },
             result: AuditResult::Error,
             error_message: Some(format!("Security violation: {}", violation_type)),
+            operator: self.operator.clone(),
+            chain: None,
         };
 
         self.send_event(event)
@@ -348,6 +834,8 @@ impl AuditLogger {
             },
             result: AuditResult::Error,
             error_message: Some(format!("Authentication failed: {}", failure_reason)),
+            operator: self.operator.clone(),
+            chain: None,
         };
 
         self.send_event(event)
@@ -413,6 +901,8 @@ impl AuditLogger {
             },
             result,
             error_message,
+            operator: self.operator.clone(),
+            chain: None,
         };
 
         self.send_event(event)
@@ -440,6 +930,10 @@ pub struct AuditTransportConfig {
     pub enable_local_persistence: bool,
     /// 本地持久化路径
     pub local_persistence_path: Option<String>,
+    /// 哈希链头保存路径，未设置时每次启动从序号 1 开始
+    pub chain_state_path: Option<String>,
+    /// 本地审计日志路径（只追加），记录每个定序的签名批次
+    pub journal_path: Option<String>,
 }
 
 impl Default for AuditTransportConfig {
@@ -453,6 +947,8 @@ impl Default for AuditTransportConfig {
             max_cached_events: 1000,
             enable_local_persistence: true,
             local_persistence_path: None,
+            chain_state_path: None,
+            journal_path: None,
         }
     }
 }
@@ -480,10 +976,13 @@ pub struct AuditBatchResponse {
 pub struct AuditEventHandler {
     receiver: mpsc::UnboundedReceiver<AuditEvent>,
     config: AuditTransportConfig,
-    device_id: String,
+    /// 尚未定序的事件，上送时才接到哈希链上
     event_buffer: Arc<Mutex<VecDeque<AuditEvent>>>,
+    /// 上传失败且未能本地持久化的已定序事件，已写入本地日志，下次上送时重试
+    retry_buffer: Mutex<VecDeque<AuditEvent>>,
     http_client: Option<reqwest::Client>,
-    crypto_manager: Option<Arc<crate::core::crypto::CryptoManager>>,
+    signer: SharedSigner,
+    chain: Mutex<AuditChain>,
 }
 
 impl AuditEventHandler {
@@ -491,32 +990,36 @@ impl AuditEventHandler {
         Self {
             receiver,
             config: AuditTransportConfig::default(),
-            device_id: String::new(),
             event_buffer: Arc::new(Mutex::new(VecDeque::new())),
+            retry_buffer: Mutex::new(VecDeque::new()),
             http_client: None,
-            crypto_manager: None,
+            signer: SharedSigner::default(),
+            chain: Mutex::new(AuditChain::load(None)),
         }
     }
 
-    /// 创建带完整配置的处理器
+    /// 创建带完整配置的处理器，设备注册前签名身份为空，事件留在缓冲区
     pub fn with_config(
         receiver: mpsc::UnboundedReceiver<AuditEvent>,
         config: AuditTransportConfig,
-        device_id: String,
-        crypto_manager: Option<Arc<crate::core::crypto::CryptoManager>>,
+        signer: SharedSigner,
     ) -> Self {
         let http_client = reqwest::Client::builder()
             .timeout(Duration::from_secs(30))
             .build()
             .ok();
 
+        let chain = AuditChain::load(config.chain_state_path.as_ref().map(std::path::PathBuf::from))
+            .with_journal(config.journal_path.as_ref().map(std::path::PathBuf::from));
+
         Self {
             receiver,
             config,
-            device_id,
             event_buffer: Arc::new(Mutex::new(VecDeque::new())),
+            retry_buffer: Mutex::new(VecDeque::new()),
             http_client,
-            crypto_manager,
+            signer,
+            chain: Mutex::new(chain),
         }
     }
 
@@ -569,7 +1072,7 @@ impl AuditEventHandler {
         let mut buffer = self.event_buffer.lock().await;
         buffer.push_back(event);
 
-        // 如果超过最大缓存数，移除最旧的事件
+        // 如果超过最大缓存数，移除最旧的事件（尚未定序，不会在链上留下缺口）
         while buffer.len() > self.config.max_cached_events {
             if let Some(dropped) = buffer.pop_front() {
                 tracing::warn!(
@@ -584,7 +1087,21 @@ impl AuditEventHandler {
 
     /// 刷新缓冲区，批量上传到服务端
     async fn flush_buffer(&self) {
-        let events: Vec<AuditEvent> = {
+        let Some((crypto, device_id)) = self.signer() else {
+            tracing::debug!("Device not enrolled yet, holding audit events");
+            return;
+        };
+
+        // 先重试之前失败的事件，保持上送顺序
+        let retry: Vec<AuditEvent> = self.retry_buffer.lock().await.drain(..).collect();
+        if !retry.is_empty() {
+            match PersistedAuditBatch::sign(&crypto, &device_id, retry) {
+                Ok(batch) => self.deliver(batch).await,
+                Err(e) => tracing::error!("Failed to sign audit events: {}", e),
+            }
+        }
+
+        let mut events: Vec<AuditEvent> = {
             let mut buffer = self.event_buffer.lock().await;
             buffer.drain(..).collect()
         };
@@ -595,27 +1112,63 @@ impl AuditEventHandler {
 
         tracing::info!("Flushing {} audit events to server", events.len());
 
-        // 尝试上传到服务端
-        if let Err(e) = self.upload_events(&events).await {
-            tracing::error!("Failed to upload audit events: {}", e);
-
-            // 上传失败，尝试本地持久化
-            if self.config.enable_local_persistence {
-                if let Err(e) = self.persist_events_locally(&events).await {
-                    tracing::error!("Failed to persist audit events locally: {}", e);
+        // 定序、签名一次并写入本地日志，同一批次用于上传与失败时的本地持久化
+        let batch = {
+            let mut chain = self.chain.lock().await;
+            if let Err(e) = chain.append(&mut events) {
+                tracing::warn!("Failed to save audit chain head: {}", e);
+            }
+            let batch = match PersistedAuditBatch::sign(&crypto, &device_id, events) {
+                Ok(batch) => batch,
+                Err(e) => {
+                    tracing::error!("Failed to sign audit events: {}", e);
+                    return;
                 }
+            };
+            if let Err(e) = chain.journal(&batch) {
+                tracing::warn!("Failed to journal audit events: {}", e);
             }
+            batch
+        };
 
-            // 将事件放回缓冲区
-            let mut buffer = self.event_buffer.lock().await;
-            for event in events.into_iter().rev() {
-                buffer.push_front(event);
+        self.deliver(batch).await;
+    }
+
+    /// 上传一个已签名的批次；失败时本地持久化，持久化也失败才放入重试队列
+    async fn deliver(&self, batch: PersistedAuditBatch) {
+        let Err(e) = self.upload_batch(&batch).await else {
+            return;
+        };
+        tracing::error!("Failed to upload audit events: {}", e);
+
+        if self.config.enable_local_persistence {
+            match self.persist_batch_locally(&batch).await {
+                // 由 load_and_upload_persisted_events 重新上传
+                Ok(()) => return,
+                Err(e) => tracing::error!("Failed to persist audit events locally: {}", e),
+            }
+        }
+
+        // 重试队列中的事件已写入本地日志，超出上限时丢弃最旧的
+        let mut retry = self.retry_buffer.lock().await;
+        retry.extend(batch.events);
+        while retry.len() > self.config.max_cached_events {
+            if let Some(dropped) = retry.pop_front() {
+                tracing::warn!(
+                    "Audit retry buffer full, dropping oldest journaled event: {:?}",
+                    dropped.event_type
+                );
             }
         }
     }
 
-    /// 上传事件到服务端
-    async fn upload_events(&self, events: &[AuditEvent]) -> Result<()> {
+    /// 当前签名身份，设备注册前为空
+    fn signer(&self) -> Option<(CryptoManager, String)> {
+        self.signer.read().unwrap().clone()
+    }
+
+    /// 上传已签名的批次到服务端
+    async fn upload_batch(&self, batch: &PersistedAuditBatch) -> Result<()> {
         let client = self
             .http_client
             .as_ref()
@@ -625,38 +1178,13 @@ impl AuditEventHandler {
             return Err(anyhow::anyhow!("Server URL not configured"));
         }
 
-        // 生成签名
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis() as u64;
-        let nonce = format!("{:016x}", rand::random::<u64>());
-
-        let signature = if let Some(crypto) = &self.crypto_manager {
-            // 构建签名数据
-            let sign_data = format!(
-                "{}:{}:{}:{}",
-                self.device_id,
-                timestamp,
-                nonce,
-                events.len()
-            );
-            let sig_bytes = crypto.sign(sign_data.as_bytes());
-            format!(
-                "{}:{}",
-                timestamp,
-                base64::engine::general_purpose::STANDARD.encode(&sig_bytes)
-            )
-        } else {
-            format!("{}:unsigned", timestamp)
-        };
-
+        // 批次签名覆盖批内最后一个事件的哈希
         let request = AuditBatchRequest {
-            device_id: self.device_id.clone(),
-            timestamp,
-            nonce,
-            signature,
-            events: events.to_vec(),
+            device_id: batch.device_id.clone(),
+            timestamp: batch.timestamp,
+            nonce: batch.nonce.clone(),
+            signature: batch.signature.clone(),
+            events: batch.events.clone(),
         };
 
         // 发送请求
@@ -708,8 +1236,8 @@ impl AuditEventHandler {
         ))
     }
 
-    /// 本地持久化事件
-    async fn persist_events_locally(&self, events: &[AuditEvent]) -> Result<()> {
+    /// 本地持久化已签名的批次
+    async fn persist_batch_locally(&self, batch: &PersistedAuditBatch) -> Result<()> {
         let path = self
             .config
             .local_persistence_path
@@ -730,14 +1258,14 @@ impl AuditEventHandler {
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default()
             .as_millis();
-        let filename = format!("audit_batch_{}.json", timestamp);
+        let filename = format!("audit_batch_{}_{}.json", timestamp, batch.nonce);
         let filepath = path.join(filename);
 
-        // 写入文件
-        let json = serde_json::to_string_pretty(events)?;
+        // 写入带签名的批次
+        let json = serde_json::to_string_pretty(batch)?;
         tokio::fs::write(&filepath, json).await?;
 
-        tracing::info!("Persisted {} audit events to {:?}", events.len(), filepath);
+        tracing::info!("Persisted {} audit events to {:?}", batch.events.len(), filepath);
 
         Ok(())
     }
@@ -790,10 +1318,12 @@ impl AuditEventHandler {
 
     async fn process_persisted_file(&self, file_path: &std::path::Path) -> Result<usize> {
         let content = tokio::fs::read_to_string(file_path).await?;
-        let events: Vec<AuditEvent> = serde_json::from_str(&content)?;
+        let events = PersistedAuditBatch::parse(&content)?.events;
         let count = events.len();
 
-        self.upload_events(&events).await?;
+        // 重新签名：服务端只接受时间戳较新的请求
+        let (crypto, device_id) = self.signer().ok_or_else(|| anyhow::anyhow!("Device not enrolled yet"))?;
+        self.upload_batch(&PersistedAuditBatch::sign(&crypto, &device_id, events)?).await?;
 
         Ok(count)
     }
@@ -883,4 +1413,68 @@ mod tests {
         assert!(!logger.is_sensitive_command("echo hello"));
         assert!(!logger.is_sensitive_command("cat file.txt"));
     }
+
+    #[tokio::test]
+    async fn test_flush_sequences_and_signs_each_batch_once() {
+        use crate::core::audit_chain::{verify_journal, CHAIN_STATE_FILE, JOURNAL_FILE};
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("cache");
+        let state = dir.path().join(CHAIN_STATE_FILE);
+        let journal = dir.path().join(JOURNAL_FILE);
+        let (_logger, receiver) = AuditLogger::new("device".to_string());
+        let signer = SharedSigner::default();
+        let handler = AuditEventHandler::with_config(
+            receiver,
+            AuditTransportConfig {
+                max_retries: 1,
+                max_cached_events: 2,
+                local_persistence_path: Some(cache.to_string_lossy().to_string()),
+                chain_state_path: Some(state.to_string_lossy().to_string()),
+                journal_path: Some(journal.to_string_lossy().to_string()),
+                ..Default::default()
+            },
+            signer.clone(),
+        );
+
+        // 注册前超出缓存上限丢弃的事件尚未定序，链上不留缺口
+        for i in 0..3 {
+            let event = AuditEvent {
+                event_type: AuditEventType::FileDelete,
+                timestamp: i,
+                device_id: "device".to_string(),
+                session_id: None,
+                data: AuditEventData::FileDelete {
+                    path: format!("/tmp/{}", i),
+                    operation_id: "op".to_string(),
+                },
+                result: AuditResult::Success,
+                error_message: None,
+                operator: None,
+                chain: None,
+            };
+            handler.handle_event(event).await.unwrap();
+        }
+        handler.flush_buffer().await;
+        assert!(!journal.exists());
+
+        // 上传失败（未配置服务端）后本地持久化，不再放回重试队列
+        let crypto = CryptoManager::generate().unwrap();
+        *signer.write().unwrap() = Some((crypto.clone(), "device".to_string()));
+        handler.flush_buffer().await;
+        assert!(handler.retry_buffer.lock().await.is_empty());
+        assert!(handler.event_buffer.lock().await.is_empty());
+
+        let report = verify_journal(&journal, &state, &crypto).unwrap();
+        assert!(report.is_intact(), "{}", report);
+        assert_eq!(report.last_sequence, Some(2));
+
+        // 本地日志与持久化文件是同一个签名批次
+        let journaled = PersistedAuditBatch::parse(std::fs::read_to_string(&journal).unwrap().trim()).unwrap();
+        let files: Vec<_> = std::fs::read_dir(&cache).unwrap().filter_map(|entry| entry.ok()).collect();
+        assert_eq!(files.len(), 1);
+        let persisted = PersistedAuditBatch::parse(&std::fs::read_to_string(files[0].path()).unwrap()).unwrap();
+        assert_eq!(persisted.signature, journaled.signature);
+        assert_eq!(persisted.events.len(), 2);
+    }
 }
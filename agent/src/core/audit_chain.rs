@@ -0,0 +1,467 @@
+// agent/src/core/audit_chain.rs
+// 审计事件哈希链
+//
+// 每个事件携带递增序号与前一事件的哈希（SHA-256，按事件 JSON 计算），批次签名覆盖
+// 批内最后一个事件的哈希，因此修改、删除或插入事件都会使链断开或签名失效。
+// 事件在上送时才按批定序，定序后立即保存链头（最后分配的序号与哈希），重启后接续。
+// 每个批次签名一次，追加到只追加的本地日志并用于上传，校验时与保存的链头比对，
+// 尾部截断或整体删除都能发现。
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::core::audit::AuditEvent;
+use crate::core::crypto::CryptoManager;
+use crate::platform::atomic_write;
+
+/// 链上第一个事件的前驱哈希
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// 数据目录下的链头状态文件名
+pub const CHAIN_STATE_FILE: &str = "audit_chain.json";
+
+/// 数据目录下的本地审计日志文件名
+pub const JOURNAL_FILE: &str = "audit_journal.jsonl";
+
+/// 事件在链上的位置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainLink {
+    /// 从 1 开始的序号
+    pub sequence: u64,
+    /// 前一事件的哈希
+    pub prev_hash: String,
+}
+
+/// 链头：最后一个事件的序号与哈希
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainHead {
+    pub sequence: u64,
+    pub hash: String,
+}
+
+impl Default for ChainHead {
+    fn default() -> Self {
+        Self {
+            sequence: 0,
+            hash: GENESIS_HASH.to_string(),
+        }
+    }
+}
+
+/// 计算事件哈希（包含其链接信息）
+pub fn event_hash(event: &AuditEvent) -> Result<String> {
+    let json = serde_json::to_vec(event)?;
+    Ok(hex::encode(Sha256::digest(&json)))
+}
+
+/// 批次签名内容：设备、时间戳、nonce、事件数，以及批内首个序号与最后一个事件的哈希
+pub fn batch_sign_data(device_id: &str, timestamp: u64, nonce: &str, events: &[AuditEvent]) -> Result<String> {
+    let first_sequence = events
+        .first()
+        .and_then(|event| event.chain.as_ref())
+        .map(|link| link.sequence)
+        .unwrap_or(0);
+    let last_hash = match events.last() {
+        Some(event) => event_hash(event)?,
+        None => GENESIS_HASH.to_string(),
+    };
+    Ok(format!(
+        "{}:{}:{}:{}:{}:{}",
+        device_id,
+        timestamp,
+        nonce,
+        events.len(),
+        first_sequence,
+        last_hash
+    ))
+}
+
+/// 为事件分配序号并维护链头
+pub struct AuditChain {
+    head: ChainHead,
+    state_path: Option<PathBuf>,
+    journal_path: Option<PathBuf>,
+}
+
+impl AuditChain {
+    /// 从状态文件恢复链头；没有状态文件时从头开始
+    pub fn load(state_path: Option<PathBuf>) -> Self {
+        let head: ChainHead = state_path
+            .as_ref()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|data| match serde_json::from_slice(&data) {
+                Ok(head) => Some(head),
+                Err(e) => {
+                    tracing::warn!("Ignoring corrupt audit chain state: {}", e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        Self {
+            head,
+            state_path,
+            journal_path: None,
+        }
+    }
+
+    /// 设置本地审计日志路径
+    pub fn with_journal(mut self, journal_path: Option<PathBuf>) -> Self {
+        self.journal_path = journal_path;
+        self
+    }
+
+    /// 把签名批次追加到本地日志（每行一个批次）
+    pub fn journal(&self, batch: &PersistedAuditBatch) -> Result<()> {
+        if let Some(path) = &self.journal_path {
+            if !batch.events.is_empty() {
+                let mut line = serde_json::to_vec(batch)?;
+                line.push(b'\n');
+                let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+                file.write_all(&line)?;
+                file.sync_data()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 按顺序把事件接到链尾并保存链头
+    ///
+    /// 保存失败时序号已经分配，返回错误只表示重启后可能无法接续。
+    pub fn append(&mut self, events: &mut [AuditEvent]) -> Result<()> {
+        for event in events.iter_mut() {
+            let sequence = self.head.sequence + 1;
+            event.chain = Some(ChainLink {
+                sequence,
+                prev_hash: self.head.hash.clone(),
+            });
+            self.head = ChainHead {
+                sequence,
+                hash: event_hash(event)?,
+            };
+        }
+        self.save()
+    }
+
+    /// 持久化当前链头
+    pub fn save(&self) -> Result<()> {
+        if let Some(path) = &self.state_path {
+            atomic_write(path, &serde_json::to_vec(&self.head)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// 本地持久化的审计批次
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistedAuditBatch {
+    pub device_id: String,
+    pub timestamp: u64,
+    pub nonce: String,
+    /// `{timestamp}:{签名}`，旧版未签名批次为 `{timestamp}:unsigned`
+    pub signature: String,
+    pub events: Vec<AuditEvent>,
+}
+
+impl PersistedAuditBatch {
+    /// 用设备密钥为一批事件签名
+    pub fn sign(crypto: &CryptoManager, device_id: &str, events: Vec<AuditEvent>) -> Result<Self> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let nonce = format!("{:016x}", rand::random::<u64>());
+        let data = batch_sign_data(device_id, timestamp, &nonce, &events)?;
+        Ok(Self {
+            device_id: device_id.to_string(),
+            timestamp,
+            signature: format!("{}:{}", timestamp, crypto.sign(data.as_bytes())),
+            nonce,
+            events,
+        })
+    }
+
+    /// 解析持久化文件，兼容旧版的事件数组格式
+    pub fn parse(content: &str) -> Result<Self> {
+        if let Ok(batch) = serde_json::from_str::<Self>(content) {
+            return Ok(batch);
+        }
+        let events: Vec<AuditEvent> = serde_json::from_str(content)?;
+        Ok(Self {
+            device_id: String::new(),
+            timestamp: 0,
+            nonce: String::new(),
+            signature: String::new(),
+            events,
+        })
+    }
+
+    /// 校验批次签名
+    pub fn verify_signature(&self, crypto: &CryptoManager) -> Result<bool> {
+        let signature = self
+            .signature
+            .split_once(':')
+            .map(|(_, signature)| signature)
+            .filter(|signature| *signature != "unsigned")
+            .ok_or_else(|| anyhow!("batch is not signed"))?;
+        let data = batch_sign_data(&self.device_id, self.timestamp, &self.nonce, &self.events)?;
+        crypto.verify(data.as_bytes(), signature)
+    }
+}
+
+/// 校验结果
+#[derive(Debug, Default)]
+pub struct ChainReport {
+    pub events: usize,
+    pub first_sequence: Option<u64>,
+    pub last_sequence: Option<u64>,
+    /// 缺失的序号区间（闭区间）
+    pub gaps: Vec<(u64, u64)>,
+    /// 签名失效、链接断开等问题
+    pub problems: Vec<String>,
+}
+
+impl ChainReport {
+    pub fn is_intact(&self) -> bool {
+        self.gaps.is_empty() && self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for ChainReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.first_sequence, self.last_sequence) {
+            (Some(first), Some(last)) => writeln!(f, "{} events, sequence {}-{}", self.events, first, last)?,
+            _ => writeln!(f, "{} events", self.events)?,
+        }
+        for (start, end) in &self.gaps {
+            writeln!(f, "missing events {}-{}", start, end)?;
+        }
+        for problem in &self.problems {
+            writeln!(f, "{}", problem)?;
+        }
+        write!(f, "{}", if self.is_intact() { "chain intact" } else { "chain broken" })
+    }
+}
+
+/// 校验事件链：序号连续、每个事件的前驱哈希与前一事件一致，同一序号的多个副本内容相同
+pub fn verify_events(events: impl IntoIterator<Item = AuditEvent>) -> Result<ChainReport> {
+    let mut report = ChainReport::default();
+    let mut chain: BTreeMap<u64, (ChainLink, String)> = BTreeMap::new();
+    for event in events {
+        let Some(link) = event.chain.clone() else {
+            report.problems.push(format!("event at {} has no chain link", event.timestamp));
+            continue;
+        };
+        let hash = event_hash(&event)?;
+        match chain.get(&link.sequence) {
+            // 上传失败后重新持久化的同一事件
+            Some((_, existing)) if *existing == hash => {}
+            Some(_) => report
+                .problems
+                .push(format!("sequence {} has conflicting copies", link.sequence)),
+            None => {
+                chain.insert(link.sequence, (link, hash));
+            }
+        }
+    }
+
+    let mut previous: Option<(u64, &String)> = None;
+    for (&sequence, (link, hash)) in &chain {
+        match previous {
+            Some((prev_sequence, prev_hash)) if sequence == prev_sequence + 1 && link.prev_hash != *prev_hash => {
+                report
+                    .problems
+                    .push(format!("sequence {} does not follow {} (modified event)", sequence, prev_sequence));
+            }
+            Some((prev_sequence, _)) if sequence == prev_sequence + 1 => {}
+            Some((prev_sequence, _)) => report.gaps.push((prev_sequence + 1, sequence - 1)),
+            None if sequence == 1 && link.prev_hash != GENESIS_HASH => {
+                report.problems.push("sequence 1 does not start the chain".to_string());
+            }
+            None => {}
+        }
+        previous = Some((sequence, hash));
+    }
+    report.events = chain.len();
+    report.first_sequence = chain.keys().next().copied();
+    report.last_sequence = chain.keys().next_back().copied();
+    Ok(report)
+}
+
+/// 校验本地审计日志：每个批次的签名、哈希链连续且从序号 1 开始，并与保存的链头一致
+///
+/// 日志缺少链头之前的事件（尾部被截断或文件被删除）记为缺口，链头与最后一个事件
+/// 的哈希不符或日志超出链头记为问题。
+pub fn verify_journal(journal_path: &Path, state_path: &Path, crypto: &CryptoManager) -> Result<ChainReport> {
+    let mut problems = Vec::new();
+    let head: ChainHead = match std::fs::read(state_path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .map_err(|e| anyhow!("{}: unreadable chain head ({})", state_path.display(), e))?,
+        Err(e) => {
+            problems.push(format!("{}: chain head missing ({})", state_path.display(), e));
+            ChainHead::default()
+        }
+    };
+
+    let content = match std::fs::read_to_string(journal_path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(anyhow!("Failed to read {}: {}", journal_path.display(), e)),
+    };
+    let mut events = Vec::new();
+    for (index, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let batch = match PersistedAuditBatch::parse(line) {
+            Ok(batch) => batch,
+            Err(e) => {
+                problems.push(format!("{}:{}: unreadable ({})", journal_path.display(), index + 1, e));
+                continue;
+            }
+        };
+        match batch.verify_signature(crypto) {
+            Ok(true) => {}
+            Ok(false) => problems.push(format!("{}:{}: signature mismatch", journal_path.display(), index + 1)),
+            Err(e) => problems.push(format!("{}:{}: {}", journal_path.display(), index + 1, e)),
+        }
+        events.extend(batch.events);
+    }
+
+    let head_hash = events
+        .iter()
+        .find(|event| event.chain.as_ref().is_some_and(|link| link.sequence == head.sequence))
+        .map(event_hash)
+        .transpose()?;
+    let mut report = verify_events(events)?;
+    if let Some(first) = report.first_sequence.filter(|first| *first > 1) {
+        report.gaps.insert(0, (1, first - 1));
+    }
+    let last = report.last_sequence.unwrap_or(0);
+    if last < head.sequence {
+        report.gaps.push((last + 1, head.sequence));
+    } else if last > head.sequence {
+        problems.push(format!("journal extends past saved chain head {}", head.sequence));
+    } else if head.sequence > 0 && head_hash.as_deref() != Some(head.hash.as_str()) {
+        problems.push(format!("sequence {} does not match saved chain head", head.sequence));
+    }
+    problems.append(&mut report.problems);
+    report.problems = problems;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::audit::{AuditEventData, AuditEventType, AuditResult};
+    use tempfile::tempdir;
+
+    fn event(path: &str) -> AuditEvent {
+        AuditEvent {
+            event_type: AuditEventType::FileDelete,
+            timestamp: 1,
+            device_id: "device".to_string(),
+            session_id: None,
+            data: AuditEventData::FileDelete {
+                path: path.to_string(),
+                operation_id: "op".to_string(),
+            },
+            result: AuditResult::Success,
+            error_message: None,
+            operator: None,
+            chain: None,
+        }
+    }
+
+    fn chained(chain: &mut AuditChain, count: usize) -> Vec<AuditEvent> {
+        let mut events: Vec<AuditEvent> = (0..count).map(|i| event(&format!("/tmp/{}", i))).collect();
+        chain.append(&mut events).unwrap();
+        events
+    }
+
+    #[test]
+    fn test_chain_detects_tampering() {
+        let dir = tempdir().unwrap();
+        let state = dir.path().join("chain.json");
+        let mut chain = AuditChain::load(Some(state.clone()));
+        let mut events = chained(&mut chain, 3);
+        // 链头在定序时保存，重启后接续序号
+        let mut resumed = AuditChain::load(Some(state));
+        events.extend(chained(&mut resumed, 2));
+        assert!(verify_events(events.clone()).unwrap().is_intact());
+
+        // 修改事件会使后继的前驱哈希不匹配
+        let mut modified = events.clone();
+        modified[1].result = AuditResult::Error;
+        let report = verify_events(modified).unwrap();
+        assert_eq!(report.problems, vec!["sequence 3 does not follow 2 (modified event)"]);
+
+        // 删除事件留下缺口，重复上传的相同副本不算问题
+        let mut removed = events.clone();
+        removed.remove(3);
+        removed.push(events[0].clone());
+        let report = verify_events(removed).unwrap();
+        assert_eq!(report.gaps, vec![(4, 4)]);
+        assert!(report.problems.is_empty());
+    }
+
+    #[test]
+    fn test_persisted_batch_signature() {
+        let crypto = CryptoManager::generate().unwrap();
+        let mut chain = AuditChain::load(None);
+        let mut batch = PersistedAuditBatch::sign(&crypto, "device", chained(&mut chain, 2)).unwrap();
+        assert!(batch.verify_signature(&crypto).unwrap());
+
+        // 改动最后一个事件只能由批次签名发现
+        batch.events[1].operator = Some("mallory".to_string());
+        assert!(!batch.verify_signature(&crypto).unwrap());
+        assert!(verify_events(batch.events).unwrap().is_intact());
+    }
+
+    #[test]
+    fn test_journal_checked_against_head() {
+        let crypto = CryptoManager::generate().unwrap();
+        let dir = tempdir().unwrap();
+        let state = dir.path().join(CHAIN_STATE_FILE);
+        let journal = dir.path().join(JOURNAL_FILE);
+        let mut chain = AuditChain::load(Some(state.clone())).with_journal(Some(journal.clone()));
+        for _ in 0..3 {
+            let events = chained(&mut chain, 2);
+            chain.journal(&PersistedAuditBatch::sign(&crypto, "device", events).unwrap()).unwrap();
+        }
+        let report = verify_journal(&journal, &state, &crypto).unwrap();
+        assert!(report.is_intact(), "{}", report);
+        assert_eq!(report.last_sequence, Some(6));
+
+        // 截断最后一个批次
+        let content = std::fs::read_to_string(&journal).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        std::fs::write(&journal, format!("{}\n{}\n", lines[0], lines[1])).unwrap();
+        let report = verify_journal(&journal, &state, &crypto).unwrap();
+        assert_eq!(report.gaps, vec![(5, 6)]);
+
+        // 删除开头的批次
+        std::fs::write(&journal, format!("{}\n{}\n", lines[1], lines[2])).unwrap();
+        let report = verify_journal(&journal, &state, &crypto).unwrap();
+        assert_eq!(report.gaps, vec![(1, 2)]);
+
+        // 删除整个日志
+        std::fs::remove_file(&journal).unwrap();
+        let report = verify_journal(&journal, &state, &crypto).unwrap();
+        assert_eq!(report.gaps, vec![(1, 6)]);
+
+        // 链头与日志最后一个事件不符
+        std::fs::write(&journal, &content).unwrap();
+        std::fs::write(&state, serde_json::to_vec(&ChainHead { sequence: 6, hash: GENESIS_HASH.to_string() }).unwrap())
+            .unwrap();
+        let report = verify_journal(&journal, &state, &crypto).unwrap();
+        assert_eq!(report.problems, vec!["sequence 6 does not match saved chain head"]);
+
+        // 其他设备密钥签的日志无法通过校验
+        let other = CryptoManager::generate().unwrap();
+        let report = verify_journal(&journal, &state, &other).unwrap();
+        assert!(!report.is_intact());
+    }
+}
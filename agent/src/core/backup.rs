@@ -0,0 +1,235 @@
+// agent/src/core/backup.rs
+// 文件覆盖前的版本备份与回滚
+//
+// 内容按 SHA-256 去重保存在 objects/ 下，index.json 记录每个路径的历史版本。
+// 每个路径保留的版本数与全部备份的总大小都有上限，超出时删除最旧的版本。
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+use crate::core::transfer::file_checksum;
+use crate::platform::atomic_write;
+
+/// 一个已备份的版本
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupVersion {
+    pub path: String,
+    pub checksum: String,
+    pub size: u64,
+    /// 备份时间（Unix 秒）
+    pub created: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+}
+
+pub struct BackupStore {
+    dir: PathBuf,
+    /// 每个路径保留的版本数
+    max_versions: usize,
+    /// 全部备份内容的总字节数上限
+    max_total_size: u64,
+    /// 超过该大小的文件不备份
+    max_file_size: u64,
+    /// 串行化索引的读改写
+    index_lock: Mutex<()>,
+}
+
+impl BackupStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            max_versions: 10,
+            max_total_size: 512 * 1024 * 1024,
+            max_file_size: 16 * 1024 * 1024,
+            index_lock: Mutex::new(()),
+        }
+    }
+
+    /// 设置保留版本数与大小上限
+    pub fn with_limits(mut self, max_versions: usize, max_total_size: u64, max_file_size: u64) -> Self {
+        self.max_versions = max_versions.max(1);
+        self.max_total_size = max_total_size;
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    /// 备份 `path` 的当前内容；不存在、非普通文件或超过大小上限时返回 None
+    pub fn backup(&self, path: &Path) -> Result<Option<BackupVersion>> {
+        let Ok(metadata) = fs::symlink_metadata(path) else {
+            return Ok(None);
+        };
+        if !metadata.is_file() || metadata.len() > self.max_file_size {
+            return Ok(None);
+        }
+
+        let _guard = self.index_lock.lock().unwrap();
+        let checksum = file_checksum(path)?;
+        let key = path.to_string_lossy().to_string();
+        let mut index = self.load_index()?;
+        // 与最近一次备份相同则不重复记录
+        if let Some(latest) = index.iter().rev().find(|v| v.path == key) {
+            if latest.checksum == checksum {
+                return Ok(Some(latest.clone()));
+            }
+        }
+
+        let object = self.object_path(&checksum);
+        if !object.exists() {
+            fs::create_dir_all(self.dir.join("objects"))?;
+            let tmp = object.with_extension("tmp");
+            fs::copy(path, &tmp)?;
+            fs::rename(&tmp, &object)?;
+        }
+
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(metadata.permissions().mode())
+        };
+        #[cfg(not(unix))]
+        let mode = None;
+
+        let version = BackupVersion {
+            path: key,
+            checksum,
+            size: metadata.len(),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            mode,
+        };
+        index.push(version.clone());
+        self.prune(&mut index)?;
+        self.save_index(&index)?;
+        debug!("Backed up {} ({})", version.path, version.checksum);
+        Ok(Some(version))
+    }
+
+    /// `path` 的已保留版本，最新的在前
+    pub fn versions(&self, path: &Path) -> Result<Vec<BackupVersion>> {
+        let _guard = self.index_lock.lock().unwrap();
+        let key = path.to_string_lossy();
+        let mut versions: Vec<BackupVersion> = self.load_index()?.into_iter().filter(|v| v.path == key).collect();
+        versions.reverse();
+        Ok(versions)
+    }
+
+    /// 将 `path` 恢复为指定版本，恢复前先备份当前内容
+    pub fn restore(&self, path: &Path, checksum: &str) -> Result<BackupVersion> {
+        let version = self
+            .versions(path)?
+            .into_iter()
+            .find(|v| v.checksum == checksum)
+            .ok_or_else(|| anyhow!("No backup of {} with checksum {}", path.display(), checksum))?;
+
+        let object = self.object_path(&version.checksum);
+        let data = fs::read(&object).map_err(|e| anyhow!("Backup content missing: {}", e))?;
+        if crate::platform::calculate_checksum(&data) != version.checksum {
+            return Err(anyhow!("Backup content corrupted: {}", version.checksum));
+        }
+
+        self.backup(path)?;
+        atomic_write(path, &data)?;
+        #[cfg(unix)]
+        if let Some(mode) = version.mode {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        }
+        Ok(version)
+    }
+
+    /// 按每路径版本数与总大小淘汰旧版本，并删除不再被引用的内容
+    fn prune(&self, index: &mut Vec<BackupVersion>) -> Result<()> {
+        // 索引按时间顺序追加，从新到旧统计每个路径的版本数
+        let mut kept = Vec::with_capacity(index.len());
+        let mut counts = std::collections::HashMap::new();
+        for version in index.drain(..).rev() {
+            let count = counts.entry(version.path.clone()).or_insert(0usize);
+            *count += 1;
+            if *count <= self.max_versions {
+                kept.push(version);
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let mut total = 0u64;
+        kept.retain(|version| {
+            if seen.insert(version.checksum.clone()) {
+                total += version.size;
+            }
+            total <= self.max_total_size
+        });
+        kept.reverse();
+        *index = kept;
+
+        let referenced: HashSet<&str> = index.iter().map(|v| v.checksum.as_str()).collect();
+        if let Ok(entries) = fs::read_dir(self.dir.join("objects")) {
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if !referenced.contains(name.as_str()) {
+                    let _ = fs::remove_file(entry.path());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn object_path(&self, checksum: &str) -> PathBuf {
+        self.dir.join("objects").join(checksum)
+    }
+
+    fn load_index(&self) -> Result<Vec<BackupVersion>> {
+        match fs::read(self.dir.join("index.json")) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save_index(&self, index: &[BackupVersion]) -> Result<()> {
+        atomic_write(&self.dir.join("index.json"), &serde_json::to_vec(index)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_backup_and_restore() {
+        let dir = tempdir().unwrap();
+        let store = BackupStore::new(dir.path().join("backups")).with_limits(2, 1024, 1024);
+        let target = dir.path().join("app.conf");
+
+        assert!(store.backup(&target).unwrap().is_none());
+        fs::write(&target, b"v1").unwrap();
+        let v1 = store.backup(&target).unwrap().unwrap();
+        // 内容未变时不产生新版本
+        assert_eq!(store.backup(&target).unwrap().unwrap(), v1);
+        fs::write(&target, b"v2").unwrap();
+        store.backup(&target).unwrap();
+        fs::write(&target, b"v3").unwrap();
+
+        let restored = store.restore(&target, &v1.checksum).unwrap();
+        assert_eq!(restored.checksum, v1.checksum);
+        assert_eq!(fs::read(&target).unwrap(), b"v1");
+
+        // 恢复前的 v3 被备份，每路径只保留 2 个版本，v1 已被淘汰
+        let versions: Vec<u64> = store.versions(&target).unwrap().iter().map(|v| v.size).collect();
+        assert_eq!(versions.len(), 2);
+        assert!(store.restore(&target, &v1.checksum).is_err());
+        assert_eq!(fs::read_dir(dir.path().join("backups/objects")).unwrap().count(), 2);
+
+        // 超过单文件大小上限的不备份
+        fs::write(&target, vec![0u8; 2048]).unwrap();
+        assert!(store.backup(&target).unwrap().is_none());
+    }
+}
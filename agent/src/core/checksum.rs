@@ -0,0 +1,123 @@
+// agent/src/core/checksum.rs
+// 按需计算文件校验和的缓存
+//
+// 以（设备、inode、大小、修改时间）为键缓存 SHA-256，文件未变化时不重复读取内容。
+
+use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use crate::core::tail::file_id;
+use crate::core::transfer::file_checksum;
+
+/// 默认缓存条目数
+pub const DEFAULT_CACHE_CAPACITY: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    /// 见 [`file_id`]：Unix 下为（设备号，inode），其他平台退化为创建时间
+    id: (u64, u64),
+    size: u64,
+    modified_ns: u128,
+}
+
+pub struct ChecksumCache {
+    capacity: usize,
+    inner: Mutex<CacheInner>,
+}
+
+#[derive(Default)]
+struct CacheInner {
+    entries: HashMap<CacheKey, String>,
+    /// 插入顺序，超出容量时淘汰最早的条目
+    order: VecDeque<CacheKey>,
+}
+
+impl ChecksumCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            inner: Mutex::new(CacheInner::default()),
+        }
+    }
+
+    /// 返回文件的 SHA-256，未命中时流式计算并缓存
+    pub fn checksum(&self, path: &Path, metadata: &fs::Metadata) -> Result<String> {
+        let key = cache_key(metadata);
+        if let Some(checksum) = self.inner.lock().unwrap().entries.get(&key) {
+            return Ok(checksum.clone());
+        }
+
+        let checksum = file_checksum(path)?;
+        let mut inner = self.inner.lock().unwrap();
+        if inner.entries.insert(key.clone(), checksum.clone()).is_none() {
+            inner.order.push_back(key);
+            while inner.order.len() > self.capacity {
+                if let Some(oldest) = inner.order.pop_front() {
+                    inner.entries.remove(&oldest);
+                }
+            }
+        }
+        Ok(checksum)
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+}
+
+impl Default for ChecksumCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+fn cache_key(metadata: &fs::Metadata) -> CacheKey {
+    CacheKey {
+        id: file_id(metadata),
+        size: metadata.len(),
+        modified_ns: metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos())
+            .unwrap_or(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_cache_hits_and_invalidation() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("data.log");
+        fs::write(&path, b"first").unwrap();
+
+        let cache = ChecksumCache::new(2);
+        let first = cache.checksum(&path, &fs::metadata(&path).unwrap()).unwrap();
+        assert_eq!(first, crate::platform::calculate_checksum(b"first"));
+        assert_eq!(cache.checksum(&path, &fs::metadata(&path).unwrap()).unwrap(), first);
+        assert_eq!(cache.len(), 1);
+
+        // 内容与修改时间变化后重新计算
+        fs::write(&path, b"second").unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(5)).unwrap();
+        let second = cache.checksum(&path, &fs::metadata(&path).unwrap()).unwrap();
+        assert_eq!(second, crate::platform::calculate_checksum(b"second"));
+
+        // 超出容量时淘汰最早的条目
+        let other = dir.path().join("other.log");
+        fs::write(&other, b"x").unwrap();
+        cache.checksum(&other, &fs::metadata(&other).unwrap()).unwrap();
+        assert_eq!(cache.len(), 2);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::core::audit::{AuditLogger, AuditResult};
-use crate::core::files::{FileManager, FileManagerConfig};
+use crate::core::files::{EditConflict, FileManager, FileManagerConfig, ListOptions};
 use crate::core::protocol::{FileInfo, WSMessage};
 use crate::platform::CommandExecutor;
 use anyhow::Result;
@@ -218,8 +218,11 @@ impl CommandHandler {
                 path,
                 content,
                 checksum,
+                expected_etag,
             } => {
-                let result = self.write_file(&path, &content, &checksum).await;
+                let result = self
+                    .write_file(&path, &content, &checksum, expected_etag.as_deref())
+                    .await;
                 match result {
                     Ok(_) => {
                         // 记录成功的文件上传操作
@@ -239,6 +242,7 @@ impl CommandHandler {
                             id,
                             success: true,
                             error: None,
+                            current_etag: None,
                         }))
                     }
                     Err(e) => {
@@ -255,10 +259,14 @@ impl CommandHandler {
                             );
                         }
 
+                        let current_etag = e
+                            .downcast_ref::<EditConflict>()
+                            .and_then(|conflict| conflict.current.clone());
                         Ok(Some(WSMessage::FsPutResult {
                             id,
                             success: false,
                             error: Some(e.to_string()),
+                            current_etag,
                         }))
                     }
                 }
@@ -330,7 +338,7 @@ impl CommandHandler {
 
     /// 列出文件
     async fn list_files(&self, path: &str) -> Result<Vec<FileInfo>> {
-        let files = self.file_manager.list_files(path).await?;
+        let files = self.file_manager.list_files_page(path, &ListOptions::default()).await?.files;
         // 转换 files::FileInfo 到 protocol::FileInfo
         let protocol_files = files
             .into_iter()
@@ -339,6 +347,13 @@ impl CommandHandler {
                 size: f.size,
                 is_dir: f.is_dir,
                 modified: f.modified,
+                is_symlink: f.is_symlink,
+                symlink_target: f.symlink_target,
+                mode: f.mode,
+                owner: f.owner,
+                group: f.group,
+                inode: f.inode,
+                nlink: f.nlink,
             })
             .collect();
         Ok(protocol_files)
@@ -352,10 +367,16 @@ impl CommandHandler {
     }
 
     /// 写入文件
-    async fn write_file(&self, path: &str, content: &str, expected_checksum: &str) -> Result<()> {
+    async fn write_file(
+        &self,
+        path: &str,
+        content: &str,
+        expected_checksum: &str,
+        expected_etag: Option<&str>,
+    ) -> Result<()> {
         let content_bytes = content.as_bytes();
         self.file_manager
-            .write_file(path, content_bytes, expected_checksum)
+            .write_file(path, content_bytes, expected_checksum, expected_etag)
             .await
     }
 
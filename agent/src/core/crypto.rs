@@ -5,6 +5,7 @@ use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
+use std::sync::{Arc, RwLock};
 
 /// Ed25519 密钥对管理
 #[derive(Debug, Clone)]
@@ -14,6 +15,11 @@ pub struct CryptoManager {
     device_id: Option<String>,
 }
 
+/// 设备签名身份（密钥与设备 ID）
+///
+/// 注册完成后由 Agent 填入，审计上传与日志上送等后台任务共享同一份；注册前为 None。
+pub type SharedSigner = Arc<RwLock<Option<(CryptoManager, String)>>>;
+
 /// 设备凭证
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceCredentials {
@@ -0,0 +1,706 @@
+// agent/src/core/dirsync.rs
+// 目录同步：块签名与增量应用（类似 rsync）
+//
+// 服务端先取得 Agent 现有文件按固定块大小切分的签名（弱校验和 + SHA-256），
+// 用滚动校验和在新内容中查找可复用的块，只下发未命中的字面数据。
+// 应用时先把全部变更文件流式重建到目标旁的暂存文件并校验，全部通过后才逐个替换，
+// 替换中途失败则恢复已替换的文件；最后按需删除清单之外的文件。
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::core::archive::{relative_name, safe_relative_path, ArchiveFilter, PathCheck};
+use crate::core::path_policy::Access;
+use crate::core::transfer::file_checksum;
+use crate::platform::{calculate_checksum, open_staging_file};
+
+pub const DEFAULT_BLOCK_SIZE: usize = 4096;
+const MIN_BLOCK_SIZE: usize = 512;
+const MAX_BLOCK_SIZE: usize = 1024 * 1024;
+
+/// 单个块的签名
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockSignature {
+    /// 见 [`weak_checksum`]
+    pub weak: u32,
+    /// 块内容的 SHA-256（十六进制）
+    pub strong: String,
+}
+
+/// 单个文件的签名
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileSignature {
+    /// 相对于同步根目录，统一使用 `/` 分隔
+    pub path: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// 整个文件的 SHA-256
+    pub checksum: String,
+    /// 最后一块可能短于块大小
+    pub blocks: Vec<BlockSignature>,
+}
+
+/// 目录树签名
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TreeSignature {
+    pub root: String,
+    pub block_size: usize,
+    pub files: Vec<FileSignature>,
+    /// 因路径策略、大小上限或非普通文件而跳过的数量
+    pub skipped: usize,
+}
+
+/// 重建文件内容的一步
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum DeltaOp {
+    /// 复制现有文件从第 `index` 块开始的 `count` 块
+    Copy {
+        index: usize,
+        #[serde(default = "one")]
+        count: usize,
+    },
+    /// base64 编码的字面数据
+    Data { data: String },
+}
+
+fn one() -> usize {
+    1
+}
+
+/// 同步清单中的一个文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncEntry {
+    pub path: String,
+    /// 同步后内容的 SHA-256
+    pub checksum: String,
+    #[serde(default)]
+    pub mode: Option<u32>,
+    /// 为 None 表示文件已是最新，只校验不写入
+    #[serde(default)]
+    pub ops: Option<Vec<DeltaOp>>,
+}
+
+/// 同步清单：根目录下应存在的全部文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncManifest {
+    /// 必须与生成签名时的块大小一致
+    pub block_size: usize,
+    pub entries: Vec<SyncEntry>,
+    /// 删除根目录下不在清单中的文件
+    #[serde(default)]
+    pub delete_extraneous: bool,
+    /// 限定签名与删除的范围，与签名请求使用同一过滤条件
+    #[serde(default)]
+    pub filter: ArchiveFilter,
+}
+
+/// 同步结果
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncSummary {
+    pub updated: usize,
+    pub unchanged: usize,
+    pub deleted: Vec<String>,
+    /// 从现有文件复用的字节数
+    pub bytes_copied: u64,
+    /// 随清单下发的字面数据字节数
+    pub bytes_literal: u64,
+}
+
+/// 弱校验和（rsync 式）：`a = Σ x_i`，`b = Σ (n - i) · x_i`，均模 2^16，结果为 `a | b << 16`。
+///
+/// 窗口右移一个字节时：`a' = a - x_old + x_new`，`b' = b - n · x_old + a'`。
+pub fn weak_checksum(block: &[u8]) -> u32 {
+    let n = block.len() as u32;
+    let (mut a, mut b) = (0u32, 0u32);
+    for (i, &byte) in block.iter().enumerate() {
+        a = a.wrapping_add(byte as u32);
+        b = b.wrapping_add((n - i as u32).wrapping_mul(byte as u32));
+    }
+    (a & 0xffff) | ((b & 0xffff) << 16)
+}
+
+fn block_size_or_default(block_size: Option<usize>) -> Result<usize> {
+    let block_size = block_size.unwrap_or(DEFAULT_BLOCK_SIZE);
+    if !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size) {
+        return Err(anyhow!(
+            "Block size must be between {} and {} bytes",
+            MIN_BLOCK_SIZE,
+            MAX_BLOCK_SIZE
+        ));
+    }
+    Ok(block_size)
+}
+
+/// 计算 `root` 下所有普通文件的块签名，不跟随符号链接
+pub fn signatures(
+    root: &Path,
+    block_size: Option<usize>,
+    filter: &ArchiveFilter,
+    max_file_size: u64,
+    check: PathCheck,
+) -> Result<TreeSignature> {
+    let block_size = block_size_or_default(block_size)?;
+    let root = check(root, Access::Read)?;
+    let mut tree = TreeSignature {
+        root: root.to_string_lossy().to_string(),
+        block_size,
+        files: Vec::new(),
+        skipped: 0,
+    };
+    if !root.exists() {
+        // 目标目录尚不存在时返回空签名，全部文件以字面数据下发
+        return Ok(tree);
+    }
+    if !root.is_dir() {
+        return Err(anyhow!("Path is not a directory: {}", root.display()));
+    }
+
+    for (path, file_type) in walk(&root)? {
+        let relative = relative_name(&root, &path);
+        if file_type.is_dir() || !filter.matches(&relative) {
+            continue;
+        }
+        if !file_type.is_file() || check(&path, Access::Read).is_err() {
+            tree.skipped += 1;
+            continue;
+        }
+        let metadata = fs::metadata(&path)?;
+        if metadata.len() > max_file_size {
+            tree.skipped += 1;
+            continue;
+        }
+
+        let (size, checksum, blocks) = block_signatures(&path, block_size)?;
+        tree.files.push(FileSignature {
+            path: relative,
+            size,
+            mode: file_mode(&metadata),
+            checksum,
+            blocks,
+        });
+    }
+    Ok(tree)
+}
+
+/// 逐块读取文件计算块签名与整体 SHA-256，返回（大小，校验和，块签名）
+fn block_signatures(path: &Path, block_size: usize) -> Result<(u64, String, Vec<BlockSignature>)> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    let mut blocks = Vec::new();
+    let mut block = Vec::with_capacity(block_size);
+    let mut size = 0u64;
+    loop {
+        block.clear();
+        (&mut reader).take(block_size as u64).read_to_end(&mut block)?;
+        if block.is_empty() {
+            break;
+        }
+        hasher.update(&block);
+        size += block.len() as u64;
+        blocks.push(BlockSignature {
+            weak: weak_checksum(&block),
+            strong: calculate_checksum(&block),
+        });
+    }
+    Ok((size, format!("{:x}", hasher.finalize()), blocks))
+}
+
+/// 已重建到暂存文件、等待替换的同步文件
+struct StagedFile {
+    target: PathBuf,
+    staging: PathBuf,
+    /// 替换期间保存原文件，全部替换成功后删除
+    original: PathBuf,
+}
+
+impl StagedFile {
+    /// `nonce` 每次同步不同，暂存文件名不可预测
+    fn new(target: PathBuf, nonce: &str) -> Result<Self> {
+        let file_name = target
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid path: no file name"))?
+            .to_string_lossy()
+            .to_string();
+        let parent = target.parent().ok_or_else(|| anyhow!("Invalid path: no parent directory"))?;
+        // 与目标在同一目录，保证重命名是原子的
+        Ok(Self {
+            staging: parent.join(format!(".{}.{}.sync.part", file_name, nonce)),
+            original: parent.join(format!(".{}.{}.sync.orig", file_name, nonce)),
+            target,
+        })
+    }
+}
+
+/// 应用同步清单。`backup` 在覆盖前调用，用于保存旧版本
+pub fn apply(
+    root: &Path,
+    manifest: &SyncManifest,
+    max_file_size: u64,
+    check: PathCheck,
+    backup: &dyn Fn(&Path),
+) -> Result<SyncSummary> {
+    let block_size = block_size_or_default(Some(manifest.block_size))?;
+    let root = check(root, Access::Write)?;
+    let mut summary = SyncSummary::default();
+
+    // 先重建并校验全部文件，任何一个失败都不修改现有文件
+    let mut staged = Vec::new();
+    let listed = match stage_entries(&root, manifest, block_size, max_file_size, check, &mut staged, &mut summary) {
+        Ok(listed) => listed,
+        Err(e) => {
+            for file in &staged {
+                let _ = fs::remove_file(&file.staging);
+            }
+            return Err(e);
+        }
+    };
+
+    let mut replaced = Vec::new();
+    if let Err(e) = replace_all(&staged, backup, &mut replaced) {
+        for (file, had_original) in replaced.into_iter().rev() {
+            if had_original {
+                let _ = fs::rename(&file.original, &file.target);
+            } else {
+                let _ = fs::remove_file(&file.target);
+            }
+        }
+        for file in &staged {
+            let _ = fs::remove_file(&file.staging);
+        }
+        return Err(e);
+    }
+    for (file, had_original) in replaced {
+        if had_original {
+            let _ = fs::remove_file(&file.original);
+        }
+    }
+    summary.updated = staged.len();
+
+    if manifest.delete_extraneous && root.is_dir() {
+        for (path, file_type) in walk(&root)? {
+            let relative = relative_name(&root, &path);
+            if file_type.is_dir() || listed.contains(&relative) || !manifest.filter.matches(&relative) {
+                continue;
+            }
+            // 路径策略不允许写入的文件保留
+            if check(&path, Access::Write).is_err() {
+                continue;
+            }
+            backup(&path);
+            fs::remove_file(&path)?;
+            summary.deleted.push(relative);
+        }
+    }
+
+    Ok(summary)
+}
+
+/// 校验清单并把需要更新的文件写入暂存文件，返回清单中的相对路径
+fn stage_entries(
+    root: &Path,
+    manifest: &SyncManifest,
+    block_size: usize,
+    max_file_size: u64,
+    check: PathCheck,
+    staged: &mut Vec<StagedFile>,
+    summary: &mut SyncSummary,
+) -> Result<HashSet<String>> {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let nonce = format!("{}.{}", std::process::id(), nanos);
+    let mut listed = HashSet::new();
+    for entry in &manifest.entries {
+        let relative = safe_relative_path(Path::new(&entry.path))?;
+        if relative.as_os_str().is_empty() {
+            return Err(anyhow!("Invalid sync entry path: {:?}", entry.path));
+        }
+        if !listed.insert(relative_name(Path::new(""), &relative)) {
+            return Err(anyhow!("Duplicate sync entry: {}", entry.path));
+        }
+        let target = check(&root.join(&relative), Access::Write)?;
+
+        let Some(ops) = &entry.ops else {
+            let current = if target.is_file() { Some(file_checksum(&target)?) } else { None };
+            if current.as_deref() != Some(entry.checksum.as_str()) {
+                return Err(anyhow!("{} has changed since signatures were taken", entry.path));
+            }
+            summary.unchanged += 1;
+            continue;
+        };
+
+        let file = StagedFile::new(target, &nonce)?;
+        if let Some(parent) = file.target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let result = stage_file(&file, entry, ops, block_size, max_file_size, summary);
+        match result {
+            Ok(false) => staged.push(file),
+            Ok(true) => {
+                let _ = fs::remove_file(&file.staging);
+                summary.unchanged += 1;
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&file.staging);
+                return Err(e);
+            }
+        }
+    }
+    Ok(listed)
+}
+
+/// 按增量操作把新内容流式写入暂存文件并校验，返回内容是否与现有文件相同
+fn stage_file(
+    file: &StagedFile,
+    entry: &SyncEntry,
+    ops: &[DeltaOp],
+    block_size: usize,
+    max_file_size: u64,
+    summary: &mut SyncSummary,
+) -> Result<bool> {
+    let mut basis = if file.target.is_file() { Some(File::open(&file.target)?) } else { None };
+    let basis_len = match &basis {
+        Some(basis) => basis.metadata()?.len(),
+        None => 0,
+    };
+    let exceeds = || anyhow!("{} exceeds maximum allowed size {}", entry.path, max_file_size);
+
+    let staging = open_staging_file(&file.staging, OpenOptions::new().write(true).create_new(true))?;
+    let mut out = BufWriter::new(&staging);
+    let mut hasher = Sha256::new();
+    let mut written = 0u64;
+    for op in ops {
+        match op {
+            DeltaOp::Copy { index, count } => {
+                let start = (*index as u64).saturating_mul(block_size as u64);
+                let end = (index.saturating_add(*count) as u64)
+                    .saturating_mul(block_size as u64)
+                    .min(basis_len);
+                if *count == 0 || start >= end {
+                    return Err(anyhow!("Block {} is out of range for {}", index, entry.path));
+                }
+                if written + (end - start) > max_file_size {
+                    return Err(exceeds());
+                }
+                let basis = basis.as_mut().ok_or_else(|| anyhow!("No basis file for {}", entry.path))?;
+                basis.seek(SeekFrom::Start(start))?;
+                let copied = copy_hashed(&mut basis.take(end - start), &mut out, &mut hasher)?;
+                if copied != end - start {
+                    return Err(anyhow!("{} changed while applying", entry.path));
+                }
+                written += copied;
+                summary.bytes_copied += copied;
+            }
+            DeltaOp::Data { data: literal } => {
+                let literal = general_purpose::STANDARD
+                    .decode(literal)
+                    .map_err(|e| anyhow!("Invalid base64 data for {}: {}", entry.path, e))?;
+                if written + literal.len() as u64 > max_file_size {
+                    return Err(exceeds());
+                }
+                hasher.update(&literal);
+                out.write_all(&literal)?;
+                written += literal.len() as u64;
+                summary.bytes_literal += literal.len() as u64;
+            }
+        }
+    }
+    out.flush()?;
+    drop(out);
+    staging.sync_all()?;
+
+    let checksum = format!("{:x}", hasher.finalize());
+    if checksum != entry.checksum {
+        return Err(anyhow!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            entry.path,
+            entry.checksum,
+            checksum
+        ));
+    }
+    if basis.is_some() && basis_len == written && file_checksum(&file.target)? == checksum {
+        return Ok(true);
+    }
+
+    #[cfg(unix)]
+    if let Some(mode) = entry.mode {
+        use std::os::unix::fs::PermissionsExt;
+        staging.set_permissions(fs::Permissions::from_mode(mode & 0o7777))?;
+    }
+    Ok(false)
+}
+
+/// 边复制边计算哈希，返回复制的字节数
+fn copy_hashed(reader: &mut impl Read, out: &mut impl Write, hasher: &mut Sha256) -> Result<u64> {
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut copied = 0u64;
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        hasher.update(&buf[..n]);
+        out.write_all(&buf[..n])?;
+        copied += n as u64;
+    }
+    Ok(copied)
+}
+
+/// 逐个用暂存文件替换目标，`replaced` 记录已动过的文件及其是否原本存在，供失败时恢复
+fn replace_all<'a>(
+    staged: &'a [StagedFile],
+    backup: &dyn Fn(&Path),
+    replaced: &mut Vec<(&'a StagedFile, bool)>,
+) -> Result<()> {
+    for file in staged {
+        backup(&file.target);
+        let had_original = file.target.is_file();
+        if had_original {
+            fs::rename(&file.target, &file.original)?;
+        }
+        replaced.push((file, had_original));
+        fs::rename(&file.staging, &file.target)?;
+    }
+    Ok(())
+}
+
+/// 递归列出 `root` 下的条目（不跟随符号链接），按名称排序
+fn walk(root: &Path) -> Result<Vec<(PathBuf, fs::FileType)>> {
+    let mut found = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries: Vec<fs::DirEntry> = fs::read_dir(&dir)?.filter_map(|entry| entry.ok()).collect();
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            }
+            found.push((entry.path(), file_type));
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::path_policy::AccessMode;
+    use crate::core::test_support::policy_for;
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    const BLOCK: usize = 512;
+
+    /// 服务端的增量计算：逐字节滚动查找与签名相同的块
+    fn delta(signature: Option<&FileSignature>, data: &[u8]) -> Vec<DeltaOp> {
+        let mut blocks: HashMap<u32, Vec<(usize, &str)>> = HashMap::new();
+        for (index, block) in signature.map(|s| s.blocks.as_slice()).unwrap_or_default().iter().enumerate() {
+            blocks.entry(block.weak).or_default().push((index, &block.strong));
+        }
+
+        let mut ops = Vec::new();
+        let mut literal = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let window = &data[pos..(pos + BLOCK).min(data.len())];
+            let hit = blocks.get(&weak_checksum(window)).and_then(|candidates| {
+                let strong = calculate_checksum(window);
+                candidates.iter().find(|(_, s)| *s == strong).map(|(index, _)| *index)
+            });
+            match hit {
+                Some(index) => {
+                    if !literal.is_empty() {
+                        ops.push(DeltaOp::Data {
+                            data: general_purpose::STANDARD.encode(std::mem::take(&mut literal)),
+                        });
+                    }
+                    ops.push(DeltaOp::Copy { index, count: 1 });
+                    pos += window.len();
+                }
+                None => {
+                    literal.push(data[pos]);
+                    pos += 1;
+                }
+            }
+        }
+        if !literal.is_empty() {
+            ops.push(DeltaOp::Data {
+                data: general_purpose::STANDARD.encode(literal),
+            });
+        }
+        ops
+    }
+
+    #[test]
+    fn test_weak_checksum_rolls() {
+        let data: Vec<u8> = (0..64u32).map(|i| (i * 7 % 251) as u8).collect();
+        let n = 16u32;
+        let first = weak_checksum(&data[0..16]);
+        let (mut a, mut b) = (first & 0xffff, first >> 16);
+        a = (a.wrapping_sub(data[0] as u32).wrapping_add(data[16] as u32)) & 0xffff;
+        b = (b.wrapping_sub(n * data[0] as u32).wrapping_add(a)) & 0xffff;
+        assert_eq!(a | (b << 16), weak_checksum(&data[1..17]));
+    }
+
+    #[test]
+    fn test_sync_round_trip() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let policy = policy_for(&root, AccessMode::ReadWrite, &["**/secret*"]);
+        let check = |path: &Path, access: Access| policy.check(path, access);
+
+        let old: Vec<u8> = (0..4096u32).map(|i| (i % 253) as u8).collect();
+        fs::create_dir_all(root.join("conf")).unwrap();
+        fs::write(root.join("conf/app.bin"), &old).unwrap();
+        fs::write(root.join("conf/same.txt"), b"same").unwrap();
+        fs::write(root.join("conf/stale.txt"), b"stale").unwrap();
+        fs::write(root.join("conf/local.override"), b"keep").unwrap();
+        fs::write(root.join("secret.key"), b"k").unwrap();
+
+        let filter = ArchiveFilter {
+            include: Vec::new(),
+            exclude: vec!["**/*.override".to_string()],
+        };
+        let tree = signatures(&root, Some(BLOCK), &filter, 1 << 20, &check).unwrap();
+        assert_eq!(tree.files.len(), 3);
+        assert_eq!(tree.skipped, 1);
+        let sig = |name: &str| tree.files.iter().find(|f| f.path == name);
+
+        // 在中间插入数据，其余块应被复用
+        let mut new = old.clone();
+        new.splice(1000..1000, b"inserted".iter().copied());
+        let manifest = SyncManifest {
+            block_size: BLOCK,
+            entries: vec![
+                SyncEntry {
+                    path: "conf/app.bin".to_string(),
+                    checksum: calculate_checksum(&new),
+                    mode: Some(0o600),
+                    ops: Some(delta(sig("conf/app.bin"), &new)),
+                },
+                SyncEntry {
+                    path: "conf/same.txt".to_string(),
+                    checksum: sig("conf/same.txt").unwrap().checksum.clone(),
+                    mode: None,
+                    ops: None,
+                },
+                SyncEntry {
+                    path: "conf/new/added.txt".to_string(),
+                    checksum: calculate_checksum(b"added"),
+                    mode: None,
+                    ops: Some(delta(None, b"added")),
+                },
+            ],
+            delete_extraneous: true,
+            filter,
+        };
+
+        // 校验和不符时不修改任何文件
+        let mut broken = manifest.clone();
+        broken.entries[2].checksum = calculate_checksum(b"other");
+        assert!(apply(&root, &broken, 1 << 20, &check, &|_| {}).is_err());
+        assert_eq!(fs::read(root.join("conf/app.bin")).unwrap(), old);
+        let leftovers = fs::read_dir(root.join("conf"))
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".sync.part"))
+            .count();
+        assert_eq!(leftovers, 0);
+
+        // 重建后的内容超过大小上限
+        assert!(apply(&root, &manifest, 1024, &check, &|_| {}).is_err());
+        assert_eq!(fs::read(root.join("conf/app.bin")).unwrap(), old);
+
+        let summary = apply(&root, &manifest, 1 << 20, &check, &|_| {}).unwrap();
+        assert_eq!((summary.updated, summary.unchanged), (2, 1));
+        assert_eq!(summary.deleted, vec!["conf/stale.txt".to_string()]);
+        assert!(summary.bytes_literal < 1024);
+        assert_eq!(fs::read(root.join("conf/app.bin")).unwrap(), new);
+        assert_eq!(fs::read(root.join("conf/new/added.txt")).unwrap(), b"added");
+        assert!(root.join("conf/local.override").exists());
+        assert!(root.join("secret.key").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(root.join("conf/app.bin")).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // 签名之后文件被修改，标记为最新的条目校验失败
+        fs::write(root.join("conf/same.txt"), b"changed").unwrap();
+        assert!(apply(&root, &manifest, 1 << 20, &check, &|_| {}).is_err());
+        assert!(apply(
+            &root,
+            &SyncManifest {
+                entries: vec![SyncEntry {
+                    path: "../escape".to_string(),
+                    checksum: String::new(),
+                    mode: None,
+                    ops: Some(Vec::new()),
+                }],
+                ..manifest.clone()
+            },
+            1 << 20,
+            &check,
+            &|_| {}
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_apply_rolls_back_when_replace_fails() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let policy = policy_for(&root, AccessMode::ReadWrite, &[]);
+        let check = |path: &Path, access: Access| policy.check(path, access);
+
+        fs::write(root.join("a.txt"), b"old").unwrap();
+        // 非空目录无法被文件替换，第二个条目在替换阶段失败
+        fs::create_dir_all(root.join("b.txt/inner")).unwrap();
+        let entry = |path: &str, data: &[u8]| SyncEntry {
+            path: path.to_string(),
+            checksum: calculate_checksum(data),
+            mode: None,
+            ops: Some(delta(None, data)),
+        };
+        let manifest = SyncManifest {
+            block_size: BLOCK,
+            entries: vec![entry("a.txt", b"new"), entry("b.txt", b"file")],
+            delete_extraneous: false,
+            filter: ArchiveFilter::default(),
+        };
+
+        assert!(apply(&root, &manifest, 1 << 20, &check, &|_| {}).is_err());
+        assert_eq!(fs::read(root.join("a.txt")).unwrap(), b"old");
+        assert!(root.join("b.txt/inner").is_dir());
+        let leftovers: Vec<_> = fs::read_dir(&root)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().contains(".sync."))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+}
@@ -1,8 +1,22 @@
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+#[cfg(unix)]
+use std::ffi::{CStr, CString};
 use std::fs;
-use std::path::PathBuf;
-use tracing::{debug, info};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+use crate::config::FileOperationsSection;
+use crate::core::archive::ArchiveFilter;
+use crate::core::backup::{BackupStore, BackupVersion};
+use crate::core::checksum::ChecksumCache;
+use crate::core::dirsync::{self, SyncManifest, SyncSummary, TreeSignature};
+use crate::core::patch::{apply_line_edits, LineEdit};
+use crate::core::path_policy::{Access, PathPolicy};
+use crate::core::transfer::file_checksum;
+use crate::platform::atomic_write;
 
 /// 文件信息结构
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -11,46 +25,181 @@ pub struct FileInfo {
     pub size: u64,
     pub is_dir: bool,
     pub modified: Option<u64>,
+    #[serde(default)]
+    pub is_symlink: bool,
+    /// 符号链接指向的路径
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symlink_target: Option<String>,
+    /// 权限位（含文件类型位），如 0o100644
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inode: Option<u64>,
+    /// 硬链接数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nlink: Option<u64>,
+    /// 内容 SHA-256，仅在列目录时请求 include_checksums 才计算
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+}
+
+impl FileInfo {
+    /// 由不跟随符号链接的元数据构建
+    fn from_metadata(path: &Path, metadata: &fs::Metadata, names: &mut IdNames) -> Self {
+        let is_symlink = metadata.file_type().is_symlink();
+        let symlink_target = if is_symlink {
+            fs::read_link(path).ok().map(|target| target.to_string_lossy().to_string())
+        } else {
+            None
+        };
+
+        #[cfg(unix)]
+        let (mode, uid, gid, inode, nlink) = {
+            use std::os::unix::fs::MetadataExt;
+            (
+                Some(metadata.mode()),
+                Some(metadata.uid()),
+                Some(metadata.gid()),
+                Some(metadata.ino()),
+                Some(metadata.nlink()),
+            )
+        };
+        #[cfg(not(unix))]
+        let (mode, uid, gid, inode, nlink) = (None, None, None, None, None);
+
+        Self {
+            path: path.to_string_lossy().to_string(),
+            size: metadata.len(),
+            is_dir: metadata.is_dir(),
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+            is_symlink,
+            symlink_target,
+            mode,
+            uid,
+            gid,
+            owner: uid.and_then(|uid| names.user(uid)),
+            group: gid.and_then(|gid| names.group(gid)),
+            inode,
+            nlink,
+            checksum: None,
+        }
+    }
+}
+
+/// 列目录的排序字段
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Modified,
+}
+
+/// 列目录选项
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ListOptions {
+    /// 为普通文件计算 SHA-256
+    pub include_checksums: bool,
+    pub sort: SortKey,
+    pub descending: bool,
+    pub offset: usize,
+    /// 每页条目数，为空表示不分页
+    pub limit: Option<usize>,
+}
+
+/// 一页目录列表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileListing {
+    pub path: String,
+    pub files: Vec<FileInfo>,
+    /// 目录下的条目总数
+    pub total: usize,
+    pub offset: usize,
+    pub has_more: bool,
+}
+
+/// 批量修改（递归 chmod/chown、复制）的结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeSummary {
+    #[serde(flatten)]
+    pub info: FileInfo,
+    /// 实际修改或复制的条目数
+    pub changed: usize,
+    /// 因路径策略拒绝或为符号链接而跳过的条目数
+    pub skipped: usize,
+}
+
+/// 按行修改文件的结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchResult {
+    pub path: String,
+    /// 修改后内容的 SHA-256，可作为下次修改的 etag
+    pub etag: String,
+    pub size: u64,
+    pub lines: usize,
+}
+
+/// 文件当前内容与调用方期望的 etag 不一致
+#[derive(Debug, thiserror::Error)]
+#[error("Edit conflict on {path}: expected etag {expected}, current {}", current.as_deref().unwrap_or("<missing>"))]
+pub struct EditConflict {
+    pub path: String,
+    pub expected: String,
+    /// 当前内容的 SHA-256，文件不存在时为 None
+    pub current: Option<String>,
 }
 
 /// 文件操作客户端
 #[derive(Clone)]
 pub struct FileManager {
-    /// 允许的根路径列表
-    allowed_paths: Vec<PathBuf>,
+    /// 路径访问策略，克隆之间共享，可在运行时替换
+    policy: Arc<RwLock<PathPolicy>>,
     /// 最大文件大小 (bytes)
     max_file_size: u64,
-    /// 禁止的路径模式
-    forbidden_patterns: Vec<String>,
+    /// 覆盖写入前的版本备份
+    backups: Option<Arc<BackupStore>>,
+    /// 列目录时按需计算的校验和缓存，克隆之间共享
+    checksums: Arc<ChecksumCache>,
+    /// 串行化 etag 校验与写入，克隆之间共享
+    write_lock: Arc<Mutex<()>>,
 }
 
 /// 文件管理器配置
 #[derive(Debug, Clone)]
 pub struct FileManagerConfig {
-    pub allowed_paths: Vec<PathBuf>,
+    pub policy: PathPolicy,
     pub max_file_size: u64,
-    pub forbidden_patterns: Vec<String>,
 }
 
 impl Default for FileManagerConfig {
     fn default() -> Self {
         Self {
-            allowed_paths: vec![
-                PathBuf::from("/tmp"),
-                PathBuf::from("/var/log"),
-                #[cfg(target_os = "windows")]
-                PathBuf::from("C:\\temp"),
-                #[cfg(target_os = "windows")]
-                PathBuf::from("C:\\logs"),
-            ],
+            policy: PathPolicy::default(),
             max_file_size: 100 * 1024 * 1024, // 100MB
-            forbidden_patterns: vec![
-                "passwd".to_string(),
-                "shadow".to_string(),
-                "ssh_host_".to_string(),
-                ".ssh/id_".to_string(),
-                "private_key".to_string(),
-            ],
+        }
+    }
+}
+
+impl FileManagerConfig {
+    /// 从 Agent 配置的文件操作段构建
+    pub fn from_section(section: &FileOperationsSection, max_file_size: u64) -> Self {
+        Self {
+            policy: PathPolicy::from_section(section),
+            max_file_size,
         }
     }
 }
@@ -59,61 +208,35 @@ impl FileManager {
     /// 创建新的文件管理器
     pub fn new(config: FileManagerConfig) -> Self {
         Self {
-            allowed_paths: config.allowed_paths,
+            policy: Arc::new(RwLock::new(config.policy)),
             max_file_size: config.max_file_size,
-            forbidden_patterns: config.forbidden_patterns,
-        }
-    }
-
-    /// 验证路径是否安全
-    pub fn validate_path(&self, path: &str) -> Result<PathBuf> {
-        let path_buf = PathBuf::from(path);
-
-        // 规范化路径，防止路径遍历攻击
-        let canonical_path = match path_buf.canonicalize() {
-            Ok(p) => p,
-            Err(_) => {
-                // 如果路径不存在，尝试规范化父目录
-                if let Some(parent) = path_buf.parent() {
-                    let canonical_parent = parent
-                        .canonicalize()
-                        .map_err(|_| anyhow!("Invalid path: parent directory does not exist"))?;
-                    canonical_parent.join(path_buf.file_name().unwrap_or_default())
-                } else {
-                    return Err(anyhow!("Invalid path: cannot resolve"));
-                }
-            }
-        };
-
-        // 检查是否在允许的路径内
-        let mut path_allowed = false;
-        for allowed_path in &self.allowed_paths {
-            if let Ok(canonical_allowed) = allowed_path.canonicalize() {
-                if canonical_path.starts_with(&canonical_allowed) {
-                    path_allowed = true;
-                    break;
-                }
-            }
+            backups: None,
+            checksums: Arc::new(ChecksumCache::default()),
+            write_lock: Arc::new(Mutex::new(())),
         }
+    }
 
-        if !path_allowed {
-            return Err(anyhow!("Path not in allowed directories: {}", path));
-        }
+    /// 设置版本备份存储，覆盖写入前保存旧内容
+    pub fn with_backup_store(mut self, backups: Arc<BackupStore>) -> Self {
+        self.backups = Some(backups);
+        self
+    }
 
-        // 检查禁止的路径模式
-        let path_str = canonical_path.to_string_lossy().to_lowercase();
-        for pattern in &self.forbidden_patterns {
-            if path_str.contains(&pattern.to_lowercase()) {
-                return Err(anyhow!("Path contains forbidden pattern: {}", pattern));
-            }
-        }
+    /// 替换路径访问策略（配置热更新）
+    pub fn set_policy(&self, policy: PathPolicy) {
+        *self.policy.write().unwrap() = policy;
+    }
 
-        Ok(canonical_path)
+    /// 按访问策略验证路径，返回解析符号链接后的真实路径
+    pub fn validate_path(&self, path: &str, access: Access) -> Result<PathBuf> {
+        self.policy.read().unwrap().check(Path::new(path), access)
     }
 
-    /// 列出目录内容
-    pub async fn list_files(&self, path: &str) -> Result<Vec<FileInfo>> {
-        let validated_path = self.validate_path(path)?;
+    /// 列出目录内容，支持排序与分页
+    ///
+    /// 先只读取各条目的元数据排序分页，属主名称、链接目标与校验和只为当前页计算。
+    pub async fn list_files_page(&self, path: &str, options: &ListOptions) -> Result<FileListing> {
+        let validated_path = self.validate_path(path, Access::Read)?;
 
         debug!("Listing files in: {:?}", validated_path);
 
@@ -125,42 +248,73 @@ impl FileManager {
             return Err(anyhow!("Path is not a directory: {}", path));
         }
 
-        let mut files = Vec::new();
-        let entries = fs::read_dir(&validated_path)
+        let mut entries = Vec::new();
+        let dir = fs::read_dir(&validated_path)
             .map_err(|e| anyhow!("Failed to read directory: {}", e))?;
 
-        for entry in entries {
+        for entry in dir {
             let entry = entry.map_err(|e| anyhow!("Failed to read directory entry: {}", e))?;
-            let entry_path = entry.path();
 
+            // 不跟随符号链接，链接本身作为条目列出
             let metadata = entry
                 .metadata()
                 .map_err(|e| anyhow!("Failed to read file metadata: {}", e))?;
 
-            let file_info = FileInfo {
-                path: entry_path.to_string_lossy().to_string(),
-                size: metadata.len(),
-                is_dir: metadata.is_dir(),
-                modified: metadata
-                    .modified()
-                    .ok()
-                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                    .map(|d| d.as_secs()),
+            entries.push((entry.path(), metadata));
+        }
+
+        entries.sort_by(|(a_path, a), (b_path, b)| {
+            let ordering = match options.sort {
+                SortKey::Name => std::cmp::Ordering::Equal,
+                SortKey::Size => a.len().cmp(&b.len()),
+                SortKey::Modified => a.modified().ok().cmp(&b.modified().ok()),
             };
+            let ordering = ordering.then_with(|| a_path.cmp(b_path));
+            if options.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
 
-            files.push(file_info);
-        }
+        let total = entries.len();
+        let limit = options.limit.unwrap_or(usize::MAX);
+        let mut names = IdNames::default();
+        let files: Vec<FileInfo> = entries
+            .iter()
+            .skip(options.offset)
+            .take(limit)
+            .map(|(entry_path, metadata)| {
+                let mut info = FileInfo::from_metadata(entry_path, metadata, &mut names);
+                if options.include_checksums && metadata.is_file() {
+                    info.checksum = self.cached_checksum(entry_path, metadata);
+                }
+                info
+            })
+            .collect();
 
-        // 按名称排序
-        files.sort_by(|a, b| a.path.cmp(&b.path));
+        info!("Listed {} of {} files in {}", files.len(), total, path);
+        Ok(FileListing {
+            path: path.to_string(),
+            has_more: options.offset.saturating_add(files.len()) < total,
+            offset: options.offset,
+            total,
+            files,
+        })
+    }
 
-        info!("Listed {} files in {}", files.len(), path);
-        Ok(files)
+    /// 经缓存计算校验和，路径策略拒绝读取的文件不计算
+    fn cached_checksum(&self, path: &Path, metadata: &fs::Metadata) -> Option<String> {
+        self.validate_path(&path.to_string_lossy(), Access::Read).ok()?;
+        self.checksums
+            .checksum(path, metadata)
+            .map_err(|e| debug!("Failed to checksum {:?}: {}", path, e))
+            .ok()
     }
 
     /// 读取文件内容
     pub async fn read_file(&self, path: &str) -> Result<(Vec<u8>, String)> {
-        let validated_path = self.validate_path(path)?;
+        let validated_path = self.validate_path(path, Access::Read)?;
 
         debug!("Reading file: {:?}", validated_path);
 
@@ -193,14 +347,15 @@ impl FileManager {
         Ok((content, checksum))
     }
 
-    /// 写入文件内容
+    /// 写入文件内容；给出 `expected_etag` 时要求现有内容的 SHA-256 与之一致
     pub async fn write_file(
         &self,
         path: &str,
         content: &[u8],
         expected_checksum: &str,
+        expected_etag: Option<&str>,
     ) -> Result<()> {
-        let validated_path = self.validate_path(path)?;
+        let validated_path = self.validate_path(path, Access::Write)?;
 
         debug!(
             "Writing file: {:?} ({} bytes)",
@@ -227,30 +382,80 @@ impl FileManager {
             ));
         }
 
-        // 确保父目录存在
-        if let Some(parent) = validated_path.parent() {
-            fs::create_dir_all(parent)
-                .map_err(|e| anyhow!("Failed to create parent directory: {}", e))?;
+        let _guard = self.write_lock.lock().unwrap();
+        if let Some(expected) = expected_etag {
+            self.check_etag(&validated_path, path, expected)?;
         }
-
-        // 写入文件
-        fs::write(&validated_path, content).map_err(|e| anyhow!("Failed to write file: {}", e))?;
+        self.backup_existing(&validated_path);
+        atomic_write(&validated_path, content)?;
 
         info!("Wrote file {} ({} bytes)", path, content.len());
         Ok(())
     }
 
+    /// 按行区间修改文本文件，要求现有内容的 SHA-256 与 `expected_etag` 一致
+    pub async fn patch_file(&self, path: &str, edits: &[LineEdit], expected_etag: &str) -> Result<PatchResult> {
+        let validated_path = self.validate_path(path, Access::Write)?;
+        if !validated_path.is_file() {
+            return Err(anyhow!("Path is not a file: {}", path));
+        }
+
+        let _guard = self.write_lock.lock().unwrap();
+        self.check_etag(&validated_path, path, expected_etag)?;
+
+        let original = fs::read(&validated_path).map_err(|e| anyhow!("Failed to read file: {}", e))?;
+        let text = String::from_utf8(original).map_err(|_| anyhow!("File is not valid UTF-8 text: {}", path))?;
+        let patched = apply_line_edits(&text, edits)?;
+        if patched.len() as u64 > self.max_file_size {
+            return Err(anyhow!(
+                "File size {} exceeds maximum allowed size {}",
+                patched.len(),
+                self.max_file_size
+            ));
+        }
+
+        self.backup_existing(&validated_path);
+        atomic_write(&validated_path, patched.as_bytes())?;
+
+        info!("Patched file {} ({} edits)", path, edits.len());
+        Ok(PatchResult {
+            path: path.to_string(),
+            etag: self.calculate_checksum(patched.as_bytes()),
+            size: patched.len() as u64,
+            lines: patched.lines().count(),
+        })
+    }
+
+    /// 比较文件当前内容的 SHA-256 与期望的 etag，不一致时返回 [`EditConflict`]
+    fn check_etag(&self, validated_path: &Path, path: &str, expected: &str) -> Result<()> {
+        let current = if validated_path.is_file() {
+            Some(file_checksum(validated_path)?)
+        } else {
+            None
+        };
+        if current.as_deref() != Some(expected) {
+            return Err(EditConflict {
+                path: path.to_string(),
+                expected: expected.to_string(),
+                current,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
     /// 删除文件
+    ///
+    /// 作用于条目本身：符号链接只删除链接，不会删除其指向的文件或目录。
     pub async fn delete_file(&self, path: &str) -> Result<()> {
-        let validated_path = self.validate_path(path)?;
+        let validated_path = self.policy.read().unwrap().check_entry(Path::new(path), Access::Write)?;
 
         debug!("Deleting file: {:?}", validated_path);
 
-        if !validated_path.exists() {
-            return Err(anyhow!("File does not exist: {}", path));
-        }
+        let metadata = fs::symlink_metadata(&validated_path)
+            .map_err(|_| anyhow!("File does not exist: {}", path))?;
 
-        if validated_path.is_dir() {
+        if metadata.is_dir() {
             fs::remove_dir_all(&validated_path)
                 .map_err(|e| anyhow!("Failed to delete directory: {}", e))?;
         } else {
@@ -272,7 +477,7 @@ impl FileManager {
 
     /// 获取文件信息
     pub async fn get_file_info(&self, path: &str) -> Result<FileInfo> {
-        let validated_path = self.validate_path(path)?;
+        let validated_path = self.validate_path(path, Access::Read)?;
 
         if !validated_path.exists() {
             return Err(anyhow!("File does not exist: {}", path));
@@ -281,22 +486,418 @@ impl FileManager {
         let metadata = fs::metadata(&validated_path)
             .map_err(|e| anyhow!("Failed to read file metadata: {}", e))?;
 
-        Ok(FileInfo {
-            path: validated_path.to_string_lossy().to_string(),
-            size: metadata.len(),
-            is_dir: metadata.is_dir(),
-            modified: metadata
-                .modified()
-                .ok()
-                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                .map(|d| d.as_secs()),
+        Ok(FileInfo::from_metadata(&validated_path, &metadata, &mut IdNames::default()))
+    }
+
+    /// 覆盖前备份已有文件；备份失败只记录警告，不阻止写入
+    pub fn backup_existing(&self, path: &Path) {
+        if let Some(backups) = &self.backups {
+            if let Err(e) = backups.backup(path) {
+                warn!("Failed to back up {:?} before overwrite: {}", path, e);
+            }
+        }
+    }
+
+    /// 列出文件的已备份版本，最新的在前
+    pub async fn versions(&self, path: &str) -> Result<Vec<BackupVersion>> {
+        let validated_path = self.validate_path(path, Access::Read)?;
+        self.backup_store()?.versions(&validated_path)
+    }
+
+    /// 按校验和将文件恢复为已备份的版本
+    pub async fn restore(&self, path: &str, checksum: &str) -> Result<BackupVersion> {
+        let validated_path = self.validate_path(path, Access::Write)?;
+        let _guard = self.write_lock.lock().unwrap();
+        let version = self.backup_store()?.restore(&validated_path, checksum)?;
+        info!("Restored {} to version {}", path, checksum);
+        Ok(version)
+    }
+
+    fn backup_store(&self) -> Result<&BackupStore> {
+        self.backups
+            .as_deref()
+            .ok_or_else(|| anyhow!("File backups are disabled"))
+    }
+
+    /// 条目本身的信息（不跟随符号链接）
+    fn entry_info(&self, path: &Path) -> Result<FileInfo> {
+        let metadata = fs::symlink_metadata(path)
+            .map_err(|e| anyhow!("Failed to read file metadata: {}", e))?;
+        Ok(FileInfo::from_metadata(path, &metadata, &mut IdNames::default()))
+    }
+
+    /// 修改权限位，递归时不跟随符号链接
+    pub async fn chmod(&self, path: &str, mode: u32, recursive: bool) -> Result<ChangeSummary> {
+        let validated_path = self.validate_path(path, Access::Write)?;
+        if !validated_path.exists() {
+            return Err(anyhow!("File does not exist: {}", path));
+        }
+
+        let (changed, skipped) = self.apply_tree(&validated_path, recursive, &|entry| set_mode(entry, mode))?;
+        info!("Changed mode of {} to {:o} ({} entries)", path, mode, changed);
+        Ok(ChangeSummary {
+            info: self.entry_info(&validated_path)?,
+            changed,
+            skipped,
+        })
+    }
+
+    /// 修改属主与属组，接受名称或数字 ID
+    pub async fn chown(
+        &self,
+        path: &str,
+        owner: Option<&str>,
+        group: Option<&str>,
+        recursive: bool,
+    ) -> Result<ChangeSummary> {
+        let validated_path = self.validate_path(path, Access::Write)?;
+        if !validated_path.exists() {
+            return Err(anyhow!("File does not exist: {}", path));
+        }
+        if owner.is_none() && group.is_none() {
+            return Err(anyhow!("Either owner or group is required"));
+        }
+        let uid = owner
+            .map(|name| IdNames::user_id(name).ok_or_else(|| anyhow!("Unknown user: {}", name)))
+            .transpose()?;
+        let gid = group
+            .map(|name| IdNames::group_id(name).ok_or_else(|| anyhow!("Unknown group: {}", name)))
+            .transpose()?;
+
+        let (changed, skipped) = self.apply_tree(&validated_path, recursive, &|entry| set_owner(entry, uid, gid))?;
+        info!("Changed owner of {} ({} entries)", path, changed);
+        Ok(ChangeSummary {
+            info: self.entry_info(&validated_path)?,
+            changed,
+            skipped,
+        })
+    }
+
+    /// 创建目录
+    pub async fn create_dir(&self, path: &str, recursive: bool) -> Result<FileInfo> {
+        let validated_path = self.validate_path(path, Access::Write)?;
+        if recursive {
+            fs::create_dir_all(&validated_path)
+        } else {
+            fs::create_dir(&validated_path)
+        }
+        .map_err(|e| anyhow!("Failed to create directory: {}", e))?;
+
+        info!("Created directory: {}", path);
+        self.entry_info(&validated_path)
+    }
+
+    /// 重命名或移动，作用于条目本身（符号链接不跟随）
+    pub async fn rename(&self, from: &str, to: &str, overwrite: bool) -> Result<FileInfo> {
+        let source = self.policy.read().unwrap().check_entry(Path::new(from), Access::Write)?;
+        let dest = self.policy.read().unwrap().check_entry(Path::new(to), Access::Write)?;
+        if fs::symlink_metadata(&source).is_err() {
+            return Err(anyhow!("File does not exist: {}", from));
+        }
+        if !overwrite && fs::symlink_metadata(&dest).is_ok() {
+            return Err(anyhow!("Destination already exists: {}", to));
+        }
+
+        if let Err(e) = fs::rename(&source, &dest) {
+            if !is_cross_device(&e) {
+                return Err(anyhow!("Failed to rename: {}", e));
+            }
+            // 跨文件系统时退化为复制后删除
+            self.copy_tree(&source, &dest)?;
+            if source.is_dir() {
+                fs::remove_dir_all(&source)
+            } else {
+                fs::remove_file(&source)
+            }
+            .map_err(|e| anyhow!("Failed to remove source after move: {}", e))?;
+        }
+
+        info!("Renamed {} to {}", from, to);
+        self.entry_info(&dest)
+    }
+
+    /// 复制文件或目录，目录内的符号链接被跳过
+    pub async fn copy(&self, from: &str, to: &str, overwrite: bool) -> Result<ChangeSummary> {
+        let source = self.validate_path(from, Access::Read)?;
+        let dest = self.validate_path(to, Access::Write)?;
+        if !source.exists() {
+            return Err(anyhow!("File does not exist: {}", from));
+        }
+        if !overwrite && fs::symlink_metadata(&dest).is_ok() {
+            return Err(anyhow!("Destination already exists: {}", to));
+        }
+        if dest.starts_with(&source) {
+            return Err(anyhow!("Cannot copy a directory into itself: {}", to));
+        }
+
+        let (changed, skipped) = self.copy_tree(&source, &dest)?;
+        info!("Copied {} to {} ({} files)", from, to, changed);
+        Ok(ChangeSummary {
+            info: self.entry_info(&dest)?,
+            changed,
+            skipped,
         })
     }
+
+    /// 创建符号链接，目标须可读
+    pub async fn symlink(&self, target: &str, link: &str) -> Result<FileInfo> {
+        let link_path = self.policy.read().unwrap().check_entry(Path::new(link), Access::Write)?;
+        // 相对目标按链接所在目录解析后检查，但保留原样写入链接
+        let resolved_target = match link_path.parent() {
+            Some(parent) => parent.join(target),
+            None => PathBuf::from(target),
+        };
+        self.policy.read().unwrap().check(&resolved_target, Access::Read)?;
+        if fs::symlink_metadata(&link_path).is_ok() {
+            return Err(anyhow!("Destination already exists: {}", link));
+        }
+
+        create_symlink(Path::new(target), &link_path)?;
+        info!("Created symlink {} -> {}", link, target);
+        self.entry_info(&link_path)
+    }
+
+    /// 计算目录同步所需的块签名
+    pub async fn sync_signatures(
+        &self,
+        root: &str,
+        block_size: Option<usize>,
+        filter: &ArchiveFilter,
+    ) -> Result<TreeSignature> {
+        let check = |path: &Path, access: Access| self.validate_path(&path.to_string_lossy(), access);
+        dirsync::signatures(Path::new(root), block_size, filter, self.max_file_size, &check)
+    }
+
+    /// 按同步清单应用增量，覆盖或删除前备份旧内容
+    pub async fn sync_apply(&self, root: &str, manifest: &SyncManifest) -> Result<SyncSummary> {
+        let check = |path: &Path, access: Access| self.validate_path(&path.to_string_lossy(), access);
+        let _guard = self.write_lock.lock().unwrap();
+        let summary = dirsync::apply(Path::new(root), manifest, self.max_file_size, &check, &|path| {
+            self.backup_existing(path)
+        })?;
+        info!(
+            "Synced {}: {} updated, {} unchanged, {} deleted",
+            root,
+            summary.updated,
+            summary.unchanged,
+            summary.deleted.len()
+        );
+        Ok(summary)
+    }
+
+    /// 对 `root`（及递归时其下全部条目）执行修改，返回（修改数，跳过数）
+    fn apply_tree(&self, root: &Path, recursive: bool, apply: &dyn Fn(&Path) -> Result<()>) -> Result<(usize, usize)> {
+        apply(root)?;
+        let (mut changed, mut skipped) = (1, 0);
+        if !recursive || !root.is_dir() {
+            return Ok((changed, skipped));
+        }
+
+        let mut pending = vec![root.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            for entry in fs::read_dir(&dir)?.filter_map(|entry| entry.ok()) {
+                let path = entry.path();
+                let file_type = entry.file_type()?;
+                // 符号链接的 chmod/chown 会作用到目标，一律跳过
+                if file_type.is_symlink() || self.policy.read().unwrap().check_entry(&path, Access::Write).is_err() {
+                    skipped += 1;
+                    continue;
+                }
+                apply(&path)?;
+                changed += 1;
+                if file_type.is_dir() {
+                    pending.push(path);
+                }
+            }
+        }
+        Ok((changed, skipped))
+    }
+
+    /// 复制 `source` 到 `dest`，返回（复制的文件数，跳过数）
+    fn copy_tree(&self, source: &Path, dest: &Path) -> Result<(usize, usize)> {
+        if !source.is_dir() {
+            fs::copy(source, dest).map_err(|e| anyhow!("Failed to copy file: {}", e))?;
+            return Ok((1, 0));
+        }
+
+        let (mut copied, mut skipped) = (0, 0);
+        fs::create_dir_all(dest).map_err(|e| anyhow!("Failed to create directory: {}", e))?;
+        for entry in fs::read_dir(source)?.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let target = dest.join(entry.file_name());
+            let file_type = entry.file_type()?;
+            let policy = self.policy.read().unwrap();
+            let allowed = policy.check_entry(&path, Access::Read).is_ok() && policy.check_entry(&target, Access::Write).is_ok();
+            drop(policy);
+            if file_type.is_symlink() || !allowed {
+                skipped += 1;
+                continue;
+            }
+            let (c, s) = self.copy_tree(&path, &target)?;
+            copied += c;
+            skipped += s;
+        }
+        Ok((copied, skipped))
+    }
+}
+
+/// 解析八进制权限字符串，如 `755`、`0644`、`0o4755`
+pub fn parse_mode(mode: &str) -> Result<u32> {
+    let digits = mode.trim().trim_start_matches("0o");
+    let value = u32::from_str_radix(digits, 8).map_err(|_| anyhow!("Invalid file mode: {}", mode))?;
+    if value > 0o7777 {
+        return Err(anyhow!("Invalid file mode: {}", mode));
+    }
+    Ok(value)
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).map_err(|e| anyhow!("Failed to change mode: {}", e))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Err(anyhow!("Changing file mode is not supported on this platform"))
+}
+
+#[cfg(unix)]
+fn set_owner(path: &Path, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+    std::os::unix::fs::chown(path, uid, gid).map_err(|e| anyhow!("Failed to change owner: {}", e))
+}
+
+#[cfg(not(unix))]
+fn set_owner(_path: &Path, _uid: Option<u32>, _gid: Option<u32>) -> Result<()> {
+    Err(anyhow!("Changing file owner is not supported on this platform"))
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(target, link).map_err(|e| anyhow!("Failed to create symlink: {}", e))
+}
+
+#[cfg(not(unix))]
+fn create_symlink(_target: &Path, _link: &Path) -> Result<()> {
+    Err(anyhow!("Creating symlinks is not supported on this platform"))
+}
+
+fn is_cross_device(e: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    return e.raw_os_error() == Some(libc::EXDEV);
+    #[cfg(not(unix))]
+    return e.kind() == std::io::ErrorKind::CrossesDevices;
+}
+
+/// 用户与组名称查询，按 ID 缓存
+#[derive(Default)]
+struct IdNames {
+    users: HashMap<u32, Option<String>>,
+    groups: HashMap<u32, Option<String>>,
+}
+
+#[cfg(unix)]
+impl IdNames {
+    fn user(&mut self, uid: u32) -> Option<String> {
+        self.users
+            .entry(uid)
+            .or_insert_with(|| {
+                lookup(
+                    |pwd: &mut libc::passwd, buf, result| unsafe { libc::getpwuid_r(uid, pwd, buf.as_mut_ptr(), buf.len(), result) },
+                    |pwd| unsafe { cstr(pwd.pw_name) },
+                )
+            })
+            .clone()
+    }
+
+    fn group(&mut self, gid: u32) -> Option<String> {
+        self.groups
+            .entry(gid)
+            .or_insert_with(|| {
+                lookup(
+                    |grp: &mut libc::group, buf, result| unsafe { libc::getgrgid_r(gid, grp, buf.as_mut_ptr(), buf.len(), result) },
+                    |grp| unsafe { cstr(grp.gr_name) },
+                )
+            })
+            .clone()
+    }
+
+    fn user_id(name: &str) -> Option<u32> {
+        if let Ok(uid) = name.parse() {
+            return Some(uid);
+        }
+        let name = CString::new(name).ok()?;
+        lookup(
+            |pwd: &mut libc::passwd, buf, result| unsafe { libc::getpwnam_r(name.as_ptr(), pwd, buf.as_mut_ptr(), buf.len(), result) },
+            |pwd| pwd.pw_uid,
+        )
+    }
+
+    fn group_id(name: &str) -> Option<u32> {
+        if let Ok(gid) = name.parse() {
+            return Some(gid);
+        }
+        let name = CString::new(name).ok()?;
+        lookup(
+            |grp: &mut libc::group, buf, result| unsafe { libc::getgrnam_r(name.as_ptr(), grp, buf.as_mut_ptr(), buf.len(), result) },
+            |grp| grp.gr_gid,
+        )
+    }
+}
+
+/// # Safety
+/// `ptr` 须为有效的以 NUL 结尾的字符串
+#[cfg(unix)]
+unsafe fn cstr(ptr: *const libc::c_char) -> String {
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+#[cfg(not(unix))]
+impl IdNames {
+    fn user(&mut self, _uid: u32) -> Option<String> {
+        None
+    }
+
+    fn group(&mut self, _gid: u32) -> Option<String> {
+        None
+    }
+
+    fn user_id(name: &str) -> Option<u32> {
+        name.parse().ok()
+    }
+
+    fn group_id(name: &str) -> Option<u32> {
+        name.parse().ok()
+    }
+}
+
+/// 调用 `get*_r` 系列函数，缓冲区不足时扩大重试
+///
+/// 结果中的字符串字段指向缓冲区，`map` 在缓冲区释放前取出所需字段。
+#[cfg(unix)]
+fn lookup<T, R>(
+    call: impl Fn(&mut T, &mut [libc::c_char], *mut *mut T) -> libc::c_int,
+    map: impl FnOnce(&T) -> R,
+) -> Option<R> {
+    let mut size = 1024;
+    loop {
+        let mut entry: T = unsafe { std::mem::zeroed() };
+        let mut buf = vec![0 as libc::c_char; size];
+        let mut result = std::ptr::null_mut();
+        match call(&mut entry, &mut buf, &mut result) {
+            0 if !result.is_null() => return Some(map(&entry)),
+            0 => return None,
+            libc::ERANGE if size < 1 << 20 => size *= 4,
+            _ => return None,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::path_policy::{AccessMode, PathRule};
+    use crate::core::test_support::file_manager_for;
     use std::fs;
     use tempfile::{tempdir, NamedTempFile};
 
@@ -304,38 +905,51 @@ mod tests {
     async fn test_file_manager_creation() {
         let config = FileManagerConfig::default();
         let file_manager = FileManager::new(config);
-        assert!(!file_manager.allowed_paths.is_empty());
         assert!(file_manager.max_file_size > 0);
+        assert!(file_manager.validate_path("/usr/bin/env", Access::Read).is_err());
     }
 
     #[tokio::test]
     async fn test_path_validation() {
         let temp_dir = tempdir().unwrap();
         let config = FileManagerConfig {
-            allowed_paths: vec![temp_dir.path().to_path_buf()],
+            policy: PathPolicy::new(&[
+                PathRule::new(format!("{}/**", temp_dir.path().display()), AccessMode::ReadWrite),
+                PathRule::new("**/secret*", AccessMode::Deny),
+            ]),
             max_file_size: 1024,
-            forbidden_patterns: vec!["secret".to_string()],
         };
         let file_manager = FileManager::new(config);
 
         // 测试有效路径
         let valid_path = temp_dir.path().join("test.txt");
-        let result = file_manager.validate_path(&valid_path.to_string_lossy());
+        let result = file_manager.validate_path(&valid_path.to_string_lossy(), Access::Write);
         assert!(result.is_ok());
 
         // 测试禁止的路径模式
         let forbidden_path = temp_dir.path().join("secret.txt");
-        let result = file_manager.validate_path(&forbidden_path.to_string_lossy());
+        let result = file_manager.validate_path(&forbidden_path.to_string_lossy(), Access::Read);
         assert!(result.is_err());
+
+        // 策略热更新对已有的克隆同样生效
+        let clone = file_manager.clone();
+        file_manager.set_policy(PathPolicy::new(&[PathRule::new(
+            format!("{}/**", temp_dir.path().display()),
+            AccessMode::ReadOnly,
+        )]));
+        assert!(clone.validate_path(&valid_path.to_string_lossy(), Access::Read).is_ok());
+        assert!(clone.validate_path(&valid_path.to_string_lossy(), Access::Write).is_err());
     }
 
     #[tokio::test]
     async fn test_file_operations() {
         let temp_dir = tempdir().unwrap();
         let config = FileManagerConfig {
-            allowed_paths: vec![temp_dir.path().to_path_buf()],
+            policy: PathPolicy::new(&[PathRule::new(
+                format!("{}/**", temp_dir.path().display()),
+                AccessMode::ReadWrite,
+            )]),
             max_file_size: 1024,
-            forbidden_patterns: vec![],
         };
         let file_manager = FileManager::new(config);
 
@@ -358,7 +972,7 @@ mod tests {
         let new_checksum = file_manager.calculate_checksum(new_content);
 
         file_manager
-            .write_file(&new_file.to_string_lossy(), new_content, &new_checksum)
+            .write_file(&new_file.to_string_lossy(), new_content, &new_checksum, None)
             .await
             .unwrap();
 
@@ -367,20 +981,22 @@ mod tests {
         assert_eq!(written_content, new_content);
 
         // 测试目录列表
-        let files = file_manager
-            .list_files(&temp_dir.path().to_string_lossy())
+        let listing = file_manager
+            .list_files_page(&temp_dir.path().to_string_lossy(), &ListOptions::default())
             .await
             .unwrap();
-        assert_eq!(files.len(), 2);
+        assert_eq!(listing.files.len(), 2);
     }
 
     #[tokio::test]
     async fn test_file_size_limit() {
         let temp_dir = tempdir().unwrap();
         let config = FileManagerConfig {
-            allowed_paths: vec![temp_dir.path().to_path_buf()],
+            policy: PathPolicy::new(&[PathRule::new(
+                format!("{}/**", temp_dir.path().display()),
+                AccessMode::ReadWrite,
+            )]),
             max_file_size: 10, // 很小的限制
-            forbidden_patterns: vec![],
         };
         let file_manager = FileManager::new(config);
 
@@ -390,8 +1006,162 @@ mod tests {
         let test_file = temp_dir.path().join("large.txt");
 
         let result = file_manager
-            .write_file(&test_file.to_string_lossy(), &large_content, &checksum)
+            .write_file(&test_file.to_string_lossy(), &large_content, &checksum, None)
             .await;
         assert!(result.is_err());
     }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_metadata_operations() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let file_manager = file_manager_for(&root, AccessMode::ReadWrite, &["**/secret*"], 1024);
+        let p = |name: &str| root.join(name).to_string_lossy().to_string();
+
+        file_manager.create_dir(&p("conf/app"), true).await.unwrap();
+        assert!(file_manager.create_dir(&p("conf/app"), false).await.is_err());
+        fs::write(root.join("conf/app/app.ini"), b"x=1").unwrap();
+        fs::write(root.join("conf/secret.key"), b"k").unwrap();
+
+        let summary = file_manager.chmod(&p("conf"), parse_mode("0750").unwrap(), true).await.unwrap();
+        assert_eq!((summary.changed, summary.skipped), (3, 1));
+        assert_eq!(fs::metadata(root.join("conf/app/app.ini")).unwrap().permissions().mode() & 0o777, 0o750);
+        assert_eq!(fs::metadata(root.join("conf/secret.key")).unwrap().permissions().mode() & 0o777, 0o644);
+        assert!(parse_mode("0999").is_err());
+
+        // 属主改为自身（数字 ID），无需特权
+        let uid = fs::metadata(&root).unwrap().uid().to_string();
+        let summary = file_manager.chown(&p("conf/app/app.ini"), Some(&uid), None, false).await.unwrap();
+        assert_eq!(summary.changed, 1);
+        assert!(file_manager.chown(&p("conf"), None, None, false).await.is_err());
+
+        let copied = file_manager.copy(&p("conf"), &p("conf.bak"), false).await.unwrap();
+        assert_eq!((copied.changed, copied.skipped), (1, 1));
+        assert!(root.join("conf.bak/app/app.ini").exists());
+        assert!(file_manager.copy(&p("conf"), &p("conf.bak"), false).await.is_err());
+        assert!(file_manager.copy(&p("conf"), &p("conf/nested"), true).await.is_err());
+
+        let link = file_manager.symlink("app/app.ini", &p("conf/current")).await.unwrap();
+        assert!(link.is_symlink);
+        assert_eq!(link.symlink_target.as_deref(), Some("app/app.ini"));
+        assert!(file_manager.symlink("/usr/bin/env", &p("conf/env")).await.is_err());
+
+        // 重命名作用于链接本身，而非其目标
+        let renamed = file_manager.rename(&p("conf/current"), &p("conf/active"), false).await.unwrap();
+        assert!(renamed.is_symlink);
+        assert!(root.join("conf/app/app.ini").exists());
+
+        let files = file_manager.list_files_page(&p("conf"), &ListOptions::default()).await.unwrap().files;
+        let active = files.iter().find(|f| f.path.ends_with("active")).unwrap();
+        assert!(active.is_symlink && active.inode.is_some() && active.nlink == Some(1));
+        let app = files.iter().find(|f| f.path.ends_with("app")).unwrap();
+        assert_eq!(app.mode.unwrap() & 0o777, 0o750);
+        assert!(app.owner.is_some() || app.uid.is_some());
+
+        // 删除指向目录的链接只删除链接本身
+        std::os::unix::fs::symlink(root.join("conf/app"), root.join("conf/app-link")).unwrap();
+        file_manager.delete_file(&p("conf/app-link")).await.unwrap();
+        assert!(fs::symlink_metadata(root.join("conf/app-link")).is_err());
+        assert!(root.join("conf/app/app.ini").exists());
+    }
+
+    #[tokio::test]
+    async fn test_overwrite_backup_and_restore() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let file_manager = file_manager_for(&root, AccessMode::ReadWrite, &[], 1024)
+            .with_backup_store(Arc::new(BackupStore::new(root.join("backups"))));
+        let path = root.join("app.conf").to_string_lossy().to_string();
+
+        for content in [&b"port=80"[..], &b"port=8080"[..]] {
+            let checksum = file_manager.calculate_checksum(content);
+            file_manager.write_file(&path, content, &checksum, None).await.unwrap();
+        }
+
+        // 首次写入前文件不存在，只有第一个版本被备份
+        let versions = file_manager.versions(&path).await.unwrap();
+        assert_eq!(versions.len(), 1);
+        file_manager.restore(&path, &versions[0].checksum).await.unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"port=80");
+        assert_eq!(file_manager.versions(&path).await.unwrap().len(), 2);
+        // 没有遗留临时文件
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_etag_checked_write_and_patch() {
+        let temp_dir = tempdir().unwrap();
+        let file_manager = file_manager_for(temp_dir.path(), AccessMode::ReadWrite, &[], 1024);
+        let path = temp_dir.path().join("app.conf").to_string_lossy().to_string();
+        fs::write(&path, b"port=80\nhost=a\n").unwrap();
+        let (_, etag) = file_manager.read_file(&path).await.unwrap();
+
+        // 期间文件被他人修改，按旧 etag 写入冲突并返回当前哈希
+        fs::write(&path, b"port=81\nhost=a\n").unwrap();
+        let content = b"port=90\n";
+        let err = file_manager
+            .write_file(&path, content, &file_manager.calculate_checksum(content), Some(&etag))
+            .await
+            .unwrap_err();
+        let conflict = err.downcast_ref::<EditConflict>().unwrap();
+        let current = conflict.current.clone().unwrap();
+        assert_eq!(current, file_manager.calculate_checksum(b"port=81\nhost=a\n"));
+        assert_eq!(fs::read(&path).unwrap(), b"port=81\nhost=a\n");
+
+        let edit = LineEdit {
+            start_line: 2,
+            end_line: 2,
+            lines: vec!["host=b".to_string()],
+        };
+        assert!(file_manager.patch_file(&path, &[edit.clone()], &etag).await.is_err());
+        let patched = file_manager.patch_file(&path, &[edit], &current).await.unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"port=81\nhost=b\n");
+        assert_eq!(patched.etag, file_manager.calculate_checksum(b"port=81\nhost=b\n"));
+        assert_eq!(patched.lines, 2);
+
+        // 要求文件存在时，不存在的文件也视为冲突
+        let missing = temp_dir.path().join("missing.conf").to_string_lossy().to_string();
+        let err = file_manager
+            .write_file(&missing, content, &file_manager.calculate_checksum(content), Some(&etag))
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<EditConflict>().unwrap().current.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_files_paging() {
+        let temp_dir = tempdir().unwrap();
+        let file_manager = file_manager_for(temp_dir.path(), AccessMode::ReadWrite, &["**/secret*"], 1024);
+        for (name, size) in [("a.log", 3), ("b.log", 1), ("c.log", 2), ("secret.log", 4)] {
+            fs::write(temp_dir.path().join(name), vec![b'x'; size]).unwrap();
+        }
+        let path = temp_dir.path().to_string_lossy().to_string();
+
+        let options = ListOptions {
+            sort: SortKey::Size,
+            descending: true,
+            offset: 1,
+            limit: Some(2),
+            include_checksums: true,
+        };
+        let page = file_manager.list_files_page(&path, &options).await.unwrap();
+        let names: Vec<&str> = page.files.iter().map(|f| f.path.rsplit('/').next().unwrap()).collect();
+        assert_eq!(names, vec!["a.log", "c.log"]);
+        assert_eq!((page.total, page.has_more), (4, true));
+        assert!(page.files.iter().all(|f| f.checksum.is_some()));
+
+        // 默认不计算校验和，被策略拒绝的文件也不计算
+        let all = file_manager.list_files_page(&path, &ListOptions::default()).await.unwrap();
+        assert!(all.files.iter().all(|f| f.checksum.is_none()));
+        let options = ListOptions {
+            include_checksums: true,
+            ..Default::default()
+        };
+        let page = file_manager.list_files_page(&path, &options).await.unwrap();
+        assert!(!page.has_more);
+        assert!(page.files[3].checksum.is_none());
+    }
 }
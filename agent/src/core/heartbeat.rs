@@ -11,6 +11,7 @@ use crate::core::protocol::{
     HeartbeatRequest, HeartbeatResponse, SystemInfo, TaskReport,
     TaskItem, TaskType, TaskState,
 };
+use crate::core::path_policy::PathPolicy;
 use crate::core::state::StateManager;
 use crate::transport::HttpClient;
 
@@ -24,6 +25,15 @@ pub struct HeartbeatClient {
     retry_delay: Duration,
 }
 
+/// 心跳下发任务的处理方
+#[derive(Clone)]
+pub struct TaskHandlers {
+    pub task_manager: Arc<crate::core::task_manager::TaskManager>,
+    pub cmd_executor: Arc<crate::core::cmd_executor::CommandExecutor>,
+    pub task_handler: Arc<crate::task_handler::TaskHandler>,
+    pub file_task_handler: Arc<crate::file_tasks::FileTaskHandler>,
+}
+
 /// 心跳客户端配置
 #[derive(Debug, Clone)]
 pub struct HeartbeatConfig {
@@ -190,10 +200,14 @@ impl HeartbeatClient {
         crypto_manager: &CryptoManager,
         state_manager: &StateManager,
         config_manager: &Arc<RwLock<ConfigManager>>,
-        task_manager: &Arc<crate::core::task_manager::TaskManager>,
-        cmd_executor: &Arc<crate::core::cmd_executor::CommandExecutor>,
-        task_handler: &Arc<crate::task_handler::TaskHandler>,
+        handlers: &TaskHandlers,
     ) -> Result<()> {
+        let TaskHandlers {
+            task_manager,
+            cmd_executor,
+            task_handler,
+            file_task_handler,
+        } = handlers;
         let (mut interval_duration, mut server_url) = {
             let cm = config_manager.read().await;
             (
@@ -237,8 +251,15 @@ impl HeartbeatClient {
             // 生成待上报的 reports（从 TaskManager）
             let reports_from_manager = task_manager.generate_reports().await;
             
-            // 收集终端输出增量
-            let terminal_reports = task_handler.collect_output_reports();
+            // 收集终端输出增量，回收过期会话并上报已退出的会话，以及隧道回传数据与搜索结果
+            let mut terminal_reports = task_handler.collect_output_reports();
+            terminal_reports.extend(task_handler.collect_expired_reports().await);
+            terminal_reports.extend(task_handler.collect_exited_reports());
+            terminal_reports.extend(task_handler.collect_tunnel_reports());
+            terminal_reports.extend(file_task_handler.collect_search_reports());
+
+            // 清理长时间未完成的分块上传
+            file_task_handler.reap_stale_transfers();
             
             // 合并待上报的 reports
             let mut all_reports = pending_reports.clone();
@@ -248,11 +269,16 @@ impl HeartbeatClient {
             for tr in terminal_reports {
                 all_reports.push(TaskReport {
                     task_id: tr.task_id,
-                    state: if tr.status == "completed" { TaskState::Succeeded } else { TaskState::Failed },
+                    state: match tr.status.as_str() {
+                        "completed" => TaskState::Succeeded,
+                        "running" => TaskState::Running,
+                        _ => TaskState::Failed,
+                    },
                     progress: Some(100),
                     output_chunk: Some(tr.output_chunk),
                     output_cursor: Some(tr.output_cursor),
                     error: tr.result.get("error").and_then(|v| v.as_str()).map(|s| s.to_string()),
+                    result: Some(tr.result),
                 });
             }
             
@@ -274,6 +300,7 @@ impl HeartbeatClient {
                         task_manager.confirm_reports_sent(reports).await;
                         pending_reports.clear();
                     }
+                    file_task_handler.confirm_search_reports();
 
                     if let Err(e) = state_manager.update_heartbeat().await {
                          error!("Failed to update local heartbeat state: {}", e);
@@ -287,7 +314,7 @@ impl HeartbeatClient {
                         match task_manager.receive_task(&task).await {
                             Ok(true) => {
                                 // 任务被接受，开始处理
-                                let report = self.process_task(&task, state_manager, config_manager, task_manager, cmd_executor, task_handler).await;
+                                let report = self.process_task(&task, state_manager, config_manager, handlers).await;
                                 pending_reports.push(report);
                             }
                             Ok(false) => {
@@ -353,15 +380,22 @@ impl HeartbeatClient {
         task: &TaskItem,
         _state_manager: &StateManager,
         config_manager: &Arc<RwLock<ConfigManager>>,
-        task_manager: &Arc<crate::core::task_manager::TaskManager>,
-        cmd_executor: &Arc<crate::core::cmd_executor::CommandExecutor>,
-        task_handler: &Arc<crate::task_handler::TaskHandler>,
+        handlers: &TaskHandlers,
     ) -> TaskReport {
+        let TaskHandlers {
+            task_manager,
+            cmd_executor,
+            task_handler,
+            file_task_handler,
+        } = handlers;
         match task.task_type {
             TaskType::ConfigUpdate => {
                 if let Some(config_content) = task.payload.get("config") {
-                     match config_manager.write().await.update_from_json(&config_content.to_string()) {
+                     let mut cm = config_manager.write().await;
+                     match cm.update_from_json(&config_content.to_string()) {
                          Ok(_) => {
+                             // 路径访问策略随配置热更新
+                             file_task_handler.update_policy(PathPolicy::from_section(&cm.config().file_operations));
                              return TaskReport {
                                  task_id: task.task_id.clone(),
                                  state: TaskState::Succeeded,
@@ -369,6 +403,7 @@ impl HeartbeatClient {
                                  output_chunk: Some("Config updated".to_string()),
                                  output_cursor: None,
                                  error: None,
+                                 result: None,
                              };
                          }
                          Err(e) => {
@@ -379,6 +414,7 @@ impl HeartbeatClient {
                                  output_chunk: None,
                                  output_cursor: None,
                                  error: Some(e.to_string()),
+                                 result: None,
                              };
                          }
                      }
@@ -390,6 +426,7 @@ impl HeartbeatClient {
                      output_chunk: None,
                      output_cursor: None,
                      error: Some("Missing config payload".to_string()),
+                     result: None,
                 }
             }
             TaskType::CmdExec => {
@@ -421,6 +458,7 @@ impl HeartbeatClient {
                         output_chunk: Some("Command queued for execution".to_string()),
                         output_cursor: None,
                         error: None,
+                        result: None,
                     }
                 } else {
                     TaskReport {
@@ -430,136 +468,115 @@ impl HeartbeatClient {
                         output_chunk: None,
                         output_cursor: None,
                         error: Some("Missing cmd payload".to_string()),
+                        result: None,
                     }
                 }
             }
-            TaskType::TerminalOpen => {
-                // 解析 payload
-                match serde_json::from_value::<crate::task_handler::SessionOpenPayload>(task.payload.clone()) {
-                    Ok(payload) => {
-                        let task_id = task.task_id.clone();
-                        let handler = task_handler.clone();
-                        
-                        // 同步处理（终端创建很快）
-                        let report = handler.handle_task(crate::task_handler::Task::SessionOpen {
-                            task_id,
-                            revision: task.revision as u32,
-                            payload,
-                        });
-                        
-                        // 转换 TaskReport 格式
-                        TaskReport {
-                            task_id: report.task_id,
-                            state: if report.status == "completed" { TaskState::Succeeded } else { TaskState::Failed },
-                            progress: Some(100),
-                            output_chunk: Some(report.output_chunk),
-                            output_cursor: Some(report.output_cursor),
-                            error: report.result.get("error").and_then(|v| v.as_str()).map(|s| s.to_string()),
-                        }
-                    }
-                    Err(e) => TaskReport {
+            TaskType::FileTail => {
+                let started = serde_json::from_value::<crate::file_tasks::FileTailPayload>(task.payload.clone())
+                    .map_err(anyhow::Error::from)
+                    .and_then(|payload| file_task_handler.start_tail(task.task_id.clone(), payload, task_manager.clone()));
+                match started {
+                    // 新增的行由 TaskManager 作为任务输出增量上报
+                    Ok(()) => TaskReport {
                         task_id: task.task_id.clone(),
-                        state: TaskState::Failed,
+                        state: TaskState::Received,
                         progress: None,
                         output_chunk: None,
                         output_cursor: None,
-                        error: Some(format!("Invalid payload: {}", e)),
-                    }
-                }
-            }
-            TaskType::TerminalInput => {
-                match serde_json::from_value::<crate::task_handler::SessionInputPayload>(task.payload.clone()) {
-                    Ok(payload) => {
-                        let task_id = task.task_id.clone();
-                        let handler = task_handler.clone();
-                        
-                        let report = handler.handle_task(crate::task_handler::Task::SessionInput {
-                            task_id,
-                            revision: task.revision as u32,
-                            payload,
-                        });
-                        
+                        error: None,
+                        result: None,
+                    },
+                    Err(e) => {
+                        let _ = task_manager.set_task_error(&task.task_id, e.to_string()).await;
                         TaskReport {
-                            task_id: report.task_id,
-                            state: if report.status == "completed" { TaskState::Succeeded } else { TaskState::Failed },
-                            progress: Some(100),
-                            output_chunk: Some(report.output_chunk),
-                            output_cursor: Some(report.output_cursor),
-                            error: report.result.get("error").and_then(|v| v.as_str()).map(|s| s.to_string()),
+                            task_id: task.task_id.clone(),
+                            state: TaskState::Failed,
+                            progress: None,
+                            output_chunk: None,
+                            output_cursor: None,
+                            error: Some(e.to_string()),
+                            result: None,
                         }
                     }
-                    Err(e) => TaskReport {
-                        task_id: task.task_id.clone(),
-                        state: TaskState::Failed,
-                        progress: None,
-                        output_chunk: None,
-                        output_cursor: None,
-                        error: Some(format!("Invalid payload: {}", e)),
-                    }
                 }
             }
-            TaskType::TerminalResize => {
-                match serde_json::from_value::<crate::task_handler::SessionResizePayload>(task.payload.clone()) {
-                    Ok(payload) => {
-                        let task_id = task.task_id.clone();
-                        let handler = task_handler.clone();
-                        
-                        let report = handler.handle_task(crate::task_handler::Task::SessionResize {
-                            task_id,
-                            revision: task.revision as u32,
-                            payload,
-                        });
-                        
-                        TaskReport {
-                            task_id: report.task_id,
-                            state: if report.status == "completed" { TaskState::Succeeded } else { TaskState::Failed },
-                            progress: Some(100),
-                            output_chunk: Some(report.output_chunk),
-                            output_cursor: Some(report.output_cursor),
-                            error: report.result.get("error").and_then(|v| v.as_str()).map(|s| s.to_string()),
-                        }
-                    }
-                    Err(e) => TaskReport {
-                        task_id: task.task_id.clone(),
-                        state: TaskState::Failed,
-                        progress: None,
-                        output_chunk: None,
-                        output_cursor: None,
-                        error: Some(format!("Invalid payload: {}", e)),
+            TaskType::TerminalOpen
+            | TaskType::TerminalInput
+            | TaskType::TerminalResize
+            | TaskType::TerminalClose
+            | TaskType::TerminalSnapshot
+            | TaskType::TerminalAttach
+            | TaskType::TerminalDetach
+            | TaskType::TunnelOpen
+            | TaskType::TunnelData
+            | TaskType::TunnelClose
+            | TaskType::SocksConnect => match terminal_task(task) {
+                Ok(terminal_task) => {
+                    // 关闭会话要等待子进程退出，隧道拨号与写入也可能阻塞，统一放到阻塞线程池执行
+                    let report = task_handler.handle_task_blocking(terminal_task).await;
+
+                    TaskReport {
+                        task_id: report.task_id,
+                        state: if report.status == "completed" { TaskState::Succeeded } else { TaskState::Failed },
+                        progress: Some(100),
+                        output_chunk: Some(report.output_chunk),
+                        output_cursor: Some(report.output_cursor),
+                        error: report.result.get("error").and_then(|v| v.as_str()).map(|s| s.to_string()),
+                        result: Some(report.result),
                     }
                 }
-            }
-            TaskType::TerminalClose => {
-                match serde_json::from_value::<crate::task_handler::SessionClosePayload>(task.payload.clone()) {
-                    Ok(payload) => {
-                        let task_id = task.task_id.clone();
-                        let handler = task_handler.clone();
-                        
-                        let report = handler.handle_task(crate::task_handler::Task::SessionClose {
-                            task_id,
-                            revision: task.revision as u32,
-                            payload,
-                        });
-                        
-                        TaskReport {
-                            task_id: report.task_id,
-                            state: if report.status == "completed" { TaskState::Succeeded } else { TaskState::Failed },
-                            progress: Some(100),
-                            output_chunk: Some(report.output_chunk),
-                            output_cursor: Some(report.output_cursor),
-                            error: report.result.get("error").and_then(|v| v.as_str()).map(|s| s.to_string()),
-                        }
-                    }
-                    Err(e) => TaskReport {
-                        task_id: task.task_id.clone(),
-                        state: TaskState::Failed,
-                        progress: None,
+                Err(e) => invalid_payload(task, e),
+            },
+            TaskType::FileList
+            | TaskType::FileRead
+            | TaskType::FileWrite
+            | TaskType::FileDelete
+            | TaskType::FileStat
+            | TaskType::FileDownloadStart
+            | TaskType::FileDownloadChunk
+            | TaskType::FileUploadStart
+            | TaskType::FileUploadChunk
+            | TaskType::FileUploadCommit
+            | TaskType::FileUploadAbort
+            | TaskType::FileArchiveCreate
+            | TaskType::FileArchiveChunk
+            | TaskType::FileArchiveRelease
+            | TaskType::FileSearch
+            | TaskType::FileSearchCancel
+            | TaskType::FileChmod
+            | TaskType::FileChown
+            | TaskType::FileMkdir
+            | TaskType::FileRename
+            | TaskType::FileCopy
+            | TaskType::FileSymlink
+            | TaskType::FileVersions
+            | TaskType::FileRestore
+            | TaskType::FilePatch
+            | TaskType::DirSyncSignatures
+            | TaskType::DirSyncApply => match file_task(task) {
+                Ok(file_task) => {
+                    // 文件操作都是同步 I/O，放到阻塞线程池执行，避免拖住心跳
+                    let report = file_task_handler.handle_task_blocking(file_task).await;
+
+                    // 搜索在后台继续进行，后续结果沿用同一任务 ID 上报
+                    let state = match report.status.as_str() {
+                        "completed" => TaskState::Succeeded,
+                        "running" => TaskState::Running,
+                        _ => TaskState::Failed,
+                    };
+                    TaskReport {
+                        task_id: report.task_id,
+                        progress: (state != TaskState::Running).then_some(100),
+                        state,
                         output_chunk: None,
                         output_cursor: None,
-                        error: Some(format!("Invalid payload: {}", e)),
+                        error: report.result.get("error").and_then(|v| v.as_str()).map(|s| s.to_string()),
+                        result: Some(report.result),
                     }
                 }
-            }
+                Err(e) => invalid_payload(task, e),
+            },
         }
     }
 
@@ -857,9 +874,85 @@ del "%~f0"
 
 }
 
+/// 将心跳任务解析为文件任务
+fn file_task(task: &TaskItem) -> Result<crate::file_tasks::FileTask> {
+    use crate::file_tasks::FileTask;
+
+    let task_id = task.task_id.clone();
+    let revision = task.revision as u32;
+    let payload = task.payload.clone();
+    Ok(match task.task_type {
+        TaskType::FileList => FileTask::FileList { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileRead => FileTask::FileRead { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileWrite => FileTask::FileWrite { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileDelete => FileTask::FileDelete { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileStat => FileTask::FileStat { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileDownloadStart => FileTask::FileDownloadStart { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileDownloadChunk => FileTask::FileDownloadChunk { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileUploadStart => FileTask::FileUploadStart { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileUploadChunk => FileTask::FileUploadChunk { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileUploadCommit => FileTask::FileUploadCommit { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileUploadAbort => FileTask::FileUploadAbort { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileArchiveCreate => FileTask::FileArchiveCreate { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileArchiveChunk => FileTask::FileArchiveChunk { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileArchiveRelease => FileTask::FileArchiveRelease { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileSearch => FileTask::FileSearch { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileSearchCancel => FileTask::FileSearchCancel { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileChmod => FileTask::FileChmod { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileChown => FileTask::FileChown { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileMkdir => FileTask::FileMkdir { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileRename => FileTask::FileRename { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileCopy => FileTask::FileCopy { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileSymlink => FileTask::FileSymlink { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileVersions => FileTask::FileVersions { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FileRestore => FileTask::FileRestore { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::FilePatch => FileTask::FilePatch { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::DirSyncSignatures => FileTask::DirSyncSignatures { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::DirSyncApply => FileTask::DirSyncApply { task_id, revision, payload: serde_json::from_value(payload)? },
+        _ => return Err(anyhow!("Not a file task: {:?}", task.task_type)),
+    })
+}
+
+/// 将终端与隧道任务转换为 TaskHandler 任务
+fn terminal_task(task: &TaskItem) -> Result<crate::task_handler::Task> {
+    use crate::task_handler::Task;
+
+    let task_id = task.task_id.clone();
+    let revision = task.revision as u32;
+    let payload = task.payload.clone();
+    Ok(match task.task_type {
+        TaskType::TerminalOpen => Task::SessionOpen { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::TerminalInput => Task::SessionInput { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::TerminalResize => Task::SessionResize { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::TerminalClose => Task::SessionClose { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::TerminalSnapshot => Task::SessionSnapshot { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::TerminalAttach => Task::SessionAttach { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::TerminalDetach => Task::SessionDetach { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::TunnelOpen => Task::TunnelOpen { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::TunnelData => Task::TunnelData { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::TunnelClose => Task::TunnelClose { task_id, revision, payload: serde_json::from_value(payload)? },
+        TaskType::SocksConnect => Task::SocksConnect { task_id, revision, payload: serde_json::from_value(payload)? },
+        _ => return Err(anyhow!("Not a terminal or tunnel task: {:?}", task.task_type)),
+    })
+}
+
+/// payload 无法解析时的失败上报
+fn invalid_payload(task: &TaskItem, error: anyhow::Error) -> TaskReport {
+    TaskReport {
+        task_id: task.task_id.clone(),
+        state: TaskState::Failed,
+        progress: None,
+        output_chunk: None,
+        output_cursor: None,
+        error: Some(format!("Invalid payload: {}", error)),
+        result: None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::protocol::DesiredState;
     use crate::transport::TlsConfig;
     use tempfile::NamedTempFile;
 
@@ -927,4 +1020,27 @@ mod tests {
         assert!(!request.signature.is_empty());
         assert!(!request.nonce.is_empty());
     }
+
+    #[test]
+    fn test_task_conversion_rejects_other_types() {
+        let item = |task_type: TaskType, payload: serde_json::Value| TaskItem {
+            task_id: "t1".to_string(),
+            revision: 1,
+            task_type,
+            desired_state: DesiredState::Pending,
+            payload,
+        };
+
+        let list = item(TaskType::FileList, json!({ "path": "/tmp" }));
+        assert!(matches!(file_task(&list), Ok(crate::file_tasks::FileTask::FileList { .. })));
+        assert!(terminal_task(&list).is_err());
+
+        // 其他任务类型不会被当成列目录执行
+        let cmd = item(TaskType::CmdExec, json!({ "path": "/tmp" }));
+        assert!(file_task(&cmd).is_err());
+
+        let open = item(TaskType::TunnelOpen, json!({ "tunnel_id": "x", "host": "127.0.0.1", "port": 22 }));
+        assert!(matches!(terminal_task(&open), Ok(crate::task_handler::Task::TunnelOpen { .. })));
+        assert!(file_task(&open).is_err());
+    }
 }
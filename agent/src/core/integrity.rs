@@ -0,0 +1,604 @@
+// agent/src/core/integrity.rs
+// 文件完整性监控（FIM）
+//
+// 为配置的文件与目录建立 SHA-256、权限与属主基线，按计划全量重新扫描；
+// Linux 下可通过 inotify 只重新扫描发生变化的路径。新增、删除与修改
+// 以审计事件上报，附带变化前后的元数据。
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+use crate::config::IntegritySection;
+use crate::core::audit::AuditLogger;
+use crate::core::path_policy::glob_match;
+use crate::core::transfer::file_checksum;
+use crate::platform::atomic_write;
+
+/// 单个文件的基线记录
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileRecord {
+    /// 内容 SHA-256，目录、符号链接与超过大小上限的文件为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    /// 符号链接的目标
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<String>,
+}
+
+/// 变化类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Modified => "modified",
+        }
+    }
+}
+
+/// 与基线相比的一处变化
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IntegrityChange {
+    pub path: String,
+    pub kind: ChangeKind,
+    pub before: Option<FileRecord>,
+    pub after: Option<FileRecord>,
+}
+
+/// 路径 → 基线记录
+pub type Baseline = BTreeMap<String, FileRecord>;
+
+/// 比较两份基线，按路径排序返回变化
+pub fn diff(before: &Baseline, after: &Baseline) -> Vec<IntegrityChange> {
+    let mut changes = Vec::new();
+    for (path, old) in before {
+        match after.get(path) {
+            None => changes.push(IntegrityChange {
+                path: path.clone(),
+                kind: ChangeKind::Removed,
+                before: Some(old.clone()),
+                after: None,
+            }),
+            // 仅修改时间变化不视为修改
+            Some(new) if !same_content(old, new) => changes.push(IntegrityChange {
+                path: path.clone(),
+                kind: ChangeKind::Modified,
+                before: Some(old.clone()),
+                after: Some(new.clone()),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (path, new) in after {
+        if !before.contains_key(path) {
+            changes.push(IntegrityChange {
+                path: path.clone(),
+                kind: ChangeKind::Added,
+                before: None,
+                after: Some(new.clone()),
+            });
+        }
+    }
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    changes
+}
+
+fn same_content(a: &FileRecord, b: &FileRecord) -> bool {
+    a.checksum == b.checksum
+        && a.size == b.size
+        && a.mode == b.mode
+        && a.uid == b.uid
+        && a.gid == b.gid
+        && a.link_target == b.link_target
+}
+
+pub struct IntegrityMonitor {
+    paths: Vec<PathBuf>,
+    exclude: Vec<String>,
+    max_hash_size: u64,
+    scan_interval: Duration,
+    use_inotify: bool,
+    baseline_path: Option<PathBuf>,
+    baseline: Option<Baseline>,
+    /// 停止标志，置位后扫描中途放弃，不更新基线
+    stop: Arc<AtomicBool>,
+}
+
+/// 后台监控线程的句柄，drop 时通知线程停止
+pub struct IntegrityHandle {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl IntegrityHandle {
+    /// 通知监控线程停止并等待其退出
+    pub fn stop(mut self) {
+        self.signal();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    fn signal(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = &self.thread {
+            thread.thread().unpark();
+        }
+    }
+}
+
+impl Drop for IntegrityHandle {
+    fn drop(&mut self) {
+        self.signal();
+    }
+}
+
+impl IntegrityMonitor {
+    pub fn from_section(section: &IntegritySection) -> Self {
+        Self {
+            paths: section.paths.iter().map(PathBuf::from).collect(),
+            exclude: section.exclude.clone(),
+            max_hash_size: section.max_hash_size,
+            scan_interval: Duration::from_secs(section.scan_interval.max(1)),
+            use_inotify: section.use_inotify,
+            baseline_path: None,
+            baseline: None,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// 设置基线持久化文件，存在时加载为初始基线
+    pub fn with_baseline_path(mut self, path: PathBuf) -> Self {
+        match fs::read(&path).map(|data| serde_json::from_slice::<Baseline>(&data)) {
+            Ok(Ok(baseline)) => self.baseline = Some(baseline),
+            Ok(Err(e)) => warn!("Ignoring corrupt integrity baseline {:?}: {}", path, e),
+            Err(_) => {}
+        }
+        self.baseline_path = Some(path);
+        self
+    }
+
+    /// 全量扫描并与基线比较；首次扫描只建立基线，不返回变化
+    pub fn rescan(&mut self) -> Result<Vec<IntegrityChange>> {
+        let mut current = Baseline::new();
+        for path in self.paths.clone() {
+            self.scan_tree(&path, &mut current);
+        }
+        if self.stopped() {
+            return Ok(Vec::new());
+        }
+        let changes = match &self.baseline {
+            Some(baseline) => diff(baseline, &current),
+            None => {
+                info!("Integrity baseline created with {} entries", current.len());
+                Vec::new()
+            }
+        };
+        self.baseline = Some(current);
+        self.save()?;
+        Ok(changes)
+    }
+
+    /// 只重新扫描指定路径（目录包括其下全部内容）
+    pub fn rescan_paths(&mut self, paths: &[PathBuf]) -> Result<Vec<IntegrityChange>> {
+        let Some(mut baseline) = self.baseline.take() else {
+            return self.rescan();
+        };
+
+        let mut before = Baseline::new();
+        let mut after = Baseline::new();
+        for path in paths {
+            if !self.is_monitored(path) {
+                continue;
+            }
+            let key = path.to_string_lossy().to_string();
+            let prefix = format!("{}/", key.trim_end_matches('/'));
+            let stale: Vec<String> = baseline
+                .range(key.clone()..)
+                .take_while(|(k, _)| k.starts_with(&key))
+                .filter(|(k, _)| **k == key || k.starts_with(&prefix))
+                .map(|(k, _)| k.clone())
+                .collect();
+            for k in stale {
+                if let Some(record) = baseline.remove(&k) {
+                    before.insert(k, record);
+                }
+            }
+            self.scan_tree(path, &mut after);
+        }
+        if self.stopped() {
+            baseline.extend(before);
+            self.baseline = Some(baseline);
+            return Ok(Vec::new());
+        }
+
+        let changes = diff(&before, &after);
+        baseline.extend(after);
+        self.baseline = Some(baseline);
+        if !changes.is_empty() {
+            self.save()?;
+        }
+        Ok(changes)
+    }
+
+    /// 在独立线程中持续监控，变化以审计事件上报；返回的句柄用于停止监控
+    pub fn spawn(mut self, audit_logger: AuditLogger) -> IntegrityHandle {
+        let stop = Arc::clone(&self.stop);
+        let thread = std::thread::spawn(move || {
+            let report = |changes: Vec<IntegrityChange>| {
+                for change in changes {
+                    info!("Integrity change: {} {}", change.kind.as_str(), change.path);
+                    let _ = audit_logger.log_file_integrity(&change.path, change.kind.as_str(), change.before, change.after);
+                }
+            };
+            match self.rescan() {
+                Ok(changes) => report(changes),
+                Err(e) => warn!("Integrity scan failed: {}", e),
+            }
+
+            #[cfg(target_os = "linux")]
+            let mut watcher = if self.use_inotify {
+                match inotify_watch::Watcher::new(&self.paths) {
+                    Ok(watcher) => Some(watcher),
+                    Err(e) => {
+                        warn!("inotify unavailable, falling back to scheduled scans: {}", e);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            #[cfg(not(target_os = "linux"))]
+            if self.use_inotify {
+                debug!("inotify is only available on Linux, using scheduled scans");
+            }
+
+            let mut last_scan = Instant::now();
+            #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+            let mut scan_due = false;
+            loop {
+                std::thread::park_timeout(Duration::from_secs(1));
+                if self.stopped() {
+                    debug!("Integrity monitor stopped");
+                    break;
+                }
+
+                #[cfg(target_os = "linux")]
+                if let Some(watcher) = watcher.as_mut() {
+                    match watcher.poll() {
+                        Some(inotify_watch::Changed::Paths(paths)) if !paths.is_empty() => {
+                            match self.rescan_paths(&paths) {
+                                Ok(changes) => report(changes),
+                                Err(e) => warn!("Integrity rescan failed: {}", e),
+                            }
+                        }
+                        Some(inotify_watch::Changed::Overflow) => scan_due = true,
+                        _ => {}
+                    }
+                }
+
+                if scan_due || last_scan.elapsed() >= self.scan_interval {
+                    last_scan = Instant::now();
+                    scan_due = false;
+                    match self.rescan() {
+                        Ok(changes) => report(changes),
+                        Err(e) => warn!("Integrity scan failed: {}", e),
+                    }
+                }
+            }
+        });
+
+        IntegrityHandle { stop, thread: Some(thread) }
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+
+    fn is_monitored(&self, path: &Path) -> bool {
+        self.paths.iter().any(|root| path.starts_with(root)) && !self.is_excluded(path)
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy();
+        self.exclude.iter().any(|pattern| glob_match(pattern, &path))
+    }
+
+    /// 记录 `path`，若为目录则递归记录其下内容（不跟随符号链接）
+    fn scan_tree(&self, path: &Path, out: &mut Baseline) {
+        if self.stopped() || self.is_excluded(path) {
+            return;
+        }
+        let Ok(metadata) = fs::symlink_metadata(path) else {
+            return;
+        };
+        out.insert(path.to_string_lossy().to_string(), self.record(path, &metadata));
+        if metadata.is_dir() {
+            match fs::read_dir(path) {
+                Ok(entries) => {
+                    for entry in entries.flatten() {
+                        self.scan_tree(&entry.path(), out);
+                    }
+                }
+                Err(e) => debug!("Skipping unreadable directory {:?}: {}", path, e),
+            }
+        }
+    }
+
+    fn record(&self, path: &Path, metadata: &fs::Metadata) -> FileRecord {
+        let file_type = metadata.file_type();
+        let checksum = if file_type.is_file() && metadata.len() <= self.max_hash_size {
+            file_checksum(path).ok()
+        } else {
+            None
+        };
+        let link_target = if file_type.is_symlink() {
+            fs::read_link(path).ok().map(|target| target.to_string_lossy().to_string())
+        } else {
+            None
+        };
+        // 目录大小随文件系统实现变化，不作比较
+        let size = if file_type.is_dir() { 0 } else { metadata.len() };
+
+        #[cfg(unix)]
+        let (mode, uid, gid) = {
+            use std::os::unix::fs::MetadataExt;
+            (Some(metadata.mode()), Some(metadata.uid()), Some(metadata.gid()))
+        };
+        #[cfg(not(unix))]
+        let (mode, uid, gid) = (None, None, None);
+
+        FileRecord {
+            checksum,
+            size,
+            mode,
+            uid,
+            gid,
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+            link_target,
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let (Some(path), Some(baseline)) = (&self.baseline_path, &self.baseline) else {
+            return Ok(());
+        };
+        atomic_write(path, &serde_json::to_vec(baseline)?)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify_watch {
+    use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+    use std::collections::{BTreeSet, HashMap};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tracing::warn;
+
+    /// 单个监控器的 watch 数上限，超出部分仅依赖计划扫描
+    const MAX_WATCHES: usize = 8192;
+
+    pub enum Changed {
+        Paths(Vec<PathBuf>),
+        /// 事件队列溢出，需要全量扫描
+        Overflow,
+    }
+
+    pub struct Watcher {
+        inotify: Inotify,
+        watches: HashMap<WatchDescriptor, PathBuf>,
+        buffer: Vec<u8>,
+    }
+
+    impl Watcher {
+        pub fn new(paths: &[PathBuf]) -> std::io::Result<Self> {
+            let mut watcher = Self {
+                inotify: Inotify::init()?,
+                watches: HashMap::new(),
+                buffer: vec![0u8; 64 * 1024],
+            };
+            for path in paths {
+                watcher.watch_tree(path);
+            }
+            Ok(watcher)
+        }
+
+        fn mask() -> WatchMask {
+            WatchMask::MODIFY
+                | WatchMask::ATTRIB
+                | WatchMask::CLOSE_WRITE
+                | WatchMask::CREATE
+                | WatchMask::DELETE
+                | WatchMask::MOVE
+                | WatchMask::DONT_FOLLOW
+        }
+
+        fn watch_tree(&mut self, path: &Path) {
+            if self.watches.len() >= MAX_WATCHES {
+                return;
+            }
+            let Ok(metadata) = fs::symlink_metadata(path) else {
+                return;
+            };
+            // 单独配置的文件监控其本身
+            match self.inotify.watches().add(path, Self::mask()) {
+                Ok(wd) => {
+                    self.watches.insert(wd, path.to_path_buf());
+                    if self.watches.len() == MAX_WATCHES {
+                        warn!("inotify watch limit reached, remaining paths rely on scheduled scans");
+                    }
+                }
+                Err(e) => warn!("Failed to watch {:?}: {}", path, e),
+            }
+            if metadata.is_dir() {
+                if let Ok(entries) = fs::read_dir(path) {
+                    for entry in entries.flatten() {
+                        if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                            self.watch_tree(&entry.path());
+                        }
+                    }
+                }
+            }
+        }
+
+        /// 非阻塞读取已发生的事件
+        pub fn poll(&mut self) -> Option<Changed> {
+            let mut changed = BTreeSet::new();
+            let mut new_dirs = Vec::new();
+            loop {
+                let events = match self.inotify.read_events(&mut self.buffer) {
+                    Ok(events) => events,
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        warn!("Failed to read inotify events: {}", e);
+                        break;
+                    }
+                };
+                let mut any = false;
+                for event in events {
+                    any = true;
+                    if event.mask.contains(EventMask::Q_OVERFLOW) {
+                        return Some(Changed::Overflow);
+                    }
+                    let Some(base) = self.watches.get(&event.wd) else {
+                        continue;
+                    };
+                    let path = match event.name {
+                        Some(name) => base.join(name),
+                        None => base.clone(),
+                    };
+                    if event.mask.contains(EventMask::ISDIR)
+                        && event.mask.intersects(EventMask::CREATE | EventMask::MOVED_TO)
+                    {
+                        new_dirs.push(path.clone());
+                    }
+                    changed.insert(path);
+                }
+                if !any {
+                    break;
+                }
+            }
+            for dir in new_dirs {
+                self.watch_tree(&dir);
+            }
+            Some(Changed::Paths(changed.into_iter().collect()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn monitor_for(dir: &Path) -> IntegrityMonitor {
+        IntegrityMonitor::from_section(&IntegritySection {
+            enabled: true,
+            paths: vec![dir.join("etc").to_string_lossy().to_string()],
+            exclude: vec!["**/*.swp".to_string()],
+            ..Default::default()
+        })
+        .with_baseline_path(dir.join("baseline.json"))
+    }
+
+    #[test]
+    fn test_detects_added_removed_and_modified() {
+        let dir = tempdir().unwrap();
+        let etc = dir.path().join("etc");
+        fs::create_dir_all(&etc).unwrap();
+        fs::write(etc.join("hosts"), "127.0.0.1 localhost\n").unwrap();
+        fs::write(etc.join("motd"), "hello\n").unwrap();
+
+        let mut monitor = monitor_for(dir.path());
+        assert!(monitor.rescan().unwrap().is_empty());
+
+        fs::write(etc.join("hosts"), "10.0.0.1 evil\n").unwrap();
+        fs::remove_file(etc.join("motd")).unwrap();
+        fs::write(etc.join("cron"), "* * * * * root sh\n").unwrap();
+        fs::write(etc.join("edit.swp"), "tmp").unwrap();
+
+        // 基线已持久化，新的监控器从文件加载
+        let mut monitor = monitor_for(dir.path());
+        let changes = monitor.rescan().unwrap();
+        let summary: Vec<(&str, ChangeKind)> = changes
+            .iter()
+            .map(|c| (c.path.rsplit('/').next().unwrap(), c.kind))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("cron", ChangeKind::Added),
+                ("hosts", ChangeKind::Modified),
+                ("motd", ChangeKind::Removed),
+            ]
+        );
+        let hosts = &changes[1];
+        assert_ne!(hosts.before.as_ref().unwrap().checksum, hosts.after.as_ref().unwrap().checksum);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rescan_paths_detects_mode_change() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let etc = dir.path().join("etc");
+        fs::create_dir_all(etc.join("sudoers.d")).unwrap();
+        fs::write(etc.join("sudoers.d/admin"), "admin ALL=(ALL) ALL\n").unwrap();
+
+        let mut monitor = monitor_for(dir.path());
+        monitor.rescan().unwrap();
+
+        fs::set_permissions(etc.join("sudoers.d/admin"), fs::Permissions::from_mode(0o666)).unwrap();
+        fs::write(etc.join("sudoers.d/backdoor"), "x ALL=(ALL) NOPASSWD: ALL\n").unwrap();
+        let changes = monitor.rescan_paths(&[etc.join("sudoers.d")]).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].kind, ChangeKind::Modified);
+        assert_eq!(changes[0].after.as_ref().unwrap().mode.unwrap() & 0o777, 0o666);
+        assert_eq!(changes[1].kind, ChangeKind::Added);
+
+        // 监控范围之外的路径被忽略
+        assert!(monitor.rescan_paths(&[dir.path().join("other")]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_stop_ends_monitor_thread() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("etc")).unwrap();
+        let (audit, _events) = AuditLogger::new("test-device".to_string());
+
+        let handle = monitor_for(dir.path()).spawn(audit);
+        let started = Instant::now();
+        handle.stop();
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
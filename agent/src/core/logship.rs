@@ -0,0 +1,699 @@
+// agent/src/core/logship.rs
+// 日志采集与上送
+//
+// 后台按配置的 glob 跟随日志文件（处理轮转与截断），新增的行附带主机信息分批上送到服务端。
+// 读取位置只在服务端确认接收后才提交并持久化到数据目录，重启后从已确认的位置继续（至少一次）。
+// 未上送的行达到上限时暂停读取，服务端返回 429/503 时按 Retry-After 退避。
+// 设备注册完成、取得签名密钥之前只采集不上送。文件遍历与读取在阻塞线程中进行。
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, warn};
+
+use crate::config::LogShippingSection;
+use crate::core::crypto::{CryptoManager, SharedSigner};
+use crate::core::path_policy::glob_match;
+use crate::core::tail::{file_id, TailCursor};
+use crate::platform::atomic_write;
+
+/// 每个文件每次最多读取的字节数
+const READ_CHUNK: u64 = 64 * 1024;
+/// 展开 `**` 时的最大目录深度
+const MAX_GLOB_DEPTH: usize = 16;
+/// 连续上送失败时的最长退避
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// 上送批次附带的主机信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostMetadata {
+    pub hostname: String,
+    pub platform: String,
+    pub agent_version: String,
+}
+
+impl HostMetadata {
+    pub fn current() -> Self {
+        Self {
+            hostname: hostname(),
+            platform: std::env::consts::OS.to_string(),
+            agent_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+/// 一行日志
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogRecord {
+    pub path: String,
+    pub line: String,
+    /// 该行结束处在文件中的偏移
+    pub offset: u64,
+    /// 读取时间（Unix 毫秒）
+    pub read_at: u64,
+}
+
+/// 日志批量上送请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogBatchRequest {
+    pub device_id: String,
+    pub timestamp: u64,
+    pub nonce: String,
+    pub signature: String,
+    pub host: HostMetadata,
+    /// `records` 序列化为 JSON 后的 SHA-256，包含在签名数据中
+    pub records_sha256: String,
+    pub records: Vec<LogRecord>,
+}
+
+/// 尚未确认的一行，记录所属文件以便确认后提交读取位置
+#[derive(Debug, Clone)]
+struct Pending {
+    record: LogRecord,
+    file_id: (u64, u64),
+}
+
+/// 单个被跟随的文件
+struct Source {
+    path: PathBuf,
+    file: Option<File>,
+    file_id: Option<(u64, u64)>,
+    offset: u64,
+    partial: Vec<u8>,
+}
+
+impl Source {
+    fn open(path: PathBuf, resume: Option<TailCursor>, start_at_end: bool) -> Result<Self> {
+        let file = File::open(&path)?;
+        let metadata = file.metadata()?;
+        let id = file_id(&metadata);
+        let offset = match resume {
+            Some(cursor) if cursor.file_id == id && cursor.offset <= metadata.len() => cursor.offset,
+            // 已采集过但文件已轮转，从新文件开头读取
+            Some(_) => 0,
+            None if start_at_end => metadata.len(),
+            None => 0,
+        };
+        Ok(Self {
+            path,
+            file: Some(file),
+            file_id: Some(id),
+            offset,
+            partial: Vec::new(),
+        })
+    }
+
+    /// 读取最多 `max_lines` 行；返回当前文件是否已读到末尾
+    fn read(&mut self, out: &mut Vec<Pending>, max_lines: usize, max_line_length: usize) -> Result<bool> {
+        let (Some(file), Some(id)) = (&mut self.file, self.file_id) else {
+            return Ok(true);
+        };
+        if file.metadata()?.len() < self.offset {
+            debug!("{:?} was truncated", self.path);
+            self.offset = 0;
+            self.partial.clear();
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut data = Vec::new();
+        let read = file.take(READ_CHUNK).read_to_end(&mut data)?;
+
+        let path = self.path.to_string_lossy().to_string();
+        let start = self.offset;
+        let mut emitted = 0;
+        for (index, &byte) in data.iter().enumerate() {
+            if byte == b'\n' {
+                let line = std::mem::take(&mut self.partial);
+                out.push(pending(&path, id, &line, start + index as u64 + 1, max_line_length));
+                emitted += 1;
+                if emitted >= max_lines {
+                    // 受缓冲上限限制，剩余内容下次再读
+                    self.offset = start + index as u64 + 1;
+                    return Ok(false);
+                }
+            } else if self.partial.len() < max_line_length {
+                self.partial.push(byte);
+            }
+        }
+        self.offset = start + read as u64;
+        Ok((read as u64) < READ_CHUNK)
+    }
+
+    /// 路径已指向另一个文件（轮转）时切换，旧文件末尾不完整的行作为一行输出
+    fn follow_rotation(&mut self, out: &mut Vec<Pending>, max_line_length: usize) {
+        let Ok(metadata) = fs::metadata(&self.path) else {
+            return;
+        };
+        let id = file_id(&metadata);
+        if self.file_id == Some(id) {
+            return;
+        }
+        if let (Some(old_id), false) = (self.file_id, self.partial.is_empty()) {
+            let line = std::mem::take(&mut self.partial);
+            let path = self.path.to_string_lossy().to_string();
+            out.push(pending(&path, old_id, &line, self.offset, max_line_length));
+        }
+        match File::open(&self.path) {
+            Ok(file) => {
+                debug!("{:?} was rotated", self.path);
+                self.file = Some(file);
+                self.file_id = Some(id);
+                self.offset = 0;
+            }
+            Err(e) => debug!("Failed to reopen {:?}: {}", self.path, e),
+        }
+    }
+}
+
+fn pending(path: &str, file_id: (u64, u64), line: &[u8], offset: u64, max_line_length: usize) -> Pending {
+    let line = String::from_utf8_lossy(&line[..line.len().min(max_line_length)]);
+    Pending {
+        record: LogRecord {
+            path: path.to_string(),
+            line: line.strip_suffix('\r').unwrap_or(&line).to_string(),
+            offset,
+            read_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+        },
+        file_id,
+    }
+}
+
+/// 按 glob 发现文件并读取新增的行
+struct Collector {
+    patterns: Vec<String>,
+    exclude: Vec<String>,
+    start_at_end: bool,
+    max_line_length: usize,
+    sources: BTreeMap<PathBuf, Source>,
+    /// 已确认的读取位置，按路径保存
+    committed: HashMap<String, TailCursor>,
+}
+
+impl Collector {
+    fn new(section: &LogShippingSection, committed: HashMap<String, TailCursor>) -> Self {
+        Self {
+            patterns: section.paths.clone(),
+            exclude: section.exclude.clone(),
+            start_at_end: section.start_at_end,
+            max_line_length: section.max_line_length.max(1),
+            sources: BTreeMap::new(),
+            committed,
+        }
+    }
+
+    /// 重新展开 glob，加入新出现的文件；已删除且读完的文件不再跟随，其读取位置一并丢弃
+    fn rescan(&mut self) {
+        let mut found = Vec::new();
+        for pattern in &self.patterns {
+            expand_glob(pattern, &mut found);
+        }
+        for path in found {
+            let key = path.to_string_lossy().to_string();
+            if self.sources.contains_key(&path) || self.exclude.iter().any(|pattern| glob_match(pattern, &key)) {
+                continue;
+            }
+            match Source::open(path.clone(), self.committed.get(&key).copied(), self.start_at_end) {
+                Ok(source) => {
+                    info!("Collecting logs from {:?}", path);
+                    self.sources.insert(path, source);
+                }
+                Err(e) => debug!("Failed to open {:?}: {}", path, e),
+            }
+        }
+        self.sources.retain(|path, source| path.exists() || source.file.is_some());
+        let sources = &self.sources;
+        self.committed
+            .retain(|path, _| sources.contains_key(Path::new(path)) || Path::new(path).exists());
+    }
+
+    /// 读取新增的行，使 `out` 中的总行数不超过 `budget`
+    fn collect(&mut self, out: &mut Vec<Pending>, budget: usize) {
+        let mut gone = Vec::new();
+        for (path, source) in self.sources.iter_mut() {
+            let remaining = budget.saturating_sub(out.len());
+            if remaining == 0 {
+                break;
+            }
+            match source.read(out, remaining, self.max_line_length) {
+                Ok(true) => {
+                    source.follow_rotation(out, self.max_line_length);
+                    if !path.exists() {
+                        // 文件已删除且没有新文件替代
+                        gone.push(path.clone());
+                    }
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    warn!("Failed to read {:?}: {}", path, e);
+                    gone.push(path.clone());
+                }
+            }
+        }
+        for path in gone {
+            self.sources.remove(&path);
+        }
+    }
+
+    /// 服务端确认后提交读取位置
+    fn commit(&mut self, shipped: &[Pending]) {
+        for entry in shipped {
+            self.committed.insert(
+                entry.record.path.clone(),
+                TailCursor {
+                    file_id: entry.file_id,
+                    offset: entry.record.offset,
+                },
+            );
+        }
+    }
+}
+
+/// 展开 glob：从第一个含通配符的段之前的目录开始遍历，不跟随符号链接目录
+fn expand_glob(pattern: &str, found: &mut Vec<PathBuf>) {
+    let segments: Vec<&str> = pattern.split('/').collect();
+    let literal = segments
+        .iter()
+        .position(|segment| segment.contains(['*', '?']))
+        .unwrap_or(segments.len());
+    if literal == segments.len() {
+        let path = PathBuf::from(pattern);
+        if path.is_file() {
+            found.push(path);
+        }
+        return;
+    }
+    let base = match segments[..literal].join("/") {
+        base if base.is_empty() => "/".to_string(),
+        base => base,
+    };
+    let max_depth = if segments[literal..].contains(&"**") {
+        MAX_GLOB_DEPTH
+    } else {
+        segments.len() - literal
+    };
+
+    let mut pending = vec![(PathBuf::from(base), 1)];
+    while let Some((dir, depth)) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            if file_type.is_dir() {
+                if depth < max_depth {
+                    pending.push((path, depth + 1));
+                }
+            } else if glob_match(pattern, &path.to_string_lossy()) && path.is_file() {
+                found.push(path);
+            }
+        }
+    }
+    found.sort();
+}
+
+/// 日志上送器
+pub struct LogShipper {
+    section: LogShippingSection,
+    url: String,
+    host: HostMetadata,
+    signer: SharedSigner,
+    offsets_path: PathBuf,
+    http_client: Option<reqwest::Client>,
+}
+
+impl LogShipper {
+    pub fn new(
+        section: &LogShippingSection,
+        server_url: &str,
+        signer: SharedSigner,
+        data_dir: &Path,
+    ) -> Self {
+        Self {
+            section: section.clone(),
+            url: format!("{}{}", server_url.trim_end_matches('/'), section.endpoint),
+            host: HostMetadata::current(),
+            signer,
+            offsets_path: data_dir.join("log_offsets.json"),
+            http_client: reqwest::Client::builder().timeout(Duration::from_secs(30)).build().ok(),
+        }
+    }
+
+    /// 在后台开始采集与上送
+    pub fn spawn(self) {
+        tokio::spawn(async move { self.run().await });
+    }
+
+    async fn run(self) {
+        let (section, offsets_path) = (self.section.clone(), self.offsets_path.clone());
+        let Some(mut collector) = blocking(move || Collector::new(&section, load_offsets(&offsets_path))).await else {
+            return;
+        };
+        let poll_interval = Duration::from_millis(self.section.poll_interval_ms.max(100));
+        let scan_interval = Duration::from_secs(self.section.scan_interval.max(1));
+        let batch_interval = Duration::from_secs(self.section.batch_interval_secs);
+        let batch_size = self.section.batch_size.max(1);
+        let max_buffered = self.section.max_buffered_lines.max(batch_size);
+
+        let mut buffer: Vec<Pending> = Vec::new();
+        let mut last_scan: Option<Instant> = None;
+        let mut last_flush = Instant::now();
+        let mut retry_at: Option<Instant> = None;
+        let mut failures = 0u32;
+
+        loop {
+            let scan_due = match last_scan {
+                Some(at) => at.elapsed() >= scan_interval,
+                None => true,
+            };
+            let collected = blocking(move || {
+                if scan_due {
+                    collector.rescan();
+                }
+                // 缓冲已满时不再读取，读取位置保持不变（背压）
+                collector.collect(&mut buffer, max_buffered);
+                (collector, buffer)
+            })
+            .await;
+            let Some(collected) = collected else {
+                return;
+            };
+            (collector, buffer) = collected;
+            if scan_due {
+                last_scan = Some(Instant::now());
+            }
+
+            let due = buffer.len() >= batch_size || last_flush.elapsed() >= batch_interval;
+            let backing_off = retry_at.is_some_and(|at| Instant::now() < at);
+            let signer = self.signer.read().unwrap().clone();
+            if !buffer.is_empty() && due && !backing_off {
+                let Some((crypto, device_id)) = signer else {
+                    debug!("Device not enrolled yet, holding {} log lines", buffer.len());
+                    tokio::time::sleep(poll_interval).await;
+                    continue;
+                };
+                let count = buffer.len().min(batch_size);
+                match self.upload(&buffer[..count], &crypto, &device_id).await {
+                    Ok(()) => {
+                        let shipped: Vec<Pending> = buffer.drain(..count).collect();
+                        let offsets_path = self.offsets_path.clone();
+                        let committed = blocking(move || {
+                            collector.commit(&shipped);
+                            save_offsets(&offsets_path, &collector.committed);
+                            collector
+                        })
+                        .await;
+                        let Some(committed) = committed else {
+                            return;
+                        };
+                        collector = committed;
+                        last_flush = Instant::now();
+                        retry_at = None;
+                        failures = 0;
+                        // 还有积压时立即上送下一批
+                        if buffer.len() >= batch_size {
+                            continue;
+                        }
+                    }
+                    Err(UploadError { retry_after, message }) => {
+                        failures += 1;
+                        let backoff = retry_after.unwrap_or_else(|| {
+                            Duration::from_secs(self.section.retry_interval_secs)
+                                .saturating_mul(1 << failures.min(6))
+                                .min(MAX_BACKOFF)
+                        });
+                        warn!(
+                            "Failed to ship {} log lines ({} buffered), retrying in {:?}: {}",
+                            count,
+                            buffer.len(),
+                            backoff,
+                            message
+                        );
+                        retry_at = Some(Instant::now() + backoff);
+                    }
+                }
+            }
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+
+    /// 上送一批日志，失败时按配置重试
+    async fn upload(
+        &self,
+        batch: &[Pending],
+        crypto: &CryptoManager,
+        device_id: &str,
+    ) -> std::result::Result<(), UploadError> {
+        let client = self
+            .http_client
+            .as_ref()
+            .ok_or_else(|| UploadError::from(anyhow!("HTTP client not initialized")))?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let nonce = format!("{:016x}", rand::random::<u64>());
+        let records: Vec<LogRecord> = batch.iter().map(|entry| entry.record.clone()).collect();
+        let records_sha256 = records_hash(&records).map_err(UploadError::from)?;
+        let sign_data = format!(
+            "{}:{}:{}:{}:{}",
+            device_id,
+            timestamp,
+            nonce,
+            records.len(),
+            records_sha256
+        );
+        let request = LogBatchRequest {
+            device_id: device_id.to_string(),
+            timestamp,
+            signature: format!("{}:{}", timestamp, crypto.sign(sign_data.as_bytes())),
+            nonce,
+            host: self.host.clone(),
+            records_sha256,
+            records,
+        };
+
+        let max_retries = self.section.max_retries.max(1);
+        let mut last_error = UploadError::from(anyhow!("No attempts made"));
+        for attempt in 1..=max_retries {
+            match client.post(&self.url).json(&request).send().await {
+                Ok(response) if response.status().is_success() => {
+                    debug!("Shipped {} log lines", batch.len());
+                    return Ok(());
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    // 服务端繁忙：不在本轮重试，按其要求的时间退避
+                    if status.as_u16() == 429 || status.as_u16() == 503 {
+                        let retry_after = response
+                            .headers()
+                            .get(reqwest::header::RETRY_AFTER)
+                            .and_then(|value| value.to_str().ok())
+                            .and_then(|value| value.trim().parse::<u64>().ok())
+                            .map(Duration::from_secs);
+                        return Err(UploadError {
+                            retry_after: Some(retry_after.unwrap_or(Duration::from_secs(self.section.retry_interval_secs))),
+                            message: format!("server busy ({})", status),
+                        });
+                    }
+                    let body = response.text().await.unwrap_or_default();
+                    last_error = UploadError::from(anyhow!("{} - {}", status, body));
+                }
+                Err(e) => last_error = UploadError::from(anyhow!(e)),
+            }
+            warn!(
+                "Log upload failed (attempt {}/{}): {}",
+                attempt, max_retries, last_error.message
+            );
+            if attempt < max_retries {
+                tokio::time::sleep(Duration::from_secs(self.section.retry_interval_secs)).await;
+            }
+        }
+        Err(last_error)
+    }
+}
+
+/// 在阻塞线程中执行文件操作（glob 展开、读取、保存读取位置），不占用运行时线程
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Option<T> {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => Some(value),
+        Err(e) => {
+            error!("Log collector failed, log shipping stopped: {}", e);
+            None
+        }
+    }
+}
+
+/// 批次内容摘要：`records` 序列化为 JSON 后的 SHA-256
+fn records_hash(records: &[LogRecord]) -> Result<String> {
+    Ok(format!("{:x}", Sha256::digest(serde_json::to_vec(records)?)))
+}
+
+fn load_offsets(path: &Path) -> HashMap<String, TailCursor> {
+    match fs::read(path) {
+        Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+            warn!("Ignoring corrupt log offsets {:?}: {}", path, e);
+            HashMap::new()
+        }),
+        Err(_) => HashMap::new(),
+    }
+}
+
+fn save_offsets(path: &Path, offsets: &HashMap<String, TailCursor>) {
+    let result = serde_json::to_vec(offsets)
+        .map_err(anyhow::Error::from)
+        .and_then(|data| atomic_write(path, &data));
+    if let Err(e) = result {
+        warn!("Failed to persist log offsets: {}", e);
+    }
+}
+
+/// 上送失败；服务端要求退避时带上等待时间
+struct UploadError {
+    retry_after: Option<Duration>,
+    message: String,
+}
+
+impl From<anyhow::Error> for UploadError {
+    fn from(error: anyhow::Error) -> Self {
+        Self {
+            retry_after: None,
+            message: error.to_string(),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    // SAFETY: 缓冲区长度正确传入，gethostname 最多写入 len 字节
+    let ret = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if ret != 0 {
+        return String::new();
+    }
+    let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..end]).to_string()
+}
+
+#[cfg(not(unix))]
+fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_support::append;
+    use tempfile::tempdir;
+
+    fn section(pattern: String) -> LogShippingSection {
+        LogShippingSection {
+            paths: vec![pattern],
+            exclude: vec!["**/*.gz".to_string()],
+            start_at_end: false,
+            max_line_length: 8,
+            ..Default::default()
+        }
+    }
+
+    fn lines(batch: &[Pending]) -> Vec<&str> {
+        batch.iter().map(|entry| entry.record.line.as_str()).collect()
+    }
+
+    #[test]
+    fn test_expand_glob() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("nginx/old")).unwrap();
+        for name in ["app.log", "app.log.gz", "nginx/access.log", "nginx/old/x.log", "notes.txt"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let root = dir.path().to_string_lossy().to_string();
+
+        let mut found = Vec::new();
+        expand_glob(&format!("{}/*.log", root), &mut found);
+        assert_eq!(found, vec![dir.path().join("app.log")]);
+
+        let mut found = Vec::new();
+        expand_glob(&format!("{}/**/*.log", root), &mut found);
+        assert_eq!(found.len(), 3);
+    }
+
+    #[test]
+    fn test_collect_backpressure_rotation_and_resume() {
+        let dir = tempdir().unwrap();
+        let log = dir.path().join("app.log");
+        fs::write(&log, "one\ntwo\nthree\nfour\n").unwrap();
+        fs::write(dir.path().join("old.gz"), "x\n").unwrap();
+        let section = section(format!("{}/*", dir.path().display()));
+
+        let mut collector = Collector::new(&section, HashMap::new());
+        collector.rescan();
+        let mut buffer = Vec::new();
+        // 缓冲上限为 3 行，剩余内容留在文件中
+        collector.collect(&mut buffer, 3);
+        assert_eq!(lines(&buffer), ["one", "two", "three"]);
+        collector.collect(&mut buffer, 3);
+        assert_eq!(buffer.len(), 3);
+
+        // 只确认前两行，重启后从第三行继续
+        collector.commit(&buffer[..2]);
+        let mut restarted = Collector::new(&section, collector.committed.clone());
+        restarted.rescan();
+        let mut buffer = Vec::new();
+        restarted.collect(&mut buffer, 100);
+        assert_eq!(lines(&buffer), ["three", "four"]);
+
+        // 轮转：旧文件剩余内容先读完，长行被截断
+        append(&log, "five-is-long\nsi");
+        fs::rename(&log, dir.path().join("app.log.1")).unwrap();
+        fs::write(&log, "new\n").unwrap();
+        let mut buffer = Vec::new();
+        restarted.collect(&mut buffer, 100);
+        restarted.collect(&mut buffer, 100);
+        assert_eq!(lines(&buffer), ["five-is-", "si", "new"]);
+        assert_ne!(buffer[0].file_id, buffer[2].file_id);
+    }
+
+    #[test]
+    fn test_prune_offsets_and_records_hash() {
+        let dir = tempdir().unwrap();
+        let log = dir.path().join("app.log");
+        fs::write(&log, "one\n").unwrap();
+        let section = section(format!("{}/*.log", dir.path().display()));
+
+        let mut collector = Collector::new(&section, HashMap::new());
+        collector.committed.insert(
+            dir.path().join("gone.log").to_string_lossy().to_string(),
+            TailCursor {
+                file_id: (1, 1),
+                offset: 10,
+            },
+        );
+        // 已不存在的文件在扫描时丢弃读取位置
+        collector.rescan();
+        let mut buffer = Vec::new();
+        collector.collect(&mut buffer, 10);
+        collector.commit(&buffer);
+        assert_eq!(collector.committed.len(), 1);
+        fs::remove_file(&log).unwrap();
+        collector.collect(&mut Vec::new(), 10);
+        collector.rescan();
+        assert!(collector.committed.is_empty());
+
+        // 签名覆盖记录内容，任一记录被修改摘要随之变化
+        let records: Vec<LogRecord> = buffer.iter().map(|entry| entry.record.clone()).collect();
+        let mut tampered = records.clone();
+        tampered[0].line.push('!');
+        assert_ne!(records_hash(&records).unwrap(), records_hash(&tampered).unwrap());
+    }
+}
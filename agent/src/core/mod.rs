@@ -1,19 +1,34 @@
+pub mod archive;
 pub mod audit;
+pub mod audit_chain;
+pub mod backup;
+pub mod checksum;
 pub mod command;
 pub mod crypto;
+pub mod dirsync;
 pub mod enrollment;
 pub mod files;
+pub mod patch;
+pub mod path_policy;
+pub mod search;
 pub mod heartbeat;
+pub mod integrity;
+pub mod logship;
 pub mod protocol;
 pub mod reconnect;
 pub mod scheduler;
 pub mod state;
+pub mod tail;
 pub mod task_manager;
+pub mod transfer;
 pub mod cmd_executor;
 
 #[cfg(test)]
 pub mod property_tests;
 
+#[cfg(test)]
+pub mod test_support;
+
 #[cfg(test)]
 pub mod network_config_tests;
 
@@ -26,13 +41,18 @@ use std::time::Duration;
 use tokio::sync::RwLock;
 use tracing::{error, info};
 
-use crate::config::ConfigManager;
+use crate::config::{AgentConfig, ConfigManager};
 use crate::platform::{create_command_executor, create_file_system};
 use crate::transport::{HttpClient, TlsConfig};
 
-use self::crypto::CryptoManager;
+use self::audit::{AuditEventHandler, AuditLogger, AuditTransportConfig};
+use self::audit_chain::{CHAIN_STATE_FILE, JOURNAL_FILE};
+use self::backup::BackupStore;
+use self::crypto::{CryptoManager, SharedSigner};
+use self::integrity::{IntegrityHandle, IntegrityMonitor};
+use self::logship::LogShipper;
 use self::enrollment::{EnrollmentClient, EnrollmentConfig};
-use self::heartbeat::{HeartbeatClient, HeartbeatConfig};
+use self::heartbeat::{HeartbeatClient, HeartbeatConfig, TaskHandlers};
 use self::reconnect::ReconnectManager;
 use self::scheduler::{Scheduler, TaskType};
 use self::state::StateManager;
@@ -40,13 +60,20 @@ use self::task_manager::TaskManager;
 use self::cmd_executor::CommandExecutor;
 use crate::core::protocol::EnrollmentStatus;
 use crate::terminal::TerminalManager;
+use crate::tunnel::{TunnelManager, TunnelPolicy};
 use crate::task_handler::TaskHandler;
+use crate::file_tasks::FileTaskHandler;
+use self::files::{FileManager, FileManagerConfig};
+use self::path_policy::PathPolicy;
+use self::transfer::TransferManager;
 
 #[allow(dead_code)]
 pub struct Agent {
     config_manager: Arc<RwLock<ConfigManager>>,
     state_manager: StateManager,
     crypto_manager: Option<CryptoManager>,
+    /// 注册后填入，供后台的审计上传与日志上送签名
+    signer: SharedSigner,
     enrollment_client: EnrollmentClient,
     heartbeat_client: HeartbeatClient,
     scheduler: Scheduler,
@@ -58,6 +85,9 @@ pub struct Agent {
     cmd_executor: Arc<CommandExecutor>,
     terminal_manager: Arc<TerminalManager>,
     task_handler: Arc<TaskHandler>,
+    file_task_handler: Arc<FileTaskHandler>,
+    /// 文件完整性监控线程，未启用时为 None
+    integrity_monitor: Option<IntegrityHandle>,
 }
 
 impl Agent {
@@ -132,12 +162,15 @@ impl Agent {
         // 初始化命令执行器
         let cmd_executor = Arc::new(CommandExecutor::new(task_manager.clone()));
 
-        // 初始化终端管理器（最多 10 个并发会话）
-        let terminal_manager = Arc::new(TerminalManager::new(10));
+        // 初始化终端管理器（并发数、超时、缓冲区容量与命令审计来自配置）
+        let terminal_manager = Arc::new(
+            TerminalManager::new(config.terminal.max_sessions)
+                .with_timeouts(config.terminal.idle_timeout, config.terminal.max_duration)
+                .with_buffer_size(config.terminal.buffer_size)
+                .with_command_capture(config.terminal.audit_commands)
+                .with_output_limit(config.terminal.output_rate_limit, config.terminal.output_burst),
+        );
         
-        // 初始化任务处理器
-        let task_handler = Arc::new(TaskHandler::new(terminal_manager.clone()));
-
         // 尝试加载现有凭证
         let credentials_file = config.credentials_path();
         let crypto_manager = if credentials_file.exists() {
@@ -155,10 +188,99 @@ impl Agent {
             None
         };
 
+        let signer: SharedSigner = Arc::new(std::sync::RwLock::new(
+            crypto_manager.clone().zip(config.agent.device_id.clone()),
+        ));
+
+        // 初始化审计日志，事件按哈希链定序后批量上传到服务端 /agent/audit
+        let device_id = config.agent.device_id.clone().unwrap_or_default();
+        let (audit_logger, audit_receiver) = AuditLogger::new(device_id.clone());
+        let mut audit_handler = AuditEventHandler::with_config(
+            audit_receiver,
+            AuditTransportConfig {
+                server_url: config.server.base_url.trim_end_matches('/').to_string(),
+                chain_state_path: Some(
+                    PathBuf::from(&config.paths.data_dir)
+                        .join(CHAIN_STATE_FILE)
+                        .to_string_lossy()
+                        .to_string(),
+                ),
+                journal_path: Some(
+                    PathBuf::from(&config.paths.data_dir)
+                        .join(JOURNAL_FILE)
+                        .to_string_lossy()
+                        .to_string(),
+                ),
+                ..Default::default()
+            },
+            signer.clone(),
+        );
+        tokio::spawn(async move { audit_handler.run().await });
+
+        // 初始化任务处理器，启用隧道时按配置的目标策略创建隧道管理器
+        let mut task_handler = TaskHandler::new(terminal_manager.clone()).with_audit_logger(audit_logger.clone());
+        if config.tunnel.enabled {
+            match TunnelPolicy::from_rules(&config.tunnel.allow, &config.tunnel.deny) {
+                Ok(policy) => {
+                    let tunnel_manager = TunnelManager::new(policy, config.tunnel.max_tunnels)
+                        .with_window_size(config.tunnel.window_size)
+                        .with_timeouts(config.tunnel.connect_timeout, config.tunnel.idle_timeout)
+                        .with_socks(config.tunnel.socks_enabled);
+                    task_handler = task_handler.with_tunnel_manager(Arc::new(tunnel_manager));
+                }
+                Err(e) => error!("Invalid tunnel policy, tunnels disabled: {}", e),
+            }
+        }
+        let task_handler = Arc::new(task_handler);
+
+        // 心跳通道的文件任务沿用文件操作配置中的路径限制
+        let mut file_manager = FileManager::new(FileManagerConfig::from_section(
+            &config.file_operations,
+            config.max_file_size_bytes()?,
+        ));
+        if config.file_backup.enabled {
+            let backups = BackupStore::new(PathBuf::from(&config.paths.data_dir).join("backups")).with_limits(
+                config.file_backup.max_versions,
+                AgentConfig::parse_file_size(&config.file_backup.max_total_size)?,
+                AgentConfig::parse_file_size(&config.file_backup.max_file_size)?,
+            );
+            file_manager = file_manager.with_backup_store(Arc::new(backups));
+        }
+        let transfer_manager = TransferManager::new(file_manager.clone())
+            .with_limits(config.max_transfer_size_bytes()?, config.file_transfer.max_chunk_size)
+            .with_max_archive_entries(config.file_transfer.max_archive_entries)
+            .with_staging_dir(PathBuf::from(&config.paths.data_dir).join("transfers"))
+            .with_stale_timeout(config.file_transfer.stale_timeout);
+        let file_task_handler = Arc::new(
+            FileTaskHandler::new(file_manager)
+                .with_transfer_manager(transfer_manager)
+                .with_tail_cursor_path(PathBuf::from(&config.paths.data_dir).join("tail_cursors.json"))
+                .with_audit_logger(audit_logger.clone()),
+        );
+
+        // 文件完整性监控，基线保存在数据目录
+        let integrity_monitor = config.integrity.enabled.then(|| {
+            IntegrityMonitor::from_section(&config.integrity)
+                .with_baseline_path(PathBuf::from(&config.paths.data_dir).join("integrity_baseline.json"))
+                .spawn(audit_logger)
+        });
+
+        // 后台日志采集，读取位置保存在数据目录
+        if config.log_shipping.enabled {
+            LogShipper::new(
+                &config.log_shipping,
+                &config.server.base_url,
+                signer.clone(),
+                &PathBuf::from(&config.paths.data_dir),
+            )
+            .spawn();
+        }
+
         Ok(Self {
             config_manager,
             state_manager,
             crypto_manager,
+            signer,
             enrollment_client,
             heartbeat_client,
             scheduler,
@@ -170,6 +292,8 @@ impl Agent {
             cmd_executor,
             terminal_manager,
             task_handler,
+            file_task_handler,
+            integrity_monitor,
         })
     }
 
@@ -279,6 +403,12 @@ impl Agent {
                         }
                     }
 
+                    // 后台任务从此使用注册得到的密钥签名
+                    let device_id = self.config_manager.read().await.config().agent.device_id.clone();
+                    if let (Some(crypto_manager), Some(device_id)) = (&self.crypto_manager, device_id) {
+                        *self.signer.write().unwrap() = Some((crypto_manager.clone(), device_id));
+                    }
+
                     // 同步配置
                     if let Err(e) = self.sync_config().await {
                         error!("Initial config sync failed: {}", e);
@@ -292,9 +422,12 @@ impl Agent {
                             let crypto_manager = crypto_manager.clone();
                             let state_manager = self.state_manager.clone();
                             let config_manager = self.config_manager.clone();
-                            let task_manager = self.task_manager.clone();
-                            let cmd_executor = self.cmd_executor.clone();
-                            let task_handler = self.task_handler.clone();
+                            let handlers = TaskHandlers {
+                                task_manager: self.task_manager.clone(),
+                                cmd_executor: self.cmd_executor.clone(),
+                                task_handler: self.task_handler.clone(),
+                                file_task_handler: self.file_task_handler.clone(),
+                            };
 
                             tokio::spawn(async move {
                                 if let Err(e) = heartbeat_client
@@ -302,9 +435,7 @@ impl Agent {
                                         &crypto_manager,
                                         &state_manager,
                                         &config_manager,
-                                        &task_manager,
-                                        &cmd_executor,
-                                        &task_handler,
+                                        &handlers,
                                     )
                                     .await
                                 {
@@ -321,6 +452,9 @@ impl Agent {
                             }
                             _ = tokio::signal::ctrl_c() => {
                                 info!("Received shutdown signal");
+                                if let Some(monitor) = self.integrity_monitor.take() {
+                                    monitor.stop();
+                                }
                                 return Ok(());
                             }
                         }
@@ -459,10 +593,11 @@ impl Agent {
         let resp_json: serde_json::Value = response.json().await?;
 
         if let Some(config_content) = resp_json.get("config") {
-            self.config_manager
-                .write()
-                .await
-                .update_from_json(&config_content.to_string())?;
+            let mut cm = self.config_manager.write().await;
+            cm.update_from_json(&config_content.to_string())?;
+            // 路径访问策略随配置同步更新，与 ConfigUpdate 任务一致
+            self.file_task_handler
+                .update_policy(PathPolicy::from_section(&cm.config().file_operations));
             info!("Dynamic config updated via sync");
         }
 
@@ -0,0 +1,112 @@
+// agent/src/core/patch.rs
+// 按行号区间修改文本文件
+//
+// 行号从 1 开始，区间为闭区间，均相对于修改前的内容。未修改的行保留原有换行符，
+// 新插入的行在文件含 CRLF 时使用 CRLF，否则使用 LF；文件末尾是否有换行保持不变。
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// 一处行区间替换
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LineEdit {
+    /// 起始行（从 1 开始）
+    pub start_line: usize,
+    /// 结束行（含）；为 `start_line - 1` 时表示在 `start_line` 之前插入
+    pub end_line: usize,
+    /// 替换内容，每项一行，不含换行符；为空表示删除
+    #[serde(default)]
+    pub lines: Vec<String>,
+}
+
+/// 将多处互不重叠的行区间替换应用到 `text`
+pub fn apply_line_edits(text: &str, edits: &[LineEdit]) -> Result<String> {
+    let segments: Vec<&str> = text.split_inclusive('\n').collect();
+    let line_count = segments.len();
+    let eol = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    // 空文件视为有末尾换行，追加的内容以换行结束
+    let trailing_newline = text.is_empty() || text.ends_with('\n');
+
+    let mut sorted: Vec<&LineEdit> = edits.iter().collect();
+    sorted.sort_by_key(|e| (e.start_line, e.end_line));
+    let mut next_free = 1;
+    for edit in &sorted {
+        if edit.start_line == 0 || edit.end_line + 1 < edit.start_line {
+            return Err(anyhow!("Invalid line range {}-{}", edit.start_line, edit.end_line));
+        }
+        if edit.end_line > line_count || edit.start_line > line_count + 1 {
+            return Err(anyhow!(
+                "Line range {}-{} is outside the file ({} lines)",
+                edit.start_line,
+                edit.end_line,
+                line_count
+            ));
+        }
+        if edit.start_line < next_free {
+            return Err(anyhow!("Overlapping edits at line {}", edit.start_line));
+        }
+        next_free = edit.end_line + 1;
+    }
+
+    let mut output: Vec<String> = Vec::with_capacity(line_count);
+    let mut cursor = 0;
+    for edit in sorted {
+        output.extend(segments[cursor..edit.start_line - 1].iter().map(|s| s.to_string()));
+        output.extend(edit.lines.iter().map(|line| format!("{}{}", line, eol)));
+        cursor = cursor.max(edit.end_line);
+    }
+    output.extend(segments[cursor..].iter().map(|s| s.to_string()));
+
+    // 原本没有换行的末行后面追加了内容时补上换行，并保持文件末尾的换行状态
+    let last = output.len().saturating_sub(1);
+    for (index, line) in output.iter_mut().enumerate() {
+        let has_newline = line.ends_with('\n');
+        if index < last && !has_newline {
+            line.push_str(eol);
+        } else if index == last && has_newline && !trailing_newline {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        } else if index == last && !has_newline && trailing_newline {
+            line.push_str(eol);
+        }
+    }
+    Ok(output.concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(start_line: usize, end_line: usize, lines: &[&str]) -> LineEdit {
+        LineEdit {
+            start_line,
+            end_line,
+            lines: lines.iter().map(|l| l.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_replace_insert_delete() {
+        let text = "a\nb\nc\nd\n";
+        // 编辑顺序无关，行号都相对于原内容
+        let edits = [edit(4, 4, &[]), edit(2, 2, &["B", "B2"]), edit(1, 0, &["top"]), edit(5, 4, &["end"])];
+        assert_eq!(apply_line_edits(text, &edits).unwrap(), "top\na\nB\nB2\nc\nend\n");
+
+        assert!(apply_line_edits(text, &[edit(2, 3, &[]), edit(3, 3, &["x"])]).is_err());
+        assert!(apply_line_edits(text, &[edit(4, 5, &[])]).is_err());
+        assert!(apply_line_edits(text, &[edit(0, 0, &[])]).is_err());
+        assert!(apply_line_edits(text, &[edit(3, 1, &[])]).is_err());
+    }
+
+    #[test]
+    fn test_line_endings_preserved() {
+        assert_eq!(apply_line_edits("a\r\nb\r\n", &[edit(2, 2, &["x"])]).unwrap(), "a\r\nx\r\n");
+        // 末尾没有换行的文件保持没有换行
+        assert_eq!(apply_line_edits("a\nb", &[edit(2, 2, &["x"])]).unwrap(), "a\nx");
+        assert_eq!(apply_line_edits("a\nb", &[edit(3, 2, &["c"])]).unwrap(), "a\nb\nc");
+        assert_eq!(apply_line_edits("a\nb", &[edit(2, 2, &[])]).unwrap(), "a");
+        assert_eq!(apply_line_edits("", &[edit(1, 0, &["new"])]).unwrap(), "new\n");
+    }
+}
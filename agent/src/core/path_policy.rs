@@ -0,0 +1,496 @@
+// agent/src/core/path_policy.rs
+// 统一的路径访问策略（glob 规则 + 只读/读写/拒绝）
+//
+// 规则按解析符号链接后的真实路径匹配，拒绝规则优先，其余规则中最具体的一条生效。
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::FileOperationsSection;
+
+/// 访问模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessMode {
+    ReadOnly,
+    ReadWrite,
+    Deny,
+}
+
+/// 请求的访问类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl AccessMode {
+    fn permits(self, access: Access) -> bool {
+        match self {
+            AccessMode::ReadWrite => true,
+            AccessMode::ReadOnly => access == Access::Read,
+            AccessMode::Deny => false,
+        }
+    }
+}
+
+/// 单条路径规则
+///
+/// `path` 为 glob：`*` 匹配单级目录内的任意字符，`?` 匹配单个字符，
+/// `**` 匹配任意多级目录（含零级），如 `/var/log/**`、`**/.ssh/id_*`。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PathRule {
+    pub path: String,
+    pub mode: AccessMode,
+}
+
+impl PathRule {
+    pub fn new(path: impl Into<String>, mode: AccessMode) -> Self {
+        Self {
+            path: path.into(),
+            mode,
+        }
+    }
+
+    /// 目录本身及其下的全部内容
+    pub fn directory(dir: &str, mode: AccessMode) -> Self {
+        Self::new(format!("{}/**", dir.trim_end_matches(['/', '\\'])), mode)
+    }
+}
+
+/// 编译后的规则
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    pattern: String,
+    segments: Vec<String>,
+    mode: AccessMode,
+    /// 非通配字符数，越大越具体
+    specificity: usize,
+}
+
+impl CompiledRule {
+    fn compile(rule: &PathRule) -> Self {
+        let pattern = canonicalize_literal_prefix(&normalize_separators(&rule.path));
+        let segments: Vec<String> = pattern.split('/').map(str::to_string).collect();
+        let specificity = pattern.chars().filter(|c| !matches!(c, '*' | '?')).count();
+        Self {
+            pattern,
+            segments,
+            mode: rule.mode,
+            specificity,
+        }
+    }
+
+    fn matches(&self, path: &[&str]) -> bool {
+        match_segments(&self.segments, path)
+    }
+}
+
+/// 路径访问策略
+#[derive(Debug, Clone)]
+pub struct PathPolicy {
+    rules: Vec<CompiledRule>,
+    allow_hidden_files: bool,
+}
+
+impl Default for PathPolicy {
+    fn default() -> Self {
+        let mut rules: Vec<PathRule> = default_allowed_dirs()
+            .iter()
+            .map(|dir| PathRule::directory(dir, AccessMode::ReadWrite))
+            .collect();
+        rules.extend(builtin_deny_rules());
+        Self::new(&rules)
+    }
+}
+
+impl PathPolicy {
+    /// 由规则构建策略；未命中任何规则的路径一律拒绝
+    pub fn new(rules: &[PathRule]) -> Self {
+        Self {
+            rules: rules.iter().map(CompiledRule::compile).collect(),
+            allow_hidden_files: true,
+        }
+    }
+
+    /// 设置是否允许访问隐藏文件（以 `.` 开头的文件名）
+    pub fn with_hidden_files(mut self, allow: bool) -> Self {
+        self.allow_hidden_files = allow;
+        self
+    }
+
+    /// 从文件操作配置构建
+    ///
+    /// `allowed_paths` 视为读写目录，`blocked_paths` 视为拒绝目录，`rules` 追加在其后；
+    /// 未配置任何允许规则时沿用默认目录。内置的敏感文件拒绝规则始终生效。
+    pub fn from_section(section: &FileOperationsSection) -> Self {
+        let mut rules: Vec<PathRule> = section
+            .allowed_paths
+            .iter()
+            .map(|dir| PathRule::directory(dir, AccessMode::ReadWrite))
+            .collect();
+        rules.extend(
+            section
+                .blocked_paths
+                .iter()
+                .map(|dir| PathRule::directory(dir, AccessMode::Deny)),
+        );
+        rules.extend(section.rules.iter().cloned());
+        if rules.iter().all(|rule| rule.mode == AccessMode::Deny) {
+            rules.extend(
+                default_allowed_dirs()
+                    .iter()
+                    .map(|dir| PathRule::directory(dir, AccessMode::ReadWrite)),
+            );
+        }
+        rules.extend(builtin_deny_rules());
+
+        Self::new(&rules).with_hidden_files(section.allow_hidden_files)
+    }
+
+    /// 检查访问权限，返回解析符号链接后的真实路径
+    pub fn check(&self, path: &Path, access: Access) -> Result<PathBuf> {
+        validate_file_name(path)?;
+        let (lexical, resolved) = resolve(path)?;
+
+        let mode = self.mode_for(&resolved);
+        if mode.is_some_and(|(mode, _)| mode.permits(access)) {
+            if !self.allow_hidden_files && is_hidden(&resolved) {
+                return Err(anyhow!("Hidden files not allowed: {}", path.display()));
+            }
+            return Ok(resolved);
+        }
+
+        // 字面路径允许而真实路径不允许，说明经由符号链接指向了策略之外
+        if lexical != resolved && self.mode_for(&lexical).is_some_and(|(mode, _)| mode.permits(access)) {
+            return Err(anyhow!(
+                "Path escapes allowed directories via symbolic link: {}",
+                path.display()
+            ));
+        }
+
+        Err(denied(mode, path))
+    }
+
+    /// 检查路径条目本身：只解析父目录，最后一级是符号链接时不跟随
+    ///
+    /// 用于重命名、创建符号链接等作用于链接本身的操作。
+    pub fn check_entry(&self, path: &Path, access: Access) -> Result<PathBuf> {
+        validate_file_name(path)?;
+        let name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid path: {}", path.display()))?;
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let (_, parent) = resolve(parent)?;
+        let entry = parent.join(name);
+
+        let mode = self.mode_for(&entry);
+        if !mode.is_some_and(|(mode, _)| mode.permits(access)) {
+            return Err(denied(mode, path));
+        }
+        if !self.allow_hidden_files && is_hidden(&entry) {
+            return Err(anyhow!("Hidden files not allowed: {}", path.display()));
+        }
+        Ok(entry)
+    }
+
+    /// 生效的访问模式及对应规则：拒绝优先，其余取最具体的规则
+    fn mode_for(&self, path: &Path) -> Option<(AccessMode, &str)> {
+        let normalized = normalize_separators(&path.to_string_lossy());
+        let segments: Vec<&str> = normalized.split('/').collect();
+
+        let matched: Vec<&CompiledRule> = self.rules.iter().filter(|rule| rule.matches(&segments)).collect();
+        if let Some(rule) = matched.iter().find(|rule| rule.mode == AccessMode::Deny) {
+            return Some((AccessMode::Deny, &rule.pattern));
+        }
+        matched
+            .into_iter()
+            .max_by_key(|rule| rule.specificity)
+            .map(|rule| (rule.mode, rule.pattern.as_str()))
+    }
+}
+
+fn default_allowed_dirs() -> Vec<&'static str> {
+    vec![
+        "/tmp",
+        "/var/log",
+        #[cfg(target_os = "windows")]
+        "C:\\temp",
+        #[cfg(target_os = "windows")]
+        "C:\\logs",
+    ]
+}
+
+/// 内置的敏感文件拒绝规则
+fn builtin_deny_rules() -> Vec<PathRule> {
+    [
+        "**/*passwd*/**",
+        "**/*shadow*/**",
+        "**/ssh_host_*",
+        "**/.ssh/id_*",
+        "**/*private_key*/**",
+    ]
+    .iter()
+    .map(|pattern| PathRule::new(*pattern, AccessMode::Deny))
+    .collect()
+}
+
+/// 返回 (字面规范化路径, 解析符号链接后的路径)
+///
+/// 路径不存在时解析最近的已存在祖先目录，再拼接其余部分，以便检查待创建的文件。
+fn resolve(path: &Path) -> Result<(PathBuf, PathBuf)> {
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
+
+    let mut lexical = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                lexical.pop();
+            }
+            other => lexical.push(other),
+        }
+    }
+
+    // 悬空的符号链接也视为已存在，交给 canonicalize 报错，避免写入时跟随到策略之外
+    let mut existing = lexical.clone();
+    let mut missing = Vec::new();
+    while existing.symlink_metadata().is_err() {
+        let name = existing
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid path: cannot resolve {}", path.display()))?
+            .to_os_string();
+        missing.push(name);
+        if !existing.pop() {
+            return Err(anyhow!("Invalid path: cannot resolve {}", path.display()));
+        }
+    }
+
+    let mut resolved = existing
+        .canonicalize()
+        .map_err(|e| anyhow!("Invalid path {}: {}", path.display(), e))?;
+    for name in missing.into_iter().rev() {
+        resolved.push(name);
+    }
+    Ok((lexical, strip_verbatim_prefix(resolved)))
+}
+
+/// Windows 的 canonicalize 返回 `\\?\C:\...`，去掉前缀以便与规则比较
+fn strip_verbatim_prefix(path: PathBuf) -> PathBuf {
+    match path.to_str().and_then(|p| p.strip_prefix(r"\\?\")) {
+        Some(stripped) => PathBuf::from(stripped),
+        None => path,
+    }
+}
+
+fn denied(mode: Option<(AccessMode, &str)>, path: &Path) -> anyhow::Error {
+    match mode {
+        Some((AccessMode::Deny, pattern)) => {
+            anyhow!("Path denied by policy rule {}: {}", pattern, path.display())
+        }
+        Some((AccessMode::ReadOnly, _)) => anyhow!("Path is read-only: {}", path.display()),
+        _ => anyhow!("Path not in allowed directories: {}", path.display()),
+    }
+}
+
+/// 路径中任一级以 `.` 开头即视为隐藏（如 `~/.ssh/known_hosts`）
+fn is_hidden(path: &Path) -> bool {
+    path.components().any(|component| match component {
+        Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+        _ => false,
+    })
+}
+
+/// 拒绝以 `.` 或 `..` 结尾的路径：它们指向目录本身或上级目录，而不是一个条目
+///
+/// `Path::components` 会丢弃末尾的 `.`，因此按原始字符串判断。
+fn validate_file_name(path: &Path) -> Result<()> {
+    let normalized = normalize_separators(&path.to_string_lossy());
+    let name = normalized.trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+    if name == "." || name == ".." {
+        return Err(anyhow!("Invalid filename: {:?}", name));
+    }
+    Ok(())
+}
+
+fn normalize_separators(path: &str) -> String {
+    path.replace('\\', "/")
+}
+
+/// 规范化规则中不含通配符的前缀（如 macOS 上 /tmp 实为 /private/tmp）
+fn canonicalize_literal_prefix(pattern: &str) -> String {
+    let segments: Vec<&str> = pattern.split('/').collect();
+    let literal = segments
+        .iter()
+        .position(|segment| segment.contains(['*', '?']))
+        .unwrap_or(segments.len());
+    if literal == 0 {
+        return pattern.to_string();
+    }
+
+    let prefix = segments[..literal].join("/");
+    match Path::new(&prefix).canonicalize() {
+        Ok(canonical) => {
+            let mut canonical = normalize_separators(&strip_verbatim_prefix(canonical).to_string_lossy());
+            for segment in &segments[literal..] {
+                canonical.push('/');
+                canonical.push_str(segment);
+            }
+            canonical
+        }
+        Err(_) => pattern.to_string(),
+    }
+}
+
+/// 按 `/` 分段的 glob 匹配（与策略规则语法一致），用于相对路径过滤
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<String> = normalize_separators(pattern).split('/').map(str::to_string).collect();
+    let path = normalize_separators(path);
+    let segments: Vec<&str> = path.split('/').collect();
+    match_segments(&pattern, &segments)
+}
+
+fn match_segments(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| match_segments(rest, &path[skip..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((segment, path_rest)) => match_segment(first, segment) && match_segments(rest, path_rest),
+            None => false,
+        },
+    }
+}
+
+/// 单级目录名的通配匹配
+fn match_segment(pattern: &str, name: &str) -> bool {
+    #[cfg(target_os = "windows")]
+    let (pattern, name) = (pattern.to_lowercase(), name.to_lowercase());
+
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_glob_matching() {
+        let segments = |p: &str| p.split('/').map(str::to_string).collect::<Vec<_>>();
+        let path = |p: &'static str| p.split('/').collect::<Vec<_>>();
+
+        assert!(match_segments(&segments("/var/log/**"), &path("/var/log")));
+        assert!(match_segments(&segments("/var/log/**"), &path("/var/log/nginx/access.log")));
+        assert!(!match_segments(&segments("/var/log/**"), &path("/var/logs/a")));
+        assert!(match_segments(&segments("/var/log/*.log"), &path("/var/log/syslog.log")));
+        assert!(!match_segments(&segments("/var/log/*.log"), &path("/var/log/nginx/a.log")));
+        assert!(match_segments(&segments("**/.ssh/id_*"), &path("/home/u/.ssh/id_rsa")));
+        assert!(match_segments(&segments("**/*passwd*/**"), &path("/etc/passwd")));
+        assert!(match_segments(&segments("/data/app?/**"), &path("/data/app1/x")));
+    }
+
+    #[test]
+    fn test_policy_modes() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("logs")).unwrap();
+        fs::create_dir_all(root.join("secrets")).unwrap();
+        let base = root.to_string_lossy().to_string();
+
+        let policy = PathPolicy::new(&[
+            PathRule::new(format!("{}/**", base), AccessMode::ReadWrite),
+            PathRule::new(format!("{}/logs/**", base), AccessMode::ReadOnly),
+            PathRule::new(format!("{}/secrets/**", base), AccessMode::Deny),
+        ]);
+
+        assert!(policy.check(&root.join("new/file.txt"), Access::Write).is_ok());
+        // 更具体的只读规则覆盖读写规则
+        assert!(policy.check(&root.join("logs/app.log"), Access::Read).is_ok());
+        let err = policy.check(&root.join("logs/app.log"), Access::Write).unwrap_err();
+        assert!(err.to_string().contains("read-only"));
+        assert!(policy.check(&root.join("secrets/key"), Access::Read).is_err());
+        // `..` 不能越过允许的目录
+        assert!(policy.check(&root.join("logs/../../outside"), Access::Read).is_err());
+        // 以 `.` 或 `..` 结尾的路径不是有效的条目
+        assert!(policy.check(Path::new(&format!("{}/logs/..", base)), Access::Write).is_err());
+        assert!(policy.check_entry(Path::new(&format!("{}/logs/.", base)), Access::Write).is_err());
+
+        let hidden = policy.clone().with_hidden_files(false);
+        assert!(hidden.check(&root.join(".env"), Access::Read).is_err());
+        assert!(hidden.check(&root.join(".git/config"), Access::Read).is_err());
+        assert!(policy.check(&root.join(".env"), Access::Read).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_escape() {
+        let allowed = tempdir().unwrap();
+        let outside = tempdir().unwrap();
+        let allowed_root = allowed.path().canonicalize().unwrap();
+        fs::write(outside.path().join("data"), b"x").unwrap();
+        std::os::unix::fs::symlink(outside.path(), allowed_root.join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.path().join("gone"), allowed_root.join("dangling")).unwrap();
+
+        let policy = PathPolicy::new(&[PathRule::directory(&allowed_root.to_string_lossy(), AccessMode::ReadWrite)]);
+        let err = policy.check(&allowed_root.join("link/data"), Access::Read).unwrap_err();
+        assert!(err.to_string().contains("symbolic link"));
+        // 新建文件时同样按真实路径判断
+        assert!(policy.check(&allowed_root.join("link/new"), Access::Write).is_err());
+        assert!(policy.check(&allowed_root.join("dangling"), Access::Write).is_err());
+
+        // 作用于链接本身时不跟随最后一级，但父目录仍按真实路径判断
+        assert_eq!(
+            policy.check_entry(&allowed_root.join("dangling"), Access::Write).unwrap(),
+            allowed_root.join("dangling")
+        );
+        assert!(policy.check_entry(&allowed_root.join("link/data"), Access::Write).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_from_section() {
+        let section = FileOperationsSection {
+            max_file_size: "1MB".to_string(),
+            allow_hidden_files: false,
+            allowed_paths: vec![],
+            blocked_paths: vec!["/tmp/blocked".to_string()],
+            rules: vec![],
+        };
+        let policy = PathPolicy::from_section(&section);
+        assert!(policy.check(Path::new("/tmp/ok.txt"), Access::Write).is_ok());
+        assert!(policy.check(Path::new("/tmp/blocked/a"), Access::Read).is_err());
+        assert!(policy.check(Path::new("/tmp/passwd.bak"), Access::Read).is_err());
+        assert!(policy.check(Path::new("/tmp/.hidden"), Access::Read).is_err());
+        assert!(policy.check(Path::new("/usr/bin/env"), Access::Read).is_err());
+    }
+}
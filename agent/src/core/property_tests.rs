@@ -945,6 +945,7 @@ mod command_result_transmission_tests {
                     path: file_path.clone(),
                     content: file_content.clone(),
                     checksum: checksum.clone(),
+                    expected_etag: None,
                 };
 
                 let response = handler.handle_message(fs_put_message).await;
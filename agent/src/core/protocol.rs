@@ -26,6 +26,9 @@ pub struct TaskReport {
     pub output_cursor: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    /// 任务结构化结果（终端会话的输入确认、输出缺口等）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
@@ -76,6 +79,41 @@ pub enum TaskType {
     TerminalInput,
     TerminalResize,
     TerminalClose,
+    TerminalSnapshot,
+    TerminalAttach,
+    TerminalDetach,
+    TunnelOpen,
+    TunnelData,
+    TunnelClose,
+    SocksConnect,
+    FileList,
+    FileRead,
+    FileWrite,
+    FileDelete,
+    FileStat,
+    FileDownloadStart,
+    FileDownloadChunk,
+    FileUploadStart,
+    FileUploadChunk,
+    FileUploadCommit,
+    FileUploadAbort,
+    FileArchiveCreate,
+    FileArchiveChunk,
+    FileArchiveRelease,
+    FileSearch,
+    FileSearchCancel,
+    FileChmod,
+    FileChown,
+    FileMkdir,
+    FileRename,
+    FileCopy,
+    FileSymlink,
+    FileVersions,
+    FileRestore,
+    FilePatch,
+    DirSyncSignatures,
+    DirSyncApply,
+    FileTail,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -148,12 +186,18 @@ pub enum WSMessage {
         path: String,
         content: String,
         checksum: String,
+        /// 期望的现有内容 SHA-256（通常取自 FsGetResult），不一致时拒绝写入
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expected_etag: Option<String>,
     },
     #[serde(rename = "fs_put_result")]
     FsPutResult {
         id: String,
         success: bool,
         error: Option<String>,
+        /// etag 冲突时文件当前内容的 SHA-256
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        current_etag: Option<String>,
     },
     #[serde(rename = "presence")]
     Presence { status: PresenceStatus },
@@ -170,6 +214,20 @@ pub struct FileInfo {
     pub size: u64,
     pub is_dir: bool,
     pub modified: Option<u64>,
+    #[serde(default)]
+    pub is_symlink: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symlink_target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inode: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nlink: Option<u64>,
 }
 
 /// 在线状态
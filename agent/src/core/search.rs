@@ -0,0 +1,427 @@
+// agent/src/core/search.rs
+// 按文件名 glob、大小、修改时间与内容正则搜索文件
+//
+// 搜索在后台线程中遍历目录，匹配结果逐步累积，由心跳循环增量取走上报。
+// 遍历不跟随符号链接，每个文件都经过路径策略检查。
+
+use anyhow::{anyhow, Result};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+use tracing::debug;
+
+use crate::core::archive::relative_name;
+use crate::core::files::FileManager;
+use crate::core::path_policy::{glob_match, Access};
+
+/// 默认最大遍历深度
+pub const DEFAULT_MAX_DEPTH: usize = 16;
+/// 默认最大结果数
+pub const DEFAULT_MAX_RESULTS: usize = 1000;
+/// 结果数硬上限
+const MAX_RESULTS_LIMIT: usize = 10_000;
+/// 单个文件最多返回的匹配行数
+const MAX_LINES_PER_FILE: usize = 20;
+/// 匹配行摘录的最大字符数
+const MAX_SNIPPET_CHARS: usize = 200;
+/// 内容搜索跳过超过该大小的文件
+const MAX_CONTENT_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// 搜索条件
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchQuery {
+    /// 搜索根目录
+    pub root: String,
+    /// 名称 glob：不含 `/` 时匹配文件名，否则匹配相对于根目录的路径；为空表示全部
+    #[serde(default)]
+    pub name: Vec<String>,
+    /// 排除的 glob（规则同 name），命中的目录不再深入
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub min_size: Option<u64>,
+    #[serde(default)]
+    pub max_size: Option<u64>,
+    /// 修改时间下限（Unix 秒）
+    #[serde(default)]
+    pub modified_after: Option<u64>,
+    /// 修改时间上限（Unix 秒）
+    #[serde(default)]
+    pub modified_before: Option<u64>,
+    /// 文件内容正则
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub case_insensitive: bool,
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    #[serde(default)]
+    pub max_results: Option<usize>,
+}
+
+/// 内容匹配行
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LineMatch {
+    /// 行号（从 1 开始）
+    pub line: usize,
+    pub text: String,
+}
+
+/// 单个匹配文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchMatch {
+    pub path: String,
+    pub size: u64,
+    pub modified: Option<u64>,
+    /// 内容匹配行（未指定内容正则时为空）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lines: Vec<LineMatch>,
+}
+
+/// 自上次取走以来的进度
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchProgress {
+    pub matches: Vec<SearchMatch>,
+    /// 迄今为止的匹配总数（含已确认上报的）
+    pub found: usize,
+    /// 已检查的文件数
+    pub scanned: u64,
+    pub done: bool,
+    /// 达到结果数上限或被取消而提前结束
+    pub truncated: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct SearchState {
+    pending: Vec<SearchMatch>,
+    found: usize,
+    scanned: u64,
+    done: bool,
+    truncated: bool,
+    error: Option<String>,
+}
+
+/// 正在进行的搜索
+pub struct FileSearch {
+    state: Mutex<SearchState>,
+    cancelled: AtomicBool,
+}
+
+impl FileSearch {
+    /// 校验条件并在后台线程开始搜索
+    pub fn start(file_manager: FileManager, query: SearchQuery) -> Result<Arc<Self>> {
+        let root = file_manager.validate_path(&query.root, Access::Read)?;
+        if !root.is_dir() {
+            return Err(anyhow!("Path is not a directory: {}", query.root));
+        }
+        let content = query
+            .content
+            .as_deref()
+            .map(|pattern| {
+                RegexBuilder::new(pattern)
+                    .case_insensitive(query.case_insensitive)
+                    .size_limit(1 << 20)
+                    .build()
+                    .map_err(|e| anyhow!("Invalid content pattern: {}", e))
+            })
+            .transpose()?;
+
+        let search = Arc::new(Self {
+            state: Mutex::new(SearchState::default()),
+            cancelled: AtomicBool::new(false),
+        });
+        let worker = Arc::clone(&search);
+        std::thread::spawn(move || {
+            let walker = Walker {
+                file_manager,
+                root: root.clone(),
+                content,
+                max_depth: query.max_depth.unwrap_or(DEFAULT_MAX_DEPTH),
+                max_results: query.max_results.unwrap_or(DEFAULT_MAX_RESULTS).clamp(1, MAX_RESULTS_LIMIT),
+                query,
+                search: &worker,
+            };
+            let result = walker.walk(&root, 0);
+            let mut state = worker.state.lock().unwrap();
+            state.done = true;
+            if let Err(e) = result {
+                state.error = Some(e.to_string());
+            }
+        });
+        Ok(search)
+    }
+
+    /// 尚未确认上报的匹配结果与当前状态，确认前重复调用返回相同的结果
+    pub fn progress(&self) -> SearchProgress {
+        let state = self.state.lock().unwrap();
+        SearchProgress {
+            matches: state.pending.clone(),
+            found: state.found,
+            scanned: state.scanned,
+            done: state.done,
+            truncated: state.truncated,
+            error: state.error.clone(),
+        }
+    }
+
+    /// 确认前 `sent` 条匹配结果已上报，从待上报列表中移除
+    pub fn acknowledge(&self, sent: usize) {
+        let mut state = self.state.lock().unwrap();
+        let sent = sent.min(state.pending.len());
+        state.pending.drain(..sent);
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+struct Walker<'a> {
+    file_manager: FileManager,
+    root: PathBuf,
+    query: SearchQuery,
+    content: Option<Regex>,
+    max_depth: usize,
+    max_results: usize,
+    search: &'a FileSearch,
+}
+
+impl Walker<'_> {
+    /// 深度优先遍历；返回 false 表示应停止
+    fn walk(&self, dir: &Path, depth: usize) -> Result<bool> {
+        let mut entries: Vec<fs::DirEntry> = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok()).collect(),
+            // 根目录以外无权限读取的目录直接跳过
+            Err(e) if depth > 0 => {
+                debug!("Skipping unreadable directory {:?}: {}", dir, e);
+                return Ok(true);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            if self.search.cancelled.load(Ordering::Relaxed) {
+                self.search.state.lock().unwrap().truncated = true;
+                return Ok(false);
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            let relative = relative_name(&self.root, &path);
+            let name = entry.file_name().to_string_lossy().to_string();
+            if self.query.exclude.iter().any(|pattern| name_matches(pattern, &name, &relative)) {
+                continue;
+            }
+
+            if file_type.is_dir() {
+                if depth < self.max_depth && !self.walk(&path, depth + 1)? {
+                    return Ok(false);
+                }
+                continue;
+            }
+            if !file_type.is_file() {
+                continue;
+            }
+
+            self.search.state.lock().unwrap().scanned += 1;
+            if !self.query.name.is_empty() && !self.query.name.iter().any(|pattern| name_matches(pattern, &name, &relative)) {
+                continue;
+            }
+            if let Some(found) = self.check_file(&path) {
+                let mut state = self.search.state.lock().unwrap();
+                state.pending.push(found);
+                state.found += 1;
+                if state.found >= self.max_results {
+                    state.truncated = true;
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn check_file(&self, path: &Path) -> Option<SearchMatch> {
+        let path = self.file_manager.validate_path(&path.to_string_lossy(), Access::Read).ok()?;
+        let metadata = fs::metadata(&path).ok()?;
+        let size = metadata.len();
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+
+        if self.query.min_size.is_some_and(|min| size < min) || self.query.max_size.is_some_and(|max| size > max) {
+            return None;
+        }
+        if let Some(after) = self.query.modified_after {
+            if modified.is_none_or(|m| m < after) {
+                return None;
+            }
+        }
+        if let Some(before) = self.query.modified_before {
+            if modified.is_none_or(|m| m > before) {
+                return None;
+            }
+        }
+
+        let lines = match &self.content {
+            Some(regex) => {
+                if size > MAX_CONTENT_FILE_SIZE {
+                    return None;
+                }
+                let lines = grep_file(&path, regex).ok()?;
+                if lines.is_empty() {
+                    return None;
+                }
+                lines
+            }
+            None => Vec::new(),
+        };
+
+        Some(SearchMatch {
+            path: path.to_string_lossy().to_string(),
+            size,
+            modified,
+            lines,
+        })
+    }
+}
+
+/// 逐行匹配内容，跳过二进制文件
+fn grep_file(path: &Path, regex: &Regex) -> Result<Vec<LineMatch>> {
+    let mut file = File::open(path)?;
+    let mut head = [0u8; 8192];
+    let n = file.read(&mut head)?;
+    if head[..n].contains(&0) {
+        return Ok(Vec::new());
+    }
+
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut lines = Vec::new();
+    let mut buf = Vec::new();
+    let mut number = 0;
+    while lines.len() < MAX_LINES_PER_FILE {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        number += 1;
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim_end_matches(['\r', '\n']);
+        if regex.is_match(line) {
+            lines.push(LineMatch {
+                line: number,
+                text: line.chars().take(MAX_SNIPPET_CHARS).collect(),
+            });
+        }
+    }
+    Ok(lines)
+}
+
+fn name_matches(pattern: &str, name: &str, relative: &str) -> bool {
+    if pattern.contains('/') {
+        glob_match(pattern, relative)
+    } else {
+        glob_match(pattern, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::path_policy::AccessMode;
+    use crate::core::test_support::file_manager_for;
+    use std::time::{Duration, Instant};
+    use tempfile::tempdir;
+
+    fn run(file_manager: &FileManager, query: SearchQuery) -> SearchProgress {
+        let search = FileSearch::start(file_manager.clone(), query).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut matches = Vec::new();
+        loop {
+            let mut progress = search.progress();
+            search.acknowledge(progress.matches.len());
+            matches.append(&mut progress.matches);
+            if progress.done || Instant::now() > deadline {
+                progress.matches = matches;
+                return progress;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_search_by_name_and_content() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("etc/app")).unwrap();
+        fs::create_dir_all(dir.path().join("cache")).unwrap();
+        fs::write(dir.path().join("etc/app/app.conf"), "port = 80\nlisten_addr = 0.0.0.0\n").unwrap();
+        fs::write(dir.path().join("etc/other.conf"), "timeout = 5\n").unwrap();
+        fs::write(dir.path().join("cache/stale.conf"), "listen_addr = ::\n").unwrap();
+        fs::write(dir.path().join("etc/secret.conf"), "listen_addr = x\n").unwrap();
+
+        let file_manager = file_manager_for(dir.path(), AccessMode::ReadOnly, &["**/secret*"], 1024);
+        let root = dir.path().to_string_lossy().to_string();
+
+        let progress = run(
+            &file_manager,
+            SearchQuery {
+                root: root.clone(),
+                name: vec!["*.conf".to_string()],
+                exclude: vec!["cache".to_string()],
+                content: Some("LISTEN_ADDR".to_string()),
+                case_insensitive: true,
+                ..Default::default()
+            },
+        );
+        assert!(progress.done);
+        assert_eq!(progress.matches.len(), 1);
+        assert!(progress.matches[0].path.ends_with("app.conf"));
+        assert_eq!(
+            progress.matches[0].lines,
+            vec![LineMatch {
+                line: 2,
+                text: "listen_addr = 0.0.0.0".to_string()
+            }]
+        );
+
+        // 深度与结果数限制
+        let progress = run(
+            &file_manager,
+            SearchQuery {
+                root: root.clone(),
+                name: vec!["*.conf".to_string()],
+                max_depth: Some(1),
+                ..Default::default()
+            },
+        );
+        assert_eq!(progress.matches.len(), 2);
+        let progress = run(
+            &file_manager,
+            SearchQuery {
+                root,
+                max_results: Some(1),
+                ..Default::default()
+            },
+        );
+        assert_eq!(progress.matches.len(), 1);
+        assert!(progress.truncated);
+
+        assert!(FileSearch::start(
+            file_manager,
+            SearchQuery {
+                root: "/usr".to_string(),
+                ..Default::default()
+            }
+        )
+        .is_err());
+    }
+}
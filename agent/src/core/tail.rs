@@ -0,0 +1,605 @@
+// agent/src/core/tail.rs
+// 日志跟随（类似 `tail -F`）
+//
+// 按（设备、inode）与偏移量跟踪文件：路径指向新文件时先读完旧文件再切换（轮转），
+// 文件变短时从头读取（截断）。新增的完整行经过可选的正则过滤后追加到任务输出，
+// 由 TaskManager 的 output cursor 增量上报。读取位置持久化，Agent 重启后同一任务可续读。
+
+use anyhow::{anyhow, Result};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::core::audit::{AuditLogger, AuditResult};
+use crate::core::files::FileManager;
+use crate::core::path_policy::Access;
+use crate::core::protocol::TaskState;
+use crate::core::task_manager::TaskManager;
+use crate::platform::atomic_write;
+
+/// 同时跟随的文件数上限
+const MAX_CONCURRENT_TAILS: usize = 8;
+/// 每次轮询最多读取的字节数
+const MAX_READ_PER_POLL: u64 = 256 * 1024;
+/// 超过该长度的行不等换行符直接输出
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+const DEFAULT_INITIAL_LINES: usize = 10;
+const DEFAULT_POLL_INTERVAL_MS: u64 = 1000;
+const MIN_POLL_INTERVAL_MS: u64 = 200;
+
+/// 跟随条件
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TailOptions {
+    pub path: String,
+    /// 只输出匹配的行
+    #[serde(default)]
+    pub include: Option<String>,
+    /// 丢弃匹配的行，优先于 include
+    #[serde(default)]
+    pub exclude: Option<String>,
+    #[serde(default)]
+    pub case_insensitive: bool,
+    /// 开始时先输出的末尾行数，默认 10
+    #[serde(default)]
+    pub initial_lines: Option<usize>,
+    /// 从文件开头读取
+    #[serde(default)]
+    pub from_start: bool,
+    #[serde(default)]
+    pub poll_interval_ms: Option<u64>,
+    /// 跟随时长上限，到达后任务成功结束
+    #[serde(default)]
+    pub max_duration_secs: Option<u64>,
+}
+
+/// 已读取到的位置
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TailCursor {
+    /// Unix 下为（设备号，inode）
+    pub file_id: (u64, u64),
+    pub offset: u64,
+}
+
+/// 正在跟随的文件
+pub struct FileTailer {
+    file_manager: FileManager,
+    /// 读取位置的持久化文件，为 None 时只保存在内存中
+    cursor_path: Option<PathBuf>,
+    cursors: Mutex<HashMap<String, TailCursor>>,
+    /// 任务 ID → 停止标志；同一任务的新 revision 会停止旧的跟随
+    active: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+impl FileTailer {
+    pub fn new(file_manager: FileManager) -> Self {
+        Self {
+            file_manager,
+            cursor_path: None,
+            cursors: Mutex::new(HashMap::new()),
+            active: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 设置读取位置的持久化文件，并加载上次保存的位置
+    pub fn with_cursor_path(mut self, path: PathBuf) -> Self {
+        match fs::read(&path) {
+            Ok(data) => match serde_json::from_slice(&data) {
+                Ok(cursors) => self.cursors = Mutex::new(cursors),
+                Err(e) => warn!("Ignoring corrupt tail cursors {:?}: {}", path, e),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to read tail cursors {:?}: {}", path, e),
+        }
+        self.cursor_path = Some(path);
+        self
+    }
+
+    /// 校验条件并在后台开始跟随，输出写入 `task_manager` 中的同名任务
+    pub fn start(
+        self: &Arc<Self>,
+        task_id: String,
+        options: TailOptions,
+        task_manager: Arc<TaskManager>,
+        audit: Option<AuditLogger>,
+    ) -> Result<()> {
+        let filter = LineFilter::new(&options)?;
+        let resume = self.cursors.lock().unwrap().get(&task_id).copied();
+        let tail = Tail::open(self.file_manager.clone(), &options, filter, resume)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        {
+            let mut active = self.active.lock().unwrap();
+            if let Some(previous) = active.remove(&task_id) {
+                previous.store(true, Ordering::Relaxed);
+            }
+            if active.len() >= MAX_CONCURRENT_TAILS {
+                return Err(anyhow!("Too many concurrent tails (max {})", MAX_CONCURRENT_TAILS));
+            }
+            active.insert(task_id.clone(), Arc::clone(&stop));
+        }
+
+        let tailer = Arc::clone(self);
+        tokio::spawn(async move {
+            tailer.run(task_id, tail, options, task_manager, stop, audit).await;
+        });
+        Ok(())
+    }
+
+    async fn run(
+        self: Arc<Self>,
+        task_id: String,
+        mut tail: Tail,
+        options: TailOptions,
+        task_manager: Arc<TaskManager>,
+        stop: Arc<AtomicBool>,
+        audit: Option<AuditLogger>,
+    ) {
+        info!("Tailing {} for task {}", options.path, task_id);
+        let _ = task_manager.update_task_state(&task_id, TaskState::Running).await;
+        let poll_interval = Duration::from_millis(
+            options
+                .poll_interval_ms
+                .unwrap_or(DEFAULT_POLL_INTERVAL_MS)
+                .max(MIN_POLL_INTERVAL_MS),
+        );
+        let deadline = options
+            .max_duration_secs
+            .map(|secs| Instant::now() + Duration::from_secs(secs));
+
+        let mut streamed = 0u64;
+        // 已写入任务输出但尚未确认上报的读取位置：（对应的输出结束位置，读取位置）
+        let mut unacked: VecDeque<(u64, TailCursor)> = VecDeque::new();
+        let outcome = loop {
+            if stop.load(Ordering::Relaxed) {
+                break None;
+            }
+            let context = match task_manager.get_task(&task_id).await {
+                Some(context) if context.state != TaskState::Canceled => context,
+                _ => break None,
+            };
+
+            // 输出确认上报后才保存对应的读取位置，重启后未确认的输出会重新读取
+            let mut confirmed = None;
+            while unacked.front().is_some_and(|(end, _)| *end <= context.output_offset) {
+                confirmed = unacked.pop_front().map(|(_, cursor)| cursor);
+            }
+            if let Some(cursor) = confirmed {
+                let id = task_id.clone();
+                self.update_cursors(move |tailer| tailer.save_cursor(&id, cursor)).await;
+            }
+
+            let (returned, result) = match tokio::task::spawn_blocking(move || {
+                let result = tail.poll();
+                (tail, result)
+            })
+            .await
+            {
+                Ok(polled) => polled,
+                Err(e) => break Some(Err(anyhow!("Tail task failed: {}", e))),
+            };
+            tail = returned;
+
+            let mut end = context.output_offset + context.output_buffer.len() as u64;
+            match result {
+                Ok(output) if !output.is_empty() => {
+                    streamed += output.len() as u64;
+                    if task_manager.append_task_output(&task_id, &output).await.is_err() {
+                        break None;
+                    }
+                    end += output.len() as u64;
+                }
+                Ok(_) => {}
+                Err(e) => break Some(Err(e)),
+            }
+            if let Some(cursor) = tail.cursor() {
+                match unacked.back_mut() {
+                    Some(last) if last.0 == end => last.1 = cursor,
+                    _ => unacked.push_back((end, cursor)),
+                }
+            }
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break Some(Ok(()));
+            }
+            tokio::time::sleep(poll_interval).await;
+        };
+
+        // 被新 revision 替换时保留读取位置，由新的跟随继续使用
+        if !stop.load(Ordering::Relaxed) {
+            self.active.lock().unwrap().remove(&task_id);
+            let id = task_id.clone();
+            self.update_cursors(move |tailer| tailer.forget_cursor(&id)).await;
+        }
+
+        let (audit_result, error) = match &outcome {
+            Some(Err(e)) => (AuditResult::Error, Some(e.to_string())),
+            _ => (AuditResult::Success, None),
+        };
+        match outcome {
+            Some(Ok(())) => {
+                let _ = task_manager.update_task_state(&task_id, TaskState::Succeeded).await;
+            }
+            Some(Err(e)) => {
+                let _ = task_manager.set_task_error(&task_id, format!("Tail failed: {}", e)).await;
+            }
+            None => {}
+        }
+        if let Some(audit) = audit {
+            let _ = audit.log_file_download(None, &options.path, streamed, "", &task_id, audit_result, error);
+        }
+        info!("Stopped tailing {} for task {} ({} bytes)", options.path, task_id, streamed);
+    }
+
+    /// 在阻塞线程池中更新读取位置，持久化会同步写文件
+    async fn update_cursors<F>(self: &Arc<Self>, update: F)
+    where
+        F: FnOnce(&Self) + Send + 'static,
+    {
+        let tailer = Arc::clone(self);
+        if let Err(e) = tokio::task::spawn_blocking(move || update(&tailer)).await {
+            warn!("Tail cursor update failed: {}", e);
+        }
+    }
+
+    fn save_cursor(&self, task_id: &str, cursor: TailCursor) {
+        let mut cursors = self.cursors.lock().unwrap();
+        if cursors.insert(task_id.to_string(), cursor) != Some(cursor) {
+            self.persist(&cursors);
+        }
+    }
+
+    fn forget_cursor(&self, task_id: &str) {
+        let mut cursors = self.cursors.lock().unwrap();
+        if cursors.remove(task_id).is_some() {
+            self.persist(&cursors);
+        }
+    }
+
+    fn persist(&self, cursors: &HashMap<String, TailCursor>) {
+        if let Some(path) = &self.cursor_path {
+            let result = serde_json::to_vec(cursors)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| atomic_write(path, &data));
+            if let Err(e) = result {
+                debug!("Failed to persist tail cursors: {}", e);
+            }
+        }
+    }
+}
+
+struct LineFilter {
+    include: Option<Regex>,
+    exclude: Option<Regex>,
+}
+
+impl LineFilter {
+    fn new(options: &TailOptions) -> Result<Self> {
+        let build = |pattern: &Option<String>| {
+            pattern
+                .as_deref()
+                .map(|pattern| {
+                    RegexBuilder::new(pattern)
+                        .case_insensitive(options.case_insensitive)
+                        .size_limit(1 << 20)
+                        .build()
+                        .map_err(|e| anyhow!("Invalid filter pattern: {}", e))
+                })
+                .transpose()
+        };
+        Ok(Self {
+            include: build(&options.include)?,
+            exclude: build(&options.exclude)?,
+        })
+    }
+
+    fn matches(&self, line: &str) -> bool {
+        if self.exclude.as_ref().is_some_and(|regex| regex.is_match(line)) {
+            return false;
+        }
+        match &self.include {
+            Some(regex) => regex.is_match(line),
+            None => true,
+        }
+    }
+}
+
+/// 单个文件的跟随状态
+struct Tail {
+    file_manager: FileManager,
+    path: String,
+    filter: LineFilter,
+    file: Option<File>,
+    file_id: Option<(u64, u64)>,
+    /// 已从当前文件读取的字节数（含未完成的行）
+    offset: u64,
+    /// 尚未遇到换行符的行
+    partial: Vec<u8>,
+}
+
+impl Tail {
+    fn open(
+        file_manager: FileManager,
+        options: &TailOptions,
+        filter: LineFilter,
+        resume: Option<TailCursor>,
+    ) -> Result<Self> {
+        let validated = file_manager.validate_path(&options.path, Access::Read)?;
+        if validated.is_dir() {
+            return Err(anyhow!("Path is a directory: {}", options.path));
+        }
+        let mut tail = Self {
+            file_manager,
+            path: options.path.clone(),
+            filter,
+            file: None,
+            file_id: None,
+            offset: 0,
+            partial: Vec::new(),
+        };
+        // 文件暂不存在时等待其出现
+        let Ok(file) = File::open(&validated) else {
+            return Ok(tail);
+        };
+        let metadata = file.metadata()?;
+        let id = file_id(&metadata);
+        tail.offset = match resume {
+            Some(cursor) if cursor.file_id == id && cursor.offset <= metadata.len() => cursor.offset,
+            // 重启期间文件已轮转，从新文件开头读取
+            Some(_) => 0,
+            None if options.from_start => 0,
+            None => last_lines_offset(&validated, options.initial_lines.unwrap_or(DEFAULT_INITIAL_LINES))?,
+        };
+        tail.file = Some(file);
+        tail.file_id = Some(id);
+        Ok(tail)
+    }
+
+    /// 读取上次之后新增的行
+    fn poll(&mut self) -> Result<String> {
+        let mut output = String::new();
+        let mut drained = true;
+        if let Some(file) = &self.file {
+            if file.metadata()?.len() < self.offset {
+                output.push_str("[TAIL] file truncated\n");
+                self.offset = 0;
+                self.partial.clear();
+            }
+            drained = self.read_available(&mut output)?;
+        }
+
+        // 旧文件读完后才切换到路径指向的新文件
+        if drained {
+            if let Ok(path) = self.file_manager.validate_path(&self.path, Access::Read) {
+                if let Ok(metadata) = fs::metadata(&path) {
+                    let id = file_id(&metadata);
+                    if self.file_id != Some(id) {
+                        if self.file.is_some() {
+                            self.flush_partial(&mut output);
+                            output.push_str("[TAIL] file rotated\n");
+                        }
+                        self.file = Some(File::open(&path)?);
+                        self.file_id = Some(id);
+                        self.offset = 0;
+                        self.read_available(&mut output)?;
+                    }
+                }
+            }
+        }
+        Ok(output)
+    }
+
+    /// 从当前偏移读取，返回是否已读到文件末尾
+    fn read_available(&mut self, output: &mut String) -> Result<bool> {
+        let Some(file) = &mut self.file else {
+            return Ok(true);
+        };
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut data = Vec::new();
+        let read = file.take(MAX_READ_PER_POLL).read_to_end(&mut data)? as u64;
+        self.offset += read;
+
+        for &byte in &data {
+            if byte == b'\n' {
+                let line = std::mem::take(&mut self.partial);
+                self.emit(&line, output);
+            } else {
+                self.partial.push(byte);
+                if self.partial.len() >= MAX_LINE_LENGTH {
+                    self.flush_partial(output);
+                }
+            }
+        }
+        Ok(read < MAX_READ_PER_POLL)
+    }
+
+    fn flush_partial(&mut self, output: &mut String) {
+        if !self.partial.is_empty() {
+            let line = std::mem::take(&mut self.partial);
+            self.emit(&line, output);
+        }
+    }
+
+    fn emit(&self, line: &[u8], output: &mut String) {
+        let line = String::from_utf8_lossy(line);
+        let line = line.strip_suffix('\r').unwrap_or(&line);
+        if self.filter.matches(line) {
+            output.push_str(line);
+            output.push('\n');
+        }
+    }
+
+    /// 已输出的完整行之后的位置
+    fn cursor(&self) -> Option<TailCursor> {
+        self.file_id.map(|file_id| TailCursor {
+            file_id,
+            offset: self.offset - self.partial.len() as u64,
+        })
+    }
+}
+
+/// 文件末尾 `lines` 行的起始偏移
+fn last_lines_offset(path: &Path, lines: usize) -> Result<u64> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    if lines == 0 || len == 0 {
+        return Ok(len);
+    }
+    // 只在末尾一段内查找，过长的行按该段截断
+    let start = len.saturating_sub(MAX_READ_PER_POLL);
+    file.seek(SeekFrom::Start(start))?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+
+    let body = data.strip_suffix(b"\n").unwrap_or(&data);
+    let offset = body
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, &byte)| byte == b'\n')
+        .nth(lines - 1)
+        .map(|(index, _)| index as u64 + 1)
+        .unwrap_or(0);
+    Ok(start + offset)
+}
+
+/// 文件标识，用于识别轮转，也作为校验和缓存的键
+#[cfg(unix)]
+pub fn file_id(metadata: &fs::Metadata) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+#[cfg(not(unix))]
+pub fn file_id(metadata: &fs::Metadata) -> (u64, u64) {
+    // 没有 inode 时以创建时间区分轮转前后的文件
+    let created = metadata
+        .created()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    (0, created)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::path_policy::AccessMode;
+    use crate::core::test_support::{append, file_manager_for};
+    use tempfile::tempdir;
+
+    fn manager_for(dir: &Path) -> FileManager {
+        file_manager_for(dir, AccessMode::ReadWrite, &[], 1024)
+    }
+
+    #[test]
+    fn test_tail_rotation_truncation_and_filters() {
+        let dir = tempdir().unwrap();
+        let log = dir.path().join("app.log");
+        fs::write(&log, "one\ntwo\nthree\n").unwrap();
+        let options = TailOptions {
+            path: log.to_string_lossy().to_string(),
+            exclude: Some("^debug".to_string()),
+            initial_lines: Some(2),
+            ..Default::default()
+        };
+        let filter = LineFilter::new(&options).unwrap();
+        let mut tail = Tail::open(manager_for(dir.path()), &options, filter, None).unwrap();
+
+        assert_eq!(tail.poll().unwrap(), "two\nthree\n");
+        // 不完整的行等到换行符出现才输出
+        append(&log, "debug x\nfour\r\nfi");
+        assert_eq!(tail.poll().unwrap(), "four\n");
+        append(&log, "ve\n");
+        assert_eq!(tail.poll().unwrap(), "five\n");
+
+        // 轮转：旧文件剩余内容先输出，再从新文件开头读取
+        append(&log, "six\n");
+        fs::rename(&log, dir.path().join("app.log.1")).unwrap();
+        fs::write(&log, "new\n").unwrap();
+        assert_eq!(tail.poll().unwrap(), "six\n[TAIL] file rotated\nnew\n");
+
+        fs::write(&log, "x\n").unwrap();
+        assert_eq!(tail.poll().unwrap(), "[TAIL] file truncated\nx\n");
+
+        // 从保存的位置续读
+        let cursor = tail.cursor().unwrap();
+        append(&log, "after\n");
+        let filter = LineFilter::new(&options).unwrap();
+        let mut resumed = Tail::open(manager_for(dir.path()), &options, filter, Some(cursor)).unwrap();
+        assert_eq!(resumed.poll().unwrap(), "after\n");
+    }
+
+    #[tokio::test]
+    async fn test_cursor_saved_after_output_confirmed() {
+        use crate::core::protocol::{DesiredState, TaskItem, TaskType};
+
+        let dir = tempdir().unwrap();
+        let log = dir.path().join("app.log");
+        fs::write(&log, "one\n").unwrap();
+        let cursor_path = dir.path().join("cursors.json");
+        let tailer = Arc::new(FileTailer::new(manager_for(dir.path())).with_cursor_path(cursor_path.clone()));
+        let task_manager = Arc::new(TaskManager::new());
+        task_manager
+            .receive_task(&TaskItem {
+                task_id: "tail".to_string(),
+                revision: 1,
+                task_type: TaskType::FileTail,
+                desired_state: DesiredState::Running,
+                payload: serde_json::Value::Null,
+            })
+            .await
+            .unwrap();
+        let options = TailOptions {
+            path: log.to_string_lossy().to_string(),
+            from_start: true,
+            poll_interval_ms: Some(MIN_POLL_INTERVAL_MS),
+            ..Default::default()
+        };
+        tailer.start("tail".to_string(), options, Arc::clone(&task_manager), None).unwrap();
+
+        let mut reports = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while reports.is_empty() && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            reports = task_manager.generate_reports().await;
+            reports.retain(|report| report.output_chunk.is_some());
+        }
+        assert_eq!(reports[0].output_chunk.as_deref(), Some("one\n"));
+
+        // 未确认上报前不保存读取位置
+        tokio::time::sleep(Duration::from_millis(3 * MIN_POLL_INTERVAL_MS)).await;
+        assert!(!cursor_path.exists());
+
+        task_manager.confirm_reports_sent(&reports).await;
+        let saved = || {
+            fs::read(&cursor_path)
+                .ok()
+                .and_then(|data| serde_json::from_slice::<HashMap<String, TailCursor>>(&data).ok())
+                .and_then(|cursors| cursors.get("tail").copied())
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while saved().is_none() && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(saved().unwrap().offset, 4);
+        task_manager.cancel_task("tail", 2).await.unwrap();
+    }
+
+    #[test]
+    fn test_last_lines_offset() {
+        let dir = tempdir().unwrap();
+        let log = dir.path().join("a.log");
+        fs::write(&log, "a\nb\nc").unwrap();
+        assert_eq!(last_lines_offset(&log, 1).unwrap(), 4);
+        assert_eq!(last_lines_offset(&log, 5).unwrap(), 0);
+        assert_eq!(last_lines_offset(&log, 0).unwrap(), 5);
+    }
+}
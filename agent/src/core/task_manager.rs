@@ -24,6 +24,8 @@ pub struct TaskContext {
     pub state: TaskState,
     pub progress: Option<u32>,
     pub output_buffer: String,
+    /// 已确认发送并从缓冲区丢弃的输出字节数
+    pub output_offset: u64,
     pub output_cursor: u64,
     pub error: Option<String>,
     pub created_at: u64,
@@ -45,6 +47,7 @@ impl TaskContext {
             state: TaskState::Received,
             progress: None,
             output_buffer: String::new(),
+            output_offset: 0,
             output_cursor: 0,
             error: None,
             created_at: now,
@@ -91,8 +94,9 @@ impl TaskContext {
 
     /// 获取未发送的输出增量
     pub fn get_output_chunk(&self, last_cursor: u64) -> Option<String> {
-        if last_cursor < self.output_buffer.len() as u64 {
-            let chunk = self.output_buffer[last_cursor as usize..].to_string();
+        let start = last_cursor.saturating_sub(self.output_offset);
+        if start < self.output_buffer.len() as u64 {
+            let chunk = self.output_buffer[start as usize..].to_string();
             if !chunk.is_empty() {
                 return Some(chunk);
             }
@@ -100,11 +104,22 @@ impl TaskContext {
         None
     }
 
+    /// 丢弃 cursor 之前已确认发送的输出，cursor 仍按输出总字节数计算
+    pub fn discard_output_before(&mut self, cursor: u64) {
+        let end = cursor
+            .saturating_sub(self.output_offset)
+            .min(self.output_buffer.len() as u64) as usize;
+        if end > 0 && self.output_buffer.is_char_boundary(end) {
+            self.output_buffer.drain(..end);
+            self.output_offset += end as u64;
+        }
+    }
+
     /// 生成 TaskReport
     pub fn to_report(&self, last_sent_cursor: u64) -> TaskReport {
         let output_chunk = self.get_output_chunk(last_sent_cursor);
         let new_cursor = if output_chunk.is_some() {
-            Some(self.output_buffer.len() as u64)
+            Some(self.output_offset + self.output_buffer.len() as u64)
         } else {
             None
         };
@@ -116,6 +131,7 @@ impl TaskContext {
             output_chunk,
             output_cursor: new_cursor,
             error: self.error.clone(),
+            result: None,
         }
     }
 }
@@ -286,8 +302,9 @@ impl TaskManager {
     /// 生成待上报的 TaskReport 列表
     pub async fn generate_reports(&self) -> Vec<TaskReport> {
         let mut reports = Vec::new();
-        let pending = self.pending_reports.read().await;
+        // 与追加输出相同，先取 tasks 再取 pending_reports
         let tasks = self.tasks.read().await;
+        let pending = self.pending_reports.read().await;
         let sent_cursors = self.sent_cursors.read().await;
 
         for task_id in pending.iter() {
@@ -305,7 +322,6 @@ impl TaskManager {
     pub async fn confirm_reports_sent(&self, reports: &[TaskReport]) {
         let mut sent_cursors = self.sent_cursors.write().await;
         let mut pending = self.pending_reports.write().await;
-        let tasks = self.tasks.read().await;
 
         for report in reports {
             // 更新已发送的 cursor
@@ -326,6 +342,17 @@ impl TaskManager {
                 // 未完成的任务保留在待上报列表中，下次继续上报
             }
         }
+        drop(pending);
+        drop(sent_cursors);
+
+        // 已发送的输出不再保留（长时间运行的任务如日志跟随）。
+        // 追加输出时先持有 tasks 再取 pending_reports，这里须在释放 pending_reports 后再获取 tasks
+        let mut tasks = self.tasks.write().await;
+        for report in reports {
+            if let (Some(cursor), Some(context)) = (report.output_cursor, tasks.get_mut(&report.task_id)) {
+                context.discard_output_before(cursor);
+            }
+        }
     }
 
     /// 获取任务上下文（只读）
@@ -527,6 +554,11 @@ mod tests {
         assert_eq!(reports2.len(), 1);
         assert_eq!(reports2[0].output_chunk, Some("Line 3\n".to_string()));
         assert_eq!(reports2[0].output_cursor, Some(21));
+
+        // 已确认发送的输出已从缓冲区丢弃
+        let context = manager.get_task("task-1").await.unwrap();
+        assert_eq!(context.output_buffer, "Line 3\n");
+        assert_eq!(context.output_offset, 14);
     }
 
     #[tokio::test]
@@ -0,0 +1,35 @@
+// agent/src/core/test_support.rs
+// 测试共用的夹具：以临时目录为根的路径策略与文件管理器
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::core::files::{FileManager, FileManagerConfig};
+use crate::core::path_policy::{AccessMode, PathPolicy, PathRule};
+
+/// 允许以 `mode` 访问 `dir` 下的全部路径，`deny` 中的 glob 一律拒绝
+pub fn policy_for(dir: &Path, mode: AccessMode, deny: &[&str]) -> PathPolicy {
+    let mut rules = vec![PathRule::new(format!("{}/**", dir.display()), mode)];
+    rules.extend(deny.iter().map(|pattern| PathRule::new(*pattern, AccessMode::Deny)));
+    PathPolicy::new(&rules)
+}
+
+/// 只能访问 `dir` 下路径的文件管理器，规则同 [`policy_for`]
+pub fn file_manager_for(dir: &Path, mode: AccessMode, deny: &[&str], max_file_size: u64) -> FileManager {
+    FileManager::new(FileManagerConfig {
+        policy: policy_for(dir, mode, deny),
+        max_file_size,
+    })
+}
+
+/// 向文件末尾追加内容，文件不存在时创建
+pub fn append(path: &Path, data: &str) {
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .unwrap()
+        .write_all(data.as_bytes())
+        .unwrap();
+}
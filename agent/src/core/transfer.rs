@@ -0,0 +1,794 @@
+// agent/src/core/transfer.rs
+// 分块、可续传的文件传输
+//
+// 上传数据先写入目标目录下的暂存文件，提交时校验整体 SHA-256 后原子重命名；
+// 暂存文件名由传输 ID 决定，Agent 重启或断线重连后可按已接收的字节数续传。
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::core::archive::{self, ArchiveFilter, ArchiveLimits, ArchiveSummary};
+use crate::core::files::FileManager;
+use crate::core::path_policy::Access;
+use crate::platform::{open_staging_file, sync_dir};
+
+/// 默认单个分块的最大字节数
+pub const DEFAULT_MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// 默认归档内的最大文件数
+pub const DEFAULT_MAX_ARCHIVE_ENTRIES: usize = 10_000;
+
+/// 文件摘要（下载开始时返回，续传前用于确认文件未变化）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileDigest {
+    pub path: String,
+    pub size: u64,
+    pub modified: Option<u64>,
+    pub checksum: String,
+}
+
+/// 下载分块
+#[derive(Debug, Clone)]
+pub struct DownloadChunk {
+    pub offset: u64,
+    pub data: Vec<u8>,
+    pub checksum: String,
+    /// 文件当前大小
+    pub size: u64,
+    pub eof: bool,
+}
+
+/// 上传进度
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadStatus {
+    pub transfer_id: String,
+    pub path: String,
+    pub size: u64,
+    /// 已连续接收并落盘的字节数，续传从此处开始
+    pub received: u64,
+    /// 声明的整体 SHA-256
+    pub checksum: String,
+    /// 上传的是 tar.gz 归档，提交时解包到 `path` 目录
+    pub extract: bool,
+    /// 解包结果（仅提交归档上传时返回）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extracted: Option<ArchiveSummary>,
+}
+
+/// 已打包、等待分块下载的目录归档
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveDigest {
+    pub transfer_id: String,
+    /// 被打包的目录
+    pub path: String,
+    /// 归档（tar.gz）大小
+    pub size: u64,
+    pub checksum: String,
+    #[serde(flatten)]
+    pub summary: ArchiveSummary,
+}
+
+struct PreparedArchive {
+    file: PathBuf,
+    updated_at: Instant,
+}
+
+struct Upload {
+    target: PathBuf,
+    staging: PathBuf,
+    size: u64,
+    checksum: String,
+    received: u64,
+    extract: bool,
+    updated_at: Instant,
+    /// 已提交、放弃或清理，排队等待的分块不再写入暂存文件
+    closed: bool,
+}
+
+impl Upload {
+    fn status(&self, transfer_id: &str) -> UploadStatus {
+        UploadStatus {
+            transfer_id: transfer_id.to_string(),
+            path: self.target.to_string_lossy().to_string(),
+            size: self.size,
+            received: self.received,
+            checksum: self.checksum.clone(),
+            extract: self.extract,
+            extracted: None,
+        }
+    }
+}
+
+/// 分块传输管理器
+///
+/// `uploads` 只在查找、插入和移除时短暂加锁，文件读写与哈希只持有单个上传的锁，
+/// 一个上传提交时不会阻塞其它上传的分块。
+pub struct TransferManager {
+    file_manager: FileManager,
+    uploads: Mutex<HashMap<String, Arc<Mutex<Upload>>>>,
+    /// None 表示正在打包的预留位置
+    archives: Mutex<HashMap<String, Option<PreparedArchive>>>,
+    /// 归档与待解包上传的暂存目录（不受路径策略限制，仅 Agent 内部使用）
+    staging_dir: PathBuf,
+    max_transfer_size: u64,
+    max_chunk_size: usize,
+    max_archive_entries: usize,
+    /// 未完成上传的保留时间，None 表示不清理
+    stale_timeout: Option<Duration>,
+}
+
+impl TransferManager {
+    pub fn new(file_manager: FileManager) -> Self {
+        Self {
+            file_manager,
+            uploads: Mutex::new(HashMap::new()),
+            archives: Mutex::new(HashMap::new()),
+            staging_dir: std::env::temp_dir().join("ruinos-transfers"),
+            max_transfer_size: 10 * 1024 * 1024 * 1024,
+            max_chunk_size: DEFAULT_MAX_CHUNK_SIZE,
+            max_archive_entries: DEFAULT_MAX_ARCHIVE_ENTRIES,
+            stale_timeout: None,
+        }
+    }
+
+    /// 设置传输大小上限与单个分块上限（字节）
+    pub fn with_limits(mut self, max_transfer_size: u64, max_chunk_size: usize) -> Self {
+        self.max_transfer_size = max_transfer_size;
+        self.max_chunk_size = max_chunk_size.max(1);
+        self
+    }
+
+    /// 设置归档内的最大文件数
+    pub fn with_max_archive_entries(mut self, max_archive_entries: usize) -> Self {
+        self.max_archive_entries = max_archive_entries;
+        self
+    }
+
+    /// 设置归档暂存目录
+    pub fn with_staging_dir(mut self, staging_dir: PathBuf) -> Self {
+        self.staging_dir = staging_dir;
+        self
+    }
+
+    /// 设置未完成上传的保留时间（秒），0 表示不清理
+    pub fn with_stale_timeout(mut self, stale_timeout: u64) -> Self {
+        self.stale_timeout = (stale_timeout > 0).then(|| Duration::from_secs(stale_timeout));
+        self
+    }
+
+    /// 开始下载：返回文件大小与整体校验和
+    pub fn download_start(&self, path: &str) -> Result<FileDigest> {
+        let validated_path = self.validated_file(path)?;
+        let metadata = fs::metadata(&validated_path)?;
+        if metadata.len() > self.max_transfer_size {
+            return Err(anyhow!(
+                "File size {} exceeds maximum transfer size {}",
+                metadata.len(),
+                self.max_transfer_size
+            ));
+        }
+
+        Ok(FileDigest {
+            path: validated_path.to_string_lossy().to_string(),
+            size: metadata.len(),
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+            checksum: file_checksum(&validated_path)?,
+        })
+    }
+
+    /// 读取 `offset` 起最多 `length` 字节（不超过分块上限）
+    pub fn download_chunk(&self, path: &str, offset: u64, length: usize) -> Result<DownloadChunk> {
+        let validated_path = self.validated_file(path)?;
+        self.read_chunk(&validated_path, offset, length)
+    }
+
+    fn read_chunk(&self, path: &Path, offset: u64, length: usize) -> Result<DownloadChunk> {
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+        if offset > size {
+            return Err(anyhow!("Offset {} beyond end of file ({} bytes)", offset, size));
+        }
+
+        let length = length.clamp(1, self.max_chunk_size) as u64;
+        let mut data = Vec::with_capacity(length.min(size - offset) as usize);
+        file.seek(SeekFrom::Start(offset))?;
+        file.take(length).read_to_end(&mut data)?;
+
+        Ok(DownloadChunk {
+            offset,
+            checksum: checksum(&data),
+            eof: offset + data.len() as u64 >= size,
+            data,
+            size,
+        })
+    }
+
+    /// 开始或恢复上传
+    ///
+    /// 同一传输 ID 重复调用时返回已接收的字节数；Agent 重启后按暂存文件长度恢复。
+    /// `extract` 为 true 时上传内容为 tar.gz 归档，`path` 为解包的目标目录。
+    pub fn upload_start(
+        &self,
+        transfer_id: &str,
+        path: &str,
+        size: u64,
+        checksum: &str,
+        extract: bool,
+    ) -> Result<UploadStatus> {
+        validate_transfer_id(transfer_id)?;
+        if size > self.max_transfer_size {
+            return Err(anyhow!(
+                "File size {} exceeds maximum transfer size {}",
+                size,
+                self.max_transfer_size
+            ));
+        }
+        let checksum = checksum.to_lowercase();
+
+        if let Ok(handle) = self.upload(transfer_id) {
+            let mut upload = handle.lock().unwrap();
+            if !upload.closed {
+                if upload.size != size || upload.checksum != checksum || upload.extract != extract {
+                    return Err(anyhow!("Transfer {} already started with different parameters", transfer_id));
+                }
+                upload.updated_at = Instant::now();
+                return Ok(upload.status(transfer_id));
+            }
+        }
+
+        let target = self.file_manager.validate_path(path, Access::Write)?;
+        let staging = if extract {
+            if target.is_file() {
+                return Err(anyhow!("Extract destination is not a directory: {}", path));
+            }
+            fs::create_dir_all(&self.staging_dir)?;
+            self.staging_dir.join(format!("{}.upload.tar.gz.part", transfer_id))
+        } else {
+            let file_name = target
+                .file_name()
+                .ok_or_else(|| anyhow!("Invalid path: no file name"))?
+                .to_string_lossy()
+                .to_string();
+            let parent = target.parent().ok_or_else(|| anyhow!("Invalid path: no parent directory"))?;
+            fs::create_dir_all(parent)?;
+            // 暂存文件与目标在同一目录，保证重命名是原子的
+            parent.join(format!(".{}.{}.part", file_name, transfer_id))
+        };
+
+        let file = open_staging_file(&staging, OpenOptions::new().create(true).write(true).truncate(false))?;
+        let mut received = file.metadata()?.len();
+        if received > size {
+            file.set_len(0)?;
+            received = 0;
+        }
+        if received > 0 {
+            info!("Resuming upload {} at {} of {} bytes", transfer_id, received, size);
+        }
+
+        let upload = Upload {
+            target,
+            staging,
+            size,
+            checksum,
+            received,
+            extract,
+            updated_at: Instant::now(),
+            closed: false,
+        };
+        let status = upload.status(transfer_id);
+        let mut uploads = self.uploads.lock().unwrap();
+        if uploads.contains_key(transfer_id) {
+            return Err(anyhow!("Transfer {} is being started concurrently", transfer_id));
+        }
+        uploads.insert(transfer_id.to_string(), Arc::new(Mutex::new(upload)));
+        Ok(status)
+    }
+
+    /// 写入分块：校验分块哈希，跳过已接收的部分，拒绝出现空洞的分块
+    pub fn upload_chunk(&self, transfer_id: &str, offset: u64, data: &[u8], chunk_checksum: &str) -> Result<UploadStatus> {
+        if data.len() > self.max_chunk_size {
+            return Err(anyhow!(
+                "Chunk size {} exceeds maximum chunk size {}",
+                data.len(),
+                self.max_chunk_size
+            ));
+        }
+        let actual = checksum(data);
+        if actual != chunk_checksum.to_lowercase() {
+            return Err(anyhow!(
+                "Chunk checksum mismatch at offset {}: expected {}, got {}",
+                offset,
+                chunk_checksum,
+                actual
+            ));
+        }
+
+        let upload = self.upload(transfer_id)?;
+        let mut upload = upload.lock().unwrap();
+        if upload.closed {
+            return Err(anyhow!("Transfer not found: {}", transfer_id));
+        }
+        upload.updated_at = Instant::now();
+
+        if offset > upload.received {
+            return Err(anyhow!(
+                "Chunk offset {} leaves a gap, expected {}",
+                offset,
+                upload.received
+            ));
+        }
+        let end = offset + data.len() as u64;
+        if end > upload.size {
+            return Err(anyhow!("Chunk ends at {} beyond declared size {}", end, upload.size));
+        }
+        if end > upload.received {
+            let skip = (upload.received - offset) as usize;
+            let mut file = open_staging_file(&upload.staging, OpenOptions::new().write(true))?;
+            file.seek(SeekFrom::Start(upload.received))?;
+            file.write_all(&data[skip..])?;
+            upload.received = end;
+        }
+        Ok(upload.status(transfer_id))
+    }
+
+    /// 完成上传：校验大小与整体哈希后原子替换目标文件，归档上传则解包到目标目录
+    ///
+    /// 整体哈希与解包耗时较长，调用方应在阻塞线程中执行。
+    pub fn upload_commit(&self, transfer_id: &str) -> Result<UploadStatus> {
+        let handle = self.upload(transfer_id)?;
+        let mut upload = handle.lock().unwrap();
+        if upload.closed {
+            return Err(anyhow!("Transfer not found: {}", transfer_id));
+        }
+        if upload.received != upload.size {
+            return Err(anyhow!(
+                "Transfer incomplete: received {} of {} bytes",
+                upload.received,
+                upload.size
+            ));
+        }
+
+        let staging = open_staging_file(&upload.staging, OpenOptions::new().read(true))?;
+        let actual = reader_checksum(&staging)?;
+        if actual != upload.checksum {
+            // 内容已损坏，丢弃暂存文件，需从头重新上传
+            self.close_upload(transfer_id, &handle, &mut upload);
+            let _ = fs::remove_file(&upload.staging);
+            return Err(anyhow!(
+                "Checksum mismatch: expected {}, got {}",
+                upload.checksum,
+                actual
+            ));
+        }
+
+        // 上传期间策略可能已更新，提交前重新校验目标
+        let target = match self.file_manager.validate_path(&upload.target.to_string_lossy(), Access::Write) {
+            Ok(target) => target,
+            Err(e) => {
+                self.close_upload(transfer_id, &handle, &mut upload);
+                let _ = fs::remove_file(&upload.staging);
+                return Err(e);
+            }
+        };
+
+        if upload.extract {
+            self.close_upload(transfer_id, &handle, &mut upload);
+            let check = |path: &Path, access: Access| self.file_manager.validate_path(&path.to_string_lossy(), access);
+            let backup = |path: &Path| self.file_manager.backup_existing(path);
+            let result = archive::extract_tar_gz(&upload.staging, &target, self.archive_limits(), &check, &backup);
+            let _ = fs::remove_file(&upload.staging);
+            let summary = result?;
+            info!(
+                "Extracted upload {} into {:?} ({} files, {} bytes)",
+                transfer_id, upload.target, summary.files, summary.bytes
+            );
+            let mut status = upload.status(transfer_id);
+            status.extracted = Some(summary);
+            return Ok(status);
+        }
+
+        staging.sync_all()?;
+        self.file_manager.backup_existing(&target);
+        fs::rename(&upload.staging, &target)?;
+        if let Some(parent) = target.parent() {
+            sync_dir(parent)?;
+        }
+        self.close_upload(transfer_id, &handle, &mut upload);
+        info!("Committed upload {} to {:?} ({} bytes)", transfer_id, upload.target, upload.size);
+        Ok(upload.status(transfer_id))
+    }
+
+    fn upload(&self, transfer_id: &str) -> Result<Arc<Mutex<Upload>>> {
+        self.uploads
+            .lock()
+            .unwrap()
+            .get(transfer_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Transfer not found: {}", transfer_id))
+    }
+
+    /// 标记上传结束并从表中移除（同一 ID 可能已被重新开始，只移除自身）
+    fn close_upload(&self, transfer_id: &str, handle: &Arc<Mutex<Upload>>, upload: &mut Upload) {
+        upload.closed = true;
+        let mut uploads = self.uploads.lock().unwrap();
+        if uploads.get(transfer_id).is_some_and(|current| Arc::ptr_eq(current, handle)) {
+            uploads.remove(transfer_id);
+        }
+    }
+
+    /// 将目录打包为 tar.gz 暂存，之后通过 [`archive_chunk`](Self::archive_chunk) 分块下载
+    pub fn archive_create(
+        &self,
+        transfer_id: &str,
+        path: &str,
+        filter: &ArchiveFilter,
+        max_size: Option<u64>,
+    ) -> Result<ArchiveDigest> {
+        validate_transfer_id(transfer_id)?;
+        {
+            // 检查与预留在同一把锁内完成，并发创建同一 ID 时只有一个成功
+            let mut archives = self.archives.lock().unwrap();
+            if archives.contains_key(transfer_id) {
+                return Err(anyhow!("Transfer already exists: {}", transfer_id));
+            }
+            archives.insert(transfer_id.to_string(), None);
+        }
+
+        let file = self.staging_dir.join(format!("{}.archive.tar.gz", transfer_id));
+        let result = self.pack_archive(transfer_id, path, &file, filter, max_size);
+        let mut archives = self.archives.lock().unwrap();
+        match result {
+            Ok(digest) => {
+                archives.insert(
+                    transfer_id.to_string(),
+                    Some(PreparedArchive {
+                        file,
+                        updated_at: Instant::now(),
+                    }),
+                );
+                Ok(digest)
+            }
+            Err(e) => {
+                archives.remove(transfer_id);
+                let _ = fs::remove_file(&file);
+                Err(e)
+            }
+        }
+    }
+
+    fn pack_archive(
+        &self,
+        transfer_id: &str,
+        path: &str,
+        file: &Path,
+        filter: &ArchiveFilter,
+        max_size: Option<u64>,
+    ) -> Result<ArchiveDigest> {
+        fs::create_dir_all(&self.staging_dir)?;
+        let mut limits = self.archive_limits();
+        if let Some(max_size) = max_size {
+            limits.max_total_size = limits.max_total_size.min(max_size);
+        }
+        let check = |path: &Path, access: Access| self.file_manager.validate_path(&path.to_string_lossy(), access);
+        let summary = archive::create_tar_gz(Path::new(path), file, filter, limits, &check)?;
+        Ok(ArchiveDigest {
+            transfer_id: transfer_id.to_string(),
+            path: path.to_string(),
+            size: fs::metadata(file)?.len(),
+            checksum: file_checksum(file)?,
+            summary,
+        })
+    }
+
+    /// 读取已打包归档的分块
+    pub fn archive_chunk(&self, transfer_id: &str, offset: u64, length: usize) -> Result<DownloadChunk> {
+        let file = {
+            let mut archives = self.archives.lock().unwrap();
+            let archive = archives
+                .get_mut(transfer_id)
+                .and_then(Option::as_mut)
+                .ok_or_else(|| anyhow!("Transfer not found: {}", transfer_id))?;
+            archive.updated_at = Instant::now();
+            archive.file.clone()
+        };
+        self.read_chunk(&file, offset, length)
+    }
+
+    /// 下载完成或放弃后删除归档
+    pub fn archive_release(&self, transfer_id: &str) -> Result<()> {
+        let archive = {
+            let mut archives = self.archives.lock().unwrap();
+            // 仍在打包的预留位置由 archive_create 自行处理
+            match archives.get(transfer_id) {
+                Some(Some(_)) => archives.remove(transfer_id).flatten(),
+                _ => None,
+            }
+        }
+        .ok_or_else(|| anyhow!("Transfer not found: {}", transfer_id))?;
+        let _ = fs::remove_file(&archive.file);
+        Ok(())
+    }
+
+    fn archive_limits(&self) -> ArchiveLimits {
+        ArchiveLimits {
+            max_total_size: self.max_transfer_size,
+            max_entries: self.max_archive_entries,
+        }
+    }
+
+    /// 取消上传并删除暂存文件
+    pub fn upload_abort(&self, transfer_id: &str) -> Result<UploadStatus> {
+        let handle = self
+            .uploads
+            .lock()
+            .unwrap()
+            .remove(transfer_id)
+            .ok_or_else(|| anyhow!("Transfer not found: {}", transfer_id))?;
+        let mut upload = handle.lock().unwrap();
+        upload.closed = true;
+        let _ = fs::remove_file(&upload.staging);
+        Ok(upload.status(transfer_id))
+    }
+
+    /// 清理超过保留时间未活动的上传与归档，删除其暂存文件
+    pub fn reap_stale(&self) -> Vec<UploadStatus> {
+        let Some(max_idle) = self.stale_timeout else {
+            return Vec::new();
+        };
+        self.archives.lock().unwrap().retain(|id, archive| {
+            let Some(archive) = archive else {
+                return true;
+            };
+            let stale = archive.updated_at.elapsed() >= max_idle;
+            if stale {
+                warn!("Discarding stale archive {}", id);
+                let _ = fs::remove_file(&archive.file);
+            }
+            !stale
+        });
+
+        // 正在写入或提交的上传持有自身的锁，跳过（不在持有表锁时等待单个上传的锁）
+        let mut reaped = Vec::new();
+        self.uploads.lock().unwrap().retain(|id, handle| {
+            let Ok(mut upload) = handle.try_lock() else {
+                return true;
+            };
+            if upload.updated_at.elapsed() < max_idle {
+                return true;
+            }
+            upload.closed = true;
+            warn!("Discarding stale upload {} ({} of {} bytes)", id, upload.received, upload.size);
+            let _ = fs::remove_file(&upload.staging);
+            reaped.push(upload.status(id));
+            false
+        });
+        reaped
+    }
+
+    fn validated_file(&self, path: &str) -> Result<PathBuf> {
+        let validated_path = self.file_manager.validate_path(path, Access::Read)?;
+        if !validated_path.is_file() {
+            return Err(anyhow!("Path is not a file: {}", path));
+        }
+        Ok(validated_path)
+    }
+}
+
+/// 传输 ID 会出现在暂存文件名中，只允许字母、数字、`-` 与 `_`
+fn validate_transfer_id(transfer_id: &str) -> Result<()> {
+    if transfer_id.is_empty()
+        || transfer_id.len() > 64
+        || !transfer_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(anyhow!("Invalid transfer id: {}", transfer_id));
+    }
+    Ok(())
+}
+
+fn checksum(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// 流式计算文件的 SHA-256，避免整体读入内存
+pub fn file_checksum(path: &Path) -> Result<String> {
+    reader_checksum(File::open(path)?)
+}
+
+fn reader_checksum(mut reader: impl Read) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::path_policy::AccessMode;
+    use crate::core::test_support::file_manager_for;
+    use tempfile::tempdir;
+
+    fn manager(dir: &Path) -> TransferManager {
+        TransferManager::new(file_manager_for(dir, AccessMode::ReadWrite, &[], 16)).with_limits(1024, 8)
+    }
+
+    #[test]
+    fn test_chunked_upload_resume_and_commit() {
+        let dir = tempdir().unwrap();
+        let target = dir.path().join("bundle.bin");
+        let target_str = target.to_string_lossy().to_string();
+        let content: Vec<u8> = (0..20u8).collect();
+        let whole = checksum(&content);
+
+        let transfers = manager(dir.path());
+        let status = transfers.upload_start("t1", &target_str, 20, &whole, false).unwrap();
+        assert_eq!(status.received, 0);
+        transfers.upload_chunk("t1", 0, &content[..8], &checksum(&content[..8])).unwrap();
+
+        // 分块哈希不符、出现空洞均被拒绝
+        assert!(transfers.upload_chunk("t1", 8, &content[8..16], &checksum(b"x")).is_err());
+        assert!(transfers.upload_chunk("t1", 12, &content[12..16], &checksum(&content[12..16])).is_err());
+
+        // 模拟 Agent 重启：新的管理器从暂存文件恢复进度
+        drop(transfers);
+        let transfers = manager(dir.path());
+        let status = transfers.upload_start("t1", &target_str, 20, &whole, false).unwrap();
+        assert_eq!(status.received, 8);
+
+        // 重叠的重传只写入新的部分
+        transfers.upload_chunk("t1", 4, &content[4..12], &checksum(&content[4..12])).unwrap();
+        transfers.upload_chunk("t1", 12, &content[12..20], &checksum(&content[12..20])).unwrap();
+        assert!(!target.exists());
+
+        let status = transfers.upload_commit("t1").unwrap();
+        assert_eq!(status.received, 20);
+        assert_eq!(fs::read(&target).unwrap(), content);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_commit_rejects_corrupt_upload() {
+        let dir = tempdir().unwrap();
+        let target = dir.path().join("a.bin");
+        let transfers = manager(dir.path());
+        transfers
+            .upload_start("t2", &target.to_string_lossy(), 4, &checksum(b"abcd"), false)
+            .unwrap();
+        transfers.upload_chunk("t2", 0, b"abce", &checksum(b"abce")).unwrap();
+        assert!(transfers.upload_commit("t2").is_err());
+        assert!(!target.exists());
+        assert!(transfers.upload_chunk("t2", 0, b"abcd", &checksum(b"abcd")).is_err());
+        assert!(transfers.upload_start("../x", &target.to_string_lossy(), 4, "", false).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_upload_refuses_symlinked_staging() {
+        let dir = tempdir().unwrap();
+        let outside = tempdir().unwrap();
+        let victim = outside.path().join("victim");
+        fs::write(&victim, b"keep").unwrap();
+        std::os::unix::fs::symlink(&victim, dir.path().join(".c.bin.t4.part")).unwrap();
+
+        let transfers = manager(dir.path());
+        let target = dir.path().join("c.bin");
+        assert!(transfers
+            .upload_start("t4", &target.to_string_lossy(), 4, &checksum(b"abcd"), false)
+            .is_err());
+        assert_eq!(fs::read(&victim).unwrap(), b"keep");
+    }
+
+    #[test]
+    fn test_reap_skips_busy_upload() {
+        let dir = tempdir().unwrap();
+        let target = dir.path().join("b.bin");
+        let transfers = manager(dir.path()).with_stale_timeout(1);
+        transfers
+            .upload_start("t3", &target.to_string_lossy(), 4, &checksum(b"abcd"), false)
+            .unwrap();
+        let handle = transfers.upload("t3").unwrap();
+        handle.lock().unwrap().updated_at = Instant::now() - Duration::from_secs(5);
+
+        // 正在写入或提交的上传不会被清理
+        {
+            let _busy = handle.lock().unwrap();
+            assert!(transfers.reap_stale().is_empty());
+        }
+
+        let reaped = transfers.reap_stale();
+        assert_eq!(reaped.len(), 1);
+        assert!(handle.lock().unwrap().closed);
+        assert!(transfers.upload_chunk("t3", 0, b"abcd", &checksum(b"abcd")).is_err());
+    }
+
+    #[test]
+    fn test_archive_download_and_extract_upload() {
+        let dir = tempdir().unwrap();
+        let staging = tempdir().unwrap();
+        let src = dir.path().join("logs");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("a.log"), b"alpha").unwrap();
+        fs::write(src.join("sub/b.log"), b"beta").unwrap();
+
+        let transfers = manager(dir.path()).with_staging_dir(staging.path().to_path_buf());
+        let digest = transfers
+            .archive_create("a1", &src.to_string_lossy(), &ArchiveFilter::default(), None)
+            .unwrap();
+        assert_eq!(digest.summary.files, 2);
+        assert!(transfers.archive_create("a1", &src.to_string_lossy(), &ArchiveFilter::default(), None).is_err());
+
+        let mut archive = Vec::new();
+        loop {
+            let chunk = transfers.archive_chunk("a1", archive.len() as u64, 8).unwrap();
+            archive.extend(chunk.data);
+            if chunk.eof {
+                break;
+            }
+        }
+        assert_eq!(checksum(&archive), digest.checksum);
+        transfers.archive_release("a1").unwrap();
+        assert!(transfers.archive_chunk("a1", 0, 8).is_err());
+
+        // 将同一归档上传并解包到另一个目录
+        let dest = dir.path().join("restored");
+        let status = transfers
+            .upload_start("u1", &dest.to_string_lossy(), archive.len() as u64, &digest.checksum, true)
+            .unwrap();
+        assert!(status.extract);
+        for (i, chunk) in archive.chunks(8).enumerate() {
+            transfers.upload_chunk("u1", (i * 8) as u64, chunk, &checksum(chunk)).unwrap();
+        }
+        let status = transfers.upload_commit("u1").unwrap();
+        assert_eq!(status.extracted.unwrap().files, 2);
+        assert_eq!(fs::read(dest.join("sub/b.log")).unwrap(), b"beta");
+        assert_eq!(fs::read_dir(staging.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_chunked_download() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("log.txt");
+        let content: Vec<u8> = (0..20u8).collect();
+        fs::write(&path, &content).unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let transfers = manager(dir.path());
+        // 大于整文件读取上限的文件仍可分块下载
+        let digest = transfers.download_start(&path).unwrap();
+        assert_eq!(digest.size, 20);
+        assert_eq!(digest.checksum, checksum(&content));
+
+        let mut received = Vec::new();
+        let mut offset = 0;
+        loop {
+            let chunk = transfers.download_chunk(&path, offset, 100).unwrap();
+            assert!(chunk.data.len() <= 8);
+            assert_eq!(chunk.checksum, checksum(&chunk.data));
+            offset += chunk.data.len() as u64;
+            received.extend(chunk.data);
+            if chunk.eof {
+                break;
+            }
+        }
+        assert_eq!(received, content);
+        assert!(transfers.download_chunk(&path, 21, 8).is_err());
+    }
+}
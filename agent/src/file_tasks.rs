@@ -0,0 +1,1332 @@
+// agent/src/file_tasks.rs
+// Agent 端文件任务处理器（心跳任务通道）
+
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::core::audit::{AuditLogger, AuditResult, FileModifyEvent};
+use crate::core::archive::ArchiveFilter;
+use crate::core::dirsync::SyncManifest;
+use crate::core::files::{parse_mode, EditConflict, FileManager, ListOptions};
+use crate::core::patch::LineEdit;
+use crate::core::path_policy::PathPolicy;
+use crate::core::search::{FileSearch, SearchProgress, SearchQuery};
+use crate::core::tail::{FileTailer, TailOptions};
+use crate::core::task_manager::TaskManager;
+use crate::core::transfer::TransferManager;
+use crate::task_handler::TaskReport;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "task_type", rename_all = "snake_case")]
+pub enum FileTask {
+    FileList {
+        task_id: String,
+        revision: u32,
+        payload: FileListPayload,
+    },
+    FileRead {
+        task_id: String,
+        revision: u32,
+        payload: FilePathPayload,
+    },
+    FileWrite {
+        task_id: String,
+        revision: u32,
+        payload: FileWritePayload,
+    },
+    FileDelete {
+        task_id: String,
+        revision: u32,
+        payload: FilePathPayload,
+    },
+    FileStat {
+        task_id: String,
+        revision: u32,
+        payload: FilePathPayload,
+    },
+    FileDownloadStart {
+        task_id: String,
+        revision: u32,
+        payload: FilePathPayload,
+    },
+    FileDownloadChunk {
+        task_id: String,
+        revision: u32,
+        payload: FileDownloadChunkPayload,
+    },
+    FileUploadStart {
+        task_id: String,
+        revision: u32,
+        payload: FileUploadStartPayload,
+    },
+    FileUploadChunk {
+        task_id: String,
+        revision: u32,
+        payload: FileUploadChunkPayload,
+    },
+    FileUploadCommit {
+        task_id: String,
+        revision: u32,
+        payload: FileTransferPayload,
+    },
+    FileUploadAbort {
+        task_id: String,
+        revision: u32,
+        payload: FileTransferPayload,
+    },
+    FileArchiveCreate {
+        task_id: String,
+        revision: u32,
+        payload: FileArchiveCreatePayload,
+    },
+    FileArchiveChunk {
+        task_id: String,
+        revision: u32,
+        payload: FileArchiveChunkPayload,
+    },
+    FileArchiveRelease {
+        task_id: String,
+        revision: u32,
+        payload: FileTransferPayload,
+    },
+    FileSearch {
+        task_id: String,
+        revision: u32,
+        payload: FileSearchPayload,
+    },
+    FileSearchCancel {
+        task_id: String,
+        revision: u32,
+        payload: FileSearchCancelPayload,
+    },
+    FileChmod {
+        task_id: String,
+        revision: u32,
+        payload: FileChmodPayload,
+    },
+    FileChown {
+        task_id: String,
+        revision: u32,
+        payload: FileChownPayload,
+    },
+    FileMkdir {
+        task_id: String,
+        revision: u32,
+        payload: FileMkdirPayload,
+    },
+    FileRename {
+        task_id: String,
+        revision: u32,
+        payload: FileMovePayload,
+    },
+    FileCopy {
+        task_id: String,
+        revision: u32,
+        payload: FileMovePayload,
+    },
+    FileSymlink {
+        task_id: String,
+        revision: u32,
+        payload: FileSymlinkPayload,
+    },
+    FileVersions {
+        task_id: String,
+        revision: u32,
+        payload: FilePathPayload,
+    },
+    FileRestore {
+        task_id: String,
+        revision: u32,
+        payload: FileRestorePayload,
+    },
+    FilePatch {
+        task_id: String,
+        revision: u32,
+        payload: FilePatchPayload,
+    },
+    DirSyncSignatures {
+        task_id: String,
+        revision: u32,
+        payload: DirSyncSignaturesPayload,
+    },
+    DirSyncApply {
+        task_id: String,
+        revision: u32,
+        payload: DirSyncApplyPayload,
+    },
+}
+
+impl FileTask {
+    pub fn task_id(&self) -> &str {
+        match self {
+            FileTask::FileList { task_id, .. }
+            | FileTask::FileRead { task_id, .. }
+            | FileTask::FileWrite { task_id, .. }
+            | FileTask::FileDelete { task_id, .. }
+            | FileTask::FileStat { task_id, .. }
+            | FileTask::FileDownloadStart { task_id, .. }
+            | FileTask::FileDownloadChunk { task_id, .. }
+            | FileTask::FileUploadStart { task_id, .. }
+            | FileTask::FileUploadChunk { task_id, .. }
+            | FileTask::FileUploadCommit { task_id, .. }
+            | FileTask::FileUploadAbort { task_id, .. }
+            | FileTask::FileArchiveCreate { task_id, .. }
+            | FileTask::FileArchiveChunk { task_id, .. }
+            | FileTask::FileArchiveRelease { task_id, .. }
+            | FileTask::FileSearch { task_id, .. }
+            | FileTask::FileSearchCancel { task_id, .. }
+            | FileTask::FileChmod { task_id, .. }
+            | FileTask::FileChown { task_id, .. }
+            | FileTask::FileMkdir { task_id, .. }
+            | FileTask::FileRename { task_id, .. }
+            | FileTask::FileCopy { task_id, .. }
+            | FileTask::FileSymlink { task_id, .. }
+            | FileTask::FileVersions { task_id, .. }
+            | FileTask::FileRestore { task_id, .. }
+            | FileTask::FilePatch { task_id, .. }
+            | FileTask::DirSyncSignatures { task_id, .. }
+            | FileTask::DirSyncApply { task_id, .. } => task_id,
+        }
+    }
+}
+
+/// 只携带路径的文件任务（列目录、读取、删除、查看信息）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilePathPayload {
+    pub path: String,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileListPayload {
+    pub path: String,
+    /// 排序、分页与是否计算校验和
+    #[serde(flatten)]
+    pub options: ListOptions,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileWritePayload {
+    pub path: String,
+    /// base64 编码的文件内容
+    pub content: String,
+    /// 内容的 SHA-256 校验和（十六进制）
+    pub checksum: String,
+    /// 期望的现有内容 SHA-256，不一致时拒绝覆盖
+    #[serde(default)]
+    pub expected_etag: Option<String>,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileDownloadChunkPayload {
+    pub path: String,
+    pub offset: u64,
+    /// 请求的字节数，超过 Agent 的分块上限时被截断
+    pub length: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileUploadStartPayload {
+    /// 由服务端分配，续传时沿用
+    pub transfer_id: String,
+    pub path: String,
+    pub size: u64,
+    /// 整个文件的 SHA-256（十六进制）
+    pub checksum: String,
+    /// 上传内容为 tar.gz 归档，提交时解包到 `path` 目录
+    #[serde(default)]
+    pub extract: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileUploadChunkPayload {
+    pub transfer_id: String,
+    pub offset: u64,
+    /// base64 编码的分块内容
+    pub data: String,
+    /// 分块的 SHA-256（十六进制）
+    pub checksum: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileTransferPayload {
+    pub transfer_id: String,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileArchiveCreatePayload {
+    pub transfer_id: String,
+    /// 要打包的目录
+    pub path: String,
+    #[serde(flatten)]
+    pub filter: ArchiveFilter,
+    /// 文件内容总字节数上限，不超过 Agent 的传输上限
+    #[serde(default)]
+    pub max_size: Option<u64>,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileArchiveChunkPayload {
+    pub transfer_id: String,
+    pub offset: u64,
+    pub length: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileSearchPayload {
+    /// 由服务端分配，用于取消；增量结果沿用发起搜索的任务 ID 上报
+    pub search_id: String,
+    #[serde(flatten)]
+    pub query: SearchQuery,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileSearchCancelPayload {
+    pub search_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileChmodPayload {
+    pub path: String,
+    /// 八进制权限，如 "0644"
+    pub mode: String,
+    #[serde(default)]
+    pub recursive: bool,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileChownPayload {
+    pub path: String,
+    /// 用户名或 uid
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// 组名或 gid
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub recursive: bool,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileMkdirPayload {
+    pub path: String,
+    /// 同时创建缺失的上级目录
+    #[serde(default)]
+    pub recursive: bool,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+/// 重命名/移动与复制
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileMovePayload {
+    pub from: String,
+    pub to: String,
+    #[serde(default)]
+    pub overwrite: bool,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileSymlinkPayload {
+    /// 链接指向的路径，相对路径按链接所在目录解析
+    pub target: String,
+    pub link: String,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileRestorePayload {
+    pub path: String,
+    /// 要恢复的版本的 SHA-256
+    pub checksum: String,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilePatchPayload {
+    pub path: String,
+    /// 修改所基于的内容的 SHA-256
+    pub expected_etag: String,
+    /// 行号均相对于修改前的内容，区间不可重叠
+    pub edits: Vec<LineEdit>,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirSyncSignaturesPayload {
+    pub root: String,
+    /// 块大小（字节），默认 4096
+    #[serde(default)]
+    pub block_size: Option<usize>,
+    #[serde(default)]
+    pub filter: ArchiveFilter,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirSyncApplyPayload {
+    pub root: String,
+    #[serde(flatten)]
+    pub manifest: SyncManifest,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileTailPayload {
+    #[serde(flatten)]
+    pub options: TailOptions,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+/// 同时进行的搜索数上限
+const MAX_CONCURRENT_SEARCHES: usize = 4;
+
+/// 正在进行的搜索及发起它的任务
+struct RunningSearch {
+    task_id: String,
+    search: Arc<FileSearch>,
+    query: SearchQuery,
+    /// 搜索结束时记录审计
+    audit: Option<AuditLogger>,
+    /// 最近一次收集上报的匹配数与是否已结束，心跳发送成功后据此确认
+    collected: Option<(usize, bool)>,
+}
+
+pub struct FileTaskHandler {
+    file_manager: FileManager,
+    transfers: Arc<TransferManager>,
+    searches: Mutex<HashMap<String, RunningSearch>>,
+    tailer: Arc<FileTailer>,
+    audit_logger: Option<AuditLogger>,
+}
+
+impl FileTaskHandler {
+    pub fn new(file_manager: FileManager) -> Self {
+        Self {
+            transfers: Arc::new(TransferManager::new(file_manager.clone())),
+            tailer: Arc::new(FileTailer::new(file_manager.clone())),
+            file_manager,
+            searches: Mutex::new(HashMap::new()),
+            audit_logger: None,
+        }
+    }
+
+    /// 设置分块传输管理器
+    pub fn with_transfer_manager(mut self, transfers: TransferManager) -> Self {
+        self.transfers = Arc::new(transfers);
+        self
+    }
+
+    /// 设置日志跟随读取位置的持久化文件
+    pub fn with_tail_cursor_path(mut self, path: std::path::PathBuf) -> Self {
+        self.tailer = Arc::new(FileTailer::new(self.file_manager.clone()).with_cursor_path(path));
+        self
+    }
+
+    /// 设置审计日志记录器
+    pub fn with_audit_logger(mut self, audit_logger: AuditLogger) -> Self {
+        self.audit_logger = Some(audit_logger);
+        self
+    }
+
+    /// 处理任务
+    pub async fn handle_task(&self, task: FileTask) -> TaskReport {
+        match task {
+            FileTask::FileList { task_id, payload, .. } => self.handle_file_list(task_id, payload).await,
+            FileTask::FileRead { task_id, payload, .. } => self.handle_file_read(task_id, payload).await,
+            FileTask::FileWrite { task_id, payload, .. } => self.handle_file_write(task_id, payload).await,
+            FileTask::FileDelete { task_id, payload, .. } => self.handle_file_delete(task_id, payload).await,
+            FileTask::FileStat { task_id, payload, .. } => self.handle_file_stat(task_id, payload).await,
+            FileTask::FileDownloadStart { task_id, payload, .. } => self.handle_download_start(task_id, payload).await,
+            FileTask::FileDownloadChunk { task_id, payload, .. } => self.handle_download_chunk(task_id, payload),
+            FileTask::FileUploadStart { task_id, payload, .. } => self.handle_upload_start(task_id, payload),
+            FileTask::FileUploadChunk { task_id, payload, .. } => self.handle_upload_chunk(task_id, payload),
+            FileTask::FileUploadCommit { task_id, payload, .. } => self.handle_upload_commit(task_id, payload).await,
+            FileTask::FileUploadAbort { task_id, payload, .. } => self.handle_upload_abort(task_id, payload),
+            FileTask::FileArchiveCreate { task_id, payload, .. } => self.handle_archive_create(task_id, payload).await,
+            FileTask::FileArchiveChunk { task_id, payload, .. } => self.handle_archive_chunk(task_id, payload),
+            FileTask::FileArchiveRelease { task_id, payload, .. } => self.handle_archive_release(task_id, payload),
+            FileTask::FileSearch { task_id, payload, .. } => self.handle_search(task_id, payload),
+            FileTask::FileSearchCancel { task_id, payload, .. } => self.handle_search_cancel(task_id, payload),
+            FileTask::FileChmod { task_id, payload, .. } => self.handle_chmod(task_id, payload).await,
+            FileTask::FileChown { task_id, payload, .. } => self.handle_chown(task_id, payload).await,
+            FileTask::FileMkdir { task_id, payload, .. } => self.handle_mkdir(task_id, payload).await,
+            FileTask::FileRename { task_id, payload, .. } => self.handle_rename(task_id, payload).await,
+            FileTask::FileCopy { task_id, payload, .. } => self.handle_copy(task_id, payload).await,
+            FileTask::FileSymlink { task_id, payload, .. } => self.handle_symlink(task_id, payload).await,
+            FileTask::FileVersions { task_id, payload, .. } => {
+                let result = self.file_manager.versions(&payload.path).await;
+                report(task_id, result)
+            }
+            FileTask::FileRestore { task_id, payload, .. } => self.handle_restore(task_id, payload).await,
+            FileTask::FilePatch { task_id, payload, .. } => self.handle_patch(task_id, payload).await,
+            FileTask::DirSyncSignatures { task_id, payload, .. } => self.handle_sync_signatures(task_id, payload).await,
+            FileTask::DirSyncApply { task_id, payload, .. } => self.handle_sync_apply(task_id, payload).await,
+        }
+    }
+
+    /// 在阻塞线程池中处理任务
+    ///
+    /// FileManager 的读写都是同步文件 I/O，异步上下文中应通过此方法调用，避免拖住心跳。
+    pub async fn handle_task_blocking(self: &Arc<Self>, task: FileTask) -> TaskReport {
+        let task_id = task.task_id().to_string();
+        let handler = Arc::clone(self);
+        let runtime = tokio::runtime::Handle::current();
+        match tokio::task::spawn_blocking(move || runtime.block_on(handler.handle_task(task))).await {
+            Ok(report) => report,
+            Err(e) => failed(task_id, format!("File task failed: {}", e)),
+        }
+    }
+
+    /// 开始跟随日志，新增的行作为任务输出经 TaskManager 增量上报
+    pub fn start_tail(&self, task_id: String, payload: FileTailPayload, task_manager: Arc<TaskManager>) -> anyhow::Result<()> {
+        let audit = self.audit_for(payload.operator);
+        self.tailer.start(task_id, payload.options, task_manager, audit)
+    }
+
+    /// 替换路径访问策略，分块传输共享同一策略
+    pub fn update_policy(&self, policy: PathPolicy) {
+        self.file_manager.set_policy(policy);
+    }
+
+    /// 清理长时间未完成的上传
+    pub fn reap_stale_transfers(&self) {
+        self.transfers.reap_stale();
+    }
+
+    /// 收集搜索的增量结果（用于心跳），以发起搜索的任务 ID 上报
+    ///
+    /// 结果在 [`confirm_search_reports`](Self::confirm_search_reports) 之前不会丢弃，
+    /// 心跳发送失败时下一次收集会连同新结果重新上报。
+    pub fn collect_search_reports(&self) -> Vec<TaskReport> {
+        let mut searches = self.searches.lock().unwrap();
+        let mut reports = Vec::new();
+
+        for (search_id, running) in searches.iter_mut() {
+            let progress = running.search.progress();
+            if !progress.done && progress.matches.is_empty() {
+                running.collected = None;
+                continue;
+            }
+            running.collected = Some((progress.matches.len(), progress.done));
+            let status = match (progress.done, progress.error.is_some()) {
+                (false, _) => "running",
+                (true, false) => "completed",
+                (true, true) => "failed",
+            };
+            let mut result = serde_json::json!(progress);
+            result["search_id"] = serde_json::json!(search_id);
+            reports.push(TaskReport {
+                task_id: running.task_id.clone(),
+                status: status.to_string(),
+                result,
+                output_cursor: 0,
+                output_chunk: String::new(),
+            });
+        }
+
+        reports
+    }
+
+    /// 心跳发送成功后确认已上报的搜索结果，移除已结束的搜索并记录审计
+    pub fn confirm_search_reports(&self) {
+        let mut finished = Vec::new();
+        self.searches.lock().unwrap().retain(|_, running| match running.collected.take() {
+            Some((sent, done)) => {
+                running.search.acknowledge(sent);
+                if done {
+                    finished.push((running.task_id.clone(), running.search.clone(), running.query.clone(), running.audit.take()));
+                }
+                !done
+            }
+            None => true,
+        });
+
+        for (task_id, search, query, audit) in finished {
+            if let Some(audit) = audit {
+                let progress = search.progress();
+                let audit_result = match progress.error {
+                    Some(_) => AuditResult::Error,
+                    None => AuditResult::Success,
+                };
+                let error = progress.error.clone();
+                let _ = audit.log_file_search(&query, &progress, &task_id, audit_result, error);
+            }
+        }
+    }
+
+    async fn handle_file_list(&self, task_id: String, payload: FileListPayload) -> TaskReport {
+        let result = self.file_manager.list_files_page(&payload.path, &payload.options).await;
+
+        if let Some(audit) = self.audit_for(payload.operator) {
+            let (count, audit_result, error) = match &result {
+                Ok(listing) => (listing.files.len(), AuditResult::Success, None),
+                Err(e) => (0, AuditResult::Error, Some(e.to_string())),
+            };
+            let _ = audit.log_file_list(None, &payload.path, count, &task_id, audit_result, error);
+        }
+
+        report(task_id, result)
+    }
+
+    async fn handle_file_read(&self, task_id: String, payload: FilePathPayload) -> TaskReport {
+        let result = self.file_manager.read_file(&payload.path).await;
+
+        if let Some(audit) = self.audit_for(payload.operator) {
+            let _ = match &result {
+                Ok((content, checksum)) => audit.log_file_download(
+                    None,
+                    &payload.path,
+                    content.len() as u64,
+                    checksum,
+                    &task_id,
+                    AuditResult::Success,
+                    None,
+                ),
+                Err(e) => audit.log_file_download(
+                    None,
+                    &payload.path,
+                    0,
+                    "",
+                    &task_id,
+                    AuditResult::Error,
+                    Some(e.to_string()),
+                ),
+            };
+        }
+
+        match result {
+            Ok((content, checksum)) => completed(
+                task_id,
+                serde_json::json!({
+                    "path": payload.path,
+                    "size": content.len(),
+                    "checksum": checksum,
+                    "content": general_purpose::STANDARD.encode(&content),
+                }),
+            ),
+            Err(e) => failed(task_id, e.to_string()),
+        }
+    }
+
+    async fn handle_file_write(&self, task_id: String, payload: FileWritePayload) -> TaskReport {
+        let result = match general_purpose::STANDARD.decode(&payload.content) {
+            Ok(content) => self
+                .file_manager
+                .write_file(&payload.path, &content, &payload.checksum, payload.expected_etag.as_deref())
+                .await
+                .map(|_| content.len() as u64),
+            Err(e) => Err(anyhow::anyhow!("Invalid base64 content: {}", e)),
+        };
+
+        if let Some(audit) = self.audit_for(payload.operator) {
+            let (size, audit_result, error) = match &result {
+                Ok(size) => (*size, AuditResult::Success, None),
+                Err(e) => (0, AuditResult::Error, Some(e.to_string())),
+            };
+            let _ = audit.log_file_upload(
+                None,
+                &payload.path,
+                size,
+                &payload.checksum,
+                &task_id,
+                audit_result,
+                error,
+            );
+        }
+
+        match result {
+            Ok(size) => completed(
+                task_id,
+                serde_json::json!({
+                    "path": payload.path,
+                    "size": size,
+                    "checksum": payload.checksum,
+                }),
+            ),
+            Err(e) => write_failed(task_id, e),
+        }
+    }
+
+    async fn handle_file_delete(&self, task_id: String, payload: FilePathPayload) -> TaskReport {
+        let result = self.file_manager.delete_file(&payload.path).await;
+
+        if let Some(audit) = self.audit_for(payload.operator) {
+            let (audit_result, error) = match &result {
+                Ok(()) => (AuditResult::Success, None),
+                Err(e) => (AuditResult::Error, Some(e.to_string())),
+            };
+            let _ = audit.log_file_delete(None, &payload.path, &task_id, audit_result, error);
+        }
+
+        match result {
+            Ok(()) => completed(
+                task_id,
+                serde_json::json!({
+                    "path": payload.path,
+                    "deleted": true,
+                }),
+            ),
+            Err(e) => failed(task_id, e.to_string()),
+        }
+    }
+
+    async fn handle_file_stat(&self, task_id: String, payload: FilePathPayload) -> TaskReport {
+        match self.file_manager.get_file_info(&payload.path).await {
+            Ok(info) => completed(task_id, serde_json::json!(info)),
+            Err(e) => failed(task_id, e.to_string()),
+        }
+    }
+
+    /// 在阻塞线程池中执行整文件哈希、打包与解包，避免阻塞心跳所在的运行时线程
+    async fn run_transfer<T, F>(&self, op: F) -> anyhow::Result<T>
+    where
+        F: FnOnce(&TransferManager) -> anyhow::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let transfers = self.transfers.clone();
+        tokio::task::spawn_blocking(move || op(&transfers))
+            .await
+            .map_err(|e| anyhow::anyhow!("Transfer task failed: {}", e))?
+    }
+
+    async fn handle_download_start(&self, task_id: String, payload: FilePathPayload) -> TaskReport {
+        let path = payload.path.clone();
+        let result = self.run_transfer(move |transfers| transfers.download_start(&path)).await;
+
+        if let Some(audit) = self.audit_for(payload.operator) {
+            let _ = match &result {
+                Ok(digest) => audit.log_file_download(
+                    None,
+                    &payload.path,
+                    digest.size,
+                    &digest.checksum,
+                    &task_id,
+                    AuditResult::Success,
+                    None,
+                ),
+                Err(e) => audit.log_file_download(
+                    None,
+                    &payload.path,
+                    0,
+                    "",
+                    &task_id,
+                    AuditResult::Error,
+                    Some(e.to_string()),
+                ),
+            };
+        }
+
+        match result {
+            Ok(digest) => completed(task_id, serde_json::json!(digest)),
+            Err(e) => failed(task_id, e.to_string()),
+        }
+    }
+
+    fn handle_download_chunk(&self, task_id: String, payload: FileDownloadChunkPayload) -> TaskReport {
+        match self.transfers.download_chunk(&payload.path, payload.offset, payload.length) {
+            Ok(chunk) => completed(
+                task_id,
+                serde_json::json!({
+                    "path": payload.path,
+                    "offset": chunk.offset,
+                    "data": general_purpose::STANDARD.encode(&chunk.data),
+                    "checksum": chunk.checksum,
+                    "size": chunk.size,
+                    "eof": chunk.eof,
+                }),
+            ),
+            Err(e) => failed(task_id, e.to_string()),
+        }
+    }
+
+    fn handle_upload_start(&self, task_id: String, payload: FileUploadStartPayload) -> TaskReport {
+        match self
+            .transfers
+            .upload_start(
+                &payload.transfer_id,
+                &payload.path,
+                payload.size,
+                &payload.checksum,
+                payload.extract,
+            )
+        {
+            Ok(status) => completed(task_id, serde_json::json!(status)),
+            Err(e) => failed(task_id, e.to_string()),
+        }
+    }
+
+    fn handle_upload_chunk(&self, task_id: String, payload: FileUploadChunkPayload) -> TaskReport {
+        let result = general_purpose::STANDARD
+            .decode(&payload.data)
+            .map_err(|e| anyhow::anyhow!("Invalid base64 data: {}", e))
+            .and_then(|data| {
+                self.transfers
+                    .upload_chunk(&payload.transfer_id, payload.offset, &data, &payload.checksum)
+            });
+
+        match result {
+            Ok(status) => completed(task_id, serde_json::json!(status)),
+            Err(e) => failed(task_id, e.to_string()),
+        }
+    }
+
+    async fn handle_upload_commit(&self, task_id: String, payload: FileTransferPayload) -> TaskReport {
+        let transfer_id = payload.transfer_id.clone();
+        match self.run_transfer(move |transfers| transfers.upload_commit(&transfer_id)).await {
+            Ok(status) => {
+                if let Some(audit) = self.audit_for(payload.operator) {
+                    let _ = audit.log_file_upload(
+                        None,
+                        &status.path,
+                        status.size,
+                        &status.checksum,
+                        &task_id,
+                        AuditResult::Success,
+                        None,
+                    );
+                }
+                completed(task_id, serde_json::json!(status))
+            }
+            Err(e) => {
+                if let Some(audit) = self.audit_for(payload.operator) {
+                    let _ = audit.log_file_upload(
+                        None,
+                        &payload.transfer_id,
+                        0,
+                        "",
+                        &task_id,
+                        AuditResult::Error,
+                        Some(e.to_string()),
+                    );
+                }
+                failed(task_id, e.to_string())
+            }
+        }
+    }
+
+    fn handle_upload_abort(&self, task_id: String, payload: FileTransferPayload) -> TaskReport {
+        match self.transfers.upload_abort(&payload.transfer_id) {
+            Ok(status) => completed(task_id, serde_json::json!(status)),
+            Err(e) => failed(task_id, e.to_string()),
+        }
+    }
+
+    async fn handle_archive_create(&self, task_id: String, payload: FileArchiveCreatePayload) -> TaskReport {
+        let (transfer_id, path, filter, max_size) = (
+            payload.transfer_id.clone(),
+            payload.path.clone(),
+            payload.filter.clone(),
+            payload.max_size,
+        );
+        let result = self
+            .run_transfer(move |transfers| transfers.archive_create(&transfer_id, &path, &filter, max_size))
+            .await;
+
+        if let Some(audit) = self.audit_for(payload.operator) {
+            let _ = match &result {
+                Ok(digest) => audit.log_file_download(
+                    None,
+                    &payload.path,
+                    digest.size,
+                    &digest.checksum,
+                    &task_id,
+                    AuditResult::Success,
+                    None,
+                ),
+                Err(e) => audit.log_file_download(
+                    None,
+                    &payload.path,
+                    0,
+                    "",
+                    &task_id,
+                    AuditResult::Error,
+                    Some(e.to_string()),
+                ),
+            };
+        }
+
+        match result {
+            Ok(digest) => completed(task_id, serde_json::json!(digest)),
+            Err(e) => failed(task_id, e.to_string()),
+        }
+    }
+
+    fn handle_archive_chunk(&self, task_id: String, payload: FileArchiveChunkPayload) -> TaskReport {
+        match self
+            .transfers
+            .archive_chunk(&payload.transfer_id, payload.offset, payload.length)
+        {
+            Ok(chunk) => completed(
+                task_id,
+                serde_json::json!({
+                    "transfer_id": payload.transfer_id,
+                    "offset": chunk.offset,
+                    "data": general_purpose::STANDARD.encode(&chunk.data),
+                    "checksum": chunk.checksum,
+                    "size": chunk.size,
+                    "eof": chunk.eof,
+                }),
+            ),
+            Err(e) => failed(task_id, e.to_string()),
+        }
+    }
+
+    fn handle_archive_release(&self, task_id: String, payload: FileTransferPayload) -> TaskReport {
+        match self.transfers.archive_release(&payload.transfer_id) {
+            Ok(()) => completed(
+                task_id,
+                serde_json::json!({
+                    "transfer_id": payload.transfer_id,
+                    "released": true,
+                }),
+            ),
+            Err(e) => failed(task_id, e.to_string()),
+        }
+    }
+
+    fn handle_search(&self, task_id: String, payload: FileSearchPayload) -> TaskReport {
+        if let Err(e) = search_slot(&self.searches.lock().unwrap(), &payload.search_id) {
+            return failed(task_id, e.to_string());
+        }
+
+        // 启动时会校验路径并编译正则，不持有搜索表的锁
+        let audit = self.audit_for(payload.operator);
+        let search = match FileSearch::start(self.file_manager.clone(), payload.query.clone()) {
+            Ok(search) => search,
+            Err(e) => {
+                if let Some(audit) = &audit {
+                    let _ = audit.log_file_search(
+                        &payload.query,
+                        &SearchProgress::default(),
+                        &task_id,
+                        AuditResult::Error,
+                        Some(e.to_string()),
+                    );
+                }
+                return failed(task_id, e.to_string());
+            }
+        };
+
+        let mut searches = self.searches.lock().unwrap();
+        // 启动期间可能已有同 ID 的搜索登记
+        if let Err(e) = search_slot(&searches, &payload.search_id) {
+            search.cancel();
+            return failed(task_id, e.to_string());
+        }
+        searches.insert(
+            payload.search_id.clone(),
+            RunningSearch {
+                task_id: task_id.clone(),
+                search,
+                query: payload.query,
+                audit,
+                collected: None,
+            },
+        );
+
+        TaskReport {
+            task_id,
+            status: "running".to_string(),
+            result: serde_json::json!({
+                "search_id": payload.search_id,
+                "state": "running",
+            }),
+            output_cursor: 0,
+            output_chunk: String::new(),
+        }
+    }
+
+    fn handle_search_cancel(&self, task_id: String, payload: FileSearchCancelPayload) -> TaskReport {
+        // 取消后的最终结果仍由下一次心跳上报
+        match self.searches.lock().unwrap().get(&payload.search_id) {
+            Some(running) => {
+                running.search.cancel();
+                completed(
+                    task_id,
+                    serde_json::json!({
+                        "search_id": payload.search_id,
+                        "cancelled": true,
+                    }),
+                )
+            }
+            None => failed(task_id, format!("Search not found: {}", payload.search_id)),
+        }
+    }
+
+    async fn handle_chmod(&self, task_id: String, payload: FileChmodPayload) -> TaskReport {
+        let result = match parse_mode(&payload.mode) {
+            Ok(mode) => self.file_manager.chmod(&payload.path, mode, payload.recursive).await,
+            Err(e) => Err(e),
+        };
+        let detail = format!("mode={} recursive={}", payload.mode, payload.recursive);
+        self.audit_modify(payload.operator, FileModifyEvent::new("chmod", &payload.path, &task_id).with_detail(detail), &result);
+        report(task_id, result)
+    }
+
+    async fn handle_chown(&self, task_id: String, payload: FileChownPayload) -> TaskReport {
+        let result = self
+            .file_manager
+            .chown(
+                &payload.path,
+                payload.owner.as_deref(),
+                payload.group.as_deref(),
+                payload.recursive,
+            )
+            .await;
+        let detail = format!(
+            "owner={} group={} recursive={}",
+            payload.owner.as_deref().unwrap_or("-"),
+            payload.group.as_deref().unwrap_or("-"),
+            payload.recursive
+        );
+        self.audit_modify(payload.operator, FileModifyEvent::new("chown", &payload.path, &task_id).with_detail(detail), &result);
+        report(task_id, result)
+    }
+
+    async fn handle_mkdir(&self, task_id: String, payload: FileMkdirPayload) -> TaskReport {
+        let result = self.file_manager.create_dir(&payload.path, payload.recursive).await;
+        self.audit_modify(payload.operator, FileModifyEvent::new("mkdir", &payload.path, &task_id), &result);
+        report(task_id, result)
+    }
+
+    async fn handle_rename(&self, task_id: String, payload: FileMovePayload) -> TaskReport {
+        let result = self.file_manager.rename(&payload.from, &payload.to, payload.overwrite).await;
+        self.audit_modify(
+            payload.operator,
+            FileModifyEvent::new("rename", &payload.from, &task_id).with_target(payload.to),
+            &result,
+        );
+        report(task_id, result)
+    }
+
+    async fn handle_copy(&self, task_id: String, payload: FileMovePayload) -> TaskReport {
+        let result = self.file_manager.copy(&payload.from, &payload.to, payload.overwrite).await;
+        self.audit_modify(
+            payload.operator,
+            FileModifyEvent::new("copy", &payload.from, &task_id).with_target(payload.to),
+            &result,
+        );
+        report(task_id, result)
+    }
+
+    async fn handle_symlink(&self, task_id: String, payload: FileSymlinkPayload) -> TaskReport {
+        let result = self.file_manager.symlink(&payload.target, &payload.link).await;
+        self.audit_modify(
+            payload.operator,
+            FileModifyEvent::new("symlink", &payload.link, &task_id).with_target(payload.target),
+            &result,
+        );
+        report(task_id, result)
+    }
+
+    async fn handle_restore(&self, task_id: String, payload: FileRestorePayload) -> TaskReport {
+        let result = self.file_manager.restore(&payload.path, &payload.checksum).await;
+        self.audit_modify(
+            payload.operator,
+            FileModifyEvent::new("restore", &payload.path, &task_id).with_detail(format!("checksum={}", payload.checksum)),
+            &result,
+        );
+        report(task_id, result)
+    }
+
+    async fn handle_patch(&self, task_id: String, payload: FilePatchPayload) -> TaskReport {
+        let result = self
+            .file_manager
+            .patch_file(&payload.path, &payload.edits, &payload.expected_etag)
+            .await;
+        let detail = format!("edits={} etag={}", payload.edits.len(), payload.expected_etag);
+        self.audit_modify(
+            payload.operator,
+            FileModifyEvent::new("patch", &payload.path, &task_id).with_detail(detail),
+            &result,
+        );
+        match result {
+            Ok(patched) => completed(task_id, serde_json::json!(patched)),
+            Err(e) => write_failed(task_id, e),
+        }
+    }
+
+    async fn handle_sync_signatures(&self, task_id: String, payload: DirSyncSignaturesPayload) -> TaskReport {
+        let result = self
+            .file_manager
+            .sync_signatures(&payload.root, payload.block_size, &payload.filter)
+            .await;
+
+        if let Some(audit) = self.audit_for(payload.operator) {
+            let (count, audit_result, error) = match &result {
+                Ok(tree) => (tree.files.len(), AuditResult::Success, None),
+                Err(e) => (0, AuditResult::Error, Some(e.to_string())),
+            };
+            let _ = audit.log_file_list(None, &payload.root, count, &task_id, audit_result, error);
+        }
+
+        report(task_id, result)
+    }
+
+    async fn handle_sync_apply(&self, task_id: String, payload: DirSyncApplyPayload) -> TaskReport {
+        let result = self.file_manager.sync_apply(&payload.root, &payload.manifest).await;
+        let detail = match &result {
+            Ok(summary) => format!(
+                "updated={} unchanged={} deleted={}",
+                summary.updated,
+                summary.unchanged,
+                summary.deleted.len()
+            ),
+            Err(_) => format!("entries={}", payload.manifest.entries.len()),
+        };
+        self.audit_modify(payload.operator, FileModifyEvent::new("sync", &payload.root, &task_id).with_detail(detail), &result);
+        report(task_id, result)
+    }
+
+    /// 记录文件修改操作的审计事件
+    fn audit_modify<T>(&self, operator: Option<String>, event: FileModifyEvent<'_>, result: &anyhow::Result<T>) {
+        if let Some(audit) = self.audit_for(operator) {
+            let (audit_result, error) = match result {
+                Ok(_) => (AuditResult::Success, None),
+                Err(e) => (AuditResult::Error, Some(e.to_string())),
+            };
+            let _ = audit.log_file_modify(event, audit_result, error);
+        }
+    }
+
+    /// 按任务的操作者派生审计记录器
+    fn audit_for(&self, operator: Option<String>) -> Option<AuditLogger> {
+        self.audit_logger.as_ref().map(|audit| audit.for_operator(operator))
+    }
+}
+
+/// 检查能否以该 ID 登记新的搜索
+fn search_slot(searches: &HashMap<String, RunningSearch>, search_id: &str) -> anyhow::Result<()> {
+    if searches.contains_key(search_id) {
+        return Err(anyhow::anyhow!("Search already running: {}", search_id));
+    }
+    if searches.len() >= MAX_CONCURRENT_SEARCHES {
+        return Err(anyhow::anyhow!("Too many concurrent searches"));
+    }
+    Ok(())
+}
+
+fn report<T: Serialize>(task_id: String, result: anyhow::Result<T>) -> TaskReport {
+    match result {
+        Ok(value) => completed(task_id, serde_json::json!(value)),
+        Err(e) => failed(task_id, e.to_string()),
+    }
+}
+
+fn completed(task_id: String, result: serde_json::Value) -> TaskReport {
+    TaskReport {
+        task_id,
+        status: "completed".to_string(),
+        result,
+        output_cursor: 0,
+        output_chunk: String::new(),
+    }
+}
+
+fn failed(task_id: String, error: String) -> TaskReport {
+    TaskReport {
+        task_id,
+        status: "failed".to_string(),
+        result: serde_json::json!({ "error": error }),
+        output_cursor: 0,
+        output_chunk: String::new(),
+    }
+}
+
+/// 写入失败；etag 冲突时附带文件当前的 SHA-256
+fn write_failed(task_id: String, error: anyhow::Error) -> TaskReport {
+    let mut report = failed(task_id, error.to_string());
+    if let Some(conflict) = error.downcast_ref::<EditConflict>() {
+        report.result["conflict"] = serde_json::json!(true);
+        report.result["current_etag"] = serde_json::json!(conflict.current);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::audit::{AuditEventData, AuditEventType};
+    use crate::core::path_policy::AccessMode;
+    use crate::core::test_support::file_manager_for;
+    use sha2::{Digest, Sha256};
+    use tempfile::tempdir;
+
+    fn task_path(dir: &std::path::Path, name: &str) -> FilePathPayload {
+        FilePathPayload {
+            path: dir.join(name).to_string_lossy().to_string(),
+            operator: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_task_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let handler = FileTaskHandler::new(file_manager_for(
+            temp_dir.path(),
+            AccessMode::ReadWrite,
+            &["**/secret*"],
+            1024,
+        ));
+
+        let content = b"hello tasks";
+        let write = handler
+            .handle_task(FileTask::FileWrite {
+                task_id: "w".to_string(),
+                revision: 1,
+                payload: FileWritePayload {
+                    path: temp_dir.path().join("a.txt").to_string_lossy().to_string(),
+                    content: general_purpose::STANDARD.encode(content),
+                    checksum: format!("{:x}", Sha256::digest(content)),
+                    expected_etag: None,
+                    operator: None,
+                },
+            })
+            .await;
+        assert_eq!(write.status, "completed");
+
+        let read = handler
+            .handle_task(FileTask::FileRead {
+                task_id: "r".to_string(),
+                revision: 1,
+                payload: task_path(temp_dir.path(), "a.txt"),
+            })
+            .await;
+        assert_eq!(read.status, "completed");
+        assert_eq!(
+            general_purpose::STANDARD.decode(read.result["content"].as_str().unwrap()).unwrap(),
+            content
+        );
+
+        let stat = handler
+            .handle_task(FileTask::FileStat {
+                task_id: "s".to_string(),
+                revision: 1,
+                payload: task_path(temp_dir.path(), "a.txt"),
+            })
+            .await;
+        assert_eq!(stat.result["size"], content.len());
+
+        let list = handler
+            .handle_task(FileTask::FileList {
+                task_id: "l".to_string(),
+                revision: 1,
+                payload: serde_json::from_value(serde_json::json!({
+                    "path": temp_dir.path().to_string_lossy(),
+                    "include_checksums": true,
+                    "sort": "size",
+                    "limit": 10,
+                }))
+                .unwrap(),
+            })
+            .await;
+        assert_eq!(list.result["files"].as_array().unwrap().len(), 1);
+        assert_eq!(list.result["total"], 1);
+        assert_eq!(list.result["files"][0]["checksum"], format!("{:x}", Sha256::digest(content)));
+
+        let delete = handler
+            .handle_task(FileTask::FileDelete {
+                task_id: "d".to_string(),
+                revision: 1,
+                payload: task_path(temp_dir.path(), "a.txt"),
+            })
+            .await;
+        assert_eq!(delete.status, "completed");
+        assert!(!temp_dir.path().join("a.txt").exists());
+
+        // 路径校验与直连通道一致
+        let denied = handler
+            .handle_task(FileTask::FileRead {
+                task_id: "x".to_string(),
+                revision: 1,
+                payload: task_path(temp_dir.path(), "secret.txt"),
+            })
+            .await;
+        assert_eq!(denied.status, "failed");
+        assert!(denied.result["error"].as_str().unwrap().contains("denied"));
+    }
+
+    #[tokio::test]
+    async fn test_search_reports_kept_until_confirmed() {
+        let temp_dir = tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("a.conf"), "x").unwrap();
+        std::fs::write(temp_dir.path().join("b.conf"), "y").unwrap();
+        let (audit, mut events) = AuditLogger::new("test-device".to_string());
+        let handler = FileTaskHandler::new(file_manager_for(temp_dir.path(), AccessMode::ReadOnly, &[], 1024))
+            .with_audit_logger(audit);
+
+        let started = handler
+            .handle_task(FileTask::FileSearch {
+                task_id: "find".to_string(),
+                revision: 1,
+                payload: FileSearchPayload {
+                    search_id: "s1".to_string(),
+                    query: SearchQuery {
+                        root: temp_dir.path().to_string_lossy().to_string(),
+                        name: vec!["*.conf".to_string()],
+                        ..Default::default()
+                    },
+                    operator: None,
+                },
+            })
+            .await;
+        assert_eq!(started.status, "running");
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        let reports = loop {
+            let reports = handler.collect_search_reports();
+            if reports.iter().any(|r| r.status == "completed") || std::time::Instant::now() > deadline {
+                break reports;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        };
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].task_id, "find");
+        assert_eq!(reports[0].result["matches"].as_array().unwrap().len(), 2);
+
+        // 心跳发送失败（未确认）时重新收集仍能得到全部结果
+        let retried = handler.collect_search_reports();
+        assert_eq!(retried.len(), 1);
+        assert_eq!(retried[0].result["matches"].as_array().unwrap().len(), 2);
+
+        // 搜索结束并确认后才记录审计，附带匹配总数
+        assert!(events.try_recv().is_err());
+        handler.confirm_search_reports();
+        assert!(handler.collect_search_reports().is_empty());
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.event_type, AuditEventType::FileSearch);
+        match event.data {
+            AuditEventData::FileSearch { match_count, operation_id, .. } => {
+                assert_eq!(match_count, 2);
+                assert_eq!(operation_id, "find");
+            }
+            other => panic!("unexpected audit data: {:?}", other),
+        }
+    }
+}
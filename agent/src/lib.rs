@@ -6,6 +6,8 @@ pub mod core;
 pub mod platform;
 pub mod transport;
 pub mod terminal;
+pub mod tunnel;
 pub mod task_handler;
+pub mod file_tasks;
 
 pub use crate::core::Agent;
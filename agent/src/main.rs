@@ -7,9 +7,13 @@ mod core;
 mod platform;
 mod transport;
 mod terminal;
+mod tunnel;
 mod task_handler;
+mod file_tasks;
 
 use crate::config::{BootstrapConfig, ConfigManager};
+use crate::core::audit_chain::{verify_journal, CHAIN_STATE_FILE, JOURNAL_FILE};
+use crate::core::crypto::CryptoManager;
 use crate::core::Agent;
 
 // 构建时信息
@@ -25,6 +29,7 @@ async fn main() -> Result<()> {
     let mut server_url: Option<String> = None;
     let mut enrollment_token: Option<String> = None;
     let mut service_mode = false;
+    let mut verify_audit_dir: Option<String> = None;
 
     let mut i = 1;
     while i < args.len() {
@@ -47,6 +52,15 @@ async fn main() -> Result<()> {
                     std::process::exit(1);
                 }
             }
+            "--verify-audit" => {
+                if i + 1 < args.len() {
+                    verify_audit_dir = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    eprintln!("错误: --verify-audit 需要指定目录");
+                    std::process::exit(1);
+                }
+            }
             "--service" => {
                 service_mode = true;
                 i += 1;
@@ -88,6 +102,11 @@ async fn main() -> Result<()> {
 
     let config_manager = ConfigManager::new(bootstrap);
 
+    if let Some(dir) = verify_audit_dir {
+        let intact = verify_audit(&dir, &config_manager.config().credentials_path())?;
+        std::process::exit(if intact { 0 } else { 2 });
+    }
+
     // 初始化日志
     // 优先使用 RUST_LOG 环境变量，如果没有设置，则使用配置中的日志级别
     let log_level = &config_manager.config().logging.level;
@@ -165,6 +184,21 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// 校验数据目录中的本地审计日志：批次签名、哈希链，以及与保存的链头是否一致
+///
+/// 没有设备凭据时无法校验签名，视为校验失败。
+fn verify_audit(dir: &str, credentials_path: &std::path::Path) -> Result<bool> {
+    if !credentials_path.exists() {
+        eprintln!("未找到设备凭据 {}，无法校验审计日志签名", credentials_path.display());
+        return Ok(false);
+    }
+    let crypto = CryptoManager::from_credentials_file(credentials_path)?;
+    let dir = std::path::Path::new(dir);
+    let report = verify_journal(&dir.join(JOURNAL_FILE), &dir.join(CHAIN_STATE_FILE), &crypto)?;
+    println!("{}", report);
+    Ok(report.is_intact())
+}
+
 fn print_help() {
     println!("Ruinos Agent v{}", env!("CARGO_PKG_VERSION"));
     println!("Remote Monitoring and Management Agent");
@@ -176,6 +210,7 @@ fn print_help() {
     println!("    --server <URL>     指定服务器 URL (env: RMM_SERVER_URL)");
     println!("    --token <TOKEN>    指定注册令牌 (env: RMM_ENROLLMENT_TOKEN)");
     println!("    --service          以服务模式运行");
+    println!("    --verify-audit <DIR>  校验数据目录中审计日志的哈希链、签名与链头");
     println!("    --help, -h         显示帮助信息");
     println!("    --version, -v      显示版本信息");
     println!();
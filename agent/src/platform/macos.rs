@@ -1,5 +1,5 @@
 #[cfg(target_os = "macos")]
-use super::{calculate_checksum, CommandExecutor, FileInfo, FileSystem};
+use super::{atomic_write, CommandExecutor, FileInfo, FileSystem};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use std::path::Path;
@@ -62,16 +62,8 @@ impl FileSystem for MacOSFileSystem {
             let is_dir = metadata.is_dir();
             let size = if is_dir { 0 } else { metadata.len() };
 
-            // Calculate checksum for files (not directories)
-            let checksum = if !is_dir && size > 0 && size < 10 * 1024 * 1024 {
-                // Only for files < 10MB
-                match fs::read(&entry.path()).await {
-                    Ok(data) => Some(calculate_checksum(&data)),
-                    Err(_) => None, // Skip checksum if file can't be read
-                }
-            } else {
-                None
-            };
+            // 校验和由 list_files_with_checksums 按需流式计算，列目录时不读取文件内容
+            let checksum = None;
 
             files.push(FileInfo {
                 path: entry.path(),
@@ -92,19 +84,11 @@ impl FileSystem for MacOSFileSystem {
     }
 
     async fn write_file(&self, path: &Path, data: &[u8]) -> Result<()> {
-        // Create parent directories if they don't exist
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).await.map_err(|e| {
-                anyhow!(
-                    "Failed to create parent directories for {}: {}",
-                    path.display(),
-                    e
-                )
-            })?;
-        }
-
-        fs::write(path, data)
+        // 临时文件 + fsync + 重命名，避免中途崩溃截断原文件
+        let path = path.to_path_buf();
+        let data = data.to_vec();
+        tokio::task::spawn_blocking(move || atomic_write(&path, &data))
             .await
-            .map_err(|e| anyhow!("Failed to write file {}: {}", path.display(), e))
+            .map_err(|e| anyhow!("Write task failed: {}", e))?
     }
 }
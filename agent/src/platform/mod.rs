@@ -4,6 +4,8 @@ use sha2::{Digest, Sha256};
 use std::path::{Path, PathBuf};
 use std::process::Output;
 
+use crate::core::path_policy::{Access, AccessMode, PathPolicy, PathRule};
+
 #[cfg(test)]
 pub mod tests;
 
@@ -19,18 +21,36 @@ pub trait FileSystem {
     async fn read_file(&self, path: &Path) -> Result<Vec<u8>>;
     async fn write_file(&self, path: &Path, data: &[u8]) -> Result<()>;
 
+    /// 流式计算文件 SHA-256，不将整个文件读入内存
+    async fn checksum(&self, path: &Path) -> Result<String> {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || crate::core::transfer::file_checksum(&path))
+            .await
+            .map_err(|e| anyhow!("Checksum task failed: {}", e))?
+    }
+
+    /// 列出目录并为普通文件计算校验和；`list_files` 本身不读取文件内容
+    async fn list_files_with_checksums(&self, path: &Path) -> Result<Vec<FileInfo>> {
+        let mut files = self.list_files(path).await?;
+        for file in files.iter_mut().filter(|file| !file.is_dir) {
+            // 无法读取的文件不带校验和
+            file.checksum = self.checksum(&file.path).await.ok();
+        }
+        Ok(files)
+    }
+
     // 带安全策略的文件操作
     async fn list_files_secure(
         &self,
         path: &Path,
         policy: &PathSecurityPolicy,
     ) -> Result<Vec<FileInfo>> {
-        policy.is_path_allowed(path)?;
+        policy.is_path_allowed(path, Access::Read)?;
         self.list_files(path).await
     }
 
     async fn read_file_secure(&self, path: &Path, policy: &PathSecurityPolicy) -> Result<Vec<u8>> {
-        policy.is_path_allowed(path)?;
+        policy.is_path_allowed(path, Access::Read)?;
         let data = self.read_file(path).await?;
         policy.validate_file_size(data.len() as u64)?;
         Ok(data)
@@ -42,7 +62,7 @@ pub trait FileSystem {
         data: &[u8],
         policy: &PathSecurityPolicy,
     ) -> Result<()> {
-        policy.is_path_allowed(path)?;
+        policy.is_path_allowed(path, Access::Write)?;
         policy.validate_file_size(data.len() as u64)?;
         self.write_file(path, data).await
     }
@@ -97,74 +117,27 @@ impl Default for PathSecurityPolicy {
 }
 
 impl PathSecurityPolicy {
-    pub fn is_path_allowed(&self, path: &Path) -> Result<()> {
-        // For non-existent files, canonicalize the parent directory instead
-        let canonical_path = if path.exists() {
-            path.canonicalize()
-                .map_err(|e| anyhow!("Failed to canonicalize path: {}", e))?
-        } else {
-            // If file doesn't exist, canonicalize parent and append filename
-            if let Some(parent) = path.parent() {
-                let canonical_parent = parent
-                    .canonicalize()
-                    .map_err(|e| anyhow!("Failed to canonicalize parent path: {}", e))?;
-                if let Some(filename) = path.file_name() {
-                    canonical_parent.join(filename)
-                } else {
-                    return Err(anyhow!("Invalid path: no filename component"));
-                }
-            } else {
-                return Err(anyhow!("Invalid path: no parent directory"));
-            }
-        };
-
-        // Check if path is blocked
-        for blocked in &self.blocked_paths {
-            if canonical_path.starts_with(blocked) {
-                return Err(anyhow!("Path is blocked by security policy: {:?}", path));
-            }
-        }
-
-        // Check if path is in allowed list (if not empty)
-        if !self.allowed_paths.is_empty() {
-            let mut allowed = false;
-            for allowed_path in &self.allowed_paths {
-                // Canonicalize allowed path for comparison
-                let canonical_allowed = allowed_path.canonicalize().map_err(|e| {
-                    anyhow!(
-                        "Failed to canonicalize allowed path {:?}: {}",
-                        allowed_path,
-                        e
-                    )
-                })?;
-                if canonical_path.starts_with(&canonical_allowed) {
-                    allowed = true;
-                    break;
-                }
-            }
-            if !allowed {
-                return Err(anyhow!("Path not in allowed list: {:?}", path));
-            }
-        }
-
-        // Check for invalid filenames
-        if let Some(filename) = canonical_path.file_name() {
-            let filename_str = filename.to_string_lossy();
-            if filename_str == "." || filename_str == ".." {
-                return Err(anyhow!("Invalid filename: {:?}", filename_str));
-            }
-        }
+    /// 按统一的路径访问策略检查：允许列表为读写目录（为空时不限制），阻止列表为拒绝目录
+    pub fn is_path_allowed(&self, path: &Path, access: Access) -> Result<()> {
+        self.path_policy().check(path, access).map(|_| ())
+    }
 
-        // Check hidden files
-        if !self.allow_hidden_files {
-            if let Some(filename) = canonical_path.file_name() {
-                if filename.to_string_lossy().starts_with('.') {
-                    return Err(anyhow!("Hidden files not allowed: {:?}", path));
-                }
-            }
+    /// 转换为统一的路径访问策略
+    pub fn path_policy(&self) -> PathPolicy {
+        let mut rules: Vec<PathRule> = self
+            .allowed_paths
+            .iter()
+            .map(|dir| PathRule::directory(&dir.to_string_lossy(), AccessMode::ReadWrite))
+            .collect();
+        if rules.is_empty() {
+            rules.push(PathRule::new("**", AccessMode::ReadWrite));
         }
-
-        Ok(())
+        rules.extend(
+            self.blocked_paths
+                .iter()
+                .map(|dir| PathRule::directory(&dir.to_string_lossy(), AccessMode::Deny)),
+        );
+        PathPolicy::new(&rules).with_hidden_files(self.allow_hidden_files)
     }
 
     pub fn validate_file_size(&self, size: u64) -> Result<()> {
@@ -186,6 +159,93 @@ pub fn calculate_checksum(data: &[u8]) -> String {
     format!("{:x}", hasher.finalize())
 }
 
+/// 原子写入：先写同目录下的临时文件并 fsync，再重命名覆盖目标
+///
+/// 中途崩溃只会留下临时文件，目标要么是旧内容要么是新内容。已存在的目标文件
+/// 保留其权限位（以 root 运行时同时保留属主）。
+pub fn atomic_write(path: &Path, data: &[u8]) -> Result<()> {
+    use std::io::Write;
+
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    std::fs::create_dir_all(parent)
+        .map_err(|e| anyhow!("Failed to create parent directories for {}: {}", path.display(), e))?;
+
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid file path: {}", path.display()))?
+        .to_string_lossy();
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let tmp = parent.join(format!(".{}.{}.{}.tmp", name, std::process::id(), nanos));
+
+    let result = (|| -> std::io::Result<()> {
+        let mut file = std::fs::OpenOptions::new().write(true).create_new(true).open(&tmp)?;
+        if let Ok(existing) = std::fs::metadata(path) {
+            file.set_permissions(existing.permissions())?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                // 非 root 无法改属主，忽略失败
+                let _ = std::os::unix::fs::fchown(&file, Some(existing.uid()), Some(existing.gid()));
+            }
+        }
+        file.write_all(data)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)?;
+        sync_dir(parent)
+    })();
+
+    result.map_err(|e| {
+        let _ = std::fs::remove_file(&tmp);
+        anyhow!("Failed to write file {}: {}", path.display(), e)
+    })
+}
+
+/// 同步目录项，使其中的创建与重命名在断电后保留（非 Unix 平台无需也无法同步目录）
+pub fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    std::fs::File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+/// 打开操作者可写目录中的暂存文件：不跟随符号链接，且必须是当前用户拥有的普通文件
+///
+/// Agent 通常以 root 运行，跟随预先放置的符号链接会截断或写入任意文件。
+pub fn open_staging_file(path: &Path, options: &mut std::fs::OpenOptions) -> std::io::Result<std::fs::File> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+
+        let file = options.custom_flags(libc::O_NOFOLLOW).open(path)?;
+        let metadata = file.metadata()?;
+        let euid = unsafe { libc::geteuid() };
+        if !metadata.is_file() || metadata.uid() != euid {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("Staging file is not a regular file owned by the agent: {}", path.display()),
+            ));
+        }
+        Ok(file)
+    }
+    #[cfg(not(unix))]
+    {
+        if std::fs::symlink_metadata(path).is_ok_and(|m| !m.is_file()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("Staging file is not a regular file: {}", path.display()),
+            ));
+        }
+        options.open(path)
+    }
+}
+
 // 平台特定实现模块
 #[cfg(all(target_os = "windows", feature = "windows"))]
 pub mod windows;
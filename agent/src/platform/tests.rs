@@ -221,6 +221,27 @@ mod platform_isolation_tests {
         assert!(true, "Platform trait interfaces are consistent");
     }
 
+    #[tokio::test]
+    async fn test_list_files_with_checksums() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("a.txt"), b"abc").unwrap();
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        let fs = create_file_system().expect("应该能创建文件系统");
+
+        // 默认列目录不计算校验和
+        let files = fs.list_files(temp_dir.path()).await.unwrap();
+        assert!(files.iter().all(|file| file.checksum.is_none()));
+
+        let files = fs.list_files_with_checksums(temp_dir.path()).await.unwrap();
+        for file in files {
+            if file.is_dir {
+                assert!(file.checksum.is_none());
+            } else {
+                assert_eq!(file.checksum, Some(calculate_checksum(b"abc")));
+            }
+        }
+    }
+
     #[test]
     fn test_platform_conditional_compilation() {
         // 验证条件编译正确工作
@@ -1,22 +1,27 @@
 // agent/src/task_handler.rs
 // Agent 端任务处理器（集成终端任务）
 
+use base64::{engine::general_purpose, Engine as _};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use crate::terminal::{TerminalManager, SessionConfig, ShellType};
+use crate::core::audit::{AuditLogger, AuditResult};
+use crate::terminal::viewer::Viewer;
+use crate::terminal::{OutputRead, TerminalManager, TerminalSession, SessionConfig, SessionState, ShellType, ViewerRole};
+use crate::tunnel::{
+    SocksReply, SocksRequest, TunnelConnection, TunnelKind, TunnelManager, TunnelMeta, TunnelRead,
+};
 
 /// 会话 cursor 追踪器
+#[derive(Default)]
 pub struct SessionCursorTracker {
     last_reported_cursors: Arc<std::sync::Mutex<HashMap<String, u64>>>,
 }
 
 impl SessionCursorTracker {
     pub fn new() -> Self {
-        Self {
-            last_reported_cursors: Arc::new(std::sync::Mutex::new(HashMap::new())),
-        }
+        Self::default()
     }
 
     /// 获取上次上报的 cursor
@@ -69,17 +74,101 @@ pub enum Task {
         revision: u32,
         payload: SessionClosePayload,
     },
+    SessionSnapshot {
+        task_id: String,
+        revision: u32,
+        payload: SessionSnapshotPayload,
+    },
+    SessionAttach {
+        task_id: String,
+        revision: u32,
+        payload: SessionAttachPayload,
+    },
+    SessionDetach {
+        task_id: String,
+        revision: u32,
+        payload: SessionDetachPayload,
+    },
+    TunnelOpen {
+        task_id: String,
+        revision: u32,
+        payload: TunnelOpenPayload,
+    },
+    TunnelData {
+        task_id: String,
+        revision: u32,
+        payload: TunnelDataPayload,
+    },
+    TunnelClose {
+        task_id: String,
+        revision: u32,
+        payload: TunnelClosePayload,
+    },
+    SocksConnect {
+        task_id: String,
+        revision: u32,
+        payload: SocksConnectPayload,
+    },
     // 其他任务类型...
 }
 
+impl Task {
+    pub fn task_id(&self) -> &str {
+        match self {
+            Task::SessionOpen { task_id, .. }
+            | Task::SessionInput { task_id, .. }
+            | Task::SessionResize { task_id, .. }
+            | Task::SessionClose { task_id, .. }
+            | Task::SessionSnapshot { task_id, .. }
+            | Task::SessionAttach { task_id, .. }
+            | Task::SessionDetach { task_id, .. }
+            | Task::TunnelOpen { task_id, .. }
+            | Task::TunnelData { task_id, .. }
+            | Task::TunnelClose { task_id, .. }
+            | Task::SocksConnect { task_id, .. } => task_id,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionOpenPayload {
     pub session_id: String,
+    /// 缺省使用当前用户的默认 shell
+    #[serde(default)]
     pub shell_type: ShellType,
+    /// 自定义程序，设置后忽略 shell_type
+    #[serde(default)]
+    pub program: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// 是否以登录 shell 启动
+    #[serde(default)]
+    pub login: bool,
     pub cwd: Option<String>,
     pub env: Option<std::collections::HashMap<String, String>>,
     pub cols: u16,
     pub rows: u16,
+    /// 空闲超时（秒），缺省使用 Agent 配置，0 表示不限制
+    #[serde(default)]
+    pub idle_timeout: Option<u64>,
+    /// 最长存活时间（秒），缺省使用 Agent 配置，0 表示不限制
+    #[serde(default)]
+    pub max_duration: Option<u64>,
+    /// 输出缓冲区容量（字节），缺省使用 Agent 配置
+    #[serde(default)]
+    pub buffer_size: Option<usize>,
+    /// 是否捕获命令行用于审计，缺省使用 Agent 配置
+    #[serde(default)]
+    pub capture_commands: Option<bool>,
+    /// 操作者标识（用于审计）
+    #[serde(default)]
+    pub operator: Option<String>,
+    /// 输出上报速率上限（字节/秒），缺省使用 Agent 配置，0 表示不限速
+    #[serde(default)]
+    pub output_rate_limit: Option<u64>,
+    /// 输出上报突发容量（字节），缺省使用 Agent 配置
+    #[serde(default)]
+    pub output_burst: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -87,6 +176,12 @@ pub struct SessionInputPayload {
     pub session_id: String,
     pub client_seq: u64,
     pub input_data: String,
+    /// 发起输入的查看者；缺省视为会话创建者
+    #[serde(default)]
+    pub viewer_id: Option<String>,
+    /// 操作者标识（用于审计），缺省使用会话创建者
+    #[serde(default)]
+    pub operator: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -94,6 +189,8 @@ pub struct SessionResizePayload {
     pub session_id: String,
     pub cols: u16,
     pub rows: u16,
+    #[serde(default)]
+    pub operator: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -101,6 +198,74 @@ pub struct SessionClosePayload {
     pub session_id: String,
     #[serde(default)]
     pub force: bool,
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSnapshotPayload {
+    pub session_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionAttachPayload {
+    pub session_id: String,
+    pub viewer_id: String,
+    pub role: ViewerRole,
+    /// 操作者标识（用于审计）
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionDetachPayload {
+    pub session_id: String,
+    pub viewer_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelOpenPayload {
+    pub tunnel_id: String,
+    pub host: String,
+    pub port: u16,
+    /// 操作者标识（用于审计）
+    #[serde(default)]
+    pub operator: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelDataPayload {
+    pub tunnel_id: String,
+    /// data 首字节在服务端 → 目标方向上的 offset
+    #[serde(default)]
+    pub offset: u64,
+    /// Base64 编码的数据
+    #[serde(default)]
+    pub data: String,
+    /// 服务端已收到的回传 offset，用于释放窗口
+    #[serde(default)]
+    pub ack: Option<u64>,
+    /// 服务端不再发送数据，关闭写方向
+    #[serde(default)]
+    pub eof: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelClosePayload {
+    pub tunnel_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SocksConnectPayload {
+    /// 连接建立后作为隧道 ID，数据经 tunnel_data 传输
+    pub tunnel_id: String,
+    /// Base64 编码的 SOCKS5 请求报文
+    pub request: String,
+    /// 操作者本地 SOCKS 监听会话 ID，用于按会话统计流量
+    #[serde(default)]
+    pub proxy_session_id: Option<String>,
+    #[serde(default)]
+    pub operator: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -115,6 +280,8 @@ pub struct TaskReport {
 pub struct TaskHandler {
     terminal_manager: Arc<TerminalManager>,
     cursor_tracker: SessionCursorTracker,
+    audit_logger: Option<AuditLogger>,
+    tunnel_manager: Option<Arc<TunnelManager>>,
 }
 
 impl TaskHandler {
@@ -122,9 +289,23 @@ impl TaskHandler {
         Self {
             terminal_manager,
             cursor_tracker: SessionCursorTracker::new(),
+            audit_logger: None,
+            tunnel_manager: None,
         }
     }
 
+    /// 启用 TCP 隧道
+    pub fn with_tunnel_manager(mut self, tunnel_manager: Arc<TunnelManager>) -> Self {
+        self.tunnel_manager = Some(tunnel_manager);
+        self
+    }
+
+    /// 设置审计日志记录器
+    pub fn with_audit_logger(mut self, audit_logger: AuditLogger) -> Self {
+        self.audit_logger = Some(audit_logger);
+        self
+    }
+
     /// 处理任务
     pub fn handle_task(&self, task: Task) -> TaskReport {
         match task {
@@ -140,6 +321,46 @@ impl TaskHandler {
             Task::SessionClose { task_id, payload, .. } => {
                 self.handle_session_close(task_id, payload)
             }
+            Task::SessionSnapshot { task_id, payload, .. } => {
+                self.handle_session_snapshot(task_id, payload)
+            }
+            Task::SessionAttach { task_id, payload, .. } => {
+                self.handle_session_attach(task_id, payload)
+            }
+            Task::SessionDetach { task_id, payload, .. } => {
+                self.handle_session_detach(task_id, payload)
+            }
+            Task::TunnelOpen { task_id, payload, .. } => {
+                self.handle_tunnel_open(task_id, payload)
+            }
+            Task::TunnelData { task_id, payload, .. } => {
+                self.handle_tunnel_data(task_id, payload)
+            }
+            Task::TunnelClose { task_id, payload, .. } => {
+                self.handle_tunnel_close(task_id, payload)
+            }
+            Task::SocksConnect { task_id, payload, .. } => {
+                self.handle_socks_connect(task_id, payload)
+            }
+        }
+    }
+
+    /// 在阻塞线程池中处理任务
+    ///
+    /// 隧道拨号会做 DNS 解析并等待连接超时，写入目标也可能阻塞到写超时，
+    /// 异步上下文中应通过此方法调用，避免拖住心跳。
+    pub async fn handle_task_blocking(self: &Arc<Self>, task: Task) -> TaskReport {
+        let task_id = task.task_id().to_string();
+        let handler = Arc::clone(self);
+        match tokio::task::spawn_blocking(move || handler.handle_task(task)).await {
+            Ok(report) => report,
+            Err(e) => TaskReport {
+                task_id,
+                status: "failed".to_string(),
+                result: serde_json::json!({ "error": format!("Task handler failed: {}", e) }),
+                output_cursor: 0,
+                output_chunk: String::new(),
+            },
         }
     }
 
@@ -148,13 +369,38 @@ impl TaskHandler {
         let config = SessionConfig {
             session_id: payload.session_id.clone(),
             shell_type: payload.shell_type,
+            program: payload.program,
+            args: payload.args,
+            login: payload.login,
             cwd: payload.cwd,
             env: payload.env,
             cols: payload.cols,
             rows: payload.rows,
+            idle_timeout: payload.idle_timeout,
+            max_duration: payload.max_duration,
+            buffer_size: payload.buffer_size,
+            capture_commands: payload.capture_commands,
+            operator: payload.operator.clone(),
+            output_rate_limit: payload.output_rate_limit,
+            output_burst: payload.output_burst,
         };
 
-        match self.terminal_manager.create_session(config) {
+        let created = self.terminal_manager.create_session(config);
+        if let Some(audit) = self.audit_for(payload.operator.clone()) {
+            let connection_time = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let (result, error) = match created {
+                Ok(_) => (AuditResult::Success, None),
+                Err(ref e) => (AuditResult::Error, Some(e.to_string())),
+            };
+            if let Err(e) = audit.log_session_connect(&payload.session_id, connection_time, result, error) {
+                tracing::warn!("Failed to audit session open: {}", e);
+            }
+        }
+
+        match created {
             Ok(session) => {
                 let state = session.get_state();
                 let pid = session.get_pid();
@@ -195,44 +441,69 @@ impl TaskHandler {
     fn handle_session_input(&self, task_id: String, payload: SessionInputPayload) -> TaskReport {
         match self.terminal_manager.get_session(&payload.session_id) {
             Some(session) => {
+                // 只读观察者、未附加的查看者以及有查看者时未标明身份的输入都不能写入
+                if let Err(reason) = session.check_input_allowed(payload.viewer_id.as_deref()) {
+                    tracing::warn!(
+                        "Rejected input from viewer {:?} on session {}: {}",
+                        payload.viewer_id,
+                        payload.session_id,
+                        reason
+                    );
+                    return TaskReport {
+                        task_id,
+                        status: "failed".to_string(),
+                        result: serde_json::json!({
+                            "session_id": payload.session_id,
+                            "viewer_id": payload.viewer_id,
+                            "client_seq": payload.client_seq,
+                            "error": reason,
+                        }),
+                        output_cursor: session.get_output_cursor(),
+                        output_chunk: String::new(),
+                    };
+                }
+
                 let input_bytes = payload.input_data.as_bytes();
-                match session.write_input(payload.client_seq, input_bytes) {
+                let written = session.write_input(payload.client_seq, input_bytes);
+                let operator = payload.operator.clone().or_else(|| session.operator.clone());
+                if let Some(audit) = self.audit_for(operator) {
+                    let (result, error) = match written {
+                        Ok(_) => (AuditResult::Success, None),
+                        Err(ref e) => (AuditResult::Error, Some(e.to_string())),
+                    };
+                    if let Err(e) = audit.log_terminal_input(
+                        &payload.session_id,
+                        payload.viewer_id.clone(),
+                        payload.client_seq,
+                        input_bytes.len(),
+                        result,
+                        error,
+                    ) {
+                        tracing::warn!("Failed to audit terminal input: {}", e);
+                    }
+                }
+                self.audit_captured_commands(&session);
+
+                match written {
                     Ok(bytes_written) => {
                         // 获取输出增量
-                        let last_cursor = self.cursor_tracker.get_last_cursor(&payload.session_id);
-                        let current_cursor = session.get_output_cursor();
-
-                        let (new_cursor, chunk) = match session.get_output_chunk(last_cursor) {
-                            Ok((cursor, data)) => (cursor, data),
-                            Err(crate::terminal::session::BufferError::DataLost {
-                                oldest_available,
-                                ..
-                            }) => {
-                                // 数据丢失，从最旧可用位置开始
-                                eprintln!(
-                                    "Warning: Output data lost for session {}, resetting cursor from {} to {}",
-                                    payload.session_id, last_cursor, oldest_available
-                                );
-                                session
-                                    .get_output_chunk(oldest_available)
-                                    .unwrap_or((current_cursor, Vec::new()))
-                            }
-                            Err(_) => (current_cursor, Vec::new()),
-                        };
-
-                        self.cursor_tracker
-                            .update_cursor(&payload.session_id, new_cursor);
+                        let (last_cursor, output) = self.read_new_output(&session);
+
+                        let mut result = serde_json::json!({
+                            "session_id": payload.session_id,
+                            "client_seq": payload.client_seq,
+                            "bytes_written": bytes_written,
+                            "acked_seq": session.acked_input_seq(),
+                            "pending_input": session.pending_input_count(),
+                        });
+                        attach_output_gap(&mut result, last_cursor, &output);
 
                         TaskReport {
                             task_id,
                             status: "completed".to_string(),
-                            result: serde_json::json!({
-                                "session_id": payload.session_id,
-                                "client_seq": payload.client_seq,
-                                "bytes_written": bytes_written,
-                            }),
-                            output_cursor: new_cursor,
-                            output_chunk: String::from_utf8_lossy(&chunk).to_string(),
+                            result,
+                            output_cursor: output.next_cursor,
+                            output_chunk: String::from_utf8_lossy(&output.data).to_string(),
                         }
                     }
                     Err(e) => TaskReport {
@@ -264,17 +535,25 @@ impl TaskHandler {
     fn handle_session_resize(&self, task_id: String, payload: SessionResizePayload) -> TaskReport {
         match self.terminal_manager.get_session(&payload.session_id) {
             Some(session) => match session.resize(payload.cols, payload.rows) {
-                Ok(_) => TaskReport {
-                    task_id,
-                    status: "completed".to_string(),
-                    result: serde_json::json!({
-                        "session_id": payload.session_id,
-                        "cols": payload.cols,
-                        "rows": payload.rows,
-                    }),
-                    output_cursor: session.get_output_cursor(),
-                    output_chunk: String::new(),
-                },
+                Ok(_) => {
+                    let operator = payload.operator.clone().or_else(|| session.operator.clone());
+                    if let Some(audit) = self.audit_for(operator) {
+                        if let Err(e) = audit.log_terminal_resize(&payload.session_id, payload.cols, payload.rows) {
+                            tracing::warn!("Failed to audit terminal resize: {}", e);
+                        }
+                    }
+                    TaskReport {
+                        task_id,
+                        status: "completed".to_string(),
+                        result: serde_json::json!({
+                            "session_id": payload.session_id,
+                            "cols": payload.cols,
+                            "rows": payload.rows,
+                        }),
+                        output_cursor: session.get_output_cursor(),
+                        output_chunk: String::new(),
+                    }
+                }
                 Err(e) => TaskReport {
                     task_id,
                     status: "failed".to_string(),
@@ -303,38 +582,65 @@ impl TaskHandler {
     fn handle_session_close(&self, task_id: String, payload: SessionClosePayload) -> TaskReport {
         match self.terminal_manager.get_session(&payload.session_id) {
             Some(session) => {
-                let last_cursor = self.cursor_tracker.get_last_cursor(&payload.session_id);
-                let (final_cursor, final_chunk) = session
-                    .get_output_chunk(last_cursor)
-                    .unwrap_or_else(|_| (session.get_output_cursor(), Vec::new()));
+                let (last_cursor, output) = self.read_new_output(&session);
+                self.audit_captured_commands(&session);
+
+                let closed = session.close(payload.force);
+                let operator = payload.operator.clone().or_else(|| session.operator.clone());
+                if let Some(audit) = self.audit_for(operator) {
+                    let reason = if payload.force { "force_closed" } else { "closed" };
+                    let (result, error) = match closed {
+                        Ok(_) => (AuditResult::Success, None),
+                        Err(ref e) => (AuditResult::Error, Some(e.to_string())),
+                    };
+                    if let Err(e) = audit.log_session_disconnect(
+                        &payload.session_id,
+                        reason,
+                        session.uptime(),
+                        result,
+                        error,
+                    ) {
+                        tracing::warn!("Failed to audit session close: {}", e);
+                    }
+                }
 
-                match session.close(payload.force) {
+                match closed {
                     Ok(exit_code) => {
                         self.terminal_manager.remove_session(&payload.session_id);
                         self.cursor_tracker.remove_session(&payload.session_id);
+                        self.detach_all_viewers(&session, "session_closed");
+
+                        let mut result = serde_json::json!({
+                            "session_id": payload.session_id,
+                            "state": "closed",
+                            "exit_code": exit_code,
+                            "signal": session.get_exit_info().and_then(|i| i.signal),
+                        });
+                        attach_output_gap(&mut result, last_cursor, &output);
 
                         TaskReport {
                             task_id,
                             status: "completed".to_string(),
-                            result: serde_json::json!({
-                                "session_id": payload.session_id,
-                                "state": "closed",
-                                "exit_code": exit_code,
-                            }),
-                            output_cursor: final_cursor,
-                            output_chunk: String::from_utf8_lossy(&final_chunk).to_string(),
+                            result,
+                            output_cursor: output.next_cursor,
+                            output_chunk: String::from_utf8_lossy(&output.data).to_string(),
                         }
                     }
-                    Err(e) => TaskReport {
-                        task_id,
-                        status: "failed".to_string(),
-                        result: serde_json::json!({
+                    Err(e) => {
+                        let mut result = serde_json::json!({
                             "session_id": payload.session_id,
                             "error": e.to_string(),
-                        }),
-                        output_cursor: final_cursor,
-                        output_chunk: String::from_utf8_lossy(&final_chunk).to_string(),
-                    },
+                        });
+                        attach_output_gap(&mut result, last_cursor, &output);
+
+                        TaskReport {
+                            task_id,
+                            status: "failed".to_string(),
+                            result,
+                            output_cursor: output.next_cursor,
+                            output_chunk: String::from_utf8_lossy(&output.data).to_string(),
+                        }
+                    }
                 }
             }
             None => TaskReport {
@@ -351,6 +657,203 @@ impl TaskHandler {
         }
     }
 
+    /// 处理 session_snapshot：返回当前屏幕状态，不影响输出上报进度
+    fn handle_session_snapshot(&self, task_id: String, payload: SessionSnapshotPayload) -> TaskReport {
+        match self.terminal_manager.get_session(&payload.session_id) {
+            Some(session) => {
+                let snapshot = session.screen_snapshot();
+                let cursor = snapshot.output_cursor;
+
+                TaskReport {
+                    task_id,
+                    status: "completed".to_string(),
+                    result: serde_json::json!({
+                        "session_id": payload.session_id,
+                        "state": session.get_state(),
+                        "screen": snapshot,
+                    }),
+                    output_cursor: cursor,
+                    output_chunk: String::new(),
+                }
+            }
+            None => TaskReport {
+                task_id,
+                status: "failed".to_string(),
+                result: serde_json::json!({
+                    "session_id": payload.session_id,
+                    "error": "Session not found",
+                }),
+                output_cursor: 0,
+                output_chunk: String::new(),
+            },
+        }
+    }
+
+    /// 处理 session_attach：附加查看者并返回当前屏幕快照
+    fn handle_session_attach(&self, task_id: String, payload: SessionAttachPayload) -> TaskReport {
+        let Some(session) = self.terminal_manager.get_session(&payload.session_id) else {
+            return TaskReport {
+                task_id,
+                status: "failed".to_string(),
+                result: serde_json::json!({
+                    "session_id": payload.session_id,
+                    "viewer_id": payload.viewer_id,
+                    "error": "Session not found",
+                }),
+                output_cursor: 0,
+                output_chunk: String::new(),
+            };
+        };
+
+        let attached = session.attach_viewer(&payload.viewer_id, payload.role, payload.operator.clone());
+        if let Some(audit) = self.audit_for(payload.operator.clone()) {
+            let (result, error) = match attached {
+                Ok(_) => (AuditResult::Success, None),
+                Err(ref e) => (AuditResult::Error, Some(e.to_string())),
+            };
+            if let Err(e) = audit.log_viewer_attach(
+                &payload.session_id,
+                &payload.viewer_id,
+                payload.role.as_str(),
+                payload.operator.clone(),
+                result,
+                error,
+            ) {
+                tracing::warn!("Failed to audit viewer attach: {}", e);
+            }
+        }
+
+        match attached {
+            Ok(snapshot) => {
+                let cursor = snapshot.output_cursor;
+                TaskReport {
+                    task_id,
+                    status: "completed".to_string(),
+                    result: serde_json::json!({
+                        "session_id": payload.session_id,
+                        "viewer_id": payload.viewer_id,
+                        "role": payload.role,
+                        "viewers": session.list_viewers(),
+                        "screen": snapshot,
+                    }),
+                    output_cursor: cursor,
+                    output_chunk: String::new(),
+                }
+            }
+            Err(e) => TaskReport {
+                task_id,
+                status: "failed".to_string(),
+                result: serde_json::json!({
+                    "session_id": payload.session_id,
+                    "viewer_id": payload.viewer_id,
+                    "error": e.to_string(),
+                }),
+                output_cursor: session.get_output_cursor(),
+                output_chunk: String::new(),
+            },
+        }
+    }
+
+    /// 处理 session_detach（幂等）
+    fn handle_session_detach(&self, task_id: String, payload: SessionDetachPayload) -> TaskReport {
+        let session = self.terminal_manager.get_session(&payload.session_id);
+        let detached = session
+            .as_ref()
+            .and_then(|s| s.detach_viewer(&payload.viewer_id));
+        if let Some(ref viewer) = detached {
+            self.audit_viewer_detach(&payload.session_id, viewer, "detached");
+        }
+
+        TaskReport {
+            task_id,
+            status: "completed".to_string(),
+            result: serde_json::json!({
+                "session_id": payload.session_id,
+                "viewer_id": payload.viewer_id,
+                "detached": detached.is_some(),
+            }),
+            output_cursor: session.map(|s| s.get_output_cursor()).unwrap_or(0),
+            output_chunk: String::new(),
+        }
+    }
+
+    /// 按操作者生成审计记录器；未配置审计时返回 None
+    fn audit_for(&self, operator: Option<String>) -> Option<AuditLogger> {
+        self.audit_logger.as_ref().map(|audit| audit.for_operator(operator))
+    }
+
+    /// 审计会话中已捕获的命令行（未开启命令捕获时为空）
+    fn audit_captured_commands(&self, session: &TerminalSession) {
+        let commands = session.take_captured_commands();
+        if commands.is_empty() {
+            return;
+        }
+        if let Some(audit) = self.audit_for(session.operator.clone()) {
+            for captured in commands {
+                if let Err(e) =
+                    audit.log_terminal_command(&session.session_id, &captured.command, captured.redacted)
+                {
+                    tracing::warn!("Failed to audit terminal command: {}", e);
+                }
+            }
+        }
+    }
+
+    /// 会话结束时审计断开事件
+    fn audit_session_end(&self, session: &TerminalSession, reason: &str) {
+        self.audit_captured_commands(session);
+        if let Some(audit) = self.audit_for(session.operator.clone()) {
+            if let Err(e) = audit.log_session_disconnect(
+                &session.session_id,
+                reason,
+                session.uptime(),
+                AuditResult::Success,
+                None,
+            ) {
+                tracing::warn!("Failed to audit session end: {}", e);
+            }
+        }
+    }
+
+    /// 会话结束时断开全部查看者并审计
+    fn detach_all_viewers(&self, session: &TerminalSession, reason: &str) {
+        for viewer in session.detach_all_viewers() {
+            self.audit_viewer_detach(&session.session_id, &viewer, reason);
+        }
+    }
+
+    fn audit_viewer_detach(&self, session_id: &str, viewer: &Viewer, reason: &str) {
+        if let Some(audit) = self.audit_for(viewer.operator.clone()) {
+            if let Err(e) = audit.log_viewer_detach(
+                session_id,
+                &viewer.viewer_id,
+                viewer.role.as_str(),
+                viewer.operator.clone(),
+                reason,
+                viewer.duration(),
+            ) {
+                tracing::warn!("Failed to audit viewer detach: {}", e);
+            }
+        }
+    }
+
+    /// 读取会话自上次上报以来的输出并推进上报 cursor，返回 (上次 cursor, 读取结果)
+    fn read_new_output(&self, session: &TerminalSession) -> (u64, OutputRead) {
+        let last_cursor = self.cursor_tracker.get_last_cursor(&session.session_id);
+        let output = session.read_report_output(last_cursor);
+        if output.resync {
+            tracing::warn!(
+                "Output gap in session {}: {} bytes dropped, cursor {} -> {}",
+                session.session_id,
+                output.dropped_bytes,
+                last_cursor,
+                output.next_cursor - output.data.len() as u64
+            );
+        }
+        self.cursor_tracker.update_cursor(&session.session_id, output.next_cursor);
+        (last_cursor, output)
+    }
+
     /// 收集所有会话的输出增量（用于心跳）
     pub fn collect_output_reports(&self) -> Vec<TaskReport> {
         let sessions = self.terminal_manager.list_sessions();
@@ -358,65 +861,464 @@ impl TaskHandler {
 
         for info in sessions {
             if let Some(session) = self.terminal_manager.get_session(&info.session_id) {
-                let last_cursor = self.cursor_tracker.get_last_cursor(&info.session_id);
-                let current_cursor = session.get_output_cursor();
-
-                // 只有当有新输出时才上报
-                if current_cursor > last_cursor {
-                    match session.get_output_chunk(last_cursor) {
-                        Ok((new_cursor, chunk)) => {
-                            if !chunk.is_empty() {
-                                self.cursor_tracker.update_cursor(&info.session_id, new_cursor);
-
-                                reports.push(TaskReport {
-                                    task_id: format!("heartbeat-{}", info.session_id),
-                                    status: "running".to_string(),
-                                    result: serde_json::json!({
-                                        "session_id": info.session_id,
-                                        "state": info.state,
-                                        "pid": info.pid,
-                                    }),
-                                    output_cursor: new_cursor,
-                                    output_chunk: String::from_utf8_lossy(&chunk).to_string(),
-                                });
-                            }
-                        }
-                        Err(crate::terminal::session::BufferError::DataLost {
-                            oldest_available,
-                            ..
-                        }) => {
-                            // 数据丢失，从最旧可用位置重新开始
-                            eprintln!(
-                                "Warning: Output data lost for session {}, resetting cursor to {}",
-                                info.session_id, oldest_available
-                            );
-                            if let Ok((new_cursor, chunk)) =
-                                session.get_output_chunk(oldest_available)
-                            {
-                                self.cursor_tracker.update_cursor(&info.session_id, new_cursor);
-
-                                reports.push(TaskReport {
-                                    task_id: format!("heartbeat-{}", info.session_id),
-                                    status: "running".to_string(),
-                                    result: serde_json::json!({
-                                        "session_id": info.session_id,
-                                        "state": info.state,
-                                        "pid": info.pid,
-                                        "warning": "Output buffer overflow, some data may be lost",
-                                    }),
-                                    output_cursor: new_cursor,
-                                    output_chunk: String::from_utf8_lossy(&chunk).to_string(),
-                                });
-                            }
-                        }
-                        Err(_) => {
-                            // 其他错误，跳过
-                        }
+                // 缺口等待超时的乱序输入在心跳时补写
+                let flushed = match session.flush_stale_input() {
+                    Ok(n) => n > 0,
+                    Err(e) => {
+                        tracing::warn!("Failed to flush input for session {}: {}", info.session_id, e);
+                        false
                     }
+                };
+                if flushed {
+                    self.audit_captured_commands(&session);
+                }
+
+                // 只有当有新输出、输出缺口或输入确认推进时才上报
+                let last_cursor = self.cursor_tracker.get_last_cursor(&info.session_id);
+                if session.get_output_cursor() == last_cursor && !flushed {
+                    continue;
+                }
+
+                let (last_cursor, output) = self.read_new_output(&session);
+                // 限速暂停期间没有可发送的数据
+                if output.data.is_empty() && !output.resync && output.throttle.is_none() && !flushed {
+                    continue;
                 }
+                let mut result = serde_json::json!({
+                    "session_id": info.session_id,
+                    "state": info.state,
+                    "pid": info.pid,
+                    "acked_seq": session.acked_input_seq(),
+                });
+                attach_output_gap(&mut result, last_cursor, &output);
+                // 同一段输出只上报一次，附带各查看者的起始 cursor 供服务端分发
+                let viewers = session.advance_viewers(output.next_cursor);
+                if !viewers.is_empty() {
+                    result["viewers"] = serde_json::json!(viewers);
+                }
+
+                reports.push(TaskReport {
+                    task_id: format!("heartbeat-{}", info.session_id),
+                    status: "running".to_string(),
+                    result,
+                    output_cursor: output.next_cursor,
+                    output_chunk: String::from_utf8_lossy(&output.data).to_string(),
+                });
             }
         }
 
         reports
     }
+
+    /// 回收过期会话并清理已关闭会话（用于心跳）
+    pub async fn collect_expired_reports(&self) -> Vec<TaskReport> {
+        let mut reports = Vec::new();
+
+        // 强制关闭要等待子进程退出，放到阻塞线程池执行，避免拖住心跳
+        let terminal_manager = Arc::clone(&self.terminal_manager);
+        let expired = match tokio::task::spawn_blocking(move || terminal_manager.reap_expired_sessions()).await {
+            Ok(expired) => expired,
+            Err(e) => {
+                tracing::error!("Terminal session reaper failed: {}", e);
+                return reports;
+            }
+        };
+
+        for expired in expired {
+            let session = &expired.session;
+            let session_id = session.session_id.clone();
+            let (last_cursor, output) = self.read_new_output(session);
+
+            tracing::info!(
+                "Terminal session {} expired ({}), closed after {:?}",
+                session_id,
+                expired.reason.as_str(),
+                session.uptime()
+            );
+
+            self.cursor_tracker.remove_session(&session_id);
+            self.detach_all_viewers(session, expired.reason.as_str());
+            self.audit_session_end(session, expired.reason.as_str());
+
+            let mut result = serde_json::json!({
+                "session_id": session_id,
+                "state": "closed",
+                "exit_code": expired.exit_code,
+                "signal": session.get_exit_info().and_then(|i| i.signal),
+                "reason": expired.reason,
+            });
+            attach_output_gap(&mut result, last_cursor, &output);
+
+            reports.push(TaskReport {
+                task_id: format!("heartbeat-{}", session_id),
+                status: "completed".to_string(),
+                result,
+                output_cursor: output.next_cursor,
+                output_chunk: String::from_utf8_lossy(&output.data).to_string(),
+            });
+        }
+
+        reports
+    }
+
+    /// 按策略打开隧道并审计
+    fn open_tunnel(
+        &self,
+        tunnel_id: &str,
+        host: &str,
+        port: u16,
+        meta: TunnelMeta,
+    ) -> std::io::Result<Arc<TunnelConnection>> {
+        let kind = meta.kind;
+        let operator = meta.operator.clone();
+        let proxy_session_id = meta.proxy_session_id.clone();
+        let opened = match self.tunnel_manager {
+            Some(ref tunnels) if kind != TunnelKind::Socks5 || tunnels.socks_enabled() => {
+                tunnels.open(tunnel_id, host, port, meta)
+            }
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("{} tunnels are not enabled on this agent", kind.as_str()),
+            )),
+        };
+
+        match opened {
+            Ok(ref tunnel) => tracing::info!(
+                "Tunnel {} ({}) opened to {} ({}) by {}",
+                tunnel_id,
+                kind.as_str(),
+                tunnel.target,
+                tunnel.peer_addr,
+                operator.as_deref().unwrap_or("unknown")
+            ),
+            Err(ref e) => tracing::warn!(
+                "Tunnel {} ({}) to {}:{} rejected: {}",
+                tunnel_id,
+                kind.as_str(),
+                host,
+                port,
+                e
+            ),
+        }
+
+        if let Some(audit) = self.audit_for(operator) {
+            let (result, error) = match opened {
+                Ok(_) => (AuditResult::Success, None),
+                Err(ref e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                    (AuditResult::Denied, Some(e.to_string()))
+                }
+                Err(ref e) => (AuditResult::Error, Some(e.to_string())),
+            };
+            if let Err(e) = audit.log_tunnel_open(
+                tunnel_id,
+                kind.as_str(),
+                &format!("{}:{}", host, port),
+                opened.as_ref().ok().map(|t| t.peer_addr.to_string()),
+                proxy_session_id,
+                result,
+                error,
+            ) {
+                tracing::warn!("Failed to audit tunnel open: {}", e);
+            }
+        }
+
+        opened
+    }
+
+    /// 审计隧道关闭及双向字节数
+    fn audit_tunnel_close(&self, tunnel: &TunnelConnection, reason: &str) {
+        let info = tunnel.info();
+        tracing::info!(
+            "Tunnel {} to {} closed ({}) after {:?}: {} bytes in, {} bytes out",
+            info.tunnel_id,
+            info.target,
+            reason,
+            tunnel.uptime(),
+            info.bytes_in,
+            info.bytes_out
+        );
+        if let Some(audit) = self.audit_for(tunnel.meta.operator.clone()) {
+            if let Err(e) = audit.log_tunnel_close(
+                &info.tunnel_id,
+                tunnel.meta.kind.as_str(),
+                &info.target,
+                tunnel.meta.proxy_session_id.clone(),
+                info.bytes_in,
+                info.bytes_out,
+                tunnel.uptime(),
+                reason,
+            ) {
+                tracing::warn!("Failed to audit tunnel close: {}", e);
+            }
+        }
+    }
+
+    /// 处理 tunnel_open：按策略连接目标
+    fn handle_tunnel_open(&self, task_id: String, payload: TunnelOpenPayload) -> TaskReport {
+        let meta = TunnelMeta {
+            kind: TunnelKind::Tcp,
+            operator: payload.operator.clone(),
+            proxy_session_id: None,
+        };
+        match self.open_tunnel(&payload.tunnel_id, &payload.host, payload.port, meta) {
+            Ok(tunnel) => TaskReport {
+                task_id,
+                status: "completed".to_string(),
+                result: serde_json::json!({
+                    "tunnel_id": payload.tunnel_id,
+                    "state": tunnel.state(),
+                    "peer_addr": tunnel.peer_addr.to_string(),
+                    "error": null,
+                }),
+                output_cursor: 0,
+                output_chunk: String::new(),
+            },
+            Err(e) => TaskReport {
+                task_id,
+                status: "failed".to_string(),
+                result: serde_json::json!({
+                    "tunnel_id": payload.tunnel_id,
+                    "state": "failed",
+                    "error": e.to_string(),
+                }),
+                output_cursor: 0,
+                output_chunk: String::new(),
+            },
+        }
+    }
+
+    /// 处理 socks_connect：解析 SOCKS5 请求并拨号，返回可直接写回客户端的应答报文
+    fn handle_socks_connect(&self, task_id: String, payload: SocksConnectPayload) -> TaskReport {
+        let request = general_purpose::STANDARD
+            .decode(payload.request.as_bytes())
+            .map_err(|_| SocksReply::GeneralFailure)
+            .and_then(|bytes| SocksRequest::parse(&bytes))
+            .map_err(|reply| (reply, format!("Invalid SOCKS5 request: {:?}", reply)));
+
+        let outcome = request.and_then(|request| {
+            let meta = TunnelMeta {
+                kind: TunnelKind::Socks5,
+                operator: payload.operator.clone(),
+                proxy_session_id: payload.proxy_session_id.clone(),
+            };
+            self.open_tunnel(&payload.tunnel_id, &request.host, request.port, meta)
+                .map_err(|e| (SocksReply::from_error(&e), e.to_string()))
+        });
+
+        let (status, reply, bound, state, error) = match outcome {
+            Ok(tunnel) => (
+                "completed",
+                SocksReply::Succeeded,
+                Some(tunnel.local_addr),
+                serde_json::json!(tunnel.state()),
+                None,
+            ),
+            Err((reply, error)) => ("failed", reply, None, serde_json::json!("failed"), Some(error)),
+        };
+        TaskReport {
+            task_id,
+            status: status.to_string(),
+            result: serde_json::json!({
+                "tunnel_id": payload.tunnel_id,
+                "proxy_session_id": payload.proxy_session_id,
+                "state": state,
+                "reply_code": reply as u8,
+                "reply": general_purpose::STANDARD.encode(reply.encode(bound)),
+                "error": error,
+            }),
+            output_cursor: 0,
+            output_chunk: String::new(),
+        }
+    }
+
+    /// 处理 tunnel_data：写入目标、确认回传数据，并附带新的回传数据
+    fn handle_tunnel_data(&self, task_id: String, payload: TunnelDataPayload) -> TaskReport {
+        let Some(tunnel) = self.tunnel_manager.as_ref().and_then(|t| t.get(&payload.tunnel_id)) else {
+            return TaskReport {
+                task_id,
+                status: "failed".to_string(),
+                result: serde_json::json!({
+                    "tunnel_id": payload.tunnel_id,
+                    "error": "Tunnel not found",
+                }),
+                output_cursor: 0,
+                output_chunk: String::new(),
+            };
+        };
+
+        if let Some(ack) = payload.ack {
+            tunnel.ack(ack);
+        }
+
+        let written = general_purpose::STANDARD
+            .decode(payload.data.as_bytes())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+            .and_then(|data| tunnel.write(payload.offset, &data))
+            .and_then(|written| {
+                if payload.eof {
+                    tunnel.shutdown_write()?;
+                }
+                Ok(written)
+            });
+
+        let read = tunnel.read_pending();
+        let (status, error) = match written {
+            Ok(_) => ("completed", None),
+            Err(e) => ("failed", Some(e.to_string())),
+        };
+        TaskReport {
+            task_id,
+            status: status.to_string(),
+            result: tunnel_result(&tunnel, &read, error),
+            output_cursor: read.offset + read.data.len() as u64,
+            output_chunk: String::new(),
+        }
+    }
+
+    /// 处理 tunnel_close（幂等）
+    fn handle_tunnel_close(&self, task_id: String, payload: TunnelClosePayload) -> TaskReport {
+        let closed = self
+            .tunnel_manager
+            .as_ref()
+            .and_then(|t| t.close(&payload.tunnel_id));
+        let info = closed.as_ref().map(|tunnel| {
+            self.audit_tunnel_close(tunnel, "closed");
+            tunnel.info()
+        });
+
+        TaskReport {
+            task_id,
+            status: "completed".to_string(),
+            result: serde_json::json!({
+                "tunnel_id": payload.tunnel_id,
+                "state": "closed",
+                "bytes_in": info.as_ref().map(|i| i.bytes_in),
+                "bytes_out": info.as_ref().map(|i| i.bytes_out),
+            }),
+            output_cursor: 0,
+            output_chunk: String::new(),
+        }
+    }
+
+    /// 收集各隧道的回传数据，回收空闲隧道（用于心跳）
+    pub fn collect_tunnel_reports(&self) -> Vec<TaskReport> {
+        let Some(ref tunnels) = self.tunnel_manager else {
+            return Vec::new();
+        };
+        let mut reports = Vec::new();
+
+        for tunnel in tunnels.tunnels() {
+            if !tunnel.has_pending() {
+                continue;
+            }
+            let read = tunnel.read_pending();
+            // 读取出错的隧道不可恢复，上报后移除
+            let failed = read.error.is_some();
+            if failed {
+                tunnels.close(&tunnel.tunnel_id);
+                self.audit_tunnel_close(&tunnel, "error");
+            }
+            reports.push(TaskReport {
+                task_id: format!("tunnel-{}", tunnel.tunnel_id),
+                status: if failed { "failed" } else { "running" }.to_string(),
+                result: tunnel_result(&tunnel, &read, read.error.clone()),
+                output_cursor: read.offset + read.data.len() as u64,
+                output_chunk: String::new(),
+            });
+        }
+
+        for tunnel in tunnels.reap_idle() {
+            self.audit_tunnel_close(&tunnel, "idle_timeout");
+            let read = tunnel.read_pending();
+            let mut result = tunnel_result(&tunnel, &read, None);
+            result["state"] = serde_json::json!("closed");
+            result["reason"] = serde_json::json!("idle_timeout");
+            reports.push(TaskReport {
+                task_id: format!("tunnel-{}", tunnel.tunnel_id),
+                status: "completed".to_string(),
+                result,
+                output_cursor: read.offset + read.data.len() as u64,
+                output_chunk: String::new(),
+            });
+        }
+
+        reports
+    }
+
+    /// 上报子进程自行退出的会话并清理（用于心跳）
+    pub fn collect_exited_reports(&self) -> Vec<TaskReport> {
+        let mut reports = Vec::new();
+
+        for session in self.terminal_manager.cleanup_closed_sessions() {
+            let session_id = session.session_id.clone();
+            let (last_cursor, output) = self.read_new_output(&session);
+            let state = session.get_state();
+            let exit_info = session.get_exit_info();
+
+            tracing::info!(
+                "Terminal session {} process exited: {:?}",
+                session_id,
+                exit_info
+            );
+
+            self.cursor_tracker.remove_session(&session_id);
+            self.detach_all_viewers(&session, "process_exited");
+            self.audit_session_end(&session, "process_exited");
+
+            let mut result = serde_json::json!({
+                "session_id": session_id,
+                "state": state,
+                "exit_code": exit_info.and_then(|i| i.code),
+                "signal": exit_info.and_then(|i| i.signal),
+                "reason": "process_exited",
+            });
+            attach_output_gap(&mut result, last_cursor, &output);
+
+            reports.push(TaskReport {
+                task_id: format!("heartbeat-{}", session_id),
+                status: if state == SessionState::Closed {
+                    "completed".to_string()
+                } else {
+                    "failed".to_string()
+                },
+                result,
+                output_cursor: output.next_cursor,
+                output_chunk: String::from_utf8_lossy(&output.data).to_string(),
+            });
+        }
+
+        reports
+    }
+}
+
+/// 隧道上报结果：回传数据以 Base64 编码，附带双方向的 offset
+fn tunnel_result(tunnel: &TunnelConnection, read: &TunnelRead, error: Option<String>) -> serde_json::Value {
+    serde_json::json!({
+        "tunnel_id": tunnel.tunnel_id,
+        "state": tunnel.state(),
+        "offset": read.offset,
+        "data": general_purpose::STANDARD.encode(&read.data),
+        "eof": read.eof,
+        "acked": tunnel.acked(),
+        "written": tunnel.written(),
+        "error": error,
+    })
+}
+
+/// 在上报结果中附加输出状态：
+/// 存在输出缺口时附加缺口标记，供控制台显示并重新同步画面；
+/// 输出被限速暂停、合并或恢复时附加限速状态
+fn attach_output_gap(result: &mut serde_json::Value, from_cursor: u64, output: &OutputRead) {
+    if let Some(throttle) = output.throttle {
+        result["throttle"] = serde_json::json!(throttle);
+    }
+    if !output.resync {
+        return;
+    }
+    result["output_gap"] = serde_json::json!({
+        "dropped_bytes": output.dropped_bytes,
+        "from_cursor": from_cursor,
+        "resume_cursor": output.next_cursor - output.data.len() as u64,
+        "resync": true,
+    });
 }
@@ -4,14 +4,34 @@
 use std::collections::HashMap;
 use std::io;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use serde::{Deserialize, Serialize};
 
-use super::session::{SessionConfig, SessionState, TerminalSession};
+use super::session::{ExpiryReason, DEFAULT_BUFFER_SIZE, SessionConfig, SessionState, TerminalSession};
 
 /// 终端管理器
 pub struct TerminalManager {
     sessions: Arc<Mutex<HashMap<String, Arc<TerminalSession>>>>,
     max_sessions: usize,
+    /// 默认空闲超时（秒），0 表示不限制
+    default_idle_timeout: u64,
+    /// 默认最长存活时间（秒），0 表示不限制
+    default_max_duration: u64,
+    /// 默认输出缓冲区容量（字节）
+    default_buffer_size: usize,
+    /// 默认是否捕获命令行
+    default_capture_commands: bool,
+    /// 默认输出上报速率上限（字节/秒），0 表示不限速
+    default_output_rate_limit: u64,
+    /// 默认输出上报突发容量（字节）
+    default_output_burst: u64,
+}
+
+/// 被回收的过期会话
+pub struct ExpiredSession {
+    pub session: Arc<TerminalSession>,
+    pub reason: ExpiryReason,
+    pub exit_code: Option<i32>,
 }
 
 impl TerminalManager {
@@ -20,11 +40,43 @@ impl TerminalManager {
         Self {
             sessions: Arc::new(Mutex::new(HashMap::new())),
             max_sessions,
+            default_idle_timeout: 0,
+            default_max_duration: 0,
+            default_buffer_size: DEFAULT_BUFFER_SIZE,
+            default_capture_commands: false,
+            default_output_rate_limit: 0,
+            default_output_burst: 0,
         }
     }
 
+    /// 设置会话默认超时（秒），0 表示不限制
+    pub fn with_timeouts(mut self, idle_timeout: u64, max_duration: u64) -> Self {
+        self.default_idle_timeout = idle_timeout;
+        self.default_max_duration = max_duration;
+        self
+    }
+
+    /// 设置会话默认输出缓冲区容量（字节）
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.default_buffer_size = buffer_size;
+        self
+    }
+
+    /// 设置会话默认是否捕获命令行用于审计
+    pub fn with_command_capture(mut self, enabled: bool) -> Self {
+        self.default_capture_commands = enabled;
+        self
+    }
+
+    /// 设置会话默认输出上报限速（字节/秒与突发字节数），速率为 0 表示不限速
+    pub fn with_output_limit(mut self, rate: u64, burst: u64) -> Self {
+        self.default_output_rate_limit = rate;
+        self.default_output_burst = burst;
+        self
+    }
+
     /// 创建新会话
-    pub fn create_session(&self, config: SessionConfig) -> io::Result<Arc<TerminalSession>> {
+    pub fn create_session(&self, mut config: SessionConfig) -> io::Result<Arc<TerminalSession>> {
         let mut sessions = self.sessions.lock().unwrap();
 
         // 检查并发限制
@@ -43,6 +95,14 @@ impl TerminalManager {
             ));
         }
 
+        // 未指定超时则使用默认值
+        config.idle_timeout.get_or_insert(self.default_idle_timeout);
+        config.max_duration.get_or_insert(self.default_max_duration);
+        config.buffer_size.get_or_insert(self.default_buffer_size);
+        config.capture_commands.get_or_insert(self.default_capture_commands);
+        config.output_rate_limit.get_or_insert(self.default_output_rate_limit);
+        config.output_burst.get_or_insert(self.default_output_burst);
+
         let session = Arc::new(TerminalSession::new(config.clone())?);
         session.start(config.clone())?;
 
@@ -78,13 +138,53 @@ impl TerminalManager {
             .collect()
     }
 
-    /// 清理已关闭的会话
-    pub fn cleanup_closed_sessions(&self) {
+    /// 清理已关闭的会话，返回被移除的会话
+    pub fn cleanup_closed_sessions(&self) -> Vec<Arc<TerminalSession>> {
         let mut sessions = self.sessions.lock().unwrap();
+        let mut removed = Vec::new();
         sessions.retain(|_, session| {
             let state = session.get_state();
-            state != SessionState::Closed && state != SessionState::Failed
+            let keep = state != SessionState::Closed && state != SessionState::Failed;
+            if !keep {
+                removed.push(Arc::clone(session));
+            }
+            keep
         });
+        removed
+    }
+
+    /// 回收过期会话：从管理器移除并强制关闭
+    ///
+    /// 强制关闭会等待子进程退出（每个会话最多约 1 秒），异步上下文中应放到阻塞线程池执行。
+    pub fn reap_expired_sessions(&self) -> Vec<ExpiredSession> {
+        self.reap_expired_sessions_at(Instant::now())
+    }
+
+    /// 以给定时刻判断过期并回收会话
+    pub fn reap_expired_sessions_at(&self, now: Instant) -> Vec<ExpiredSession> {
+        let expired: Vec<(Arc<TerminalSession>, ExpiryReason)> = {
+            let mut sessions = self.sessions.lock().unwrap();
+            let ids: Vec<(String, ExpiryReason)> = sessions
+                .iter()
+                .filter_map(|(id, session)| session.expiry_reason_at(now).map(|r| (id.clone(), r)))
+                .collect();
+            ids.into_iter()
+                .filter_map(|(id, reason)| sessions.remove(&id).map(|s| (s, reason)))
+                .collect()
+        };
+
+        // 在锁外关闭，避免阻塞其他会话操作
+        expired
+            .into_iter()
+            .map(|(session, reason)| {
+                let exit_code = session.close(true).unwrap_or(None);
+                ExpiredSession {
+                    session,
+                    reason,
+                    exit_code,
+                }
+            })
+            .collect()
     }
 }
 
@@ -101,37 +201,26 @@ pub struct SessionInfo {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::terminal::session::ShellType;
+    use crate::terminal::session::{test_config, ShellType};
+    use std::time::Duration;
 
     #[test]
     fn test_manager_max_sessions() {
         let manager = TerminalManager::new(2);
 
         let config1 = SessionConfig {
-            session_id: "sess1".to_string(),
             shell_type: ShellType::Bash,
-            cwd: None,
-            env: None,
-            cols: 80,
-            rows: 24,
+            ..test_config("sess1")
         };
 
         let config2 = SessionConfig {
-            session_id: "sess2".to_string(),
             shell_type: ShellType::Bash,
-            cwd: None,
-            env: None,
-            cols: 80,
-            rows: 24,
+            ..test_config("sess2")
         };
 
         let config3 = SessionConfig {
-            session_id: "sess3".to_string(),
             shell_type: ShellType::Bash,
-            cwd: None,
-            env: None,
-            cols: 80,
-            rows: 24,
+            ..test_config("sess3")
         };
 
         // 前两个应该成功
@@ -141,4 +230,24 @@ mod tests {
         // 第三个应该失败
         assert!(manager.create_session(config3).is_err());
     }
+
+    #[test]
+    fn test_reap_expired_sessions() {
+        let manager = TerminalManager::new(2).with_timeouts(0, 0);
+
+        let config = SessionConfig {
+            max_duration: Some(1),
+            ..test_config("short")
+        };
+        manager.create_session(config).unwrap();
+
+        let now = Instant::now();
+        assert!(manager.reap_expired_sessions_at(now).is_empty());
+
+        let expired = manager.reap_expired_sessions_at(now + Duration::from_secs(1));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].reason, ExpiryReason::MaxDuration);
+        assert_eq!(expired[0].session.get_state(), SessionState::Closed);
+        assert!(manager.list_sessions().is_empty());
+    }
 }
@@ -2,8 +2,13 @@
 // 终端会话管理模块
 
 pub mod pty;
+pub mod recorder;
 pub mod session;
 pub mod manager;
+pub mod screen;
+pub mod throttle;
+pub mod viewer;
 
-pub use manager::{TerminalManager, SessionInfo};
-pub use session::{TerminalSession, SessionState, ShellType, SessionConfig, BufferError};
+pub use manager::{TerminalManager, SessionInfo, ExpiredSession};
+pub use session::{TerminalSession, SessionState, ShellType, SessionConfig, BufferError, ExpiryReason, ExitInfo, OutputRead};
+pub use viewer::ViewerRole;
@@ -1,6 +1,8 @@
 // agent/src/terminal/pty/mod.rs
 // PTY 平台抽象层
 
+use serde::{Deserialize, Serialize};
+
 #[cfg(unix)]
 pub mod unix;
 
@@ -8,7 +10,16 @@ pub mod unix;
 pub mod windows;
 
 #[cfg(unix)]
-pub use unix::UnixPty;
+pub use unix::{user_default_shell, UnixPty};
 
 #[cfg(windows)]
 pub use windows::WindowsPty;
+
+/// 子进程退出信息
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct ExitInfo {
+    /// 正常退出时的退出码
+    pub code: Option<i32>,
+    /// 被信号终止时的信号编号（仅 Unix）
+    pub signal: Option<i32>,
+}
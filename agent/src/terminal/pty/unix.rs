@@ -3,16 +3,19 @@
 
 #[cfg(unix)]
 use std::collections::HashMap;
-use std::ffi::CString;
-use std::io::{self, Read, Write};
-use std::os::unix::io::{AsRawFd, RawFd};
+use std::ffi::{CStr, CString};
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
 use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
 
-extern crate libc;
+use super::ExitInfo;
 
 pub struct UnixPty {
     master_fd: RawFd,
-    child: Option<Child>,
+    child: Mutex<Option<Child>>,
+    exit_info: Mutex<Option<ExitInfo>>,
     cols: u16,
     rows: u16,
 }
@@ -44,7 +47,8 @@ impl UnixPty {
 
         Ok(Self {
             master_fd,
-            child: None,
+            child: Mutex::new(None),
+            exit_info: Mutex::new(None),
             cols,
             rows,
         })
@@ -53,19 +57,24 @@ impl UnixPty {
     /// 获取 slave PTY 路径
     fn get_slave_name(&self) -> io::Result<String> {
         unsafe {
-            let mut buf = [0i8; 512];
+            let mut buf = [0 as libc::c_char; 512];
             if libc::ptsname_r(self.master_fd, buf.as_mut_ptr(), buf.len()) != 0 {
                 return Err(io::Error::last_os_error());
             }
-            let cstr = CString::from_raw(buf.as_ptr() as *mut i8);
+            // buf 为栈上缓冲区，只能借用，不能交给 CString 释放
+            let cstr = CStr::from_ptr(buf.as_ptr());
             Ok(cstr.to_string_lossy().into_owned())
         }
     }
 
-    /// 启动 shell 进程
+    /// 启动子进程
+    ///
+    /// `login` 为 true 时按惯例将 argv[0] 设为 `-<程序名>`，使 shell 以登录模式启动。
     pub fn spawn(
         &mut self,
-        shell_path: &str,
+        program: &str,
+        args: &[String],
+        login: bool,
         cwd: Option<&str>,
         env: Option<&HashMap<String, String>>,
     ) -> io::Result<u32> {
@@ -118,7 +127,16 @@ impl UnixPty {
             return Err(e);
         }
 
-        let mut cmd = Command::new(shell_path);
+        let mut cmd = Command::new(program);
+        cmd.args(args);
+
+        if login {
+            let name = std::path::Path::new(program)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| program.to_string());
+            cmd.arg0(format!("-{}", name));
+        }
         
         if let Some(dir) = cwd {
             cmd.current_dir(dir);
@@ -128,9 +146,6 @@ impl UnixPty {
             cmd.envs(env_vars);
         }
 
-        // 设置 TERM 环境变量
-        cmd.env("TERM", "xterm-256color");
-
         // 重定向 stdio 到 slave PTY
         unsafe {
             cmd.stdin(Stdio::from_raw_fd(stdin_fd));
@@ -155,7 +170,7 @@ impl UnixPty {
 
         let child = cmd.spawn()?;
         let pid = child.id();
-        self.child = Some(child);
+        *self.child.lock().unwrap() = Some(child);
 
         // slave_fd 已经被 Command 接管，会在子进程中自动关闭
 
@@ -222,32 +237,81 @@ impl UnixPty {
         Ok(())
     }
 
+    /// 终端是否开启回显（密码输入时程序通常会关闭回显）
+    pub fn echo_enabled(&self) -> bool {
+        unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(self.master_fd, &mut termios) != 0 {
+                return true;
+            }
+            termios.c_lflag & libc::ECHO != 0
+        }
+    }
+
     /// 关闭 PTY
+    ///
+    /// 子进程是会话领导者，信号发送给整个进程组；非强制关闭发送 SIGHUP + SIGTERM
+    /// （交互式 shell 会忽略 SIGTERM，但会响应挂断），强制关闭发送 SIGKILL。
     pub fn close(&self, force: bool) -> io::Result<()> {
-        if let Some(ref child) = self.child {
-            let pid = child.id() as i32;
+        if self.try_wait().is_some() {
+            return Ok(());
+        }
+        if let Some(ref child) = *self.child.lock().unwrap() {
+            let pgid = -(child.id() as i32);
             unsafe {
                 if force {
-                    libc::kill(pid, libc::SIGKILL);
+                    libc::kill(pgid, libc::SIGKILL);
                 } else {
-                    libc::kill(pid, libc::SIGTERM);
+                    libc::kill(pgid, libc::SIGHUP);
+                    libc::kill(pgid, libc::SIGTERM);
                 }
             }
         }
         Ok(())
     }
 
+    /// 非阻塞检查子进程是否已退出（退出后结果会被缓存）
+    pub fn try_wait(&self) -> Option<ExitInfo> {
+        let mut exit_info = self.exit_info.lock().unwrap();
+        if exit_info.is_none() {
+            let mut child = self.child.lock().unwrap();
+            if let Some(status) = child.as_mut().and_then(|c| c.try_wait().ok().flatten()) {
+                *exit_info = Some(ExitInfo {
+                    code: status.code(),
+                    signal: status.signal(),
+                });
+            }
+        }
+        *exit_info
+    }
+
     /// 获取退出码
     pub fn get_exit_code(&self) -> Option<i32> {
-        self.child.as_ref()?.try_wait().ok()?.and_then(|status| status.code())
+        self.try_wait().and_then(|info| info.code)
     }
+}
 
-    /// 等待进程退出并获取退出码
-    pub fn wait_exit_code(&mut self) -> Option<i32> {
-        if let Some(mut child) = self.child.take() {
-            child.wait().ok()?.code()
-        } else {
+/// 从 /etc/passwd（经 NSS）读取当前用户的登录 shell
+pub fn user_default_shell() -> Option<String> {
+    unsafe {
+        let mut pwd: libc::passwd = std::mem::zeroed();
+        let mut result: *mut libc::passwd = std::ptr::null_mut();
+        let mut buf = vec![0 as libc::c_char; 4096];
+        let ret = libc::getpwuid_r(
+            libc::getuid(),
+            &mut pwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        );
+        if ret != 0 || result.is_null() || pwd.pw_shell.is_null() {
+            return None;
+        }
+        let shell = CStr::from_ptr(pwd.pw_shell).to_string_lossy().into_owned();
+        if shell.is_empty() {
             None
+        } else {
+            Some(shell)
         }
     }
 }
@@ -19,6 +19,8 @@ use winapi::um::wincon::COORD;
 use winapi::um::heapapi::{HeapAlloc, HeapFree, GetProcessHeap};
 use winapi::um::fileapi::{ReadFile, WriteFile};
 
+use super::ExitInfo;
+
 pub struct WindowsPty {
     pseudo_console: HANDLE,
     process_info: Option<PROCESS_INFORMATION>,
@@ -96,9 +98,13 @@ impl WindowsPty {
     }
 
     /// 启动 shell 进程
+    ///
+    /// Windows 没有登录 shell 的概念，`login` 参数被忽略。
     pub fn spawn(
         &mut self,
-        shell_path: &str,
+        program: &str,
+        args: &[String],
+        _login: bool,
         cwd: Option<&str>,
         env: Option<&HashMap<String, String>>,
     ) -> io::Result<u32> {
@@ -108,8 +114,17 @@ impl WindowsPty {
 
             let mut process_info: PROCESS_INFORMATION = std::mem::zeroed();
 
-            // 构建命令行（需要 UTF-16）
-            let cmd_line = if shell_path.to_lowercase().contains("powershell") {
+            // 构建命令行（需要 UTF-16）；显式指定参数时不再附加 shell 预设参数
+            let shell_path = program;
+            let cmd_line = if !args.is_empty() {
+                let mut line = quote_arg(program);
+                for arg in args {
+                    line.push(' ');
+                    line.push_str(&quote_arg(arg));
+                }
+                line.push('\0');
+                line
+            } else if shell_path.to_lowercase().contains("powershell") {
                 // PowerShell 需要特殊参数以启用 UTF-8
                 format!("{} -NoLogo -NoProfile -ExecutionPolicy Bypass -Command \"[Console]::OutputEncoding = [System.Text.Encoding]::UTF8; [Console]::InputEncoding = [System.Text.Encoding]::UTF8; $Host.UI.RawUI.WindowTitle = 'Terminal'; while($true){{$cmd = Read-Host; if($cmd -eq 'exit'){{break}}; Invoke-Expression $cmd}}\"\0", shell_path)
             } else if shell_path.to_lowercase().contains("cmd") {
@@ -129,9 +144,14 @@ impl WindowsPty {
             });
 
             // 环境变量块（UTF-16，每个变量以 \0 结尾，整个块以 \0\0 结尾）
+            // 环境块会整体替换子进程环境，因此先继承当前进程环境再覆盖
             let env_block = if let Some(env_map) = env {
-                let mut block = String::new();
+                let mut merged: std::collections::BTreeMap<String, String> = std::env::vars().collect();
                 for (key, value) in env_map {
+                    merged.insert(key.clone(), value.clone());
+                }
+                let mut block = String::new();
+                for (key, value) in &merged {
                     block.push_str(&format!("{}={}\0", key, value));
                 }
                 block.push('\0');
@@ -204,7 +224,8 @@ impl WindowsPty {
                 ptr::null_mut(),
                 ptr::null_mut(),
                 FALSE,
-                EXTENDED_STARTUPINFO_PRESENT | 0x08000000, // CREATE_NO_WINDOW
+                // CREATE_NO_WINDOW | CREATE_UNICODE_ENVIRONMENT
+                EXTENDED_STARTUPINFO_PRESENT | 0x08000000 | 0x00000400,
                 env_block
                     .as_ref()
                     .map_or(ptr::null_mut(), |v| v.as_ptr() as *mut _),
@@ -232,6 +253,11 @@ impl WindowsPty {
         }
     }
 
+    /// 终端是否开启回显（ConPTY 无法查询，始终视为开启）
+    pub fn echo_enabled(&self) -> bool {
+        true
+    }
+
     /// 读取输出
     pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
         unsafe {
@@ -328,6 +354,14 @@ impl WindowsPty {
             None
         }
     }
+
+    /// 非阻塞检查子进程是否已退出
+    pub fn try_wait(&self) -> Option<ExitInfo> {
+        self.get_exit_code().map(|code| ExitInfo {
+            code: Some(code),
+            signal: None,
+        })
+    }
 }
 
 impl Drop for WindowsPty {
@@ -354,3 +388,30 @@ impl Drop for WindowsPty {
 // 我们需要手动实现 Send 和 Sync
 unsafe impl Send for WindowsPty {}
 unsafe impl Sync for WindowsPty {}
+
+/// 按 Windows 命令行规则为参数加引号
+fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,186 @@
+// agent/src/terminal/recorder.rs
+// 终端命令行捕获（用于审计），对密码输入做脱敏
+
+/// 单行最大捕获长度，超出部分丢弃
+const MAX_LINE_BYTES: usize = 4096;
+
+/// 脱敏后的占位内容
+pub const REDACTED: &str = "[REDACTED]";
+
+/// 常见的密码提示关键字（小写）
+const PASSWORD_PROMPTS: &[&str] = &["password", "passphrase", "passcode", "pin:", "密码", "口令"];
+
+/// 捕获到的命令行
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedCommand {
+    pub command: String,
+    pub redacted: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EscapeState {
+    Normal,
+    Escape,
+    Csi,
+}
+
+/// 从输入字节流中还原用户逐行键入的命令
+///
+/// 处理退格、Ctrl-U/Ctrl-W/Ctrl-C 等行编辑按键，忽略方向键等转义序列；
+/// 经 shell 补全或历史记录修改的行无法完全还原，只作为审计参考。
+pub struct CommandRecorder {
+    line: Vec<u8>,
+    /// 当前行是否在键入期间出现过密码输入状态，一旦置位持续到回车
+    line_secret: bool,
+    escape: EscapeState,
+    captured: Vec<CapturedCommand>,
+}
+
+impl Default for CommandRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommandRecorder {
+    pub fn new() -> Self {
+        Self {
+            line: Vec::new(),
+            line_secret: false,
+            escape: EscapeState::Normal,
+            captured: Vec::new(),
+        }
+    }
+
+    /// 处理一段输入；`secret` 为写入这段输入之前终端是否处于密码输入状态
+    ///
+    /// 只要行内任一按键在密码状态下键入，整行都会脱敏，之后回显或提示变化不影响判断。
+    pub fn feed(&mut self, data: &[u8], secret: bool) {
+        for &byte in data {
+            if secret && !matches!(byte, b'\r' | b'\n') {
+                self.line_secret = true;
+            }
+            match self.escape {
+                EscapeState::Escape => {
+                    self.escape = if byte == b'[' || byte == b'O' {
+                        EscapeState::Csi
+                    } else {
+                        EscapeState::Normal
+                    };
+                    continue;
+                }
+                EscapeState::Csi => {
+                    if (0x40..=0x7e).contains(&byte) {
+                        self.escape = EscapeState::Normal;
+                    }
+                    continue;
+                }
+                EscapeState::Normal => {}
+            }
+
+            match byte {
+                b'\r' | b'\n' => self.finish_line(),
+                0x1b => self.escape = EscapeState::Escape,
+                // Backspace / DEL：按 UTF-8 字符删除
+                0x08 | 0x7f => {
+                    while let Some(b) = self.line.pop() {
+                        if b & 0xc0 != 0x80 {
+                            break;
+                        }
+                    }
+                }
+                // Ctrl-C / Ctrl-U：丢弃当前行
+                0x03 | 0x15 => self.line.clear(),
+                // Ctrl-W：删除前一个单词
+                0x17 => {
+                    while self.line.last() == Some(&b' ') {
+                        self.line.pop();
+                    }
+                    while matches!(self.line.last(), Some(b) if *b != b' ') {
+                        self.line.pop();
+                    }
+                }
+                b'\t' => self.push(byte),
+                b if b < 0x20 => {}
+                b => self.push(b),
+            }
+        }
+    }
+
+    /// 取出已捕获的命令
+    pub fn take(&mut self) -> Vec<CapturedCommand> {
+        std::mem::take(&mut self.captured)
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.line.len() < MAX_LINE_BYTES {
+            self.line.push(byte);
+        }
+    }
+
+    fn finish_line(&mut self) {
+        let line = std::mem::take(&mut self.line);
+        let redacted = std::mem::take(&mut self.line_secret);
+        let command = String::from_utf8_lossy(&line).trim().to_string();
+        if command.is_empty() {
+            return;
+        }
+        self.captured.push(CapturedCommand {
+            command: if redacted { REDACTED.to_string() } else { command },
+            redacted,
+        });
+    }
+}
+
+/// 判断光标所在行是否为密码提示（如 `[sudo] password for root:`）
+pub fn is_password_prompt(line: &str) -> bool {
+    let line = line.trim_end().to_lowercase();
+    if !(line.ends_with(':') || line.ends_with('：')) {
+        return false;
+    }
+    PASSWORD_PROMPTS.iter().any(|keyword| line.contains(keyword))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_editing() {
+        let mut recorder = CommandRecorder::new();
+        // 退格修正、方向键序列被忽略、Ctrl-U 清空
+        recorder.feed(b"lss\x7f -la\x1b[A\r", false);
+        recorder.feed(b"rm -rf /\x15echo ok\r\r", false);
+        recorder.feed("echo 你好\x7f\x7f世界\n".as_bytes(), false);
+
+        let commands: Vec<String> = recorder.take().into_iter().map(|c| c.command).collect();
+        assert_eq!(commands, vec!["ls -la", "echo ok", "echo 世界"]);
+        assert!(recorder.take().is_empty());
+    }
+
+    #[test]
+    fn test_password_redaction() {
+        let mut recorder = CommandRecorder::new();
+        recorder.feed(b"sudo id\r", false);
+        recorder.feed(b"hunter2\r", is_password_prompt("[sudo] password for root: "));
+
+        let commands = recorder.take();
+        assert_eq!(commands[0].command, "sudo id");
+        assert!(!commands[0].redacted);
+        assert_eq!(commands[1].command, REDACTED);
+        assert!(commands[1].redacted);
+
+        // 首个按键时处于密码状态，其后状态变化（回显恢复、提示重绘）不影响整行脱敏
+        recorder.feed(b"h", true);
+        recorder.feed(b"unter2", false);
+        recorder.feed(b"\r", false);
+        recorder.feed(b"id\r", false);
+        let commands = recorder.take();
+        assert_eq!(commands[0].command, REDACTED);
+        assert_eq!(commands[1].command, "id");
+
+        assert!(is_password_prompt("Enter passphrase for key '/root/.ssh/id_ed25519':"));
+        assert!(is_password_prompt("请输入密码："));
+        assert!(!is_password_prompt("root@host:~# echo password"));
+    }
+}
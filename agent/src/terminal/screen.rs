@@ -0,0 +1,198 @@
+// agent/src/terminal/screen.rs
+// 终端屏幕状态跟踪（VT100/xterm 解析），用于向新接入的查看者提供当前画面
+
+use serde::{Deserialize, Serialize};
+
+/// 屏幕快照中保留的回滚行数（快照本身只包含可见区域）
+const SCROLLBACK_LINES: usize = 0;
+
+/// 屏幕状态跟踪器
+///
+/// 与输出环形缓冲区并行处理同一份 PTY 输出，记录已处理的字节数，
+/// 使快照能与输出 cursor 对齐：查看者渲染快照后从 `output_cursor` 继续接收增量输出。
+pub struct ScreenTracker {
+    parser: vt100::Parser,
+    processed: u64,
+}
+
+/// 当前屏幕快照
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreenSnapshot {
+    pub rows: u16,
+    pub cols: u16,
+    /// 快照对应的输出 cursor
+    pub output_cursor: u64,
+    pub cursor: CursorState,
+    /// 是否处于备用屏幕（vim、top 等全屏程序）
+    pub alternate_screen: bool,
+    pub title: String,
+    /// 按行排列的单元格
+    pub cells: Vec<Vec<CellSnapshot>>,
+    /// 可直接写入空白终端以重现当前画面的转义序列
+    pub formatted: String,
+}
+
+/// 光标状态
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct CursorState {
+    pub row: u16,
+    pub col: u16,
+    pub visible: bool,
+}
+
+/// 单元格内容与属性（默认值字段省略以减小快照体积）
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CellSnapshot {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fg: Option<CellColor>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bg: Option<CellColor>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub bold: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub italic: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub underline: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub inverse: bool,
+    /// 宽字符（占两列），其后一列为占位单元格
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub wide: bool,
+}
+
+/// 单元格颜色：256 色索引或 RGB 真彩色
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum CellColor {
+    Indexed(u8),
+    Rgb([u8; 3]),
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+fn convert_color(color: vt100::Color) -> Option<CellColor> {
+    match color {
+        vt100::Color::Default => None,
+        vt100::Color::Idx(i) => Some(CellColor::Indexed(i)),
+        vt100::Color::Rgb(r, g, b) => Some(CellColor::Rgb([r, g, b])),
+    }
+}
+
+impl ScreenTracker {
+    pub fn new(cols: u16, rows: u16) -> Self {
+        Self {
+            parser: vt100::Parser::new(rows, cols, SCROLLBACK_LINES),
+            processed: 0,
+        }
+    }
+
+    /// 处理一段 PTY 输出
+    pub fn process(&mut self, data: &[u8]) {
+        self.parser.process(data);
+        self.processed += data.len() as u64;
+    }
+
+    /// 调整屏幕尺寸
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.parser.set_size(rows, cols);
+    }
+
+    /// 光标所在行从行首到光标处的文本（用于识别密码提示等）
+    pub fn current_line(&self) -> String {
+        let screen = self.parser.screen();
+        let (row, col) = screen.cursor_position();
+        screen.contents_between(row, 0, row, col)
+    }
+
+    /// 生成当前屏幕快照
+    pub fn snapshot(&self) -> ScreenSnapshot {
+        let screen = self.parser.screen();
+        let (rows, cols) = screen.size();
+        let (cursor_row, cursor_col) = screen.cursor_position();
+
+        let cells = (0..rows)
+            .map(|row| {
+                (0..cols)
+                    .map(|col| match screen.cell(row, col) {
+                        Some(cell) => CellSnapshot {
+                            text: cell.contents(),
+                            fg: convert_color(cell.fgcolor()),
+                            bg: convert_color(cell.bgcolor()),
+                            bold: cell.bold(),
+                            italic: cell.italic(),
+                            underline: cell.underline(),
+                            inverse: cell.inverse(),
+                            wide: cell.is_wide(),
+                        },
+                        None => CellSnapshot {
+                            text: String::new(),
+                            fg: None,
+                            bg: None,
+                            bold: false,
+                            italic: false,
+                            underline: false,
+                            inverse: false,
+                            wide: false,
+                        },
+                    })
+                    .collect()
+            })
+            .collect();
+
+        ScreenSnapshot {
+            rows,
+            cols,
+            output_cursor: self.processed,
+            cursor: CursorState {
+                row: cursor_row,
+                col: cursor_col,
+                visible: !screen.hide_cursor(),
+            },
+            alternate_screen: screen.alternate_screen(),
+            title: screen.title().to_string(),
+            cells,
+            formatted: String::from_utf8_lossy(&screen.state_formatted()).to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_tracks_cells_and_cursor() {
+        let main: &[u8] = b"hi \x1b[1;31mred\x1b[0m\r\n";
+        let alternate: &[u8] = b"\x1b[?1049h\x1b[3;5Hvim";
+        let mut screen = ScreenTracker::new(20, 5);
+        screen.process(main);
+        screen.process(alternate);
+
+        let snapshot = screen.snapshot();
+        assert_eq!((snapshot.rows, snapshot.cols), (5, 20));
+        assert_eq!(snapshot.output_cursor, (main.len() + alternate.len()) as u64);
+        assert!(snapshot.alternate_screen);
+        // 备用屏幕是全新画面
+        assert_eq!(snapshot.cells[0][0].text, "");
+        assert_eq!(snapshot.cells[2][4].text, "v");
+        assert_eq!(
+            snapshot.cursor,
+            CursorState { row: 2, col: 7, visible: true }
+        );
+
+        // 离开备用屏幕后恢复主屏幕内容与属性
+        screen.process(b"\x1b[?1049l");
+        let snapshot = screen.snapshot();
+        assert!(!snapshot.alternate_screen);
+        assert_eq!(snapshot.cells[0][0].text, "h");
+        assert_eq!(snapshot.cells[0][3].text, "r");
+        assert!(snapshot.cells[0][3].bold);
+        assert_eq!(screen.current_line(), "");
+        screen.process(b"Password: ");
+        assert_eq!(screen.current_line(), "Password: ");
+        assert_eq!(snapshot.cells[0][3].fg, Some(CellColor::Indexed(1)));
+    }
+}
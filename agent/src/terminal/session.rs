@@ -1,67 +1,137 @@
 // agent/src/terminal/session.rs
 // 单个终端会话的状态与逻辑
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use serde::{Deserialize, Serialize};
 
+pub use crate::terminal::pty::ExitInfo;
+use crate::terminal::recorder::{is_password_prompt, CapturedCommand, CommandRecorder};
+use crate::terminal::screen::{ScreenSnapshot, ScreenTracker};
+use crate::terminal::throttle::{utf8_prefix_len, OutputLimit, OutputThrottle, ThrottleStatus};
+use crate::terminal::viewer::{Viewer, ViewerInfo, ViewerRegistry, ViewerRole};
 #[cfg(unix)]
 use crate::terminal::pty::unix::UnixPty;
 #[cfg(windows)]
 use crate::terminal::pty::windows::WindowsPty;
 
+#[cfg(unix)]
+type PlatformPty = UnixPty;
+#[cfg(windows)]
+type PlatformPty = WindowsPty;
+
+/// 关闭会话时等待子进程退出的最长时间
+const EXIT_WAIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 乱序输入等待缺口补齐的最长时间
+const INPUT_REORDER_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 重排缓冲区最多暂存的乱序输入条数
+const INPUT_REORDER_MAX_PENDING: usize = 256;
+
+/// 输出环形缓冲区默认容量
+pub const DEFAULT_BUFFER_SIZE: usize = 10 * 1024 * 1024;
+
+/// 输出环形缓冲区容量上下限
+const MIN_BUFFER_SIZE: usize = 4 * 1024;
+const MAX_BUFFER_SIZE: usize = 256 * 1024 * 1024;
+
 /// Shell 类型
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum ShellType {
+    /// 当前用户的默认 shell（Unix 取自 /etc/passwd，Windows 取自 %COMSPEC%）
+    #[default]
+    Default,
     Cmd,
     PowerShell,
     Pwsh,
     Sh,
     Bash,
     Zsh,
+    Fish,
 }
 
+/// PATH 之外额外搜索的 Unix shell 目录
+#[cfg(unix)]
+const UNIX_SHELL_DIRS: &[&str] = &["/bin", "/usr/bin", "/usr/local/bin", "/opt/homebrew/bin"];
+
 impl ShellType {
     /// 获取 shell 可执行文件路径
     pub fn get_shell_path(&self) -> io::Result<String> {
         #[cfg(windows)]
         {
             match self {
+                ShellType::Default => Ok(std::env::var("COMSPEC")
+                    .ok()
+                    .filter(|s| !s.is_empty())
+                    .unwrap_or_else(|| "cmd.exe".to_string())),
                 ShellType::Cmd => Ok("cmd.exe".to_string()),
                 ShellType::PowerShell => Ok("powershell.exe".to_string()),
                 ShellType::Pwsh => Ok("pwsh.exe".to_string()),
-                _ => Err(io::Error::new(io::ErrorKind::Unsupported, "Shell not supported on Windows")),
+                ShellType::Sh | ShellType::Bash | ShellType::Zsh | ShellType::Fish => {
+                    let name = format!("{}.exe", self.binary_name());
+                    find_executable(&name, &[]).ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, format!("{} not found", name))
+                    })
+                }
             }
         }
-        
+
         #[cfg(unix)]
         {
             match self {
+                ShellType::Default => Ok(crate::terminal::pty::user_default_shell()
+                    .filter(|s| std::path::Path::new(s).exists())
+                    .or_else(|| std::env::var("SHELL").ok().filter(|s| !s.is_empty()))
+                    .unwrap_or_else(|| "/bin/sh".to_string())),
                 ShellType::Sh => Ok("/bin/sh".to_string()),
-                ShellType::Bash => Ok("/bin/bash".to_string()),
-                ShellType::Zsh => {
-                    // 尝试常见路径
-                    if std::path::Path::new("/bin/zsh").exists() {
-                        Ok("/bin/zsh".to_string())
-                    } else if std::path::Path::new("/usr/bin/zsh").exists() {
-                        Ok("/usr/bin/zsh".to_string())
-                    } else {
-                        Err(io::Error::new(io::ErrorKind::NotFound, "zsh not found"))
-                    }
+                ShellType::Bash | ShellType::Zsh | ShellType::Fish | ShellType::Pwsh => {
+                    let name = self.binary_name();
+                    find_executable(name, UNIX_SHELL_DIRS).ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, format!("{} not found", name))
+                    })
                 }
                 _ => Err(io::Error::new(io::ErrorKind::Unsupported, "Shell not supported on Unix")),
             }
         }
-        
+
         #[cfg(not(any(unix, windows)))]
         {
             Err(io::Error::new(io::ErrorKind::Unsupported, "Unsupported platform"))
         }
     }
+
+    /// 可执行文件名（不含路径与扩展名）
+    fn binary_name(&self) -> &'static str {
+        match self {
+            ShellType::Default => "",
+            ShellType::Cmd => "cmd",
+            ShellType::PowerShell => "powershell",
+            ShellType::Pwsh => "pwsh",
+            ShellType::Sh => "sh",
+            ShellType::Bash => "bash",
+            ShellType::Zsh => "zsh",
+            ShellType::Fish => "fish",
+        }
+    }
+}
+
+/// 在 PATH 及额外目录中查找可执行文件
+fn find_executable(name: &str, extra_dirs: &[&str]) -> Option<String> {
+    let path_dirs = std::env::var_os("PATH")
+        .map(|p| std::env::split_paths(&p).collect::<Vec<_>>())
+        .unwrap_or_default();
+    path_dirs
+        .into_iter()
+        .chain(extra_dirs.iter().map(std::path::PathBuf::from))
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+        .map(|p| p.to_string_lossy().into_owned())
 }
 
 /// 会话状态
@@ -75,15 +145,130 @@ pub enum SessionState {
     Failed,
 }
 
+/// 会话过期原因
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpiryReason {
+    /// 超过空闲超时（既无输入也无输出）
+    IdleTimeout,
+    /// 超过最长存活时间
+    MaxDuration,
+}
+
+impl ExpiryReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExpiryReason::IdleTimeout => "idle_timeout",
+            ExpiryReason::MaxDuration => "max_duration",
+        }
+    }
+}
+
 /// 终端会话配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionConfig {
     pub session_id: String,
+    #[serde(default)]
     pub shell_type: ShellType,
+    /// 自定义程序路径，设置后忽略 shell_type
+    #[serde(default)]
+    pub program: Option<String>,
+    /// 传给程序的参数（不含 argv[0]）
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// 以登录 shell 方式启动（argv[0] 前加 `-`）
+    #[serde(default)]
+    pub login: bool,
     pub cwd: Option<String>,
     pub env: Option<HashMap<String, String>>,
     pub cols: u16,
     pub rows: u16,
+    /// 空闲超时（秒），None 表示使用管理器默认值，0 表示不限制
+    #[serde(default)]
+    pub idle_timeout: Option<u64>,
+    /// 最长存活时间（秒），None 表示使用管理器默认值，0 表示不限制
+    #[serde(default)]
+    pub max_duration: Option<u64>,
+    /// 输出环形缓冲区容量（字节），None 表示使用管理器默认值
+    #[serde(default)]
+    pub buffer_size: Option<usize>,
+    /// 是否捕获输入的命令行用于审计，None 表示使用管理器默认值
+    #[serde(default)]
+    pub capture_commands: Option<bool>,
+    /// 打开会话的操作者（用于审计）
+    #[serde(default)]
+    pub operator: Option<String>,
+    /// 输出上报速率上限（字节/秒），None 表示使用管理器默认值，0 表示不限速
+    #[serde(default)]
+    pub output_rate_limit: Option<u64>,
+    /// 输出上报突发容量（字节），None 表示使用管理器默认值
+    #[serde(default)]
+    pub output_burst: Option<u64>,
+}
+
+impl SessionConfig {
+    /// 解析实际要启动的程序路径
+    pub fn resolve_program(&self) -> io::Result<String> {
+        match self.program.as_deref() {
+            Some(program) if !program.is_empty() => Ok(program.to_string()),
+            _ => self.shell_type.get_shell_path(),
+        }
+    }
+
+    /// 构建子进程环境变量
+    ///
+    /// 默认设置 TERM、COLORTERM 与 UTF-8 的 LANG，调用方传入的变量优先。
+    pub fn build_env(&self, program: &str) -> HashMap<String, String> {
+        let mut env = HashMap::new();
+        env.insert("TERM".to_string(), "xterm-256color".to_string());
+        env.insert("COLORTERM".to_string(), "truecolor".to_string());
+        env.insert("LANG".to_string(), default_lang());
+        if self.program.is_none() {
+            env.insert("SHELL".to_string(), program.to_string());
+        }
+        if let Some(ref user_env) = self.env {
+            env.extend(user_env.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        env
+    }
+}
+
+/// 测试用会话配置：sh、80x24，其余取管理器默认值，按需用结构体更新语法覆盖
+#[cfg(test)]
+pub fn test_config(session_id: &str) -> SessionConfig {
+    SessionConfig {
+        session_id: session_id.to_string(),
+        shell_type: ShellType::Sh,
+        program: None,
+        args: Vec::new(),
+        login: false,
+        cwd: None,
+        env: None,
+        cols: 80,
+        rows: 24,
+        idle_timeout: None,
+        max_duration: None,
+        buffer_size: None,
+        capture_commands: None,
+        operator: None,
+        output_rate_limit: None,
+        output_burst: None,
+    }
+}
+
+/// 默认 LANG：沿用 agent 自身的 UTF-8 locale，否则回退到平台通用的 UTF-8 locale
+fn default_lang() -> String {
+    if let Ok(lang) = std::env::var("LANG") {
+        let lower = lang.to_lowercase();
+        if lower.ends_with(".utf-8") || lower.ends_with(".utf8") {
+            return lang;
+        }
+    }
+    if cfg!(target_os = "macos") {
+        "en_US.UTF-8".to_string()
+    } else {
+        "C.UTF-8".to_string()
+    }
 }
 
 /// 终端会话
@@ -94,17 +279,29 @@ pub struct TerminalSession {
     pub shell_path: Arc<Mutex<Option<String>>>,
     pub output_cursor: Arc<Mutex<u64>>,
     pub output_buffer: Arc<Mutex<RingBuffer>>,
-    pub last_client_seq: Arc<Mutex<u64>>,
-    
-    #[cfg(unix)]
-    pty: Arc<Mutex<Option<UnixPty>>>,
-    #[cfg(windows)]
-    pty: Arc<Mutex<Option<WindowsPty>>>,
-    
+    /// 与输出缓冲区同步更新的屏幕状态
+    screen: Arc<Mutex<ScreenTracker>>,
+    /// 附加的查看者，各自记录已分发到的输出 cursor
+    viewers: Arc<Mutex<ViewerRegistry>>,
+    /// 命令行捕获（未启用时为 None）
+    command_recorder: Option<Mutex<CommandRecorder>>,
+    pub operator: Option<String>,
+    /// 输出上报限速（未限速时为 None）
+    output_throttle: Option<Mutex<OutputThrottle>>,
+    pub input_buffer: Arc<Mutex<InputReorderBuffer>>,
+    pub created_at: Instant,
+    pub last_activity: Arc<Mutex<Instant>>,
+    idle_timeout: Option<Duration>,
+    max_duration: Option<Duration>,
+    pub exit_info: Arc<Mutex<Option<ExitInfo>>>,
+
+    pty: Arc<Mutex<Option<PlatformPty>>>,
+
     reader_thread: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
+    stop_reader: Arc<AtomicBool>,
 }
 
-/// 环形缓冲区（10MB）
+/// 输出环形缓冲区（默认 10MB，可按会话配置）
 pub struct RingBuffer {
     buffer: Vec<u8>,
     capacity: usize,
@@ -186,6 +383,108 @@ impl RingBuffer {
     }
 }
 
+/// 输入重排缓冲区
+///
+/// 按 client_seq 顺序交付输入：乱序到达的输入先暂存，等缺口补齐后一起交付；
+/// 缺口超过等待时间（或暂存过多）时跳过缺口，避免输入永久卡住。
+/// 服务端按会话从 1 开始分配 seq，第一条输入先于 seq 1 到达时同样等待。
+pub struct InputReorderBuffer {
+    /// 已交付的最大连续 seq
+    acked_seq: u64,
+    pending: BTreeMap<u64, PendingInput>,
+    timeout: Duration,
+}
+
+struct PendingInput {
+    data: Vec<u8>,
+    received_at: Instant,
+}
+
+impl InputReorderBuffer {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            acked_seq: 0,
+            pending: BTreeMap::new(),
+            timeout,
+        }
+    }
+
+    /// 接收一条输入，返回现在可以按序写入的数据
+    pub fn push(&mut self, seq: u64, data: Vec<u8>, now: Instant) -> Vec<Vec<u8>> {
+        if seq <= self.acked_seq || self.pending.contains_key(&seq) {
+            // 重复输入，忽略
+            return Vec::new();
+        }
+        self.pending.insert(seq, PendingInput { data, received_at: now });
+
+        let mut ready = self.drain_contiguous();
+        if self.pending.len() > INPUT_REORDER_MAX_PENDING {
+            ready.extend(self.skip_gap());
+        }
+        ready
+    }
+
+    /// 最早的暂存输入等待超时后跳过缺口，返回可写入的数据
+    pub fn take_expired(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let mut ready = Vec::new();
+        while let Some(first) = self.pending.values().next() {
+            if now.duration_since(first.received_at) < self.timeout {
+                break;
+            }
+            ready.extend(self.skip_gap());
+        }
+        ready
+    }
+
+    /// 已交付的最大连续 seq
+    pub fn acked_seq(&self) -> u64 {
+        self.acked_seq
+    }
+
+    /// 等待缺口补齐的输入条数
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn drain_contiguous(&mut self) -> Vec<Vec<u8>> {
+        let mut ready = Vec::new();
+        while let Some(input) = self.pending.remove(&(self.acked_seq + 1)) {
+            self.acked_seq += 1;
+            ready.push(input.data);
+        }
+        ready
+    }
+
+    fn skip_gap(&mut self) -> Vec<Vec<u8>> {
+        match self.pending.keys().next() {
+            Some(&first) => {
+                tracing::warn!(
+                    "Input seq {}..{} never arrived, skipping gap",
+                    self.acked_seq + 1,
+                    first - 1
+                );
+                self.acked_seq = first - 1;
+                self.drain_contiguous()
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+/// 一次输出读取的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputRead {
+    pub data: Vec<u8>,
+    /// 读取后的 cursor
+    pub next_cursor: u64,
+    /// 因缓冲区溢出而丢弃、未能送达的字节数
+    pub dropped_bytes: u64,
+    /// 调用方的 cursor 已失效，需要从 data 起点重新同步画面
+    pub resync: bool,
+    /// 限速状态：输出被截断、合并或恢复时设置
+    pub throttle: Option<ThrottleStatus>,
+}
+
 /// 缓冲区错误类型
 #[derive(Debug)]
 pub enum BufferError {
@@ -202,6 +501,15 @@ impl TerminalSession {
     pub fn new(config: SessionConfig) -> io::Result<Self> {
         let session_id = config.session_id.clone();
         let state = Arc::new(Mutex::new(SessionState::Opening));
+        let now = Instant::now();
+        let buffer_size = config
+            .buffer_size
+            .unwrap_or(DEFAULT_BUFFER_SIZE)
+            .clamp(MIN_BUFFER_SIZE, MAX_BUFFER_SIZE);
+        let output_limit = OutputLimit::new(
+            config.output_rate_limit.unwrap_or(0),
+            config.output_burst.unwrap_or(0),
+        );
         
         Ok(Self {
             session_id,
@@ -209,23 +517,41 @@ impl TerminalSession {
             pid: Arc::new(Mutex::new(None)),
             shell_path: Arc::new(Mutex::new(None)),
             output_cursor: Arc::new(Mutex::new(0)),
-            output_buffer: Arc::new(Mutex::new(RingBuffer::new(10 * 1024 * 1024))), // 10MB
-            last_client_seq: Arc::new(Mutex::new(0)),
+            output_buffer: Arc::new(Mutex::new(RingBuffer::new(buffer_size))),
+            screen: Arc::new(Mutex::new(ScreenTracker::new(config.cols, config.rows))),
+            viewers: Arc::new(Mutex::new(ViewerRegistry::new())),
+            command_recorder: config
+                .capture_commands
+                .unwrap_or(false)
+                .then(|| Mutex::new(CommandRecorder::new())),
+            operator: config.operator.clone(),
+            output_throttle: output_limit.map(|limit| Mutex::new(OutputThrottle::new(limit))),
+            input_buffer: Arc::new(Mutex::new(InputReorderBuffer::new(INPUT_REORDER_TIMEOUT))),
+            created_at: now,
+            last_activity: Arc::new(Mutex::new(now)),
+            idle_timeout: config.idle_timeout.filter(|&s| s > 0).map(Duration::from_secs),
+            max_duration: config.max_duration.filter(|&s| s > 0).map(Duration::from_secs),
+            exit_info: Arc::new(Mutex::new(None)),
             pty: Arc::new(Mutex::new(None)),
             reader_thread: Arc::new(Mutex::new(None)),
+            stop_reader: Arc::new(AtomicBool::new(false)),
         })
     }
 
     /// 启动会话
     pub fn start(&self, config: SessionConfig) -> io::Result<()> {
-        let shell_path = config.shell_type.get_shell_path()?;
-        
-        #[cfg(unix)]
-        let mut pty = UnixPty::new(config.cols, config.rows)?;
-        #[cfg(windows)]
-        let mut pty = WindowsPty::new(config.cols, config.rows)?;
+        let shell_path = config.resolve_program()?;
+        let env = config.build_env(&shell_path);
 
-        let pid = pty.spawn(&shell_path, config.cwd.as_deref(), config.env.as_ref())?;
+        let mut pty = PlatformPty::new(config.cols, config.rows)?;
+
+        let pid = pty.spawn(
+            &shell_path,
+            &config.args,
+            config.login,
+            config.cwd.as_deref(),
+            Some(&env),
+        )?;
 
         *self.pid.lock().unwrap() = Some(pid);
         *self.shell_path.lock().unwrap() = Some(shell_path);
@@ -238,118 +564,158 @@ impl TerminalSession {
     }
 
     /// 启动输出读取线程
-    #[cfg(unix)]
-    fn start_reader_thread(&self, pty: UnixPty) -> io::Result<()> {
+    ///
+    /// 子进程退出后读尽剩余输出，记录退出码与信号并切换会话状态：
+    /// 正常退出为 Closed，被信号终止或读取出错为 Failed。
+    fn start_reader_thread(&self, pty: PlatformPty) -> io::Result<()> {
+        *self.pty.lock().unwrap() = Some(pty);
+
+        let pty = Arc::clone(&self.pty);
         let output_buffer = Arc::clone(&self.output_buffer);
+        let screen = Arc::clone(&self.screen);
         let output_cursor = Arc::clone(&self.output_cursor);
+        let last_activity = Arc::clone(&self.last_activity);
         let state = Arc::clone(&self.state);
-        let pty = Arc::new(Mutex::new(Some(pty)));
+        let exit_info = Arc::clone(&self.exit_info);
+        let stop = Arc::clone(&self.stop_reader);
 
         let handle = thread::spawn(move || {
             let mut buf = [0u8; 4096];
-            loop {
-                let pty_guard = pty.lock().unwrap();
-                if pty_guard.is_none() {
-                    break;
-                }
-                let pty_ref = pty_guard.as_ref().unwrap();
+            let mut exited = None;
 
-                match pty_ref.read(&mut buf) {
-                    Ok(0) => {
-                        // EOF
-                        *state.lock().unwrap() = SessionState::Closed;
-                        break;
-                    }
-                    Ok(n) => {
-                        let data = &buf[..n];
-                        output_buffer.lock().unwrap().write(data);
+            while !stop.load(Ordering::SeqCst) {
+                // 读取与退出检查在锁内完成，休眠前释放锁，避免阻塞输入与关闭
+                let (result, child_exit) = {
+                    let guard = pty.lock().unwrap();
+                    let Some(pty_ref) = guard.as_ref() else { break };
+                    let result = pty_ref.read(&mut buf);
+                    let child_exit = match result {
+                        Ok(n) if n > 0 => None,
+                        _ => pty_ref.try_wait(),
+                    };
+                    (result, child_exit)
+                };
+
+                match result {
+                    Ok(n) if n > 0 => {
+                        output_buffer.lock().unwrap().write(&buf[..n]);
+                        screen.lock().unwrap().process(&buf[..n]);
                         *output_cursor.lock().unwrap() += n as u64;
+                        *last_activity.lock().unwrap() = Instant::now();
                     }
-                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        // 后台进程可能仍占用 slave 端，因此不能只依赖 EOF 判断退出
+                        if child_exit.is_some() {
+                            exited = child_exit;
+                            break;
+                        }
                         thread::sleep(Duration::from_millis(10));
                     }
-                    Err(_) => {
-                        *state.lock().unwrap() = SessionState::Failed;
+                    _ => {
+                        // EOF，或 Linux 上 slave 端全部关闭后的 EIO
+                        exited = child_exit.or_else(|| wait_for_exit(&pty, EXIT_WAIT_TIMEOUT));
                         break;
                     }
                 }
             }
-        });
 
-        *self.reader_thread.lock().unwrap() = Some(handle);
-        *self.pty.lock().unwrap() = Some(pty.lock().unwrap().take().unwrap());
-
-        Ok(())
-    }
-
-    #[cfg(windows)]
-    fn start_reader_thread(&self, pty: WindowsPty) -> io::Result<()> {
-        let output_buffer = Arc::clone(&self.output_buffer);
-        let output_cursor = Arc::clone(&self.output_cursor);
-        let state = Arc::clone(&self.state);
-        let pty_arc = Arc::new(Mutex::new(Some(pty)));
-        let pty_clone = Arc::clone(&pty_arc);
-
-        let handle = thread::spawn(move || {
-            let mut buf = [0u8; 4096];
-            loop {
-                let pty_guard = pty_clone.lock().unwrap();
-                if pty_guard.is_none() {
-                    break;
-                }
-                let pty_ref = pty_guard.as_ref().unwrap();
+            if stop.load(Ordering::SeqCst) {
+                // 由 close() 主动关闭，状态与退出信息由 close() 负责
+                return;
+            }
 
-                match pty_ref.read(&mut buf) {
-                    Ok(0) => {
-                        *state.lock().unwrap() = SessionState::Closed;
-                        break;
-                    }
-                    Ok(n) => {
-                        let data = &buf[..n];
-                        // Windows ConPTY 输出已是 UTF-8（如果配置正确）
-                        output_buffer.lock().unwrap().write(data);
-                        *output_cursor.lock().unwrap() += n as u64;
-                    }
-                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
-                        thread::sleep(Duration::from_millis(10));
-                    }
-                    Err(_) => {
-                        *state.lock().unwrap() = SessionState::Failed;
-                        break;
-                    }
-                }
+            let mut state = state.lock().unwrap();
+            if *state != SessionState::Closed {
+                *state = match exited {
+                    Some(ExitInfo { signal: Some(_), .. }) | None => SessionState::Failed,
+                    Some(_) => SessionState::Closed,
+                };
             }
+            *exit_info.lock().unwrap() = exited;
         });
 
         *self.reader_thread.lock().unwrap() = Some(handle);
-        *self.pty.lock().unwrap() = pty_arc.lock().unwrap().take();
 
         Ok(())
     }
 
-    /// 写入输入（带去重）
+    /// 写入输入（去重并按 client_seq 重排）
+    ///
+    /// 返回本次实际写入 PTY 的字节数；乱序到达的输入会暂存到缺口补齐或超时。
     pub fn write_input(&self, client_seq: u64, data: &[u8]) -> io::Result<usize> {
-        let mut last_seq = self.last_client_seq.lock().unwrap();
-        if client_seq <= *last_seq {
-            // 重复输入，忽略
+        let mut input = self.input_buffer.lock().unwrap();
+        let pty_guard = self.pty.lock().unwrap();
+        let pty = pty_guard
+            .as_ref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "PTY not initialized"))?;
+
+        let now = Instant::now();
+        let mut ready = input.take_expired(now);
+        ready.extend(input.push(client_seq, data.to_vec(), now));
+
+        self.write_ready(pty, &ready)
+    }
+
+    /// 写入等待超时的乱序输入，返回写入的字节数
+    pub fn flush_stale_input(&self) -> io::Result<usize> {
+        let mut input = self.input_buffer.lock().unwrap();
+        let ready = input.take_expired(Instant::now());
+        if ready.is_empty() {
             return Ok(0);
         }
 
         let pty_guard = self.pty.lock().unwrap();
-        if let Some(ref pty) = *pty_guard {
-            let written = pty.write(data)?;
-            *last_seq = client_seq;
-            Ok(written)
-        } else {
-            Err(io::Error::new(io::ErrorKind::NotConnected, "PTY not initialized"))
+        let pty = pty_guard
+            .as_ref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "PTY not initialized"))?;
+        self.write_ready(pty, &ready)
+    }
+
+    /// 按序写入已就绪的输入，并交给命令捕获
+    ///
+    /// 密码状态在写入之前判断：写入后 shell 可能已切换回显或重绘提示。
+    fn write_ready(&self, pty: &PlatformPty, ready: &[Vec<u8>]) -> io::Result<usize> {
+        let mut written = 0;
+        for chunk in ready {
+            if let Some(ref recorder) = self.command_recorder {
+                // 回显关闭或光标停在密码提示上时，该行视为密码
+                let secret = !pty.echo_enabled()
+                    || is_password_prompt(&self.screen.lock().unwrap().current_line());
+                recorder.lock().unwrap().feed(chunk, secret);
+            }
+            written += pty.write(chunk)?;
+        }
+        if !ready.is_empty() {
+            *self.last_activity.lock().unwrap() = Instant::now();
+        }
+        Ok(written)
+    }
+
+    /// 取出已捕获的命令行
+    pub fn take_captured_commands(&self) -> Vec<CapturedCommand> {
+        match self.command_recorder {
+            Some(ref recorder) => recorder.lock().unwrap().take(),
+            None => Vec::new(),
         }
     }
 
+    /// 已按序写入的最大连续 client_seq
+    pub fn acked_input_seq(&self) -> u64 {
+        self.input_buffer.lock().unwrap().acked_seq()
+    }
+
+    /// 等待缺口补齐的输入条数
+    pub fn pending_input_count(&self) -> usize {
+        self.input_buffer.lock().unwrap().pending_len()
+    }
+
     /// 调整窗口大小
     pub fn resize(&self, cols: u16, rows: u16) -> io::Result<()> {
         let pty_guard = self.pty.lock().unwrap();
         if let Some(ref pty) = *pty_guard {
-            pty.resize(cols, rows)
+            pty.resize(cols, rows)?;
+            self.screen.lock().unwrap().resize(cols, rows);
+            Ok(())
         } else {
             Err(io::Error::new(io::ErrorKind::NotConnected, "PTY not initialized"))
         }
@@ -368,6 +734,157 @@ impl TerminalSession {
         Ok((current_cursor, data))
     }
 
+    /// 从指定 cursor 读取输出，cursor 已被覆盖时从最旧可用位置继续并标记缺口
+    ///
+    /// 缺口与补读在同一次加锁内完成，读取线程并发写入不会让上报的丢失字节数偏小。
+    pub fn read_output(&self, from_cursor: u64) -> OutputRead {
+        let buffer = self.output_buffer.lock().unwrap();
+        let current_cursor = *self.output_cursor.lock().unwrap();
+        let resync = |next_cursor, data, dropped_bytes| OutputRead {
+            data,
+            next_cursor,
+            dropped_bytes,
+            resync: true,
+            throttle: None,
+        };
+
+        if from_cursor > current_cursor {
+            // cursor 超前（例如服务端状态来自之前的会话），直接对齐到当前位置
+            return resync(current_cursor, Vec::new(), 0);
+        }
+        match buffer.read_from(from_cursor) {
+            Ok(data) => OutputRead {
+                data,
+                next_cursor: current_cursor,
+                dropped_bytes: 0,
+                resync: false,
+                throttle: None,
+            },
+            Err(BufferError::DataLost { oldest_available, .. }) => {
+                let data = buffer.read_from(oldest_available).unwrap_or_default();
+                resync(current_cursor, data, oldest_available - from_cursor)
+            }
+            Err(BufferError::CursorTooLarge) => resync(current_cursor, Vec::new(), 0),
+        }
+    }
+
+    /// 读取用于上报的输出，受会话输出限速约束
+    pub fn read_report_output(&self, from_cursor: u64) -> OutputRead {
+        match self.output_throttle {
+            Some(ref throttle) => self.read_output_throttled(from_cursor, &mut throttle.lock().unwrap()),
+            None => self.read_output(from_cursor),
+        }
+    }
+
+    /// 按令牌桶限速读取输出
+    ///
+    /// 只限制上报，读取线程仍持续读取 PTY，子进程不会因输出阻塞，Ctrl-C 等输入照常送达。
+    /// 积压超过突发容量时中间画面已无意义，跳过积压并以当前屏幕快照代替。
+    fn read_output_throttled(&self, from_cursor: u64, throttle: &mut OutputThrottle) -> OutputRead {
+        let mut output = self.read_output(from_cursor);
+        let available = output.data.len() as u64;
+        let allowance = throttle.allowance();
+
+        if available <= allowance {
+            throttle.consume(available);
+            if throttle.set_paused(false) {
+                output.throttle = Some(ThrottleStatus {
+                    paused: false,
+                    pending_bytes: 0,
+                    coalesced_bytes: 0,
+                });
+            }
+            return output;
+        }
+
+        let start = output.next_cursor - available;
+        let mut coalesced_bytes = 0;
+        if available - allowance > throttle.burst() {
+            let snapshot = self.screen_snapshot();
+            let resume_cursor = snapshot.output_cursor.max(start);
+            // 快照会重绘整个画面，缓冲区溢出丢弃的部分一并计入合并字节数
+            coalesced_bytes = resume_cursor - start + output.dropped_bytes;
+            output.data = snapshot.formatted.into_bytes();
+            output.next_cursor = resume_cursor;
+            output.dropped_bytes = 0;
+            output.resync = false;
+        } else {
+            let len = utf8_prefix_len(&output.data, allowance as usize);
+            output.data.truncate(len);
+            output.next_cursor = start + len as u64;
+        }
+        throttle.consume(output.data.len() as u64);
+
+        // 暂停期间没有新数据可发时不重复通知
+        if throttle.set_paused(true) || !output.data.is_empty() {
+            output.throttle = Some(ThrottleStatus {
+                paused: true,
+                pending_bytes: self.get_output_cursor().saturating_sub(output.next_cursor),
+                coalesced_bytes,
+            });
+        }
+        output
+    }
+
+    /// 获取当前屏幕快照（供新接入或重连的查看者渲染）
+    ///
+    /// 快照自带对应的输出 cursor，查看者从该 cursor 继续拉取增量输出即可无缝衔接。
+    pub fn screen_snapshot(&self) -> ScreenSnapshot {
+        self.screen.lock().unwrap().snapshot()
+    }
+
+    /// 附加查看者，返回当前屏幕快照；查看者从快照对应的 cursor 开始跟随输出
+    pub fn attach_viewer(
+        &self,
+        viewer_id: &str,
+        role: ViewerRole,
+        operator: Option<String>,
+    ) -> io::Result<ScreenSnapshot> {
+        let snapshot = self.screen_snapshot();
+        self.viewers
+            .lock()
+            .unwrap()
+            .attach(viewer_id, role, operator, snapshot.output_cursor)?;
+        Ok(snapshot)
+    }
+
+    /// 断开查看者
+    pub fn detach_viewer(&self, viewer_id: &str) -> Option<Viewer> {
+        self.viewers.lock().unwrap().detach(viewer_id)
+    }
+
+    /// 断开全部查看者
+    pub fn detach_all_viewers(&self) -> Vec<Viewer> {
+        self.viewers.lock().unwrap().detach_all()
+    }
+
+    /// 检查输入来源能否写入 PTY
+    ///
+    /// 只读观察者与未附加的查看者不能输入；已有查看者附加时输入必须带 viewer_id，
+    /// 否则观察者省略 viewer_id 即可绕过检查。
+    pub fn check_input_allowed(&self, viewer_id: Option<&str>) -> Result<(), &'static str> {
+        let viewers = self.viewers.lock().unwrap();
+        match viewer_id {
+            Some(viewer_id) => match viewers.role(viewer_id) {
+                Some(role) if role.can_write() => Ok(()),
+                Some(_) => Err("Viewer is read-only"),
+                None => Err("Viewer not attached"),
+            },
+            None if viewers.ids().is_empty() => Ok(()),
+            None => Err("viewer_id is required while viewers are attached"),
+        }
+    }
+
+    pub fn list_viewers(&self) -> Vec<ViewerInfo> {
+        self.viewers.lock().unwrap().list()
+    }
+
+    /// 会话输出只读取上报一次：把查看者 cursor 推进到 `next_cursor`，返回各查看者
+    /// 推进前的 cursor，由服务端按各自起点分发同一段输出
+    pub fn advance_viewers(&self, next_cursor: u64) -> Vec<ViewerInfo> {
+        self.viewers.lock().unwrap().advance(next_cursor)
+    }
+
     /// 获取最旧可用 cursor
     pub fn get_oldest_available_cursor(&self) -> u64 {
         self.output_buffer.lock().unwrap().oldest_available_cursor()
@@ -377,31 +894,28 @@ impl TerminalSession {
     pub fn close(&self, force: bool) -> io::Result<Option<i32>> {
         *self.state.lock().unwrap() = SessionState::Closed;
 
-        let pty_guard = self.pty.lock().unwrap();
-        if let Some(ref pty) = *pty_guard {
+        if let Some(ref pty) = *self.pty.lock().unwrap() {
             pty.close(force)?;
         }
 
-        // 等待读取线程结束
+        // 先等待子进程退出，让读取线程读尽剩余输出，再停止读取线程
+        let exited = wait_for_exit(&self.pty, EXIT_WAIT_TIMEOUT);
+        self.stop_reader.store(true, Ordering::SeqCst);
         if let Some(handle) = self.reader_thread.lock().unwrap().take() {
             let _ = handle.join();
         }
 
-        // 获取退出码（平台相关）
-        #[cfg(unix)]
-        {
-            if let Some(ref pty) = *pty_guard {
-                return Ok(pty.get_exit_code());
-            }
-        }
-        #[cfg(windows)]
-        {
-            if let Some(ref pty) = *pty_guard {
-                return Ok(pty.get_exit_code());
-            }
+        let mut exit_info = self.exit_info.lock().unwrap();
+        if exit_info.is_none() {
+            *exit_info = exited;
         }
 
-        Ok(None)
+        Ok(exit_info.and_then(|info| info.code))
+    }
+
+    /// 获取子进程退出信息（未退出时为 None）
+    pub fn get_exit_info(&self) -> Option<ExitInfo> {
+        *self.exit_info.lock().unwrap()
     }
 
     /// 获取当前状态
@@ -423,6 +937,41 @@ impl TerminalSession {
     pub fn get_output_cursor(&self) -> u64 {
         *self.output_cursor.lock().unwrap()
     }
+
+    /// 以给定时刻判断会话是否过期（最长存活时间优先）
+    pub fn expiry_reason_at(&self, now: Instant) -> Option<ExpiryReason> {
+        if let Some(max_duration) = self.max_duration {
+            if now.saturating_duration_since(self.created_at) >= max_duration {
+                return Some(ExpiryReason::MaxDuration);
+            }
+        }
+
+        if let Some(idle_timeout) = self.idle_timeout {
+            let last_activity = *self.last_activity.lock().unwrap();
+            if now.saturating_duration_since(last_activity) >= idle_timeout {
+                return Some(ExpiryReason::IdleTimeout);
+            }
+        }
+
+        None
+    }
+
+    /// 获取已存活时长
+    pub fn uptime(&self) -> Duration {
+        self.created_at.elapsed()
+    }
+}
+
+/// 在超时时间内轮询等待子进程退出
+fn wait_for_exit(pty: &Mutex<Option<PlatformPty>>, timeout: Duration) -> Option<ExitInfo> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let exited = pty.lock().unwrap().as_ref().and_then(|p| p.try_wait());
+        if exited.is_some() || Instant::now() >= deadline {
+            return exited;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
 }
 
 #[cfg(test)]
@@ -452,6 +1001,249 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_session_expiry() {
+        let session = TerminalSession::new(SessionConfig {
+            idle_timeout: Some(60),
+            max_duration: Some(600),
+            ..test_config("expiry")
+        })
+        .unwrap();
+
+        let start = session.created_at;
+        assert_eq!(session.expiry_reason_at(start + Duration::from_secs(30)), None);
+        assert_eq!(
+            session.expiry_reason_at(start + Duration::from_secs(61)),
+            Some(ExpiryReason::IdleTimeout)
+        );
+
+        // 有活动时重置空闲计时，但不影响最长存活时间
+        *session.last_activity.lock().unwrap() = start + Duration::from_secs(590);
+        assert_eq!(session.expiry_reason_at(start + Duration::from_secs(599)), None);
+        assert_eq!(
+            session.expiry_reason_at(start + Duration::from_secs(600)),
+            Some(ExpiryReason::MaxDuration)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_child_exit_detected() {
+        let config = test_config("exit");
+        let session = TerminalSession::new(config.clone()).unwrap();
+        session.start(config).unwrap();
+        session.write_input(1, b"exit 3\n").unwrap();
+
+        // 等待读取线程检测到子进程退出
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while session.get_exit_info().is_none() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        assert_eq!(
+            session.get_exit_info(),
+            Some(ExitInfo {
+                code: Some(3),
+                signal: None
+            })
+        );
+        assert_eq!(session.get_state(), SessionState::Closed);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_custom_program_with_env() {
+        let mut env = HashMap::new();
+        env.insert("LANG".to_string(), "zh_CN.UTF-8".to_string());
+        let config = SessionConfig {
+            shell_type: ShellType::Default,
+            program: Some("/bin/sh".to_string()),
+            args: vec!["-c".to_string(), "echo \"$0|$TERM|$COLORTERM|$LANG\"; exit 4".to_string()],
+            login: true,
+            env: Some(env),
+            ..test_config("program")
+        };
+        let session = TerminalSession::new(config.clone()).unwrap();
+        session.start(config).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while session.get_exit_info().is_none() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        assert_eq!(session.get_exit_info().and_then(|info| info.code), Some(4));
+        let (_, output) = session.get_output_chunk(0).unwrap();
+        let output = String::from_utf8_lossy(&output);
+        assert!(output.contains("-sh|xterm-256color|truecolor|zh_CN.UTF-8"), "{}", output);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_password_typed_per_keystroke_not_captured() {
+        let config = SessionConfig {
+            shell_type: ShellType::Default,
+            program: Some("/bin/sh".to_string()),
+            args: vec!["-c".to_string(), "printf 'Password:'; read -r pw; sleep 1".to_string()],
+            capture_commands: Some(true),
+            ..test_config("password")
+        };
+        let session = TerminalSession::new(config.clone()).unwrap();
+        session.start(config).unwrap();
+
+        let wait_for_line = |expected: &str| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while session.screen.lock().unwrap().current_line().trim_end() != expected && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+        wait_for_line("Password:");
+
+        // 逐个按键输入并等待回显，回车时光标行已不再像密码提示
+        let mut typed = String::from("Password:");
+        for (seq, key) in "hunter2".chars().enumerate() {
+            session.write_input(seq as u64 + 1, key.to_string().as_bytes()).unwrap();
+            typed.push(key);
+            wait_for_line(&typed);
+        }
+        session.write_input(8, b"\r").unwrap();
+
+        let captured = session.take_captured_commands();
+        assert!(captured.iter().all(|command| command.redacted), "{:?}", captured);
+        assert!(captured.iter().all(|command| !command.command.contains("hunter")), "{:?}", captured);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_default_shell_resolves() {
+        let path = ShellType::Default.get_shell_path().unwrap();
+        assert!(!path.is_empty());
+    }
+
+    #[test]
+    fn test_input_reorder() {
+        let start = Instant::now();
+        let mut input = InputReorderBuffer::new(Duration::from_secs(2));
+
+        assert_eq!(input.push(1, b"a".to_vec(), start), vec![b"a".to_vec()]);
+        // seq 3 先到，等待 seq 2
+        assert!(input.push(3, b"c".to_vec(), start).is_empty());
+        assert_eq!(input.acked_seq(), 1);
+        assert_eq!(input.pending_len(), 1);
+        // 缺口补齐后按序交付
+        assert_eq!(
+            input.push(2, b"b".to_vec(), start),
+            vec![b"b".to_vec(), b"c".to_vec()]
+        );
+        assert_eq!(input.acked_seq(), 3);
+        // 重复输入被忽略
+        assert!(input.push(2, b"b".to_vec(), start).is_empty());
+
+        // seq 4 始终未到，超时后跳过
+        assert!(input.push(5, b"e".to_vec(), start).is_empty());
+        assert!(input.take_expired(start + Duration::from_secs(1)).is_empty());
+        assert_eq!(
+            input.take_expired(start + Duration::from_secs(2)),
+            vec![b"e".to_vec()]
+        );
+        assert_eq!(input.acked_seq(), 5);
+        assert_eq!(input.pending_len(), 0);
+    }
+
+    #[test]
+    fn test_input_reorder_first_seq_out_of_order() {
+        let start = Instant::now();
+        let mut input = InputReorderBuffer::new(Duration::from_secs(2));
+
+        // seq 2 先于 seq 1 到达，不能把 seq 2 当作起点而丢掉 seq 1
+        assert!(input.push(2, b"s".to_vec(), start).is_empty());
+        assert_eq!(input.acked_seq(), 0);
+        assert_eq!(
+            input.push(1, b"l".to_vec(), start),
+            vec![b"l".to_vec(), b"s".to_vec()]
+        );
+        assert_eq!(input.acked_seq(), 2);
+    }
+
+    #[test]
+    fn test_read_output_reports_gap() {
+        let session = TerminalSession::new(SessionConfig {
+            buffer_size: Some(MIN_BUFFER_SIZE),
+            ..test_config("gap")
+        })
+        .unwrap();
+
+        let data = vec![b'x'; MIN_BUFFER_SIZE + 100];
+        session.output_buffer.lock().unwrap().write(&data);
+        *session.output_cursor.lock().unwrap() = data.len() as u64;
+
+        let read = session.read_output(0);
+        assert!(read.resync);
+        assert_eq!(read.dropped_bytes, 100);
+        assert_eq!(read.data.len(), MIN_BUFFER_SIZE);
+        assert_eq!(read.next_cursor, data.len() as u64);
+
+        let read = session.read_output(read.next_cursor);
+        assert!(!read.resync);
+        assert!(read.data.is_empty());
+    }
+
+    #[test]
+    fn test_input_requires_writable_viewer() {
+        let session = TerminalSession::new(test_config("viewers"))
+        .unwrap();
+
+        // 没有查看者时保持单用户行为
+        assert!(session.check_input_allowed(None).is_ok());
+
+        session.attach_viewer("alice", ViewerRole::Owner, None).unwrap();
+        session.attach_viewer("bob", ViewerRole::Observer, None).unwrap();
+        assert!(session.check_input_allowed(Some("alice")).is_ok());
+        assert!(session.check_input_allowed(Some("bob")).is_err());
+        assert!(session.check_input_allowed(Some("mallory")).is_err());
+        // 观察者省略 viewer_id 不能绕过检查
+        assert!(session.check_input_allowed(None).is_err());
+    }
+
+    #[test]
+    fn test_report_output_throttled() {
+        let session = TerminalSession::new(SessionConfig {
+            output_rate_limit: Some(1000),
+            output_burst: Some(1000),
+            ..test_config("throttle")
+        })
+        .unwrap();
+        let feed = |data: &[u8]| {
+            session.output_buffer.lock().unwrap().write(data);
+            session.screen.lock().unwrap().process(data);
+            *session.output_cursor.lock().unwrap() += data.len() as u64;
+        };
+
+        // 超出令牌的部分暂缓上报
+        feed(&[b'x'; 1500]);
+        let read = session.read_report_output(0);
+        assert_eq!(read.data.len(), 1000);
+        assert_eq!(read.next_cursor, 1000);
+        let status = read.throttle.unwrap();
+        assert!(status.paused);
+        assert_eq!(status.pending_bytes, 500);
+
+        // 暂停期间无新数据可发，不重复通知
+        let read = session.read_report_output(read.next_cursor);
+        assert!(read.data.is_empty());
+        assert_eq!(read.next_cursor, 1000);
+        assert!(read.throttle.is_none());
+
+        // 积压超过突发容量时以屏幕快照代替
+        feed(b"\r\n$ ");
+        feed(&[b'y'; 5000]);
+        let read = session.read_report_output(1000);
+        let status = read.throttle.unwrap();
+        assert_eq!(read.next_cursor, session.get_output_cursor());
+        assert_eq!(status.coalesced_bytes, session.get_output_cursor() - 1000);
+        assert_eq!(status.pending_bytes, 0);
+        assert_eq!(read.data, session.screen_snapshot().formatted.into_bytes());
+    }
+
     #[test]
     fn test_ring_buffer_no_data_loss() {
         let mut buf = RingBuffer::new(100);
@@ -0,0 +1,134 @@
+// agent/src/terminal/throttle.rs
+// 终端输出上报限速（令牌桶）
+
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+
+/// 输出限速参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputLimit {
+    /// 持续速率（字节/秒）
+    pub rate: u64,
+    /// 突发容量（字节）
+    pub burst: u64,
+}
+
+impl OutputLimit {
+    /// 速率为 0 表示不限速；突发容量至少为一秒的速率
+    pub fn new(rate: u64, burst: u64) -> Option<Self> {
+        (rate > 0).then(|| Self {
+            rate,
+            burst: burst.max(rate),
+        })
+    }
+}
+
+/// 限速状态（附加在上报结果中，告知控制台输出被暂停或合并）
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ThrottleStatus {
+    /// 输出因限速暂停，剩余数据待后续上报
+    pub paused: bool,
+    /// 尚未上报的字节数
+    pub pending_bytes: u64,
+    /// 积压过多时跳过、改以屏幕快照代替的字节数
+    pub coalesced_bytes: u64,
+}
+
+/// 单个输出流的令牌桶
+///
+/// 令牌可以透支（例如发送屏幕快照时），透支期间输出暂停，直到令牌恢复。
+#[derive(Debug, Clone)]
+pub struct OutputThrottle {
+    limit: OutputLimit,
+    tokens: f64,
+    last_refill: Instant,
+    paused: bool,
+}
+
+impl OutputThrottle {
+    pub fn new(limit: OutputLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            last_refill: Instant::now(),
+            paused: false,
+        }
+    }
+
+    pub fn burst(&self) -> u64 {
+        self.limit.burst
+    }
+
+    /// 当前可发送的字节数
+    pub fn allowance(&mut self) -> u64 {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+        self.tokens = (self.tokens + elapsed * self.limit.rate as f64).min(self.limit.burst as f64);
+        self.tokens.max(0.0) as u64
+    }
+
+    /// 扣除已发送的字节数
+    pub fn consume(&mut self, bytes: u64) {
+        self.tokens -= bytes as f64;
+    }
+
+    /// 更新暂停状态，返回状态是否发生变化
+    pub fn set_paused(&mut self, paused: bool) -> bool {
+        let changed = self.paused != paused;
+        self.paused = paused;
+        changed
+    }
+}
+
+/// 不超过 `max` 的最长 UTF-8 完整前缀长度，避免截断多字节字符
+pub fn utf8_prefix_len(data: &[u8], max: usize) -> usize {
+    if max >= data.len() {
+        return data.len();
+    }
+    let mut end = max;
+    while end > 0 && data[end] & 0xc0 == 0x80 {
+        end -= 1;
+    }
+    // 非 UTF-8 数据（回退超过一个字符的长度）按字节截断
+    if max - end >= 4 {
+        max
+    } else {
+        end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        assert!(OutputLimit::new(0, 1024).is_none());
+        let limit = OutputLimit::new(1000, 100).unwrap();
+        assert_eq!(limit.burst, 1000);
+
+        let mut throttle = OutputThrottle::new(limit);
+        assert_eq!(throttle.allowance(), 1000);
+        throttle.consume(1500);
+        assert_eq!(throttle.allowance(), 0);
+        std::thread::sleep(std::time::Duration::from_millis(700));
+        let allowance = throttle.allowance();
+        assert!(allowance > 100 && allowance < 400, "allowance {}", allowance);
+
+        assert!(throttle.set_paused(true));
+        assert!(!throttle.set_paused(true));
+        assert!(throttle.set_paused(false));
+    }
+
+    #[test]
+    fn test_utf8_prefix_len() {
+        let text = "ab你好".as_bytes();
+        assert_eq!(utf8_prefix_len(text, 100), text.len());
+        assert_eq!(utf8_prefix_len(text, 2), 2);
+        assert_eq!(utf8_prefix_len(text, 3), 2);
+        assert_eq!(utf8_prefix_len(text, 4), 2);
+        assert_eq!(utf8_prefix_len(text, 5), 5);
+        assert_eq!(utf8_prefix_len(&[0x80; 10], 6), 6);
+    }
+}
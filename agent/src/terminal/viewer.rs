@@ -0,0 +1,182 @@
+// agent/src/terminal/viewer.rs
+// 终端会话的多查看者管理（结对与监督）
+
+use std::collections::HashMap;
+use std::io;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+/// 查看者角色
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ViewerRole {
+    /// 可读写
+    Owner,
+    /// 只读观察者，输入会被拒绝
+    Observer,
+}
+
+impl ViewerRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ViewerRole::Owner => "owner",
+            ViewerRole::Observer => "observer",
+        }
+    }
+
+    pub fn can_write(&self) -> bool {
+        matches!(self, ViewerRole::Owner)
+    }
+}
+
+/// 已接入的查看者
+#[derive(Debug, Clone)]
+pub struct Viewer {
+    pub viewer_id: String,
+    pub role: ViewerRole,
+    /// 操作者标识（由服务端提供，用于审计）
+    pub operator: Option<String>,
+    /// 该查看者已接收到的输出 cursor
+    pub cursor: u64,
+    pub attached_at: Instant,
+}
+
+impl Viewer {
+    /// 接入时长
+    pub fn duration(&self) -> Duration {
+        self.attached_at.elapsed()
+    }
+}
+
+/// 查看者信息（用于上报）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ViewerInfo {
+    pub viewer_id: String,
+    pub role: ViewerRole,
+    pub operator: Option<String>,
+    pub cursor: u64,
+}
+
+impl From<&Viewer> for ViewerInfo {
+    fn from(viewer: &Viewer) -> Self {
+        Self {
+            viewer_id: viewer.viewer_id.clone(),
+            role: viewer.role,
+            operator: viewer.operator.clone(),
+            cursor: viewer.cursor,
+        }
+    }
+}
+
+/// 单个会话的查看者表
+#[derive(Default)]
+pub struct ViewerRegistry {
+    viewers: HashMap<String, Viewer>,
+}
+
+impl ViewerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 接入查看者，输出从 `cursor` 开始跟随
+    pub fn attach(
+        &mut self,
+        viewer_id: &str,
+        role: ViewerRole,
+        operator: Option<String>,
+        cursor: u64,
+    ) -> io::Result<()> {
+        if self.viewers.contains_key(viewer_id) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Viewer already attached: {}", viewer_id),
+            ));
+        }
+        self.viewers.insert(
+            viewer_id.to_string(),
+            Viewer {
+                viewer_id: viewer_id.to_string(),
+                role,
+                operator,
+                cursor,
+                attached_at: Instant::now(),
+            },
+        );
+        Ok(())
+    }
+
+    /// 断开查看者
+    pub fn detach(&mut self, viewer_id: &str) -> Option<Viewer> {
+        self.viewers.remove(viewer_id)
+    }
+
+    /// 断开全部查看者（会话关闭时）
+    pub fn detach_all(&mut self) -> Vec<Viewer> {
+        self.viewers.drain().map(|(_, v)| v).collect()
+    }
+
+    pub fn role(&self, viewer_id: &str) -> Option<ViewerRole> {
+        self.viewers.get(viewer_id).map(|v| v.role)
+    }
+
+    pub fn list(&self) -> Vec<ViewerInfo> {
+        self.viewers.values().map(ViewerInfo::from).collect()
+    }
+
+    pub fn ids(&self) -> Vec<String> {
+        self.viewers.keys().cloned().collect()
+    }
+
+    /// 共享输出流推进到 `next_cursor`，返回各查看者推进前的 cursor
+    ///
+    /// 快照 cursor 已超过 `next_cursor` 的查看者保持不动。
+    pub fn advance(&mut self, next_cursor: u64) -> Vec<ViewerInfo> {
+        self.viewers
+            .values_mut()
+            .map(|viewer| {
+                let info = ViewerInfo::from(&*viewer);
+                viewer.cursor = viewer.cursor.max(next_cursor);
+                info
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_viewer_registry() {
+        let mut viewers = ViewerRegistry::new();
+        viewers.attach("alice", ViewerRole::Owner, None, 0).unwrap();
+        viewers
+            .attach("bob", ViewerRole::Observer, Some("bob@example.com".to_string()), 42)
+            .unwrap();
+
+        // 重复接入被拒绝
+        assert!(viewers.attach("bob", ViewerRole::Owner, None, 0).is_err());
+
+        assert!(viewers.role("alice").unwrap().can_write());
+        assert!(!viewers.role("bob").unwrap().can_write());
+        let cursor = |viewers: &ViewerRegistry, id: &str| {
+            viewers.list().into_iter().find(|v| v.viewer_id == id).unwrap().cursor
+        };
+        assert_eq!(cursor(&viewers, "bob"), 42);
+
+        // 共享输出流推进时，各查看者带上自己的起始 cursor
+        let mut from: Vec<(String, u64)> =
+            viewers.advance(30).into_iter().map(|info| (info.viewer_id, info.cursor)).collect();
+        from.sort();
+        assert_eq!(from, vec![("alice".to_string(), 0), ("bob".to_string(), 42)]);
+        assert_eq!(cursor(&viewers, "alice"), 30);
+        assert_eq!(cursor(&viewers, "bob"), 42);
+
+        let bob = viewers.detach("bob").unwrap();
+        assert_eq!(bob.operator.as_deref(), Some("bob@example.com"));
+        assert!(viewers.role("bob").is_none());
+        assert_eq!(viewers.detach_all().len(), 1);
+        assert!(viewers.list().is_empty());
+    }
+}
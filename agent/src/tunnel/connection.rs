@@ -0,0 +1,488 @@
+// agent/src/tunnel/connection.rs
+// 单条 TCP 隧道连接：目标方向写入与带窗口流控的回传缓冲
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+/// 读取线程检查停止标志的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 写入目标的超时时间
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 单次读取的最大字节数
+const READ_CHUNK: usize = 16 * 1024;
+
+/// 隧道状态
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TunnelState {
+    Open,
+    /// 目标已关闭连接，剩余数据待上报
+    Draining,
+    Closed,
+    Failed,
+}
+
+/// 目标 → 服务端方向的回传缓冲
+///
+/// `data` 中保存尚未被服务端确认的字节，首字节的 offset 为 `acked`；
+/// 未确认字节达到窗口大小时读取线程暂停读取，由 TCP 背压限制目标发送速度。
+struct ReceiveBuffer {
+    data: VecDeque<u8>,
+    acked: u64,
+    sent: u64,
+    window: usize,
+    eof: bool,
+    /// EOF 已随上报送出
+    eof_reported: bool,
+    error: Option<String>,
+}
+
+impl ReceiveBuffer {
+    fn end(&self) -> u64 {
+        self.acked + self.data.len() as u64
+    }
+}
+
+/// 隧道类型
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TunnelKind {
+    /// 服务端指定目标的端口转发
+    Tcp,
+    /// 操作者本地 SOCKS5 代理转发的 CONNECT 请求
+    Socks5,
+}
+
+impl TunnelKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TunnelKind::Tcp => "tcp",
+            TunnelKind::Socks5 => "socks5",
+        }
+    }
+}
+
+/// 隧道来源信息（用于审计）
+#[derive(Debug, Clone)]
+pub struct TunnelMeta {
+    pub kind: TunnelKind,
+    pub operator: Option<String>,
+    /// 所属代理会话（SOCKS 监听端的一次会话可包含多条连接）
+    pub proxy_session_id: Option<String>,
+}
+
+/// 一次回传读取的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct TunnelRead {
+    /// data 首字节的 offset
+    pub offset: u64,
+    pub data: Vec<u8>,
+    /// 目标已关闭连接（或读取出错），此后不再有新数据
+    pub eof: bool,
+    pub error: Option<String>,
+}
+
+/// 隧道信息（用于上报）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelInfo {
+    pub tunnel_id: String,
+    pub target: String,
+    pub peer_addr: String,
+    pub state: TunnelState,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+/// TCP 隧道连接
+pub struct TunnelConnection {
+    pub tunnel_id: String,
+    /// 请求的目标（host:port）
+    pub target: String,
+    pub peer_addr: SocketAddr,
+    /// 本地绑定地址（SOCKS 应答中的 BND.ADDR）
+    pub local_addr: SocketAddr,
+    pub meta: TunnelMeta,
+    pub created_at: Instant,
+    /// 写入与关闭共用的句柄，不加锁：`&TcpStream` 可直接写入与 shutdown，
+    /// 关闭时不会等待阻塞中的写入（shutdown 会让写入立即出错返回）
+    stream: TcpStream,
+    receive: Arc<(Mutex<ReceiveBuffer>, Condvar)>,
+    /// 已写入目标的字节数（服务端 → 目标方向的 offset）
+    written: Mutex<u64>,
+    write_closed: AtomicBool,
+    last_activity: Arc<Mutex<Instant>>,
+    stop: Arc<AtomicBool>,
+    reader_thread: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl TunnelConnection {
+    /// 依次尝试连接解析出的地址，成功后启动读取线程
+    pub fn connect(
+        tunnel_id: &str,
+        target: String,
+        addrs: &[SocketAddr],
+        window: usize,
+        connect_timeout: Duration,
+        meta: TunnelMeta,
+    ) -> io::Result<Self> {
+        let mut last_error = io::Error::new(io::ErrorKind::AddrNotAvailable, "No address to connect");
+        let mut connected = None;
+        for addr in addrs {
+            match TcpStream::connect_timeout(addr, connect_timeout) {
+                Ok(stream) => {
+                    connected = Some((stream, *addr));
+                    break;
+                }
+                Err(e) => last_error = e,
+            }
+        }
+        let (stream, peer_addr) = connected.ok_or(last_error)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+
+        let now = Instant::now();
+        let connection = Self {
+            tunnel_id: tunnel_id.to_string(),
+            target,
+            peer_addr,
+            local_addr: stream.local_addr()?,
+            meta,
+            created_at: now,
+            stream: stream.try_clone()?,
+            receive: Arc::new((
+                Mutex::new(ReceiveBuffer {
+                    data: VecDeque::new(),
+                    acked: 0,
+                    sent: 0,
+                    window: window.max(READ_CHUNK),
+                    eof: false,
+                    eof_reported: false,
+                    error: None,
+                }),
+                Condvar::new(),
+            )),
+            written: Mutex::new(0),
+            write_closed: AtomicBool::new(false),
+            last_activity: Arc::new(Mutex::new(now)),
+            stop: Arc::new(AtomicBool::new(false)),
+            reader_thread: Mutex::new(None),
+        };
+        connection.start_reader_thread(stream)?;
+        Ok(connection)
+    }
+
+    fn start_reader_thread(&self, mut stream: TcpStream) -> io::Result<()> {
+        let receive = Arc::clone(&self.receive);
+        let last_activity = Arc::clone(&self.last_activity);
+        let stop = Arc::clone(&self.stop);
+
+        let handle = thread::Builder::new()
+            .name(format!("tunnel-{}", self.tunnel_id))
+            .spawn(move || {
+                let (lock, cvar) = &*receive;
+                let mut buf = vec![0u8; READ_CHUNK];
+                loop {
+                    // 等待窗口有空闲
+                    let space = {
+                        let mut receive = lock.lock().unwrap();
+                        while receive.data.len() >= receive.window && !stop.load(Ordering::SeqCst) {
+                            receive = cvar.wait_timeout(receive, POLL_INTERVAL).unwrap().0;
+                        }
+                        receive.window - receive.data.len().min(receive.window)
+                    };
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+
+                    match stream.read(&mut buf[..space.min(READ_CHUNK)]) {
+                        Ok(0) => {
+                            lock.lock().unwrap().eof = true;
+                            break;
+                        }
+                        Ok(n) => {
+                            lock.lock().unwrap().data.extend(&buf[..n]);
+                            *last_activity.lock().unwrap() = Instant::now();
+                        }
+                        Err(ref e)
+                            if matches!(
+                                e.kind(),
+                                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+                            ) => {}
+                        Err(e) => {
+                            let mut receive = lock.lock().unwrap();
+                            receive.error = Some(e.to_string());
+                            receive.eof = true;
+                            break;
+                        }
+                    }
+                }
+            })?;
+
+        *self.reader_thread.lock().unwrap() = Some(handle);
+        Ok(())
+    }
+
+    /// 按 offset 写入目标，返回写入后的 offset
+    ///
+    /// 重复的数据（offset 小于已写入位置）被跳过；出现缺口时拒绝写入，
+    /// 由服务端从返回的 offset 重发。
+    pub fn write(&self, offset: u64, data: &[u8]) -> io::Result<u64> {
+        let mut written = self.written.lock().unwrap();
+        if offset > *written {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Data gap: expected offset {}, got {}", *written, offset),
+            ));
+        }
+        let skip = ((*written - offset) as usize).min(data.len());
+        let data = &data[skip..];
+        if data.is_empty() {
+            return Ok(*written);
+        }
+        if self.write_closed.load(Ordering::SeqCst) {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "Tunnel write side closed"));
+        }
+
+        // 写入由 written 锁串行化；逐次累加已写入的字节，
+        // 中途失败时 offset 仍准确，服务端重发不会重复写入
+        let mut remaining = data;
+        while !remaining.is_empty() {
+            match (&self.stream).write(remaining) {
+                Ok(0) => {
+                    return Err(io::Error::new(io::ErrorKind::WriteZero, "Failed to write tunnel data"));
+                }
+                Ok(n) => {
+                    *written += n as u64;
+                    remaining = &remaining[n..];
+                    *self.last_activity.lock().unwrap() = Instant::now();
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(*written)
+    }
+
+    /// 关闭写方向（服务端发送 EOF）
+    pub fn shutdown_write(&self) -> io::Result<()> {
+        if !self.write_closed.swap(true, Ordering::SeqCst) {
+            self.stream.shutdown(Shutdown::Write)?;
+        }
+        Ok(())
+    }
+
+    /// 服务端确认已收到 `offset` 之前的数据，释放窗口
+    pub fn ack(&self, offset: u64) {
+        let (lock, cvar) = &*self.receive;
+        let mut receive = lock.lock().unwrap();
+        let offset = offset.clamp(receive.acked, receive.sent);
+        let released = (offset - receive.acked) as usize;
+        receive.data.drain(..released);
+        receive.acked = offset;
+        cvar.notify_all();
+    }
+
+    /// 读取尚未上报的数据并推进上报位置
+    pub fn read_pending(&self) -> TunnelRead {
+        let mut receive = self.receive.0.lock().unwrap();
+        let offset = receive.sent;
+        let start = (offset - receive.acked) as usize;
+        let data: Vec<u8> = receive.data.range(start..).copied().collect();
+        receive.sent = receive.end();
+        let eof = receive.eof;
+        receive.eof_reported |= eof;
+        TunnelRead {
+            offset,
+            data,
+            eof,
+            error: receive.error.clone(),
+        }
+    }
+
+    /// 是否有尚未上报的数据或状态变化
+    pub fn has_pending(&self) -> bool {
+        let receive = self.receive.0.lock().unwrap();
+        receive.sent < receive.end() || (receive.eof && !receive.eof_reported)
+    }
+
+    /// 已确认的回传 offset
+    pub fn acked(&self) -> u64 {
+        self.receive.0.lock().unwrap().acked
+    }
+
+    /// 已写入目标的 offset
+    pub fn written(&self) -> u64 {
+        *self.written.lock().unwrap()
+    }
+
+    pub fn state(&self) -> TunnelState {
+        let receive = self.receive.0.lock().unwrap();
+        if self.stop.load(Ordering::SeqCst) {
+            TunnelState::Closed
+        } else if receive.error.is_some() {
+            TunnelState::Failed
+        } else if receive.eof {
+            TunnelState::Draining
+        } else {
+            TunnelState::Open
+        }
+    }
+
+    pub fn info(&self) -> TunnelInfo {
+        TunnelInfo {
+            tunnel_id: self.tunnel_id.clone(),
+            target: self.target.clone(),
+            peer_addr: self.peer_addr.to_string(),
+            state: self.state(),
+            bytes_in: self.receive.0.lock().unwrap().end(),
+            bytes_out: self.written(),
+        }
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.created_at.elapsed()
+    }
+
+    pub fn idle_time(&self) -> Duration {
+        self.last_activity.lock().unwrap().elapsed()
+    }
+
+    /// 关闭连接并停止读取线程
+    pub fn close(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.receive.1.notify_all();
+        let _ = self.stream.shutdown(Shutdown::Both);
+        if let Some(handle) = self.reader_thread.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for TunnelConnection {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn wait_for(mut cond: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !cond() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_tunnel_round_trip_with_window() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut peer, _) = listener.accept().unwrap();
+            let mut request = [0u8; 4];
+            peer.read_exact(&mut request).unwrap();
+            assert_eq!(&request, b"ping");
+            // 发送超过窗口的数据，验证背压
+            peer.write_all(&vec![b'x'; READ_CHUNK * 3]).unwrap();
+        });
+
+        let tunnel = TunnelConnection::connect(
+            "t1",
+            addr.to_string(),
+            &[addr],
+            READ_CHUNK,
+            Duration::from_secs(1),
+            TunnelMeta {
+                kind: TunnelKind::Tcp,
+                operator: None,
+                proxy_session_id: None,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(tunnel.write(0, b"pi").unwrap(), 2);
+        // 重复数据被跳过，缺口被拒绝
+        assert_eq!(tunnel.write(0, b"ping").unwrap(), 4);
+        assert!(tunnel.write(10, b"x").is_err());
+
+        let mut received = 0;
+        while received < READ_CHUNK * 3 {
+            wait_for(|| tunnel.has_pending());
+            let read = tunnel.read_pending();
+            if read.eof {
+                assert!(!tunnel.has_pending());
+            }
+            assert_eq!(read.offset, received as u64);
+            // 未确认的数据不超过窗口
+            assert!(read.data.len() <= READ_CHUNK);
+            received += read.data.len();
+            tunnel.ack(received as u64);
+        }
+        server.join().unwrap();
+
+        wait_for(|| tunnel.state() == TunnelState::Draining);
+        let read = tunnel.read_pending();
+        assert!(read.data.is_empty());
+        assert!(read.eof);
+        assert_eq!(tunnel.info().bytes_in, (READ_CHUNK * 3) as u64);
+
+        tunnel.close();
+        assert_eq!(tunnel.state(), TunnelState::Closed);
+    }
+
+    #[test]
+    fn test_close_does_not_wait_for_blocked_write() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // 目标接受连接但从不读取，写入最终阻塞在发送缓冲区
+        let server = thread::spawn(move || listener.accept().unwrap().0);
+
+        let tunnel = Arc::new(
+            TunnelConnection::connect(
+                "blocked",
+                addr.to_string(),
+                &[addr],
+                READ_CHUNK,
+                Duration::from_secs(1),
+                TunnelMeta {
+                    kind: TunnelKind::Tcp,
+                    operator: None,
+                    proxy_session_id: None,
+                },
+            )
+            .unwrap(),
+        );
+        let _peer = server.join().unwrap();
+
+        let writer = {
+            let tunnel = Arc::clone(&tunnel);
+            thread::spawn(move || {
+                let chunk = vec![b'x'; 1024 * 1024];
+                let mut offset = 0;
+                while let Ok(written) = tunnel.write(offset, &chunk) {
+                    offset = written;
+                }
+            })
+        };
+        // 等待写入阻塞
+        thread::sleep(Duration::from_millis(300));
+
+        let started = Instant::now();
+        tunnel.close();
+        assert!(started.elapsed() < Duration::from_secs(2), "close waited {:?}", started.elapsed());
+        writer.join().unwrap();
+    }
+}
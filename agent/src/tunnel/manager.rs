@@ -0,0 +1,216 @@
+// agent/src/tunnel/manager.rs
+// 隧道管理器
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::connection::{TunnelConnection, TunnelMeta};
+use super::policy::TunnelPolicy;
+
+/// 默认回传窗口（字节）
+pub const DEFAULT_WINDOW_SIZE: usize = 256 * 1024;
+
+/// 隧道管理器
+pub struct TunnelManager {
+    /// 值为 None 表示正在连接的预留位置，与已打开的隧道一起计入上限
+    tunnels: Mutex<HashMap<String, Option<Arc<TunnelConnection>>>>,
+    policy: TunnelPolicy,
+    max_tunnels: usize,
+    window_size: usize,
+    connect_timeout: Duration,
+    /// 空闲超时，None 表示不限制
+    idle_timeout: Option<Duration>,
+    /// 是否接受 SOCKS5 CONNECT 转发
+    socks_enabled: bool,
+}
+
+impl TunnelManager {
+    /// 创建新的管理器，目标地址受 `policy` 限制
+    pub fn new(policy: TunnelPolicy, max_tunnels: usize) -> Self {
+        Self {
+            tunnels: Mutex::new(HashMap::new()),
+            policy,
+            max_tunnels,
+            window_size: DEFAULT_WINDOW_SIZE,
+            connect_timeout: Duration::from_secs(10),
+            idle_timeout: None,
+            socks_enabled: false,
+        }
+    }
+
+    /// 设置是否作为 SOCKS5 出口接受 CONNECT 转发
+    pub fn with_socks(mut self, enabled: bool) -> Self {
+        self.socks_enabled = enabled;
+        self
+    }
+
+    pub fn socks_enabled(&self) -> bool {
+        self.socks_enabled
+    }
+
+    /// 设置回传窗口大小（字节）
+    pub fn with_window_size(mut self, window_size: usize) -> Self {
+        self.window_size = window_size;
+        self
+    }
+
+    /// 设置连接超时与空闲超时（秒），空闲超时为 0 表示不限制
+    pub fn with_timeouts(mut self, connect_timeout: u64, idle_timeout: u64) -> Self {
+        self.connect_timeout = Duration::from_secs(connect_timeout.max(1));
+        self.idle_timeout = (idle_timeout > 0).then(|| Duration::from_secs(idle_timeout));
+        self
+    }
+
+    /// 打开到 `host:port` 的隧道
+    ///
+    /// DNS 解析与拨号会阻塞（最长为连接超时），异步上下文中应放到阻塞线程池执行。
+    pub fn open(
+        &self,
+        tunnel_id: &str,
+        host: &str,
+        port: u16,
+        meta: TunnelMeta,
+    ) -> io::Result<Arc<TunnelConnection>> {
+        self.reserve(tunnel_id)?;
+
+        // 解析与连接可能耗时，不持有锁；预留位置保证并发打开不会超出上限
+        let result = self.connect(tunnel_id, host, port, meta);
+
+        let mut tunnels = self.tunnels.lock().unwrap();
+        match result {
+            Ok(connection) => {
+                tunnels.insert(tunnel_id.to_string(), Some(Arc::clone(&connection)));
+                Ok(connection)
+            }
+            Err(e) => {
+                tunnels.remove(tunnel_id);
+                Err(e)
+            }
+        }
+    }
+
+    fn connect(&self, tunnel_id: &str, host: &str, port: u16, meta: TunnelMeta) -> io::Result<Arc<TunnelConnection>> {
+        let addrs = self.policy.resolve(host, port)?;
+        Ok(Arc::new(TunnelConnection::connect(
+            tunnel_id,
+            format!("{}:{}", host, port),
+            &addrs,
+            self.window_size,
+            self.connect_timeout,
+            meta,
+        )?))
+    }
+
+    /// 检查上限并预留位置，与插入使用同一把锁
+    fn reserve(&self, tunnel_id: &str) -> io::Result<()> {
+        let mut tunnels = self.tunnels.lock().unwrap();
+        if tunnels.contains_key(tunnel_id) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Tunnel already exists: {}", tunnel_id),
+            ));
+        }
+        if tunnels.len() >= self.max_tunnels {
+            return Err(io::Error::new(
+                io::ErrorKind::ResourceBusy,
+                format!("Maximum tunnels limit reached: {}", self.max_tunnels),
+            ));
+        }
+        tunnels.insert(tunnel_id.to_string(), None);
+        Ok(())
+    }
+
+    pub fn get(&self, tunnel_id: &str) -> Option<Arc<TunnelConnection>> {
+        self.tunnels.lock().unwrap().get(tunnel_id).cloned().flatten()
+    }
+
+    /// 移除并关闭隧道
+    pub fn close(&self, tunnel_id: &str) -> Option<Arc<TunnelConnection>> {
+        let tunnel = {
+            let mut tunnels = self.tunnels.lock().unwrap();
+            // 仍在连接的预留位置由 open 自行处理
+            tunnels.get(tunnel_id)?.as_ref()?;
+            tunnels.remove(tunnel_id).flatten()?
+        };
+        tunnel.close();
+        Some(tunnel)
+    }
+
+    /// 当前全部隧道
+    pub fn tunnels(&self) -> Vec<Arc<TunnelConnection>> {
+        self.tunnels.lock().unwrap().values().flatten().cloned().collect()
+    }
+
+    /// 回收空闲超时的隧道：从管理器移除并关闭
+    pub fn reap_idle(&self) -> Vec<Arc<TunnelConnection>> {
+        let Some(idle_timeout) = self.idle_timeout else {
+            return Vec::new();
+        };
+        let idle: Vec<Arc<TunnelConnection>> = {
+            let mut tunnels = self.tunnels.lock().unwrap();
+            let ids: Vec<String> = tunnels
+                .iter()
+                .filter(|(_, tunnel)| matches!(tunnel, Some(tunnel) if tunnel.idle_time() >= idle_timeout))
+                .map(|(id, _)| id.clone())
+                .collect();
+            ids.iter().filter_map(|id| tunnels.remove(id).flatten()).collect()
+        };
+        for tunnel in &idle {
+            tunnel.close();
+        }
+        idle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tunnel::connection::TunnelKind;
+    use std::net::TcpListener;
+
+    fn meta() -> TunnelMeta {
+        TunnelMeta {
+            kind: TunnelKind::Tcp,
+            operator: None,
+            proxy_session_id: None,
+        }
+    }
+
+    #[test]
+    fn test_manager_policy_and_limits() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let policy = TunnelPolicy::from_rules(&[format!("127.0.0.1:{}", port)], &[]).unwrap();
+        let manager = TunnelManager::new(policy, 1).with_timeouts(1, 0);
+
+        // 策略外的端口被拒绝
+        let err = manager.open("denied", "127.0.0.1", port.wrapping_add(1), meta()).map(|_| ()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        manager.open("t1", "127.0.0.1", port, meta()).unwrap();
+        assert_eq!(manager.tunnels().len(), 1);
+        let err = manager.open("t2", "127.0.0.1", port, meta()).map(|_| ()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ResourceBusy);
+
+        assert!(manager.close("t1").is_some());
+        assert!(manager.get("t1").is_none());
+        assert!(manager.reap_idle().is_empty());
+
+        // 并发打开不会超出上限
+        let manager = Arc::new(manager);
+        let opened = (0..8)
+            .map(|i| {
+                let manager = Arc::clone(&manager);
+                std::thread::spawn(move || manager.open(&format!("c{}", i), "127.0.0.1", port, meta()).is_ok())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|ok| *ok)
+            .count();
+        assert_eq!(opened, 1);
+        assert_eq!(manager.tunnels().len(), 1);
+    }
+}
@@ -0,0 +1,12 @@
+// agent/src/tunnel/mod.rs
+// TCP 端口转发隧道模块（含 SOCKS5 CONNECT 转发）
+
+pub mod connection;
+pub mod manager;
+pub mod policy;
+pub mod socks;
+
+pub use connection::{TunnelConnection, TunnelKind, TunnelMeta, TunnelRead};
+pub use manager::TunnelManager;
+pub use policy::TunnelPolicy;
+pub use socks::{SocksReply, SocksRequest};
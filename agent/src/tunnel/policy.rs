@@ -0,0 +1,260 @@
+// agent/src/tunnel/policy.rs
+// 隧道目标地址策略（白名单与黑名单）
+
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+
+/// 主机匹配方式
+#[derive(Debug, Clone, PartialEq)]
+enum HostPattern {
+    /// 任意主机
+    Any,
+    /// 精确主机名（不区分大小写）
+    Name(String),
+    /// 域名后缀通配，如 `*.corp.example.com`
+    Suffix(String),
+    /// IP 网段（单个 IP 视为 /32 或 /128）
+    Cidr(IpAddr, u8),
+}
+
+/// 端口范围（闭区间）
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PortRange {
+    start: u16,
+    end: u16,
+}
+
+/// 单条目标规则，格式为 `host:ports`
+///
+/// - host：`*`、主机名、`*.suffix`、IP、CIDR（IPv6 需加方括号，如 `[fd00::/8]`）
+/// - ports：`*`、单个端口或 `start-end`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TunnelRule {
+    host: HostPattern,
+    ports: PortRange,
+}
+
+impl TunnelRule {
+    pub fn parse(rule: &str) -> io::Result<Self> {
+        let invalid = |reason: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid tunnel rule '{}': {}", rule, reason),
+            )
+        };
+
+        let rule = rule.trim();
+        let (host, ports) = if let Some(rest) = rule.strip_prefix('[') {
+            let (host, ports) = rest.split_once("]:").ok_or_else(|| invalid("missing port"))?;
+            (host, ports)
+        } else {
+            rule.rsplit_once(':').ok_or_else(|| invalid("missing port"))?
+        };
+
+        let host = if host == "*" {
+            HostPattern::Any
+        } else if let Some(suffix) = host.strip_prefix("*.") {
+            HostPattern::Suffix(format!(".{}", suffix.to_lowercase()))
+        } else if let Some((ip, prefix)) = host.split_once('/') {
+            let ip: IpAddr = ip.parse().map_err(|_| invalid("bad network address"))?;
+            let prefix: u8 = prefix.parse().map_err(|_| invalid("bad prefix length"))?;
+            if prefix > max_prefix(&ip) {
+                return Err(invalid("prefix length too large"));
+            }
+            HostPattern::Cidr(ip, prefix)
+        } else if let Ok(ip) = host.parse::<IpAddr>() {
+            HostPattern::Cidr(ip, max_prefix(&ip))
+        } else if host.is_empty() {
+            return Err(invalid("empty host"));
+        } else {
+            HostPattern::Name(host.to_lowercase())
+        };
+
+        let ports = if ports == "*" {
+            PortRange { start: 1, end: u16::MAX }
+        } else if let Some((start, end)) = ports.split_once('-') {
+            let start = start.parse().map_err(|_| invalid("bad port"))?;
+            let end = end.parse().map_err(|_| invalid("bad port"))?;
+            if start > end {
+                return Err(invalid("empty port range"));
+            }
+            PortRange { start, end }
+        } else {
+            let port = ports.parse().map_err(|_| invalid("bad port"))?;
+            PortRange { start: port, end: port }
+        };
+
+        Ok(Self { host, ports })
+    }
+
+    fn matches_port(&self, port: u16) -> bool {
+        self.ports.start <= port && port <= self.ports.end
+    }
+
+    /// 按请求的主机名匹配（不含 IP 网段规则）
+    fn matches_name(&self, host: &str, port: u16) -> bool {
+        if !self.matches_port(port) {
+            return false;
+        }
+        let host = host.to_lowercase();
+        match self.host {
+            HostPattern::Any => true,
+            HostPattern::Name(ref name) => *name == host,
+            HostPattern::Suffix(ref suffix) => host.ends_with(suffix.as_str()),
+            HostPattern::Cidr(..) => false,
+        }
+    }
+
+    /// 按解析后的地址匹配 IP 网段规则
+    fn matches_addr(&self, addr: &SocketAddr) -> bool {
+        if !self.matches_port(addr.port()) {
+            return false;
+        }
+        match self.host {
+            HostPattern::Any => true,
+            HostPattern::Cidr(network, prefix) => ip_in_network(addr.ip(), network, prefix),
+            _ => false,
+        }
+    }
+}
+
+fn max_prefix(ip: &IpAddr) -> u8 {
+    match ip {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+fn ip_in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    // IPv4 映射的 IPv6 地址按 IPv4 处理
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        v4 => v4,
+    };
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(net) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(net)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(net) & mask
+        }
+        _ => false,
+    }
+}
+
+fn parse_rules(rules: &[String]) -> io::Result<Vec<TunnelRule>> {
+    rules.iter().map(|rule| TunnelRule::parse(rule)).collect()
+}
+
+/// 隧道目标策略：黑名单优先，未命中白名单的目标一律拒绝
+#[derive(Debug, Clone, Default)]
+pub struct TunnelPolicy {
+    allow: Vec<TunnelRule>,
+    deny: Vec<TunnelRule>,
+}
+
+impl TunnelPolicy {
+    /// 从配置中的规则字符串构建策略
+    pub fn from_rules(allow: &[String], deny: &[String]) -> io::Result<Self> {
+        Ok(Self {
+            allow: parse_rules(allow)?,
+            deny: parse_rules(deny)?,
+        })
+    }
+
+    /// 解析目标并返回允许连接的地址
+    ///
+    /// 命中黑名单主机名规则的目标直接拒绝，命中黑名单网段的地址被剔除；
+    /// 白名单主机名规则命中时允许其余全部地址，否则只保留命中 IP 网段规则的地址，
+    /// 避免通过指向内网地址的域名绕过网段限制。
+    pub fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let denied = || {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Destination not allowed by tunnel policy: {}:{}", host, port),
+            )
+        };
+        if self.allow.is_empty() || self.deny.iter().any(|rule| rule.matches_name(host, port)) {
+            return Err(denied());
+        }
+
+        let addrs: Vec<SocketAddr> = (host, port)
+            .to_socket_addrs()?
+            .filter(|addr| !self.deny.iter().any(|rule| rule.matches_addr(addr)))
+            .collect();
+        if addrs.is_empty() {
+            return Err(denied());
+        }
+        if self.allow.iter().any(|rule| rule.matches_name(host, port)) {
+            return Ok(addrs);
+        }
+
+        let allowed: Vec<SocketAddr> = addrs
+            .into_iter()
+            .filter(|addr| self.allow.iter().any(|rule| rule.matches_addr(addr)))
+            .collect();
+        if allowed.is_empty() {
+            return Err(denied());
+        }
+        Ok(allowed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_parsing() {
+        assert!(TunnelRule::parse("db.internal:5432").is_ok());
+        assert!(TunnelRule::parse("*.corp.example.com:*").is_ok());
+        assert!(TunnelRule::parse("10.0.0.0/8:8000-8100").is_ok());
+        assert!(TunnelRule::parse("[::1]:22").is_ok());
+        assert!(TunnelRule::parse("[fd00::/8]:*").is_ok());
+
+        assert!(TunnelRule::parse("db.internal").is_err());
+        assert!(TunnelRule::parse("10.0.0.0/33:80").is_err());
+        assert!(TunnelRule::parse("host:90-80").is_err());
+        assert!(TunnelRule::parse(":80").is_err());
+    }
+
+    #[test]
+    fn test_policy_resolve() {
+        let policy = TunnelPolicy::from_rules(
+            &[
+                "127.0.0.0/8:8000-8100".to_string(),
+                "*.corp.example.com:443".to_string(),
+            ],
+            &["127.0.0.9:*".to_string()],
+        )
+        .unwrap();
+
+        let addrs = policy.resolve("127.0.0.1", 8080).unwrap();
+        assert_eq!(addrs, vec!["127.0.0.1:8080".parse::<SocketAddr>().unwrap()]);
+        // localhost 解析到 127.0.0.1 时命中网段规则
+        assert!(policy
+            .resolve("localhost", 8080)
+            .map(|addrs| addrs.iter().all(|a| a.ip().is_loopback()))
+            .unwrap_or(true));
+
+        let err = policy.resolve("127.0.0.1", 22).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        // 黑名单优先于白名单
+        let err = policy.resolve("127.0.0.9", 8080).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        assert!(TunnelRule::parse("*.corp.example.com:443")
+            .unwrap()
+            .matches_name("Wiki.Corp.Example.com", 443));
+        assert!(!TunnelRule::parse("*.corp.example.com:443")
+            .unwrap()
+            .matches_name("corp.example.com.evil", 443));
+
+        assert_eq!(
+            TunnelPolicy::default().resolve("127.0.0.1", 80).unwrap_err().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+    }
+}
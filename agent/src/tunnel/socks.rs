@@ -0,0 +1,174 @@
+// agent/src/tunnel/socks.rs
+// SOCKS5 CONNECT 请求解析与应答编码（RFC 1928）
+//
+// 握手与认证由操作者本地的 SOCKS 监听端完成，Agent 只处理转发过来的请求报文：
+// 按隧道策略拨号目标，并返回可直接写回客户端的应答报文。
+//
+// 目前只实现了 Agent 端；操作者监听端与服务端中继尚未提供，见 docs/tunnel_protocol.md。
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+const SOCKS_VERSION: u8 = 0x05;
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// SOCKS5 应答码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocksReply {
+    Succeeded = 0x00,
+    GeneralFailure = 0x01,
+    NotAllowed = 0x02,
+    NetworkUnreachable = 0x03,
+    HostUnreachable = 0x04,
+    ConnectionRefused = 0x05,
+    TtlExpired = 0x06,
+    CommandNotSupported = 0x07,
+    AddressTypeNotSupported = 0x08,
+}
+
+impl SocksReply {
+    /// 将拨号错误映射为应答码
+    pub fn from_error(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => SocksReply::NotAllowed,
+            io::ErrorKind::ConnectionRefused => SocksReply::ConnectionRefused,
+            io::ErrorKind::NetworkUnreachable => SocksReply::NetworkUnreachable,
+            io::ErrorKind::HostUnreachable | io::ErrorKind::AddrNotAvailable => SocksReply::HostUnreachable,
+            io::ErrorKind::TimedOut => SocksReply::TtlExpired,
+            // Agent 自身的限制（隧道数上限、ID 冲突、功能未启用）
+            io::ErrorKind::ResourceBusy | io::ErrorKind::AlreadyExists | io::ErrorKind::Unsupported => {
+                SocksReply::GeneralFailure
+            }
+            // 其余多为域名解析失败等目标不可达的情况
+            _ => SocksReply::HostUnreachable,
+        }
+    }
+
+    /// 编码应答报文，`bound` 为 Agent 侧的本地地址
+    pub fn encode(self, bound: Option<SocketAddr>) -> Vec<u8> {
+        let bound = bound.unwrap_or_else(|| SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0));
+        let mut reply = vec![SOCKS_VERSION, self as u8, 0x00];
+        match bound.ip() {
+            IpAddr::V4(ip) => {
+                reply.push(ATYP_IPV4);
+                reply.extend_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                reply.push(ATYP_IPV6);
+                reply.extend_from_slice(&ip.octets());
+            }
+        }
+        reply.extend_from_slice(&bound.port().to_be_bytes());
+        reply
+    }
+}
+
+/// 解析后的 CONNECT 请求
+#[derive(Debug, Clone, PartialEq)]
+pub struct SocksRequest {
+    /// 目标主机（域名或 IP 字面量）
+    pub host: String,
+    pub port: u16,
+}
+
+impl SocksRequest {
+    /// 解析请求报文 `VER CMD RSV ATYP DST.ADDR DST.PORT`，失败时返回应答码
+    pub fn parse(request: &[u8]) -> Result<Self, SocksReply> {
+        let [version, command, _reserved, atyp, rest @ ..] = request else {
+            return Err(SocksReply::GeneralFailure);
+        };
+        if *version != SOCKS_VERSION {
+            return Err(SocksReply::GeneralFailure);
+        }
+        if *command != CMD_CONNECT {
+            return Err(SocksReply::CommandNotSupported);
+        }
+
+        let (host, port) = match *atyp {
+            ATYP_IPV4 => {
+                let [a, b, c, d, port @ ..] = rest else {
+                    return Err(SocksReply::GeneralFailure);
+                };
+                (Ipv4Addr::new(*a, *b, *c, *d).to_string(), port)
+            }
+            ATYP_IPV6 => {
+                if rest.len() < 16 {
+                    return Err(SocksReply::GeneralFailure);
+                }
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(&rest[..16]);
+                (Ipv6Addr::from(octets).to_string(), &rest[16..])
+            }
+            ATYP_DOMAIN => {
+                let [len, rest @ ..] = rest else {
+                    return Err(SocksReply::GeneralFailure);
+                };
+                let len = *len as usize;
+                if len == 0 || rest.len() < len {
+                    return Err(SocksReply::GeneralFailure);
+                }
+                let host = std::str::from_utf8(&rest[..len]).map_err(|_| SocksReply::GeneralFailure)?;
+                (host.to_string(), &rest[len..])
+            }
+            _ => return Err(SocksReply::AddressTypeNotSupported),
+        };
+
+        let [hi, lo] = port else {
+            return Err(SocksReply::GeneralFailure);
+        };
+        Ok(Self {
+            host,
+            port: u16::from_be_bytes([*hi, *lo]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let request = SocksRequest::parse(&[5, 1, 0, 1, 10, 0, 0, 5, 0x1f, 0x90]).unwrap();
+        assert_eq!(request, SocksRequest { host: "10.0.0.5".to_string(), port: 8080 });
+
+        let mut domain = vec![5, 1, 0, 3, 11];
+        domain.extend_from_slice(b"intranet.lo");
+        domain.extend_from_slice(&443u16.to_be_bytes());
+        let request = SocksRequest::parse(&domain).unwrap();
+        assert_eq!(request.host, "intranet.lo");
+        assert_eq!(request.port, 443);
+
+        let mut v6 = vec![5, 1, 0, 4];
+        v6.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        v6.extend_from_slice(&22u16.to_be_bytes());
+        assert_eq!(SocksRequest::parse(&v6).unwrap().host, "::1");
+
+        // BIND / UDP ASSOCIATE 不支持
+        assert_eq!(
+            SocksRequest::parse(&[5, 2, 0, 1, 10, 0, 0, 5, 0, 80]),
+            Err(SocksReply::CommandNotSupported)
+        );
+        assert_eq!(SocksRequest::parse(&[5, 1, 0, 9, 0, 80]), Err(SocksReply::AddressTypeNotSupported));
+        assert_eq!(SocksRequest::parse(&[5, 1, 0, 1, 10, 0]), Err(SocksReply::GeneralFailure));
+        assert_eq!(SocksRequest::parse(&[4, 1, 0, 80]), Err(SocksReply::GeneralFailure));
+    }
+
+    #[test]
+    fn test_encode_reply() {
+        let bound: SocketAddr = "192.168.1.2:40000".parse().unwrap();
+        assert_eq!(
+            SocksReply::Succeeded.encode(Some(bound)),
+            vec![5, 0, 0, 1, 192, 168, 1, 2, 0x9c, 0x40]
+        );
+        assert_eq!(SocksReply::NotAllowed.encode(None), vec![5, 2, 0, 1, 0, 0, 0, 0, 0, 0]);
+
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+        assert_eq!(SocksReply::from_error(&denied), SocksReply::NotAllowed);
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        assert_eq!(SocksReply::from_error(&refused), SocksReply::ConnectionRefused);
+    }
+}
@@ -0,0 +1,17 @@
+-- Migration: 0005_agent_logs
+-- Description: 存储 Agent 日志采集（log_shipping）上送的日志行
+
+CREATE TABLE agent_logs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    device_id TEXT NOT NULL,
+    hostname TEXT,
+    path TEXT NOT NULL,
+    line TEXT NOT NULL,
+    offset INTEGER NOT NULL, -- 该行结束处在文件中的偏移
+    read_at INTEGER NOT NULL, -- Agent 读取时间（Unix 毫秒）
+    received_at INTEGER NOT NULL,
+    FOREIGN KEY (device_id) REFERENCES devices(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_agent_logs_device_path ON agent_logs(device_id, path, offset);
+CREATE INDEX idx_agent_logs_read_at ON agent_logs(read_at);
@@ -61,6 +61,7 @@ function mapEventTypeToActionType(eventType: string): 'register' | 'heartbeat' |
   const typeMap: Record<string, 'register' | 'heartbeat' | 'command' | 'file_op' | 'session'> = {
     'CommandExecute': 'command',
     'FileList': 'file_op',
+    'FileSearch': 'file_op',
     'FileDownload': 'file_op',
     'FileUpload': 'file_op',
     'FileDelete': 'file_op',
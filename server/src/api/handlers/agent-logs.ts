@@ -0,0 +1,190 @@
+/**
+ * Agent 日志采集接收 API
+ * 接收 Agent log_shipping 批量上送的日志行并存储
+ */
+
+import { Env } from '../../index';
+import { createKVManager, validateNonce, checkAndUpdateRateLimit } from '../../storage/kv-manager';
+import { verifyEd25519Signature } from '../utils/crypto';
+import { getDeviceById } from '../utils/database';
+
+// ============= 类型定义 =============
+
+export interface LogRecord {
+  path: string;
+  line: string;
+  offset: number;
+  read_at: number;
+}
+
+export interface LogBatchRequest {
+  device_id: string;
+  timestamp: number;
+  nonce: string;
+  /** `{timestamp}:{base64 签名}` */
+  signature: string;
+  host: {
+    hostname: string;
+    platform: string;
+    agent_version: string;
+  };
+  /** `records` 序列化为 JSON 后的 SHA-256（hex） */
+  records_sha256: string;
+  records: LogRecord[];
+}
+
+/** 单批最多接收的行数 */
+const MAX_RECORDS_PER_BATCH = 1000;
+
+/** 速率限制：每分钟最多上送批次数 */
+const MAX_BATCHES_PER_MINUTE = 30;
+
+// ============= 工具函数 =============
+
+function createErrorResponse(
+  message: string,
+  errorCode: string,
+  status: number,
+  headers: Record<string, string> = {}
+): Response {
+  return new Response(JSON.stringify({
+    status: 'error',
+    error: message,
+    error_code: errorCode,
+  }), {
+    status,
+    headers: { 'Content-Type': 'application/json', ...headers },
+  });
+}
+
+async function sha256Hex(data: string): Promise<string> {
+  const digest = await crypto.subtle.digest('SHA-256', new TextEncoder().encode(data));
+  return Array.from(new Uint8Array(digest))
+    .map((byte) => byte.toString(16).padStart(2, '0'))
+    .join('');
+}
+
+/**
+ * 校验批次签名，签名内容与 Agent 一致：
+ * `{device_id}:{timestamp}:{nonce}:{记录数}:{records_sha256}`
+ */
+async function verifyBatch(body: LogBatchRequest, publicKey: string): Promise<string | null> {
+  if (Math.abs(Date.now() - body.timestamp) > 5 * 60 * 1000) {
+    return 'Timestamp out of range';
+  }
+
+  // Agent 按记录数组的 JSON 序列化计算摘要，这里按收到的内容重新计算
+  if (await sha256Hex(JSON.stringify(body.records)) !== body.records_sha256) {
+    return 'Records digest mismatch';
+  }
+
+  const separator = body.signature.indexOf(':');
+  const signature = separator >= 0 ? body.signature.slice(separator + 1) : body.signature;
+  const signData = [
+    body.device_id,
+    body.timestamp,
+    body.nonce,
+    body.records.length,
+    body.records_sha256,
+  ].join(':');
+  if (!await verifyEd25519Signature(publicKey, signature, signData)) {
+    return 'Invalid signature';
+  }
+  return null;
+}
+
+// ============= API 处理器 =============
+
+/**
+ * 接收 Agent 批量上送的日志
+ * POST /agent/logs
+ */
+export async function receiveAgentLogs(
+  request: Request,
+  env: Env,
+  ctx: ExecutionContext
+): Promise<Response> {
+  try {
+    const body = await request.json() as LogBatchRequest;
+
+    if (!body.device_id || !body.timestamp || !body.nonce || !body.signature || !body.records_sha256) {
+      return createErrorResponse('Missing required fields', 'INVALID_REQUEST', 400);
+    }
+
+    if (!Array.isArray(body.records) || body.records.length === 0) {
+      return createErrorResponse('Records array is empty or invalid', 'INVALID_REQUEST', 400);
+    }
+
+    if (body.records.length > MAX_RECORDS_PER_BATCH) {
+      return createErrorResponse(
+        `Too many records in batch (max ${MAX_RECORDS_PER_BATCH})`,
+        'BATCH_TOO_LARGE',
+        400
+      );
+    }
+
+    const kvManager = createKVManager(env.KV);
+
+    // Agent 收到 429 时按 Retry-After 退避，不丢弃未确认的日志
+    const rateLimitResult = await checkAndUpdateRateLimit(
+      kvManager,
+      body.device_id,
+      'logs',
+      MAX_BATCHES_PER_MINUTE,
+      60
+    );
+    if (!rateLimitResult.allowed) {
+      const retryAfter = Math.max(1, Math.ceil((rateLimitResult.resetTime - Date.now()) / 1000));
+      return createErrorResponse('Rate limit exceeded', 'RATE_LIMIT_EXCEEDED', 429, {
+        'Retry-After': String(retryAfter),
+      });
+    }
+
+    const device = await getDeviceById(env.DB, body.device_id);
+    if (!device) {
+      return createErrorResponse('Device not found', 'DEVICE_NOT_FOUND', 404);
+    }
+
+    const reason = await verifyBatch(body, device.public_key);
+    if (reason) {
+      return createErrorResponse(reason, 'INVALID_SIGNATURE', 401);
+    }
+
+    const nonceResult = await validateNonce(kvManager, body.device_id, body.nonce);
+    if (!nonceResult.valid) {
+      return createErrorResponse(
+        nonceResult.reason || 'Nonce validation failed',
+        'REPLAY_ATTACK',
+        401
+      );
+    }
+
+    // 整批写入，失败时 Agent 重试整批
+    const receivedAt = Date.now();
+    const stmt = env.DB.prepare(`
+      INSERT INTO agent_logs (
+        device_id, hostname, path, line, offset, read_at, received_at
+      ) VALUES (?, ?, ?, ?, ?, ?, ?)
+    `);
+    await env.DB.batch(body.records.map((record) => stmt.bind(
+      body.device_id,
+      body.host?.hostname || null,
+      record.path,
+      record.line,
+      record.offset,
+      record.read_at,
+      receivedAt
+    )));
+
+    return new Response(JSON.stringify({
+      status: 'ok',
+      accepted_count: body.records.length,
+    }), {
+      status: 200,
+      headers: { 'Content-Type': 'application/json' },
+    });
+  } catch (error) {
+    console.error('Error in receiveAgentLogs:', error);
+    return createErrorResponse('Internal server error', 'INTERNAL_ERROR', 500);
+  }
+}
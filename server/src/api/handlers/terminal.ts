@@ -253,15 +253,10 @@ export async function sendTerminalInput(
       });
     }
 
-    // 生成 client_seq（如果未提供）
-    const clientSeq = body.client_seq || Date.now();
-
     // 记录输入到数据库（用于去重）
+    let clientSeq: number;
     try {
-      await env.DB.prepare(`
-        INSERT INTO terminal_inputs (session_id, client_seq, input_data, created_at)
-        VALUES (?, ?, ?, datetime('now'))
-      `).bind(body.session_id, clientSeq, body.input_data).run();
+      clientSeq = await recordTerminalInput(env, body.session_id, body.client_seq, body.input_data);
     } catch (error: any) {
       // 如果是重复的 client_seq，忽略（幂等性）
       if (error.message && error.message.includes('UNIQUE constraint')) {
@@ -347,6 +342,39 @@ export async function sendTerminalInput(
   }
 }
 
+/**
+ * 记录终端输入，返回其 client_seq
+ *
+ * 未提供 client_seq 时按会话分配下一个连续序号（agent 从 1 开始按序号顺序交付输入，
+ * 序号不连续会让输入等待缺口超时）。取最大序号与插入在同一条语句中完成，
+ * 并发输入不会拿到相同序号。
+ */
+async function recordTerminalInput(
+  env: Env,
+  sessionId: string,
+  clientSeq: number | undefined,
+  inputData: string
+): Promise<number> {
+  if (clientSeq) {
+    await env.DB.prepare(`
+      INSERT INTO terminal_inputs (session_id, client_seq, input_data, created_at)
+      VALUES (?, ?, ?, datetime('now'))
+    `).bind(sessionId, clientSeq, inputData).run();
+    return clientSeq;
+  }
+
+  const row = await env.DB.prepare(`
+    INSERT INTO terminal_inputs (session_id, client_seq, input_data, created_at)
+    SELECT ?, COALESCE(MAX(client_seq), 0) + 1, ?, datetime('now')
+    FROM terminal_inputs WHERE session_id = ?
+    RETURNING client_seq
+  `).bind(sessionId, inputData, sessionId).first<{ client_seq: number }>();
+  if (!row) {
+    throw new Error(`Failed to record input for session ${sessionId}`);
+  }
+  return row.client_seq;
+}
+
 /**
  * 查询终端输出
  * GET /terminal/output/:sessionId?from_cursor=N
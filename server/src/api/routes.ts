@@ -19,6 +19,7 @@ import { getAuditLogsHandler } from './handlers/audit';
 import { getDevices, getDevice, updateDevice, deleteDevice } from './handlers/devices';
 import { getAgentCommands, ackCommand, createCommand, getCommandStatus, getDeviceCommandHistory } from './handlers/command';
 import { receiveAuditLogs, getDeviceAuditLogs } from './handlers/agent-audit';
+import { receiveAgentLogs } from './handlers/agent-logs';
 import { syncConfig, getConfigs, updateConfig, deleteConfig } from './handlers/config';
 import { adminLogin, verifyAdminSession, adminLogout } from './handlers/admin-auth';
 import { createTask, getTask, listDeviceTasks, cancelTask } from './handlers/tasks';
@@ -78,6 +79,7 @@ export function createRouter() {
   router.get('/agent/command', getAgentCommands);
   router.post('/agent/command/:id/ack', ackCommand);
   router.post('/agent/audit', receiveAuditLogs);
+  router.post('/agent/logs', receiveAgentLogs);
   router.post('/agent/config', syncConfig);
 
   // ==================== 管理员 API (需要 JWT Token 认证) ====================